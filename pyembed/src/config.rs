@@ -5,8 +5,8 @@
 //! Data structures for configuring a Python interpreter.
 
 use {
-    crate::NewInterpreterError,
-    oxidized_importer::{PackedResourcesSource, PythonResourcesState},
+    crate::{NewInterpreterError, PackedResourcesKeyProvider},
+    oxidized_importer::{PackedResourcesLayer, PackedResourcesSource, PythonResourcesState},
     pyo3::ffi as pyffi,
     python_packaging::interpreter::{
         MemoryAllocatorBackend, MultiprocessingStartMethod, PythonInterpreterConfig,
@@ -16,6 +16,7 @@ use {
         ffi::{CString, OsString},
         ops::Deref,
         path::PathBuf,
+        sync::Arc,
     },
 };
 
@@ -290,6 +291,178 @@ pub struct OxidizedPythonInterpreterConfig<'a> {
     #[cfg_attr(feature = "serialization", serde(skip))]
     pub packed_resources: Vec<PackedResourcesSource<'a>>,
 
+    /// Named layers of packed resources data to load.
+    ///
+    /// Layers are indexed after [Self::packed_resources], in order. Unlike
+    /// [Self::packed_resources], resources in layers aren't merged: a resource
+    /// in a layer replaces a resource of the same name unless the existing
+    /// resource came from a layer having a higher priority. This allows shipping
+    /// a base resources blob along with patch blobs overriding parts of it.
+    ///
+    /// Python code can query which layer provided a resource via
+    /// `OxidizedFinder.resource_layer()` and which resources were provided by
+    /// multiple layers via `OxidizedFinder.layer_conflicts()`.
+    ///
+    /// Default value: `vec![]`
+    ///
+    /// [Self::resolve()] behavior: [PackedResourcesSource::MemoryMappedPath]
+    /// sources have the special string `$ORIGIN` expanded to the string value
+    /// that [Self::origin] resolves to.
+    ///
+    /// Interpreter initialization behavior: interpreter initialization fails
+    /// if multiple layers have the same name.
+    ///
+    /// This field is ignored during serialization.
+    #[cfg_attr(feature = "serialization", serde(skip))]
+    pub packed_resources_layers: Vec<PackedResourcesLayer<'a>>,
+
+    /// Whether layer conflicts that aren't resolved by priority are an error.
+    ///
+    /// When a resource is provided by multiple entries in
+    /// [Self::packed_resources_layers] having the same priority, the layer
+    /// indexed last wins. If this is true, this is an error instead.
+    ///
+    /// Default value: `false`
+    ///
+    /// Interpreter initialization behavior: interpreter initialization fails
+    /// if a conflict isn't resolved by priority.
+    pub packed_resources_strict_layers: bool,
+
+    /// An ed25519 public key that packed resources data must be signed with.
+    ///
+    /// If set, every entry in [Self::packed_resources] must be version 4
+    /// packed resources data having an integrity trailer holding a valid
+    /// signature made by the secret key corresponding to this public key.
+    /// Data failing verification is rejected before any of its resources
+    /// are indexed.
+    ///
+    /// Default value: [None]
+    ///
+    /// Interpreter initialization behavior: interpreter initialization fails
+    /// if any packed resources fail verification.
+    pub packed_resources_public_key: Option<[u8; 32]>,
+
+    /// Supplies the key used to decrypt encrypted packed resources data.
+    ///
+    /// Packed resources data can have its blob sections encrypted with
+    /// AES-256-GCM. Encrypted data is decrypted when it is indexed, which
+    /// requires a key. Resource names remain readable without the key.
+    ///
+    /// Default value: [None]
+    ///
+    /// Interpreter initialization behavior: the key is obtained from the
+    /// provider before any packed resources are indexed. Interpreter
+    /// initialization fails if the provider fails or if encrypted packed
+    /// resources data can't be decrypted.
+    ///
+    /// This field is ignored during serialization.
+    #[cfg_attr(feature = "serialization", serde(skip))]
+    pub packed_resources_key_provider: Option<Arc<dyn PackedResourcesKeyProvider>>,
+
+    /// Whether to lazily index packed resources data having a name index.
+    ///
+    /// If true, entries in [Self::packed_resources] having a name index are not
+    /// parsed during interpreter initialization. Instead, the custom importer
+    /// looks up resources in the name index when they are first accessed. This
+    /// reduces startup overhead when there are many resources.
+    ///
+    /// Default value: `false`
+    pub packed_resources_lazy_index: bool,
+
+    /// Whether to re-index memory mapped packed resources files when they change.
+    ///
+    /// If true, [PackedResourcesSource::MemoryMappedPath] entries in
+    /// [Self::packed_resources] are read into memory instead of being memory
+    /// mapped, so they can be rewritten while the process runs. Changed files
+    /// are re-indexed when `importlib.invalidate_caches()` or
+    /// `OxidizedFinder.reload_watched_resources()` is called. The latter can
+    /// also remove modules from changed files from `sys.modules`.
+    ///
+    /// This is intended to speed up development by avoiding relinking the
+    /// binary when resources are rebuilt. It only has an effect in builds
+    /// having debug assertions enabled.
+    ///
+    /// Default value: `false`
+    pub packed_resources_hot_reload: bool,
+
+    /// Paths to zip archives to register as import sources.
+    ///
+    /// Each archive is memory mapped and an `OxidizedZipFinder` servicing it is
+    /// appended to `sys.meta_path` during interpreter initialization. Only the
+    /// central directory of each archive is read at that time: members are
+    /// decompressed when they are imported.
+    ///
+    /// A zip archive may be preceded by other data. So to import from a zip
+    /// archive appended to the current executable, add the path of the
+    /// executable to this list.
+    ///
+    /// Requires the `zipimport` feature.
+    ///
+    /// Default value: `vec![]`
+    ///
+    /// [Self::resolve()] behavior: the special string `$ORIGIN` is expanded to
+    /// the string value that [Self::origin] resolves to.
+    ///
+    /// Interpreter initialization behavior: interpreter initialization fails
+    /// if an archive cannot be opened or indexed.
+    pub zip_archives: Vec<PathBuf>,
+
+    /// Directory under which native files from wheels are extracted.
+    ///
+    /// Wheels embedded as file data in packed resources are indexed by the
+    /// custom importer, which imports their pure Python modules, package
+    /// resources and `.dist-info` metadata from memory. Extension modules and
+    /// shared libraries in wheels are extracted to a subdirectory of this
+    /// directory when an extension module from the wheel is first imported.
+    /// Subdirectories are named after the wheel and a digest of its content,
+    /// so extracted files are reused by subsequent runs.
+    ///
+    /// If [None], files are extracted to a private temporary directory that
+    /// is deleted when the interpreter is finalized.
+    ///
+    /// Requires the `zipimport` feature.
+    ///
+    /// Default value: [None]
+    ///
+    /// [Self::resolve()] behavior: the special string `$ORIGIN` is expanded to
+    /// the string value that [Self::origin] resolves to.
+    pub wheel_extension_cache_dir: Option<PathBuf>,
+
+    /// Directory under which resources marked as extracted to a cache are extracted.
+    ///
+    /// Resources in the `extract-to-cache` location are embedded in packed
+    /// resources. The first time a module from a top-level package having
+    /// such resources is found, its module source, package resources,
+    /// extension modules and shared libraries are written to a subdirectory
+    /// of this directory and `__file__` refers to the extracted files.
+    /// Subdirectories are named after the package and a digest of its files,
+    /// so extracted files are reused by subsequent runs. Subdirectories of the
+    /// package having a different digest are removed once they haven't been
+    /// used for a week.
+    ///
+    /// If [None], a directory named after the current executable in the
+    /// per-user cache directory is used. e.g. `~/.cache/pyoxidizer/myapp`
+    /// on Linux. Extraction fails if the platform doesn't define a per-user
+    /// cache directory.
+    ///
+    /// Default value: [None]
+    ///
+    /// [Self::resolve()] behavior: the special string `$ORIGIN` is expanded to
+    /// the string value that [Self::origin] resolves to.
+    pub resources_cache_dir: Option<PathBuf>,
+
+    /// Python statements to execute once the interpreter is initialized.
+    ///
+    /// These are typically `import` lines from `.pth` files, which the `site`
+    /// module would execute when processing a site-packages directory.
+    ///
+    /// Default value: `vec![]`
+    ///
+    /// Interpreter initialization behavior: each statement is executed in a
+    /// fresh namespace after importers are registered. Like the `site` module,
+    /// errors are printed to stderr and do not abort initialization.
+    pub startup_hooks: Vec<String>,
+
     /// Extra extension modules to make available to the interpreter.
     ///
     /// The values will effectively be passed to ``PyImport_ExtendInitTab()``.
@@ -419,6 +592,16 @@ pub struct OxidizedPythonInterpreterConfig<'a> {
     /// This setting is useful to record which modules are loaded during the execution
     /// of a Python interpreter.
     ///
+    /// If [Self::oxidized_importer] is enabled, an `imports-<random>.json` file
+    /// will also be written. It contains a JSON object whose `imports` key holds
+    /// the `find_spec()` and `exec_module()` calls serviced by `OxidizedFinder`,
+    /// with whether each was a hit, where the module came from, and how long
+    /// the call took.
+    ///
+    /// A `resources-<random>.json` file is written as well. Its `accessed` key
+    /// maps the names of packed resources that were accessed to counts of accesses
+    /// and its `unused` key holds the names of resources that were never accessed.
+    ///
     /// Default value: [None]
     pub write_modules_directory_env: Option<String>,
 }
@@ -444,6 +627,16 @@ impl<'a> Default for OxidizedPythonInterpreterConfig<'a> {
             oxidized_importer: false,
             filesystem_importer: true,
             packed_resources: vec![],
+            packed_resources_layers: vec![],
+            packed_resources_strict_layers: false,
+            packed_resources_public_key: None,
+            packed_resources_key_provider: None,
+            packed_resources_lazy_index: false,
+            packed_resources_hot_reload: false,
+            zip_archives: vec![],
+            wheel_extension_cache_dir: None,
+            resources_cache_dir: None,
+            startup_hooks: vec![],
             extra_extension_modules: None,
             argv: None,
             argvb: false,
@@ -496,19 +689,44 @@ impl<'a> OxidizedPythonInterpreterConfig<'a> {
 
         let origin_string = origin.display().to_string();
 
+        let resolve_source = |entry| match entry {
+            PackedResourcesSource::Memory(_) => entry,
+            PackedResourcesSource::MemoryMappedPath(p) => PackedResourcesSource::MemoryMappedPath(
+                PathBuf::from(p.display().to_string().replace("$ORIGIN", &origin_string)),
+            ),
+        };
+
         let packed_resources = self
             .packed_resources
             .into_iter()
-            .map(|entry| match entry {
-                PackedResourcesSource::Memory(_) => entry,
-                PackedResourcesSource::MemoryMappedPath(p) => {
-                    PackedResourcesSource::MemoryMappedPath(PathBuf::from(
-                        p.display().to_string().replace("$ORIGIN", &origin_string),
-                    ))
-                }
+            .map(resolve_source)
+            .collect::<Vec<_>>();
+
+        let packed_resources_layers = self
+            .packed_resources_layers
+            .into_iter()
+            .map(|layer| PackedResourcesLayer {
+                source: resolve_source(layer.source),
+                ..layer
             })
             .collect::<Vec<_>>();
 
+        let zip_archives = self
+            .zip_archives
+            .iter()
+            .map(|p| PathBuf::from(p.display().to_string().replace("$ORIGIN", &origin_string)))
+            .collect::<Vec<_>>();
+
+        let wheel_extension_cache_dir = self
+            .wheel_extension_cache_dir
+            .as_ref()
+            .map(|x| PathBuf::from(x.display().to_string().replace("$ORIGIN", &origin_string)));
+
+        let resources_cache_dir = self
+            .resources_cache_dir
+            .as_ref()
+            .map(|x| PathBuf::from(x.display().to_string().replace("$ORIGIN", &origin_string)));
+
         let module_search_paths = self
             .interpreter_config
             .module_search_paths
@@ -536,6 +754,10 @@ impl<'a> OxidizedPythonInterpreterConfig<'a> {
                 },
                 argv,
                 packed_resources,
+                packed_resources_layers,
+                zip_archives,
+                wheel_extension_cache_dir,
+                resources_cache_dir,
                 tcl_library,
                 ..self
             },
@@ -614,6 +836,25 @@ impl<'a, 'config: 'a> TryFrom<&ResolvedOxidizedPythonInterpreterConfig<'config>>
         let mut state = Self::default();
         state.set_current_exe(config.exe().to_path_buf());
         state.set_origin(config.origin().to_path_buf());
+        state.set_required_public_key(config.packed_resources_public_key);
+        if let Some(provider) = &config.packed_resources_key_provider {
+            state.set_decryption_key(Some(
+                provider
+                    .packed_resources_key()
+                    .map_err(NewInterpreterError::Dynamic)?,
+            ));
+        }
+        #[cfg(feature = "zipimport")]
+        state.set_wheel_extension_cache_dir(config.wheel_extension_cache_dir.clone());
+        #[cfg(not(feature = "zipimport"))]
+        if config.wheel_extension_cache_dir.is_some() {
+            return Err(NewInterpreterError::Simple(
+                "wheel extension cache directory requires the zipimport feature",
+            ));
+        }
+        state.set_resources_cache_dir(config.resources_cache_dir.clone());
+        state.set_lazy_index(config.packed_resources_lazy_index);
+        state.set_strict_layers(config.packed_resources_strict_layers);
 
         for source in &config.packed_resources {
             match source {
@@ -623,13 +864,31 @@ impl<'a, 'config: 'a> TryFrom<&ResolvedOxidizedPythonInterpreterConfig<'config>>
                         .map_err(NewInterpreterError::Simple)?;
                 }
                 PackedResourcesSource::MemoryMappedPath(path) => {
-                    state
-                        .index_path_memory_mapped(path)
-                        .map_err(NewInterpreterError::Dynamic)?;
+                    if config.packed_resources_hot_reload && cfg!(debug_assertions) {
+                        state
+                            .index_path_watched(path)
+                            .map_err(NewInterpreterError::Dynamic)?;
+                    } else {
+                        state
+                            .index_path_memory_mapped(path)
+                            .map_err(NewInterpreterError::Dynamic)?;
+                    }
                 }
             }
         }
 
+        for layer in &config.packed_resources_layers {
+            match &layer.source {
+                PackedResourcesSource::Memory(data) => {
+                    state.index_layer_data(&layer.name, layer.priority, data)
+                }
+                PackedResourcesSource::MemoryMappedPath(path) => {
+                    state.index_layer_path_memory_mapped(&layer.name, layer.priority, path)
+                }
+            }
+            .map_err(NewInterpreterError::Dynamic)?;
+        }
+
         state
             .index_interpreter_builtins()
             .map_err(NewInterpreterError::Simple)?;
@@ -687,4 +946,58 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_zip_archives_origin() -> Result<()> {
+        let config = OxidizedPythonInterpreterConfig {
+            origin: Some(PathBuf::from("/other/origin")),
+            zip_archives: vec![PathBuf::from("$ORIGIN/app.zip")],
+            ..Default::default()
+        };
+
+        let resolved = config.resolve()?;
+
+        assert_eq!(
+            resolved.zip_archives,
+            vec![PathBuf::from("/other/origin/app.zip")]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_wheel_extension_cache_dir_origin() -> Result<()> {
+        let config = OxidizedPythonInterpreterConfig {
+            origin: Some(PathBuf::from("/other/origin")),
+            wheel_extension_cache_dir: Some(PathBuf::from("$ORIGIN/wheels")),
+            ..Default::default()
+        };
+
+        let resolved = config.resolve()?;
+
+        assert_eq!(
+            resolved.wheel_extension_cache_dir,
+            Some(PathBuf::from("/other/origin/wheels"))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_resources_cache_dir_origin() -> Result<()> {
+        let config = OxidizedPythonInterpreterConfig {
+            origin: Some(PathBuf::from("/other/origin")),
+            resources_cache_dir: Some(PathBuf::from("$ORIGIN/cache")),
+            ..Default::default()
+        };
+
+        let resolved = config.resolve()?;
+
+        assert_eq!(
+            resolved.resources_cache_dir,
+            Some(PathBuf::from("/other/origin/cache"))
+        );
+
+        Ok(())
+    }
 }
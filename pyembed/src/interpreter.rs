@@ -32,6 +32,9 @@ use {
     },
 };
 
+#[cfg(feature = "zipimport")]
+use oxidized_importer::OxidizedZipFinder;
+
 static GLOBAL_INTERPRETER_GUARD: Lazy<std::sync::Mutex<()>> =
     Lazy::new(|| std::sync::Mutex::new(()));
 
@@ -92,6 +95,10 @@ pub struct MainPythonInterpreter<'interpreter, 'resources: 'interpreter> {
     pub(crate) allocator: Option<PythonMemoryAllocator>,
     /// File to write containing list of modules when the interpreter finalizes.
     write_modules_path: Option<PathBuf>,
+    /// File to write containing a JSON import trace when the interpreter finalizes.
+    write_import_trace_path: Option<PathBuf>,
+    /// File to write containing a JSON resource access report when the interpreter finalizes.
+    write_resource_access_path: Option<PathBuf>,
 }
 
 impl<'interpreter, 'resources> MainPythonInterpreter<'interpreter, 'resources> {
@@ -120,6 +127,8 @@ impl<'interpreter, 'resources> MainPythonInterpreter<'interpreter, 'resources> {
             interpreter_guard: None,
             allocator: None,
             write_modules_path: None,
+            write_import_trace_path: None,
+            write_resource_access_path: None,
         };
 
         res.init()?;
@@ -246,8 +255,17 @@ impl<'interpreter, 'resources> MainPythonInterpreter<'interpreter, 'resources> {
             pyffi::PyEval_SaveThread();
         }
 
-        self.write_modules_path =
-            self.with_gil(|py| self.init_post_main(py, oxidized_finder_loaded))?;
+        if let Some((modules_path, import_trace_path, resource_access_path)) =
+            self.with_gil(|py| self.init_post_main(py, oxidized_finder_loaded))?
+        {
+            self.write_modules_path = Some(modules_path);
+
+            // Import traces and resource accesses are only recorded by OxidizedFinder.
+            if oxidized_finder_loaded {
+                self.write_import_trace_path = Some(import_trace_path);
+                self.write_resource_access_path = Some(resource_access_path);
+            }
+        }
 
         debug_assert_eq!(unsafe { pyffi::PyGILState_Check() }, 0);
 
@@ -266,35 +284,48 @@ impl<'interpreter, 'resources> MainPythonInterpreter<'interpreter, 'resources> {
             return Ok(false);
         }
 
-        let resources_state = Box::new(PythonResourcesState::try_from(&self.config)?);
+        let mut resources_state = Box::new(PythonResourcesState::try_from(&self.config)?);
 
         let oxidized_importer = py.import(OXIDIZED_IMPORTER_NAME_STR).map_err(|err| {
             NewInterpreterError::new_from_pyerr(py, err, "import of oxidized importer module")
         })?;
 
-        let cb = |importer_state: &mut ImporterState| match self.config.multiprocessing_start_method
-        {
-            MultiprocessingStartMethod::None => {}
-            MultiprocessingStartMethod::Fork
-            | MultiprocessingStartMethod::ForkServer
-            | MultiprocessingStartMethod::Spawn => {
-                importer_state.set_multiprocessing_set_start_method(Some(
-                    self.config.multiprocessing_start_method.to_string(),
-                ));
-            }
-            MultiprocessingStartMethod::Auto => {
-                // Windows uses "spawn" because "fork" isn't available.
-                // Everywhere else uses "fork." The default on macOS is "spawn." This
-                // is due to https://bugs.python.org/issue33725, which only affects
-                // Python framework builds. Our assumption is we aren't using a Python
-                // framework, so "spawn" is safe.
-                let method = if cfg!(target_family = "windows") {
-                    "spawn"
-                } else {
-                    "fork"
-                };
+        // Import tracing and resource access tracking are enabled when the loaded
+        // modules file will be written, as their reports are written alongside it.
+        let trace_imports = self
+            .config
+            .write_modules_directory_env
+            .as_ref()
+            .map_or(false, |key| std::env::var_os(key).is_some());
+
+        resources_state.set_track_resource_access(trace_imports);
+
+        let cb = |importer_state: &mut ImporterState| {
+            importer_state.set_trace_imports(trace_imports);
+
+            match self.config.multiprocessing_start_method {
+                MultiprocessingStartMethod::None => {}
+                MultiprocessingStartMethod::Fork
+                | MultiprocessingStartMethod::ForkServer
+                | MultiprocessingStartMethod::Spawn => {
+                    importer_state.set_multiprocessing_set_start_method(Some(
+                        self.config.multiprocessing_start_method.to_string(),
+                    ));
+                }
+                MultiprocessingStartMethod::Auto => {
+                    // Windows uses "spawn" because "fork" isn't available.
+                    // Everywhere else uses "fork." The default on macOS is "spawn." This
+                    // is due to https://bugs.python.org/issue33725, which only affects
+                    // Python framework builds. Our assumption is we aren't using a Python
+                    // framework, so "spawn" is safe.
+                    let method = if cfg!(target_family = "windows") {
+                        "spawn"
+                    } else {
+                        "fork"
+                    };
 
-                importer_state.set_multiprocessing_set_start_method(Some(method.to_string()));
+                    importer_state.set_multiprocessing_set_start_method(Some(method.to_string()));
+                }
             }
         };
 
@@ -315,12 +346,75 @@ impl<'interpreter, 'resources> MainPythonInterpreter<'interpreter, 'resources> {
         Ok(true)
     }
 
+    /// Register configured zip archives as import sources.
+    ///
+    /// An `OxidizedZipFinder` is appended to `sys.meta_path` for each archive.
+    #[cfg(feature = "zipimport")]
+    fn register_zip_archives(
+        &self,
+        py: Python,
+        sys_module: &PyModule,
+    ) -> Result<(), NewInterpreterError> {
+        if self.config.zip_archives.is_empty() {
+            return Ok(());
+        }
+
+        let meta_path = sys_module.getattr("meta_path").map_err(|err| {
+            NewInterpreterError::new_from_pyerr(py, err, "obtaining sys.meta_path")
+        })?;
+
+        for path in &self.config.zip_archives {
+            let finder =
+                OxidizedZipFinder::new_from_path(py, path.clone(), true, None).map_err(|err| {
+                    NewInterpreterError::new_from_pyerr(py, err, "indexing zip archive")
+                })?;
+
+            let finder = Py::new(py, finder).map_err(|err| {
+                NewInterpreterError::new_from_pyerr(py, err, "constructing OxidizedZipFinder")
+            })?;
+
+            meta_path.call_method1("append", (finder,)).map_err(|err| {
+                NewInterpreterError::new_from_pyerr(py, err, "appending to sys.meta_path")
+            })?;
+        }
+
+        Ok(())
+    }
+
+    #[cfg(not(feature = "zipimport"))]
+    fn register_zip_archives(
+        &self,
+        _py: Python,
+        _sys_module: &PyModule,
+    ) -> Result<(), NewInterpreterError> {
+        if self.config.zip_archives.is_empty() {
+            Ok(())
+        } else {
+            Err(NewInterpreterError::Simple(
+                "zip archives require the zipimport feature",
+            ))
+        }
+    }
+
+    /// Execute the configured startup hooks.
+    ///
+    /// Failures are printed to stderr and don't prevent other hooks from running,
+    /// mirroring how the `site` module handles `import` lines in `.pth` files.
+    fn run_startup_hooks(&self, py: Python) {
+        for hook in &self.config.startup_hooks {
+            if let Err(err) = py.run(hook, Some(PyDict::new(py)), None) {
+                eprintln!("Error running startup hook {:?}:", hook);
+                err.print(py);
+            }
+        }
+    }
+
     /// Performs interpreter configuration after main interpreter initialization.
     fn init_post_main(
         &self,
         py: Python,
         oxidized_finder_loaded: bool,
-    ) -> Result<Option<PathBuf>, NewInterpreterError> {
+    ) -> Result<Option<(PathBuf, PathBuf, PathBuf)>, NewInterpreterError> {
         let sys_module = py
             .import("sys")
             .map_err(|e| NewInterpreterError::new_from_pyerr(py, e, "obtaining sys module"))?;
@@ -394,6 +488,8 @@ impl<'interpreter, 'resources> MainPythonInterpreter<'interpreter, 'resources> {
             })?;
         }
 
+        self.register_zip_archives(py, sys_module)?;
+
         if self.config.argvb {
             let args_objs = self
                 .config
@@ -450,7 +546,9 @@ impl<'interpreter, 'resources> MainPythonInterpreter<'interpreter, 'resources> {
             }
         }
 
-        let write_modules_path = if let Some(key) = &self.config.write_modules_directory_env {
+        self.run_startup_hooks(py);
+
+        let write_paths = if let Some(key) = &self.config.write_modules_directory_env {
             if let Ok(path) = std::env::var(key) {
                 let path = PathBuf::from(path);
 
@@ -479,7 +577,11 @@ impl<'interpreter, 'resources> MainPythonInterpreter<'interpreter, 'resources> {
                     })?
                     .to_string();
 
-                Some(path.join(format!("modules-{}", uuid_str)))
+                Some((
+                    path.join(format!("modules-{}", uuid_str)),
+                    path.join(format!("imports-{}.json", uuid_str)),
+                    path.join(format!("resources-{}.json", uuid_str)),
+                ))
             } else {
                 None
             }
@@ -487,7 +589,7 @@ impl<'interpreter, 'resources> MainPythonInterpreter<'interpreter, 'resources> {
             None
         };
 
-        Ok(write_modules_path)
+        Ok(write_paths)
     }
 
     /// Proxy for [Python::with_gil()].
@@ -725,6 +827,84 @@ fn write_modules_to_path(py: Python, path: &Path) -> Result<(), &'static str> {
     Ok(())
 }
 
+/// Find the first `OxidizedFinder` on ``sys.meta_path``.
+fn find_oxidized_finder<'p>(py: Python<'p>) -> Result<&'p PyAny, &'static str> {
+    let sys = py
+        .import("sys")
+        .map_err(|_| "could not obtain sys module")?;
+    let meta_path = sys
+        .getattr("meta_path")
+        .map_err(|_| "could not obtain sys.meta_path")?;
+
+    for entry in meta_path
+        .iter()
+        .map_err(|_| "sys.meta_path is not iterable")?
+    {
+        let entry = entry.map_err(|_| "could not iterate sys.meta_path")?;
+
+        if entry
+            .is_instance_of::<OxidizedFinder>()
+            .map_err(|_| "could not determine type of sys.meta_path entry")?
+        {
+            return Ok(entry);
+        }
+    }
+
+    Err("OxidizedFinder not found on sys.meta_path")
+}
+
+/// Write a Python object to a file as JSON.
+fn write_json_to_path(py: Python, path: &Path, value: &PyAny) -> Result<(), &'static str> {
+    // We use Python's json module to avoid a dependency on a Rust crate.
+    let json = py
+        .import("json")
+        .map_err(|_| "could not obtain json module")?
+        .call_method1("dumps", (value,))
+        .map_err(|_| "could not serialize to JSON")?
+        .extract::<String>()
+        .map_err(|_| "serialized JSON is not a str")?;
+
+    fs::write(path, json).map_err(|_| "could not write")?;
+
+    Ok(())
+}
+
+/// Write the import trace recorded by `OxidizedFinder` to a file.
+///
+/// The first `OxidizedFinder` on ``sys.meta_path`` is consulted. The file
+/// contains a JSON object with an ``imports`` key holding the list of
+/// recorded importer operations.
+fn write_import_trace_to_path(py: Python, path: &Path) -> Result<(), &'static str> {
+    let finder = find_oxidized_finder(py)?;
+
+    let imports = finder
+        .call_method0("import_trace")
+        .map_err(|_| "could not obtain import trace")?;
+
+    let document = PyDict::new(py);
+    document
+        .set_item("imports", imports)
+        .map_err(|_| "could not build import trace document")?;
+
+    write_json_to_path(py, path, document)
+}
+
+/// Write the resource accesses recorded by `OxidizedFinder` to a file.
+///
+/// The first `OxidizedFinder` on ``sys.meta_path`` is consulted. The file
+/// contains a JSON object with an ``accessed`` key mapping names of accessed
+/// resources to access counts and an ``unused`` key holding the names of
+/// resources that were never accessed.
+fn write_resource_access_to_path(py: Python, path: &Path) -> Result<(), &'static str> {
+    let finder = find_oxidized_finder(py)?;
+
+    let document = finder
+        .call_method0("resource_access")
+        .map_err(|_| "could not obtain resource access report")?;
+
+    write_json_to_path(py, path, document)
+}
+
 impl<'interpreter, 'resources> Drop for MainPythonInterpreter<'interpreter, 'resources> {
     fn drop(&mut self) {
         // Interpreter may have been finalized already. Possibly through our invocation
@@ -744,6 +924,24 @@ impl<'interpreter, 'resources> Drop for MainPythonInterpreter<'interpreter, 'res
             }
         }
 
+        if let Some(path) = self.write_import_trace_path.as_ref() {
+            match self.with_gil(|py| write_import_trace_to_path(py, path)) {
+                Ok(_) => {}
+                Err(msg) => {
+                    eprintln!("error writing import trace file: {}", msg);
+                }
+            }
+        }
+
+        if let Some(path) = self.write_resource_access_path.as_ref() {
+            match self.with_gil(|py| write_resource_access_to_path(py, path)) {
+                Ok(_) => {}
+                Err(msg) => {
+                    eprintln!("error writing resource access file: {}", msg);
+                }
+            }
+        }
+
         unsafe {
             pyffi::PyGILState_Ensure();
             pyffi::Py_FinalizeEx();
@@ -0,0 +1,130 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Supplying keys for decrypting packed resources.
+
+use std::fmt::{Debug, Formatter};
+
+/// Supplies the AES-256-GCM key used to decrypt encrypted packed resources data.
+///
+/// Implementations are consulted once, during interpreter initialization.
+/// Host applications can implement this trait to obtain the key from wherever
+/// they see fit. e.g. a secrets store or a license server.
+pub trait PackedResourcesKeyProvider: Debug + Send + Sync {
+    /// Obtain the key.
+    fn packed_resources_key(&self) -> Result<[u8; 32], String>;
+}
+
+/// Obtains the key from a hex encoded environment variable.
+#[derive(Clone, Debug)]
+pub struct EnvironmentKeyProvider {
+    variable: String,
+}
+
+impl EnvironmentKeyProvider {
+    /// Construct an instance reading the key from the named environment variable.
+    pub fn new(variable: impl ToString) -> Self {
+        Self {
+            variable: variable.to_string(),
+        }
+    }
+}
+
+impl PackedResourcesKeyProvider for EnvironmentKeyProvider {
+    fn packed_resources_key(&self) -> Result<[u8; 32], String> {
+        let value = std::env::var(&self.variable).map_err(|_| {
+            format!(
+                "environment variable {} holding packed resources key is not set",
+                self.variable
+            )
+        })?;
+
+        decode_hex_key(value.trim()).ok_or_else(|| {
+            format!(
+                "environment variable {} does not hold a hex encoded 32 byte key",
+                self.variable
+            )
+        })
+    }
+}
+
+/// Provides a key embedded in the binary.
+///
+/// This only deters casual extraction of resources, as the key can be
+/// recovered from the binary.
+#[derive(Clone)]
+pub struct EmbeddedKeyProvider {
+    key: [u8; 32],
+}
+
+impl EmbeddedKeyProvider {
+    /// Construct an instance providing the given key.
+    pub fn new(key: [u8; 32]) -> Self {
+        Self { key }
+    }
+}
+
+impl Debug for EmbeddedKeyProvider {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EmbeddedKeyProvider")
+            .finish_non_exhaustive()
+    }
+}
+
+impl PackedResourcesKeyProvider for EmbeddedKeyProvider {
+    fn packed_resources_key(&self) -> Result<[u8; 32], String> {
+        Ok(self.key)
+    }
+}
+
+/// Obtains the key by calling a function provided by the host application.
+pub struct CallbackKeyProvider<F>
+where
+    F: Fn() -> Result<[u8; 32], String> + Send + Sync,
+{
+    callback: F,
+}
+
+impl<F> CallbackKeyProvider<F>
+where
+    F: Fn() -> Result<[u8; 32], String> + Send + Sync,
+{
+    /// Construct an instance calling the given function to obtain the key.
+    pub fn new(callback: F) -> Self {
+        Self { callback }
+    }
+}
+
+impl<F> Debug for CallbackKeyProvider<F>
+where
+    F: Fn() -> Result<[u8; 32], String> + Send + Sync,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CallbackKeyProvider")
+            .finish_non_exhaustive()
+    }
+}
+
+impl<F> PackedResourcesKeyProvider for CallbackKeyProvider<F>
+where
+    F: Fn() -> Result<[u8; 32], String> + Send + Sync,
+{
+    fn packed_resources_key(&self) -> Result<[u8; 32], String> {
+        (self.callback)()
+    }
+}
+
+/// Decode a hex encoded 32 byte key.
+fn decode_hex_key(value: &str) -> Option<[u8; 32]> {
+    if value.len() != 64 || !value.is_ascii() {
+        return None;
+    }
+
+    let mut key = [0u8; 32];
+    for (i, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&value[i * 2..i * 2 + 2], 16).ok()?;
+    }
+
+    Some(key)
+}
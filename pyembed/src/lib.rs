@@ -59,6 +59,7 @@ mod conversion;
 mod error;
 mod interpreter;
 mod interpreter_config;
+mod key_provider;
 mod osutils;
 mod pyalloc;
 pub mod technotes;
@@ -74,9 +75,13 @@ pub use {
         },
         error::NewInterpreterError,
         interpreter::MainPythonInterpreter,
+        key_provider::{
+            CallbackKeyProvider, EmbeddedKeyProvider, EnvironmentKeyProvider,
+            PackedResourcesKeyProvider,
+        },
         pyalloc::PythonMemoryAllocator,
     },
-    oxidized_importer::{PackedResourcesSource, PythonResourcesState},
+    oxidized_importer::{PackedResourcesLayer, PackedResourcesSource, PythonResourcesState},
     python_packaging::{
         interpreter::{
             Allocator, BytesWarning, CheckHashPycsMode, CoerceCLocale, MemoryAllocatorBackend,
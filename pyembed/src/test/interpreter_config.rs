@@ -735,6 +735,35 @@ rusty_fork_test! {
         });
     }
 
+    #[test]
+    fn test_startup_hooks() {
+        let mut config = default_interpreter_config();
+        config.startup_hooks = vec![
+            "import sys; sys.startup_hook_ran = True".to_string(),
+            "import does_not_exist".to_string(),
+            "import sys; sys.startup_hook_count = 2".to_string(),
+        ];
+
+        let interp = MainPythonInterpreter::new(config).unwrap();
+
+        interp.with_gil(|py| {
+            let sys = py.import("sys").unwrap();
+
+            assert!(sys
+                .getattr("startup_hook_ran")
+                .unwrap()
+                .extract::<bool>()
+                .unwrap());
+            assert_eq!(
+                sys.getattr("startup_hook_count")
+                    .unwrap()
+                    .extract::<i64>()
+                    .unwrap(),
+                2
+            );
+        });
+    }
+
     #[test]
     fn test_user_site_directory_false() {
         let mut config = default_interpreter_config();
@@ -3,11 +3,18 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use {
-    crate::OxidizedPythonInterpreterConfig,
+    crate::{
+        CallbackKeyProvider, EmbeddedKeyProvider, EnvironmentKeyProvider,
+        OxidizedPythonInterpreterConfig, PackedResourcesKeyProvider,
+    },
     anyhow::{anyhow, Result},
     oxidized_importer::{PackedResourcesSource, PyTempDir, PythonResourcesState},
-    python_packed_resources::Resource,
+    python_packed_resources::{BlobCompression, Resource, ResourceField, WriterOptions},
     rusty_fork::rusty_fork_test,
+    std::{
+        collections::{BTreeMap, HashMap},
+        sync::Arc,
+    },
 };
 
 #[test]
@@ -46,6 +53,270 @@ fn multiple_resource_blobs() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn compressed_resources() -> Result<()> {
+    let mut distribution = HashMap::new();
+    distribution.insert("METADATA".into(), b"Name: foo\n".repeat(16).into());
+
+    let resource = Resource {
+        name: "foo".into(),
+        is_python_module: true,
+        is_python_package: true,
+        in_memory_source: Some(b"import io\n".repeat(16).into()),
+        in_memory_distribution_resources: Some(distribution),
+        ..Default::default()
+    };
+
+    let mut compression = BTreeMap::new();
+    compression.insert(ResourceField::InMemorySource, BlobCompression::Zstd);
+    compression.insert(
+        ResourceField::InMemoryDistributionResource,
+        BlobCompression::Zstd,
+    );
+
+    let mut data = Vec::new();
+    python_packed_resources::write_packed_resources_v4(
+        &[&resource],
+        &mut data,
+        &WriterOptions {
+            compression,
+            ..WriterOptions::default()
+        },
+    )?;
+
+    let mut resources = PythonResourcesState::default();
+    resources.index_data(&data).unwrap();
+
+    assert_eq!(
+        resources
+            .resolve_package_distribution_resource("foo", "METADATA")?
+            .unwrap()
+            .as_ref(),
+        b"Name: foo\n".repeat(16)
+    );
+
+    // Serializing should emit decompressed data.
+    let serialized = resources.serialize_resources(true, true)?;
+    let loaded = python_packed_resources::load_resources(&serialized)
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(loaded, vec![resource]);
+
+    Ok(())
+}
+
+#[test]
+fn signed_resources() -> Result<()> {
+    let signing_key = [42; 32];
+    let public_key = python_packed_resources::ed25519_public_key(&signing_key);
+
+    let resource = Resource {
+        name: "foo".into(),
+        is_python_module: true,
+        in_memory_source: Some(vec![42].into()),
+        ..Default::default()
+    };
+
+    let mut unsigned = Vec::new();
+    python_packed_resources::write_packed_resources_v4(
+        &[&resource],
+        &mut unsigned,
+        &WriterOptions::default(),
+    )?;
+    let mut signed = unsigned.clone();
+    python_packed_resources::append_integrity_trailer(&mut signed, Some(&signing_key))?;
+
+    let mut resources = PythonResourcesState::default();
+    resources.set_required_public_key(Some(public_key));
+    resources.index_data(&signed).unwrap();
+    assert!(resources.has_resource("foo"));
+
+    let mut resources = PythonResourcesState::default();
+    resources.set_required_public_key(Some(public_key));
+    assert_eq!(
+        resources.index_data(&unsigned),
+        Err("packed resources data does not have an integrity trailer")
+    );
+    assert!(!resources.has_resource("foo"));
+
+    let mut resources = PythonResourcesState::default();
+    resources.set_required_public_key(Some(python_packed_resources::ed25519_public_key(&[1; 32])));
+    assert_eq!(
+        resources.index_data(&signed),
+        Err("packed resources signature verification failed")
+    );
+
+    Ok(())
+}
+
+#[test]
+fn encrypted_resources() -> Result<()> {
+    let key = [42; 32];
+
+    let resource = Resource {
+        name: "foo".into(),
+        is_python_module: true,
+        in_memory_source: Some(b"import io\n".repeat(16).into()),
+        ..Default::default()
+    };
+
+    let mut compression = BTreeMap::new();
+    compression.insert(ResourceField::InMemorySource, BlobCompression::Zstd);
+
+    let mut data = Vec::new();
+    python_packed_resources::write_packed_resources_v4(
+        &[&resource],
+        &mut data,
+        &WriterOptions {
+            compression,
+            encryption_key: Some(key),
+            ..WriterOptions::default()
+        },
+    )?;
+
+    let mut resources = PythonResourcesState::default();
+    assert_eq!(
+        resources.index_data(&data),
+        Err("packed resources data is encrypted but no decryption key is set")
+    );
+
+    let mut resources = PythonResourcesState::default();
+    resources.set_decryption_key(Some([1; 32]));
+    assert_eq!(
+        resources.index_data(&data),
+        Err("failed decrypting blob data")
+    );
+
+    let mut resources = PythonResourcesState::default();
+    resources.set_decryption_key(Some(key));
+    resources.index_data(&data).unwrap();
+
+    // Serializing should emit decrypted and decompressed data.
+    let serialized = resources.serialize_resources(true, true)?;
+    let loaded = python_packed_resources::load_resources(&serialized)
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(loaded, vec![resource]);
+
+    // The key can come from a provider in the interpreter config.
+    let mut config = OxidizedPythonInterpreterConfig::default();
+    config
+        .packed_resources
+        .push(PackedResourcesSource::Memory(&data));
+    config.packed_resources_key_provider = Some(Arc::new(EmbeddedKeyProvider::new(key)));
+
+    let resolved = config.clone().resolve()?;
+    let resources = PythonResourcesState::try_from(&resolved)?;
+    assert!(resources.has_resource("foo"));
+
+    config.packed_resources_key_provider = Some(Arc::new(CallbackKeyProvider::new(|| {
+        Err("key not available".to_string())
+    })));
+    let resolved = config.resolve()?;
+    assert_eq!(
+        PythonResourcesState::try_from(&resolved)
+            .unwrap_err()
+            .to_string(),
+        "key not available"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn environment_key_provider() {
+    let provider = EnvironmentKeyProvider::new("PYEMBED_TEST_PACKED_RESOURCES_KEY");
+
+    std::env::remove_var("PYEMBED_TEST_PACKED_RESOURCES_KEY");
+    assert!(provider.packed_resources_key().is_err());
+
+    std::env::set_var("PYEMBED_TEST_PACKED_RESOURCES_KEY", "2a".repeat(31));
+    assert!(provider.packed_resources_key().is_err());
+
+    std::env::set_var("PYEMBED_TEST_PACKED_RESOURCES_KEY", "2A".repeat(32));
+    assert_eq!(provider.packed_resources_key(), Ok([42; 32]));
+}
+
+#[test]
+fn lazy_index_resources() -> Result<()> {
+    let mut distribution = HashMap::new();
+    distribution.insert("METADATA".into(), b"Name: foo\n".repeat(16).into());
+
+    let resources = vec![
+        Resource {
+            name: "foo".into(),
+            is_python_module: true,
+            is_python_package: true,
+            in_memory_source: Some(b"import io\n".repeat(16).into()),
+            in_memory_distribution_resources: Some(distribution),
+            ..Default::default()
+        },
+        Resource {
+            name: "foo.bar".into(),
+            is_python_module: true,
+            in_memory_source: Some(b"import os\n".to_vec().into()),
+            ..Default::default()
+        },
+    ];
+
+    let mut compression = BTreeMap::new();
+    compression.insert(
+        ResourceField::InMemoryDistributionResource,
+        BlobCompression::Zstd,
+    );
+
+    let mut data = Vec::new();
+    python_packed_resources::write_packed_resources_v4(
+        &resources,
+        &mut data,
+        &WriterOptions {
+            compression,
+            name_index: true,
+            ..WriterOptions::default()
+        },
+    )?;
+
+    let extra = Resource {
+        name: "foo.bar".into(),
+        in_memory_bytecode: Some(b"bytecode".to_vec().into()),
+        ..Default::default()
+    };
+    let mut extra_data = Vec::new();
+    python_packed_resources::write_packed_resources_v3(&[&extra], &mut extra_data, None)?;
+
+    let mut state = PythonResourcesState::default();
+    state.set_lazy_index(true);
+    state.index_data(&data).unwrap();
+
+    assert!(state.has_resource("foo"));
+    assert!(state.has_resource("foo.bar"));
+    assert!(!state.has_resource("missing"));
+    assert_eq!(
+        state
+            .resolve_package_distribution_resource("foo", "METADATA")?
+            .unwrap()
+            .as_ref(),
+        b"Name: foo\n".repeat(16)
+    );
+
+    // Eagerly indexed data merges into lazily indexed resources.
+    state.index_data(&extra_data).unwrap();
+
+    let serialized = state.serialize_resources(true, true)?;
+    let loaded = python_packed_resources::load_resources(&serialized)
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+
+    let mut merged = resources[1].clone();
+    merged.merge_from(extra).unwrap();
+    assert_eq!(loaded, vec![resources[0].clone(), merged]);
+
+    Ok(())
+}
+
 #[test]
 fn test_memory_mapped_file_resources() -> Result<()> {
     let current_dir = std::env::current_exe()?
@@ -99,6 +370,237 @@ fn test_memory_mapped_file_resources() -> Result<()> {
     Ok(())
 }
 
+#[cfg(feature = "zipimport")]
+#[test]
+fn wheel_resources() -> Result<()> {
+    use python_packaging::{resource::BytecodeOptimizationLevel, wheel_builder::WheelBuilder};
+
+    let cache_dir = std::env::current_exe()?
+        .parent()
+        .ok_or_else(|| anyhow!("unable to find current exe parent"))?
+        .join("wheel_resources");
+    if cache_dir.exists() {
+        std::fs::remove_dir_all(&cache_dir)?;
+    }
+
+    let mut builder = WheelBuilder::new("foo", "1.0");
+    builder.add_file("foo/__init__.py", b"import os\n".to_vec())?;
+    builder.add_file("foo/bar.py", b"import sys\n".to_vec())?;
+    builder.add_file("foo/data.txt", b"data".to_vec())?;
+    builder.add_file("foo/_ext.abi3.so", b"extension".to_vec())?;
+    builder.add_file("foo.libs/libbaz.so.1", b"library".to_vec())?;
+    builder.add_file_dist_info("METADATA", b"Name: foo\nVersion: 1.0\n".to_vec())?;
+
+    let mut data = std::io::Cursor::new(Vec::new());
+    builder.write_wheel_data(&mut data)?;
+    let data = data.into_inner();
+
+    let mut resources = PythonResourcesState::default();
+    resources.set_wheel_extension_cache_dir(Some(cache_dir.clone()));
+    resources
+        .add_resource(Resource {
+            name: format!("lib/{}", builder.wheel_file_name()).into(),
+            is_utf8_filename_data: true,
+            file_data_embedded: Some(data.into()),
+            ..Default::default()
+        })
+        .unwrap();
+    resources
+        .index_embedded_wheels("cpython-311", &[".abi3.so".to_string()])
+        .unwrap();
+
+    for name in ["foo", "foo.bar", "foo._ext"] {
+        assert!(resources
+            .resolve_importable_module(name, BytecodeOptimizationLevel::Zero)
+            .is_some());
+    }
+    assert!(resources.is_package_resource("foo", "data.txt"));
+    assert_eq!(
+        resources
+            .resolve_package_distribution_resource("foo", "METADATA")?
+            .map(|data| data.into_owned()),
+        Some(b"Name: foo\nVersion: 1.0\n".to_vec())
+    );
+
+    // Native files are only extracted when an extension module is imported.
+    assert!(!cache_dir.exists());
+    resources.extract_wheel_extension_module("foo.bar").unwrap();
+    assert!(!cache_dir.exists());
+    resources
+        .extract_wheel_extension_module("foo._ext")
+        .unwrap();
+
+    let entries = std::fs::read_dir(&cache_dir)?.collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(entries.len(), 1);
+    let wheel_dir = entries[0].path();
+    assert!(wheel_dir
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap()
+        .starts_with("foo-1.0-py3-none-any-"));
+    assert_eq!(
+        std::fs::read(wheel_dir.join("foo/_ext.abi3.so"))?,
+        b"extension"
+    );
+    assert_eq!(
+        std::fs::read(wheel_dir.join("foo.libs/libbaz.so.1"))?,
+        b"library"
+    );
+    assert!(!wheel_dir.join("foo/bar.py").exists());
+
+    Ok(())
+}
+
+#[cfg(feature = "zipimport")]
+#[test]
+fn lazy_wheel_resources() -> Result<()> {
+    use python_packaging::{resource::BytecodeOptimizationLevel, wheel_builder::WheelBuilder};
+
+    let mut builder = WheelBuilder::new("foo", "1.0");
+    builder.add_file("foo/__init__.py", b"import os\n".to_vec())?;
+    builder.add_file_dist_info("METADATA", b"Name: foo\nVersion: 1.0\n".to_vec())?;
+
+    let mut wheel_data = std::io::Cursor::new(Vec::new());
+    builder.write_wheel_data(&mut wheel_data)?;
+
+    let wheel = Resource {
+        name: format!("lib/{}", builder.wheel_file_name()).into(),
+        is_utf8_filename_data: true,
+        file_data_embedded: Some(wheel_data.into_inner().into()),
+        ..Default::default()
+    };
+
+    let mut data = Vec::new();
+    python_packed_resources::write_packed_resources_v4(
+        &[&wheel],
+        &mut data,
+        &WriterOptions {
+            name_index: true,
+            ..WriterOptions::default()
+        },
+    )?;
+
+    // Wheels in lazily indexed data are found via the name index.
+    let mut resources = PythonResourcesState::default();
+    resources.set_lazy_index(true);
+    resources.index_data(&data).unwrap();
+    resources
+        .index_embedded_wheels("cpython-311", &[".abi3.so".to_string()])
+        .unwrap();
+
+    assert!(resources
+        .resolve_importable_module("foo", BytecodeOptimizationLevel::Zero)
+        .is_some());
+
+    Ok(())
+}
+
+#[test]
+fn extract_to_cache_resources() -> Result<()> {
+    let cache_dir = std::env::current_exe()?
+        .parent()
+        .ok_or_else(|| anyhow!("unable to find current exe parent"))?
+        .join("extract_to_cache_resources");
+    if cache_dir.exists() {
+        std::fs::remove_dir_all(&cache_dir)?;
+    }
+
+    // A directory left behind by another build of the package a long time ago.
+    let stale_dir = cache_dir.join("foo-0123456789abcdef");
+    std::fs::create_dir_all(&stale_dir)?;
+    std::fs::File::create(stale_dir.join(".last-used"))?.set_modified(
+        std::time::SystemTime::now() - std::time::Duration::from_secs(30 * 24 * 60 * 60),
+    )?;
+    // A directory possibly in use by a running build of the package.
+    let recent_dir = cache_dir.join("foo-fedcba9876543210");
+    std::fs::create_dir_all(&recent_dir)?;
+    let other_dir = cache_dir.join("foobar-0123456789abcdef");
+    std::fs::create_dir_all(&other_dir)?;
+
+    let new_resources = || {
+        let mut resources = PythonResourcesState::default();
+        resources.set_resources_cache_dir(Some(cache_dir.clone()));
+        resources
+            .add_resource(Resource {
+                name: "foo".into(),
+                is_python_module: true,
+                is_python_package: true,
+                in_memory_source: Some(b"import os\n".to_vec().into()),
+                in_memory_package_resources: Some(HashMap::from([(
+                    "data.txt".into(),
+                    b"data".to_vec().into(),
+                )])),
+                extract_to_cache: true,
+                ..Default::default()
+            })
+            .unwrap();
+        resources
+            .add_resource(Resource {
+                name: "foo.bar".into(),
+                is_python_module: true,
+                in_memory_source: Some(b"import sys\n".to_vec().into()),
+                extract_to_cache: true,
+                ..Default::default()
+            })
+            .unwrap();
+        resources
+            .add_resource(Resource {
+                name: "baz".into(),
+                is_python_module: true,
+                in_memory_source: Some(b"pass\n".to_vec().into()),
+                ..Default::default()
+            })
+            .unwrap();
+
+        resources
+    };
+
+    let resources = new_resources();
+
+    // Modules not marked as extracted are never extracted.
+    resources.extract_module_to_cache("baz").unwrap();
+    assert!(!cache_dir.join("baz").exists());
+
+    resources.extract_module_to_cache("foo.bar").unwrap();
+
+    let entries = std::fs::read_dir(&cache_dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(entries.len(), 3);
+    assert!(!stale_dir.exists());
+    assert!(recent_dir.exists());
+    assert!(other_dir.exists());
+
+    let package_dir = entries
+        .into_iter()
+        .find(|path| path != &other_dir && path != &recent_dir)
+        .unwrap();
+    assert!(package_dir
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap()
+        .starts_with("foo-"));
+    assert_eq!(
+        std::fs::read(package_dir.join("foo/__init__.py"))?,
+        b"import os\n"
+    );
+    assert_eq!(
+        std::fs::read(package_dir.join("foo/bar.py"))?,
+        b"import sys\n"
+    );
+    assert_eq!(std::fs::read(package_dir.join("foo/data.txt"))?, b"data");
+
+    // Existing files having unexpected content are replaced.
+    std::fs::write(package_dir.join("foo/bar.py"), b"modified")?;
+    new_resources().extract_module_to_cache("foo").unwrap();
+    assert_eq!(
+        std::fs::read(package_dir.join("foo/bar.py"))?,
+        b"import sys\n"
+    );
+
+    Ok(())
+}
+
 fn get_interpreter<'interp, 'rsrc>() -> crate::MainPythonInterpreter<'interp, 'rsrc> {
     let mut config = crate::OxidizedPythonInterpreterConfig::default();
     config.interpreter_config.parse_argv = Some(false);
@@ -9,6 +9,7 @@ import tempfile
 import unittest
 
 from oxidized_importer import (
+    OxidizedResource,
     OxidizedResourceCollector,
     OxidizedFinder,
     find_resources_in_path,
@@ -43,6 +44,17 @@ class TestImporterConstruction(unittest.TestCase):
 
         return finder.serialize_indexed_resources()
 
+    def get_module_data(self, name: str, source: bytes) -> bytes:
+        resource = OxidizedResource()
+        resource.name = name
+        resource.is_module = True
+        resource.in_memory_source = source
+
+        finder = OxidizedFinder()
+        finder.add_resource(resource)
+
+        return finder.serialize_indexed_resources()
+
     def test_index_interpreter_builtins(self):
         f = OxidizedFinder()
         f.index_interpreter_builtins()
@@ -81,6 +93,37 @@ class TestImporterConstruction(unittest.TestCase):
         f = OxidizedFinder()
         f.index_file_memory_mapped(path)
 
+    def test_index_layers(self):
+        f = OxidizedFinder()
+        f.index_bytes(self.get_module_data("foo", b"value = 0"))
+        f.index_bytes(
+            self.get_module_data("foo", b"value = 2"), layer="patch", priority=10
+        )
+        f.index_bytes(self.get_module_data("foo", b"value = 1"), layer="base")
+
+        self.assertEqual(f.get_source("foo"), "value = 2")
+        self.assertEqual(f.resource_layer("foo"), "patch")
+        self.assertIsNone(f.resource_layer("missing"))
+        self.assertEqual(
+            f.layer_conflicts(),
+            [{"name": "foo", "layer": "patch", "shadowed_layer": "base"}],
+        )
+
+        with self.assertRaises(ValueError):
+            f.index_bytes(self.get_module_data("bar", b""), layer="base")
+
+    def test_index_layers_strict(self):
+        f = OxidizedFinder(strict_layers=True)
+        f.index_bytes(self.get_module_data("foo", b"value = 1"), layer="a")
+        f.index_bytes(self.get_module_data("foo", b"value = 2"), layer="b", priority=1)
+
+        with self.assertRaises(ValueError):
+            f.index_bytes(
+                self.get_module_data("foo", b"value = 3"), layer="c", priority=1
+            )
+
+        self.assertEqual(f.resource_layer("foo"), "b")
+
 
 if __name__ == "__main__":
     unittest.main()
@@ -2,18 +2,21 @@
 # License, v. 2.0. If a copy of the MPL was not distributed with this
 # file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+import _imp
 import importlib.machinery
 import importlib.util
 import importlib
 import marshal
 import os
 import pathlib
+import py_compile
 import sys
 import tempfile
 import unittest
 
 from oxidized_importer import (
     OxidizedFinder,
+    OxidizedResource,
     OxidizedResourceCollector,
     find_resources_in_path,
 )
@@ -182,6 +185,327 @@ class TestImporterModuleLoading(unittest.TestCase):
         self.assertIn("dotinit.bar", sys.modules)
         self.assertNotIn("dotinit.__init__", sys.modules)
 
+    def _relative_bytecode_finder(self, invalidation_mode):
+        """Create a finder for a filesystem-relative module with stale bytecode.
+
+        The bytecode is compiled from ``value = 1`` and the source is then
+        changed to ``value = 22``.
+        """
+        source_path = self.td / "mod.py"
+        with source_path.open("w") as fh:
+            fh.write("value = 1\n")
+
+        py_compile.compile(
+            str(source_path),
+            cfile=str(self.td / "mod.pyc"),
+            doraise=True,
+            invalidation_mode=invalidation_mode,
+        )
+
+        with source_path.open("w") as fh:
+            fh.write("value = 22\n")
+
+        resource = OxidizedResource()
+        resource.is_module = True
+        resource.name = "mod"
+        resource.relative_path_module_source = pathlib.Path("mod.py")
+        resource.relative_path_module_bytecode = pathlib.Path("mod.pyc")
+
+        f = OxidizedFinder(relative_path_origin=self.td)
+        f.add_resource(resource)
+
+        return f
+
+    def _module_value(self, f):
+        namespace = {}
+        exec(f.get_code("mod"), namespace)
+        return namespace["value"]
+
+    def _with_check_hash_pycs_mode(self, mode, fn):
+        old_mode = _imp.check_hash_based_pycs
+        _imp.check_hash_based_pycs = mode
+        try:
+            return fn()
+        finally:
+            _imp.check_hash_based_pycs = old_mode
+
+    def test_relative_bytecode_current(self):
+        source_path = self.td / "mod.py"
+        with source_path.open("w") as fh:
+            fh.write("value = 1\n")
+
+        py_compile.compile(
+            str(source_path),
+            cfile=str(self.td / "mod.pyc"),
+            doraise=True,
+            invalidation_mode=py_compile.PycInvalidationMode.CHECKED_HASH,
+        )
+
+        # Bytecode is used when it matches the source.
+        with (self.td / "mod.pyc").open("rb") as fh:
+            expected = marshal.loads(fh.read()[16:])
+
+        resource = OxidizedResource()
+        resource.is_module = True
+        resource.name = "mod"
+        resource.relative_path_module_source = pathlib.Path("mod.py")
+        resource.relative_path_module_bytecode = pathlib.Path("mod.pyc")
+
+        f = OxidizedFinder(relative_path_origin=self.td)
+        f.add_resource(resource)
+
+        self.assertEqual(f.get_code("mod"), expected)
+
+    def test_relative_bytecode_checked_hash(self):
+        f = self._relative_bytecode_finder(
+            py_compile.PycInvalidationMode.CHECKED_HASH
+        )
+
+        self.assertEqual(self._module_value(f), 22)
+        self.assertEqual(
+            self._with_check_hash_pycs_mode("never", lambda: self._module_value(f)),
+            1,
+        )
+
+    def test_relative_bytecode_unchecked_hash(self):
+        f = self._relative_bytecode_finder(
+            py_compile.PycInvalidationMode.UNCHECKED_HASH
+        )
+
+        self.assertEqual(self._module_value(f), 1)
+        self.assertEqual(
+            self._with_check_hash_pycs_mode("always", lambda: self._module_value(f)),
+            22,
+        )
+
+    def test_relative_bytecode_timestamp(self):
+        f = self._relative_bytecode_finder(py_compile.PycInvalidationMode.TIMESTAMP)
+
+        self.assertEqual(self._module_value(f), 22)
+
+    def test_relative_bytecode_bad_magic(self):
+        f = self._relative_bytecode_finder(
+            py_compile.PycInvalidationMode.UNCHECKED_HASH
+        )
+
+        with (self.td / "mod.pyc").open("r+b") as fh:
+            fh.write(b"\0\0\0\0")
+
+        self.assertEqual(self._module_value(f), 22)
+
+    def test_relative_bytecode_without_source(self):
+        f = self._relative_bytecode_finder(
+            py_compile.PycInvalidationMode.CHECKED_HASH
+        )
+        (self.td / "mod.py").unlink()
+
+        self.assertEqual(self._module_value(f), 1)
+
+    def test_import_trace_disabled(self):
+        f = OxidizedFinder()
+        self.assertIsNone(f.import_trace())
+
+    def test_import_trace(self):
+        p = self._make_package("my_package")
+
+        with (p / "__init__.py").open("wb") as fh:
+            fh.write(b"value = 1\n")
+
+        collector = OxidizedResourceCollector(allowed_locations=["in-memory"])
+        for r in find_resources_in_path(self.td):
+            collector.add_in_memory(r)
+
+        f = OxidizedFinder(trace_imports=True)
+        f.add_resources(collector.oxidize()[0])
+
+        self.assertEqual(f.import_trace(), [])
+
+        self.assertIsNone(f.find_spec("missing_package", None))
+        spec = f.find_spec("my_package", None)
+        m = importlib.util.module_from_spec(spec)
+        f.exec_module(m)
+        self.assertEqual(m.value, 1)
+
+        trace = f.import_trace()
+        self.assertEqual(len(trace), 3)
+
+        self.assertEqual(
+            [(t["operation"], t["name"], t["hit"], t["source"]) for t in trace],
+            [
+                ("find_spec", "missing_package", False, None),
+                ("find_spec", "my_package", True, "in-memory"),
+                ("exec_module", "my_package", True, "in-memory"),
+            ],
+        )
+
+        for t in trace:
+            self.assertIsInstance(t["start"], float)
+            self.assertGreaterEqual(t["start"], 0.0)
+            self.assertIsInstance(t["elapsed"], float)
+            self.assertGreaterEqual(t["elapsed"], 0.0)
+
+    def test_resource_access_disabled(self):
+        f = OxidizedFinder()
+        self.assertIsNone(f.resource_access())
+
+    def test_resource_access(self):
+        p = self._make_package("my_package")
+
+        with (p / "__init__.py").open("wb") as fh:
+            fh.write(b"value = 1\n")
+        with (p / "data.txt").open("wb") as fh:
+            fh.write(b"data")
+
+        p = self._make_package("unused_package")
+
+        with (p / "__init__.py").open("wb") as fh:
+            fh.write(b"")
+
+        collector = OxidizedResourceCollector(allowed_locations=["in-memory"])
+        for r in find_resources_in_path(self.td):
+            collector.add_in_memory(r)
+
+        f = OxidizedFinder(track_resource_access=True)
+        f.add_resources(collector.oxidize()[0])
+
+        self.assertEqual(
+            f.resource_access(),
+            {"accessed": {}, "unused": ["my_package", "unused_package"]},
+        )
+
+        spec = f.find_spec("my_package", None)
+        m = importlib.util.module_from_spec(spec)
+        f.exec_module(m)
+        self.assertEqual(m.value, 1)
+
+        reader = f.get_resource_reader("my_package")
+        with reader.open_resource("data.txt") as fh:
+            self.assertEqual(fh.read(), b"data")
+
+        access = f.resource_access()
+        self.assertEqual(access["unused"], ["unused_package"])
+        self.assertEqual(list(access["accessed"]), ["my_package"])
+
+        counts = access["accessed"]["my_package"]
+        self.assertGreaterEqual(counts["module"], 1)
+        self.assertEqual(counts["package_resource"], 1)
+        self.assertEqual(counts["distribution_resource"], 0)
+        self.assertEqual(counts["shared_library"], 0)
+
+    def test_namespace_package(self):
+        portion = self.td / "portion"
+        (portion / "my_namespace").mkdir(parents=True)
+        with (portion / "my_namespace" / "on_disk.py").open("wb") as fh:
+            fh.write(b"value = 2\n")
+
+        package = OxidizedResource()
+        package.name = "my_namespace"
+        package.is_module = True
+        package.is_package = True
+        package.is_namespace_package = True
+
+        child = OxidizedResource()
+        child.name = "my_namespace.in_memory"
+        child.is_module = True
+        child.in_memory_source = b"value = 1\n"
+
+        f = OxidizedFinder()
+        f.add_resources([package, child])
+
+        sys.meta_path.insert(0, f)
+        sys.path.insert(0, str(portion))
+        try:
+            spec = f.find_spec("my_namespace", None)
+            self.assertIsNone(spec.origin)
+            self.assertIn(
+                str(portion / "my_namespace"), spec.submodule_search_locations
+            )
+
+            import my_namespace.in_memory
+            import my_namespace.on_disk
+
+            self.assertEqual(my_namespace.in_memory.value, 1)
+            self.assertEqual(my_namespace.on_disk.value, 2)
+        finally:
+            sys.path.remove(str(portion))
+            for name in list(sys.modules):
+                if name == "my_namespace" or name.startswith("my_namespace."):
+                    del sys.modules[name]
+
+    def test_watched_file(self):
+        p = self._make_package("my_package")
+
+        with (p / "__init__.py").open("wb") as fh:
+            fh.write(b"value = 1\n")
+
+        with tempfile.TemporaryDirectory(prefix="oxidized_importer-test-") as td:
+            packed = pathlib.Path(td) / "packed-resources"
+
+            with packed.open("wb") as fh:
+                fh.write(self._finder_from_td().serialize_indexed_resources())
+
+            f = OxidizedFinder()
+            f.index_file_watched(packed)
+
+            sys.meta_path.insert(0, f)
+            try:
+                import my_package
+
+                self.assertEqual(my_package.value, 1)
+                self.assertEqual(f.reload_watched_resources(), [])
+
+                with (p / "__init__.py").open("wb") as fh:
+                    fh.write(b"value = 2\n")
+
+                with packed.open("wb") as fh:
+                    fh.write(self._finder_from_td().serialize_indexed_resources())
+
+                # Ensure the change is detected even if the file system has coarse
+                # timestamp resolution.
+                st = packed.stat()
+                os.utime(packed, ns=(st.st_atime_ns, st.st_mtime_ns + 1_000_000_000))
+
+                self.assertEqual(
+                    f.reload_watched_resources(invalidate_modules=True), ["my_package"]
+                )
+                self.assertNotIn("my_package", sys.modules)
+
+                import my_package
+
+                self.assertEqual(my_package.value, 2)
+            finally:
+                sys.modules.pop("my_package", None)
+
+    def test_watched_file_invalid(self):
+        p = self._make_package("my_package")
+
+        with (p / "__init__.py").open("wb") as fh:
+            fh.write(b"value = 1\n")
+
+        with tempfile.TemporaryDirectory(prefix="oxidized_importer-test-") as td:
+            packed = pathlib.Path(td) / "packed-resources"
+
+            with packed.open("wb") as fh:
+                fh.write(self._finder_from_td().serialize_indexed_resources())
+
+            f = OxidizedFinder()
+            f.index_file_watched(packed)
+
+            with packed.open("wb") as fh:
+                fh.write(b"garbage")
+
+            st = packed.stat()
+            os.utime(packed, ns=(st.st_atime_ns, st.st_mtime_ns + 1_000_000_000))
+
+            # Errors are logged by invalidate_caches() and raised otherwise.
+            with self.assertLogs("oxidized_importer", level="WARNING"):
+                f.invalidate_caches()
+            with self.assertRaises(ValueError):
+                f.reload_watched_resources()
+
+            # Resources from the previous content are retained.
+            self.assertIsNotNone(f.find_spec("my_package", None))
+
 
 if __name__ == "__main__":
     unittest.main()
@@ -4,11 +4,14 @@
 
 import importlib
 import importlib.machinery
+import importlib.metadata
+import importlib.resources
 import importlib.util
 import io
 import marshal
 import os
 import pathlib
+import pkgutil
 import struct
 import sys
 import tempfile
@@ -359,6 +362,244 @@ class TestImporter(unittest.TestCase):
         self.assertEqual(spec.origin, str(p / "foo.py"))
         self.assertIsNone(spec.submodule_search_locations)
 
+    def test_zip_file_memory_map(self):
+        source = b"foo = 42\n"
+
+        # Zip data appended to other data, as happens when appended to an executable.
+        zip_data = make_zip({"foo.py": (DEFAULT_MTIME, source)}, prefix=b"#" * 1024)
+
+        p = self.td / "test.bin"
+
+        with p.open("wb") as fh:
+            fh.write(zip_data)
+
+        importer = OxidizedZipFinder.from_path(p, memory_map=True)
+        spec = importer.find_spec("foo", None)
+
+        self.assertEqual(spec.origin, str(p / "foo.py"))
+        self.assertEqual(importer.get_source("foo"), source.decode("ascii"))
+
+        sys.meta_path.insert(0, importer)
+        m = importlib.import_module("foo")
+        self.assertEqual(m.foo, 42)
+
+    def test_bytecode_stale_mtime(self):
+        source = b"foo = 42\n"
+        stale_code = compile("foo = 41\n", "foo.py", "exec")
+        bytecode = make_pyc(stale_code, DEFAULT_MTIME - 10, len(source))
+
+        zip_data = make_zip(
+            {"foo.py": (DEFAULT_MTIME, source), "foo.pyc": (DEFAULT_MTIME, bytecode)}
+        )
+
+        importer = OxidizedZipFinder.from_zip_data(zip_data)
+
+        self.assertEqual(
+            importer.get_code("foo"), compile(source.decode("ascii"), "foo.py", "exec")
+        )
+
+    def test_bytecode_stale_size(self):
+        source = b"foo = 42\n"
+        stale_code = compile("foo = 41\n", "foo.py", "exec")
+        bytecode = make_pyc(stale_code, DEFAULT_MTIME, len(source) + 1)
+
+        zip_data = make_zip(
+            {"foo.py": (DEFAULT_MTIME, source), "foo.pyc": (DEFAULT_MTIME, bytecode)}
+        )
+
+        importer = OxidizedZipFinder.from_zip_data(zip_data)
+
+        self.assertEqual(
+            importer.get_code("foo"), compile(source.decode("ascii"), "foo.py", "exec")
+        )
+
+    def test_bytecode_bad_magic(self):
+        source = b"foo = 42\n"
+        stale_code = compile("foo = 41\n", "foo.py", "exec")
+        bytecode = b"\x00\x00\x00\x00" + make_pyc(
+            stale_code, DEFAULT_MTIME, len(source)
+        )[4:]
+
+        zip_data = make_zip(
+            {"foo.py": (DEFAULT_MTIME, source), "foo.pyc": (DEFAULT_MTIME, bytecode)}
+        )
+        importer = OxidizedZipFinder.from_zip_data(zip_data)
+
+        self.assertEqual(
+            importer.get_code("foo"), compile(source.decode("ascii"), "foo.py", "exec")
+        )
+
+        zip_data = make_zip({"foo.pyc": (DEFAULT_MTIME, bytecode)})
+        importer = OxidizedZipFinder.from_zip_data(zip_data)
+
+        with self.assertRaisesRegex(ImportError, "bad magic number"):
+            importer.get_code("foo")
+
+    def test_bytecode_checked_hash(self):
+        source = b"foo = 42\n"
+        stale_code = compile("foo = 41\n", "foo.py", "exec")
+        bytecode = b"%s%s%s%s" % (
+            importlib.util.MAGIC_NUMBER,
+            struct.pack("<L", 0b11),
+            importlib.util.source_hash(b"foo = 41\n"),
+            marshal.dumps(stale_code),
+        )
+
+        zip_data = make_zip(
+            {"foo.py": (DEFAULT_MTIME, source), "foo.pyc": (DEFAULT_MTIME, bytecode)}
+        )
+        importer = OxidizedZipFinder.from_zip_data(zip_data)
+
+        self.assertEqual(
+            importer.get_code("foo"), compile(source.decode("ascii"), "foo.py", "exec")
+        )
+
+    def test_get_data(self):
+        zip_data = make_zip(
+            {
+                "foo/__init__.py": (DEFAULT_MTIME, b""),
+                "foo/data.txt": (DEFAULT_MTIME, b"data"),
+            }
+        )
+
+        importer = OxidizedZipFinder.from_zip_data(zip_data)
+
+        self.assertEqual(importer.get_data("foo/data.txt"), b"data")
+        self.assertEqual(
+            importer.get_data(os.path.join(sys.executable, "foo", "data.txt")), b"data"
+        )
+
+        with self.assertRaises(OSError):
+            importer.get_data("foo/missing.txt")
+
+        sys.meta_path.insert(0, importer)
+        self.assertEqual(pkgutil.get_data("foo", "data.txt"), b"data")
+
+    def test_resource_reader(self):
+        zip_data = make_zip(
+            {
+                "foo/__init__.py": (DEFAULT_MTIME, b""),
+                "foo/bar.py": (DEFAULT_MTIME, b""),
+                "foo/data.txt": (DEFAULT_MTIME, b"data"),
+                "foo/subdir/nested.txt": (DEFAULT_MTIME, b"nested"),
+            }
+        )
+
+        importer = OxidizedZipFinder.from_zip_data(zip_data)
+
+        self.assertIsNone(importer.get_resource_reader("missing"))
+        self.assertIsNone(importer.get_resource_reader("foo.bar"))
+
+        reader = importer.get_resource_reader("foo")
+        self.assertEqual(
+            reader.contents(), ["__init__.py", "bar.py", "data.txt", "subdir"]
+        )
+        self.assertTrue(reader.is_resource("data.txt"))
+        self.assertFalse(reader.is_resource("subdir"))
+
+        with self.assertRaises(FileNotFoundError):
+            reader.is_resource("missing.txt")
+
+        with reader.open_resource("data.txt") as fh:
+            self.assertEqual(fh.read(), b"data")
+
+        with self.assertRaises(FileNotFoundError):
+            reader.open_resource("missing.txt")
+
+        with self.assertRaises(FileNotFoundError):
+            reader.resource_path("data.txt")
+
+    def test_files(self):
+        zip_data = make_zip(
+            {
+                "foo/__init__.py": (DEFAULT_MTIME, b""),
+                "foo/data.txt": (DEFAULT_MTIME, b"data"),
+                "foo/subdir/nested.txt": (DEFAULT_MTIME, b"nested"),
+            }
+        )
+
+        importer = OxidizedZipFinder.from_zip_data(zip_data)
+        sys.meta_path.insert(0, importer)
+
+        files = importlib.resources.files("foo")
+        self.assertTrue(files.is_dir())
+        self.assertFalse(files.is_file())
+        self.assertEqual(files.name, "foo")
+        self.assertEqual(
+            sorted(p.name for p in files.iterdir()),
+            ["__init__.py", "data.txt", "subdir"],
+        )
+
+        data = files / "data.txt"
+        self.assertTrue(data.is_file())
+        self.assertEqual(data.read_bytes(), b"data")
+        self.assertEqual(data.read_text(), "data")
+
+        with data.open("rb") as fh:
+            self.assertEqual(fh.read(), b"data")
+
+        nested = files.joinpath("subdir", "nested.txt")
+        self.assertEqual(nested.read_text(encoding="utf-8"), "nested")
+        self.assertEqual(files.joinpath("subdir/nested.txt").read_bytes(), b"nested")
+        self.assertEqual(nested.parent.name, "subdir")
+
+        with self.assertRaises(FileNotFoundError):
+            (files / "missing.txt").read_bytes()
+
+    def test_iter_modules(self):
+        zip_data = make_zip(
+            {
+                "foo.py": (DEFAULT_MTIME, b""),
+                "bar.pyc": (DEFAULT_MTIME, b""),
+                "pkg/__init__.py": (DEFAULT_MTIME, b""),
+                "pkg/child.py": (DEFAULT_MTIME, b""),
+                "data/file.txt": (DEFAULT_MTIME, b""),
+            }
+        )
+
+        importer = OxidizedZipFinder.from_zip_data(zip_data)
+
+        self.assertEqual(
+            importer.iter_modules(),
+            [("bar", False), ("foo", False), ("pkg", True)],
+        )
+        self.assertEqual(
+            importer.iter_modules("prefix."),
+            [("prefix.bar", False), ("prefix.foo", False), ("prefix.pkg", True)],
+        )
+
+    def test_find_distributions(self):
+        zip_data = make_zip(
+            {
+                "foo/__init__.py": (DEFAULT_MTIME, b""),
+                "my_package-1.0.dist-info/METADATA": (
+                    DEFAULT_MTIME,
+                    b"Name: my-package\nVersion: 1.0\n",
+                ),
+                "other-2.0.dist-info/METADATA": (
+                    DEFAULT_MTIME,
+                    b"Name: other\nVersion: 2.0\n",
+                ),
+            }
+        )
+
+        importer = OxidizedZipFinder.from_zip_data(zip_data)
+
+        dists = list(importer.find_distributions())
+        self.assertEqual(len(dists), 2)
+        self.assertEqual(
+            sorted(d.metadata["Name"] for d in dists), ["my-package", "other"]
+        )
+
+        context = importlib.metadata.DistributionFinder.Context(name="my-package")
+        dists = list(importer.find_distributions(context))
+        self.assertEqual(len(dists), 1)
+        self.assertEqual(dists[0].version, "1.0")
+        self.assertIsNone(dists[0].read_text("missing"))
+
+        sys.meta_path.insert(0, importer)
+        self.assertEqual(importlib.metadata.version("other"), "2.0")
+
 
 if __name__ == "__main__":
     unittest.main()
@@ -25,6 +25,22 @@ This command will invoke Rust's build system tool (Cargo) to build
 the project.
 ";
 
+const LOCK_ABOUT: &str = "\
+Build a PyOxidizer project and write a lock file.
+
+The PATH argument is a filesystem path to a directory containing an
+existing PyOxidizer enabled project.
+
+The project is built like `pyoxidizer build` would. Every Python
+distribution and every wheel or source distribution that `pip_download()`
+and `pip_install()` pull in is recorded in a `pyoxidizer.lock` file next
+to the configuration file. Package archives are stored in PyOxidizer's
+cache.
+
+`pyoxidizer build --locked` only accepts artifacts recorded in the lock
+file and obtains package archives from the cache, without network access.
+";
+
 const INIT_RUST_PROJECT_ABOUT: &str = "\
 Create a new Rust project embedding Python.
 
@@ -98,6 +114,50 @@ conversion is critical for properly packaging Python applications and
 bugs can result in incorrect install layouts, missing resources, etc.
 ";
 
+const CONVERT_RESOURCES_ABOUT: &str = "\
+Convert between packed resources data and JSON or YAML descriptions.
+
+If INPUT_PATH holds packed resources data, a description of its resources
+is written to OUTPUT_PATH. Otherwise INPUT_PATH is parsed as a description
+and packed resources data is written to OUTPUT_PATH.
+
+Descriptions are YAML if the path ends in `.yaml` or `.yml` and JSON
+otherwise. Data fields in descriptions are either base64 encoded inline
+(`{\"base64\": \"...\"}`) or reference a file (`{\"path\": \"...\"}`)
+resolved relative to the directory containing the description.
+";
+
+const GENERATE_RESOURCES_FILTER_ABOUT: &str = "\
+Generate a resources filter file from resource access reports.
+
+When `PythonInterpreterConfig.write_modules_directory_env` is active, the
+embedded interpreter writes a `resources-<random>.json` report recording
+which packed resources were accessed during a run. This command reads one
+or more of these reports and writes a file listing the names of every
+accessed resource and their parent packages.
+
+The written file can be passed to
+`PythonExecutable.filter_resources_from_files()` to only package resources
+that were used during the recorded runs.
+";
+
+const INSPECT_RESOURCES_ABOUT: &str = "\
+Inspect the content of packed resources data.
+
+The PATH argument is a filesystem path to a file holding packed resources
+data (such as a `packed-resources` file written by a build) or a binary
+having packed resources data embedded within it.
+
+By default, all resources are printed along with their flags, the size of
+their data fields, and their filesystem relative paths.
+
+`--extract NAME --dest-dir DIR` writes the data fields of the named
+resource to files in a directory.
+
+`--diff OTHER_PATH` compares the resources in PATH against those in
+OTHER_PATH and prints added (+), removed (-), and changed (~) resources.
+";
+
 const VAR_HELP: &str = "\
 Defines a single string key to set in the VARS global dict.
 
@@ -250,6 +310,12 @@ pub fn run_cli() -> Result<()> {
                     .action(ArgAction::SetTrue)
                     .help("Build a release binary"),
             )
+            .arg(
+                Arg::new("locked")
+                    .long("locked")
+                    .action(ArgAction::SetTrue)
+                    .help("Only use artifacts recorded in the project's lock file"),
+            )
             .arg(
                 Arg::new("path")
                     .long("path")
@@ -271,6 +337,28 @@ pub fn run_cli() -> Result<()> {
     let app =
         app.subcommand(Command::new("cache-clear").about("Clear PyOxidizer's user-specific cache"));
 
+    let app = app.subcommand(
+        Command::new("convert-resources")
+            .about("Convert between packed resources data and JSON or YAML")
+            .long_about(CONVERT_RESOURCES_ABOUT)
+            .arg(
+                Arg::new("input")
+                    .action(ArgAction::Set)
+                    .value_parser(value_parser!(PathBuf))
+                    .value_name("INPUT_PATH")
+                    .required(true)
+                    .help("Packed resources data or description to convert"),
+            )
+            .arg(
+                Arg::new("output")
+                    .action(ArgAction::Set)
+                    .value_parser(value_parser!(PathBuf))
+                    .value_name("OUTPUT_PATH")
+                    .required(true)
+                    .help("Path to write converted output to"),
+            ),
+    );
+
     let app = app.subcommand(
         Command::new("find-resources")
             .about("Find resources in a file or directory")
@@ -332,6 +420,30 @@ pub fn run_cli() -> Result<()> {
             ),
     ));
 
+    let app = app.subcommand(
+        Command::new("generate-resources-filter")
+            .about("Generate a resources filter file from resource access reports")
+            .long_about(GENERATE_RESOURCES_FILTER_ABOUT)
+            .arg(
+                Arg::new("output")
+                    .long("output")
+                    .action(ArgAction::Set)
+                    .value_parser(value_parser!(PathBuf))
+                    .value_name("OUTPUT_PATH")
+                    .required(true)
+                    .help("Path to write the resources filter file to"),
+            )
+            .arg(
+                Arg::new("reports")
+                    .action(ArgAction::Append)
+                    .value_parser(value_parser!(PathBuf))
+                    .value_name("REPORT_PATH")
+                    .num_args(1..)
+                    .required(true)
+                    .help("Resource access reports to read"),
+            ),
+    );
+
     let app = app.subcommand(
         Command::new("init-config-file")
             .about("Create a new PyOxidizer configuration file.")
@@ -369,6 +481,52 @@ pub fn run_cli() -> Result<()> {
             ),
     );
 
+    let app = app.subcommand(
+        Command::new("inspect-resources")
+            .about("Inspect the content of packed resources data")
+            .long_about(INSPECT_RESOURCES_ABOUT)
+            .arg(
+                Arg::new("json")
+                    .long("json")
+                    .action(ArgAction::SetTrue)
+                    .help("Emit output as JSON"),
+            )
+            .arg(
+                Arg::new("diff")
+                    .long("diff")
+                    .action(ArgAction::Set)
+                    .value_parser(value_parser!(PathBuf))
+                    .value_name("OTHER_PATH")
+                    .conflicts_with("extract")
+                    .help("Packed resources data or binary to compare against"),
+            )
+            .arg(
+                Arg::new("extract")
+                    .long("extract")
+                    .action(ArgAction::Set)
+                    .value_name("NAME")
+                    .requires("dest_dir")
+                    .help("Name of resource to extract"),
+            )
+            .arg(
+                Arg::new("dest_dir")
+                    .long("dest-dir")
+                    .action(ArgAction::Set)
+                    .value_parser(value_parser!(PathBuf))
+                    .value_name("DIR")
+                    .requires("extract")
+                    .help("Directory to extract resource data to"),
+            )
+            .arg(
+                Arg::new("path")
+                    .action(ArgAction::Set)
+                    .value_parser(value_parser!(PathBuf))
+                    .value_name("PATH")
+                    .required(true)
+                    .help("Packed resources data or binary to inspect"),
+            ),
+    );
+
     let app = app.subcommand(
         Command::new("list-targets")
             .about("List targets available to resolve in a configuration file")
@@ -381,6 +539,34 @@ pub fn run_cli() -> Result<()> {
             ),
     );
 
+    let app = app.subcommand(add_env_args(
+        Command::new("lock")
+            .about("Build a PyOxidizer project and write a lock file")
+            .long_about(LOCK_ABOUT)
+            .arg(
+                Arg::new("target_triple")
+                    .long("target-triple")
+                    .action(ArgAction::Set)
+                    .help("Rust target triple to build for"),
+            )
+            .arg(
+                Arg::new("path")
+                    .long("path")
+                    .action(ArgAction::Set)
+                    .value_parser(value_parser!(PathBuf))
+                    .default_value(".")
+                    .value_name("PATH")
+                    .help("Directory containing project to lock"),
+            )
+            .arg(
+                Arg::new("targets")
+                    .value_name("TARGET")
+                    .action(ArgAction::Append)
+                    .num_args(0..)
+                    .help("Target to resolve"),
+            ),
+    ));
+
     let app = app.subcommand(
         Command::new("python-distribution-extract")
             .about("Extract a Python distribution archive to a directory")
@@ -464,6 +650,12 @@ pub fn run_cli() -> Result<()> {
                     .action(ArgAction::SetTrue)
                     .help("Run a release binary"),
             )
+            .arg(
+                Arg::new("locked")
+                    .long("locked")
+                    .action(ArgAction::SetTrue)
+                    .help("Only use artifacts recorded in the project's lock file"),
+            )
             .arg(
                 Arg::new("path")
                     .long("path")
@@ -553,6 +745,7 @@ pub fn run_cli() -> Result<()> {
         "build" => {
             let starlark_vars = starlark_vars(args)?;
             let release = args.get_flag("release");
+            let locked = args.get_flag("locked");
             let target_triple = args.get_one::<String>("target_triple");
             let path = args.get_one::<PathBuf>("path").unwrap();
             let resolve_targets = args
@@ -567,11 +760,19 @@ pub fn run_cli() -> Result<()> {
                 starlark_vars,
                 release,
                 verbose,
+                locked,
             )
         }
 
         "cache-clear" => projectmgmt::cache_clear(&env),
 
+        "convert-resources" => {
+            let input = args.get_one::<PathBuf>("input").unwrap();
+            let output = args.get_one::<PathBuf>("output").unwrap();
+
+            projectmgmt::convert_resources(input, output)
+        }
+
         "find-resources" => {
             let path = args.get_one::<PathBuf>("path");
             let distributions_dir = args.get_one::<PathBuf>("distributions_dir");
@@ -616,6 +817,17 @@ pub fn run_cli() -> Result<()> {
             )
         }
 
+        "generate-resources-filter" => {
+            let output = args.get_one::<PathBuf>("output").unwrap();
+            let reports = args
+                .get_many::<PathBuf>("reports")
+                .unwrap_or_default()
+                .map(|x| x.as_path())
+                .collect::<Vec<_>>();
+
+            projectmgmt::generate_resources_filter(&reports, output)
+        }
+
         "init-config-file" => {
             let code = args.get_one::<String>("python-code");
             let pip_install = args
@@ -634,6 +846,41 @@ pub fn run_cli() -> Result<()> {
             )
         }
 
+        "inspect-resources" => {
+            let path = args.get_one::<PathBuf>("path").unwrap();
+            let diff_path = args.get_one::<PathBuf>("diff");
+            let extract = args.get_one::<String>("extract");
+            let dest_dir = args.get_one::<PathBuf>("dest_dir");
+            let json = args.get_flag("json");
+
+            projectmgmt::inspect_resources(
+                path,
+                diff_path.map(|x| x.as_path()),
+                extract
+                    .zip(dest_dir)
+                    .map(|(name, dest_dir)| (name.as_str(), dest_dir.as_path())),
+                json,
+            )
+        }
+
+        "lock" => {
+            let starlark_vars = starlark_vars(args)?;
+            let target_triple = args.get_one::<String>("target_triple");
+            let path = args.get_one::<PathBuf>("path").unwrap();
+            let resolve_targets = args
+                .get_many::<String>("targets")
+                .map(|x| x.cloned().collect::<Vec<_>>());
+
+            projectmgmt::lock(
+                &env,
+                path,
+                target_triple.map(|x| x.as_str()),
+                resolve_targets,
+                starlark_vars,
+                verbose,
+            )
+        }
+
         "list-targets" => {
             let path = args.get_one::<String>("path").unwrap();
 
@@ -696,6 +943,7 @@ pub fn run_cli() -> Result<()> {
             let starlark_vars = starlark_vars(args)?;
             let target_triple = args.get_one::<String>("target_triple");
             let release = args.get_flag("release");
+            let locked = args.get_flag("locked");
             let path = args.get_one::<String>("path").unwrap();
             let target = args.get_one::<String>("target");
             let extra = args
@@ -713,6 +961,7 @@ pub fn run_cli() -> Result<()> {
                 starlark_vars,
                 &extra,
                 verbose,
+                locked,
             )
         }
 
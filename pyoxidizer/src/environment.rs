@@ -5,7 +5,10 @@
 //! Resolve details about the PyOxidizer execution environment.
 
 use {
-    crate::{project_layout::PyembedLocation, py_packaging::distribution::AppleSdkInfo},
+    crate::{
+        lockfile::LockMode, project_layout::PyembedLocation,
+        py_packaging::distribution::AppleSdkInfo,
+    },
     anyhow::{anyhow, Context, Result},
     apple_sdk::{AppleSdk, ParsedSdk, SdkSearch, SdkSearchLocation, SdkSorting},
     log::{info, warn},
@@ -247,6 +250,9 @@ pub struct Environment {
     ///
     /// Cached because lookups may be expensive.
     rust_environment: Arc<RwLock<Option<RustEnvironment>>>,
+
+    /// How external artifacts consumed by builds are locked.
+    lock_mode: LockMode,
 }
 
 impl Environment {
@@ -269,6 +275,7 @@ impl Environment {
             cache_dir,
             managed_rust,
             rust_environment: Arc::new(RwLock::new(None)),
+            lock_mode: LockMode::default(),
         })
     }
 
@@ -284,6 +291,11 @@ impl Environment {
         self.cache_dir.join("python_distributions")
     }
 
+    /// Directory to use for storing Python package archives referenced by lock files.
+    pub fn python_artifacts_dir(&self) -> PathBuf {
+        self.cache_dir.join("python_artifacts")
+    }
+
     /// Directory to hold Rust toolchains.
     pub fn rust_dir(&self) -> PathBuf {
         self.cache_dir.join("rust")
@@ -303,6 +315,16 @@ impl Environment {
         Ok(())
     }
 
+    /// How external artifacts consumed by builds are locked.
+    pub fn lock_mode(&self) -> &LockMode {
+        &self.lock_mode
+    }
+
+    /// Set how external artifacts consumed by builds are locked.
+    pub fn set_lock_mode(&mut self, mode: LockMode) {
+        self.lock_mode = mode;
+    }
+
     /// Find an executable of the given name.
     ///
     /// Resolves to `Some(T)` if an executable was found or `None` if not.
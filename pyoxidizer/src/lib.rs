@@ -14,6 +14,7 @@ This library exposes that functionality to other tools.
 mod default_python_distributions;
 pub mod environment;
 pub mod licensing;
+pub mod lockfile;
 pub mod project_building;
 pub mod project_layout;
 pub mod projectmgmt;
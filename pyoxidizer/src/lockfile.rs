@@ -0,0 +1,377 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Lock files recording the external artifacts consumed by builds.
+
+A lock file records every Python package archive (wheel or source
+distribution) and Python distribution a build pulls in. When building in
+locked mode, only the recorded artifacts may be used and package archives are
+obtained from a local artifact cache instead of the network.
+*/
+
+use {
+    crate::py_packaging::distribution::PythonDistributionLocation,
+    anyhow::{anyhow, Context, Result},
+    serde::{Deserialize, Serialize},
+    sha2::{Digest, Sha256},
+    std::{
+        io::Read,
+        path::{Path, PathBuf},
+        sync::{Arc, Mutex},
+    },
+    tugger_common::http::get_http_client,
+    url::Url,
+};
+
+/// Name of the lock file written next to a project's configuration file.
+pub const LOCK_FILE_NAME: &str = "pyoxidizer.lock";
+
+/// Version of the lock file format.
+const LOCK_FILE_VERSION: u32 = 1;
+
+/// A Python package archive consumed by a build.
+#[derive(Clone, Debug, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+pub struct LockedArtifact {
+    /// Name of the Python package.
+    pub name: String,
+
+    /// Version of the Python package.
+    pub version: String,
+
+    /// Filename of the archive.
+    pub filename: String,
+
+    /// Hex encoded SHA-256 of the archive.
+    pub sha256: String,
+
+    /// URL the archive was obtained from.
+    pub origin: String,
+}
+
+/// A Python distribution consumed by a build.
+#[derive(Clone, Debug, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+pub struct LockedPythonDistribution {
+    /// URL or local filesystem path of the distribution archive.
+    pub location: String,
+
+    /// Hex encoded SHA-256 of the distribution archive.
+    pub sha256: String,
+}
+
+impl From<&PythonDistributionLocation> for LockedPythonDistribution {
+    fn from(location: &PythonDistributionLocation) -> Self {
+        match location {
+            PythonDistributionLocation::Local { local_path, sha256 } => Self {
+                location: local_path.clone(),
+                sha256: sha256.clone(),
+            },
+            PythonDistributionLocation::Url { url, sha256 } => Self {
+                location: url.clone(),
+                sha256: sha256.clone(),
+            },
+        }
+    }
+}
+
+/// The content of a lock file.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct LockFile {
+    /// Version of the lock file format.
+    pub version: u32,
+
+    /// Python distributions resolved by the build.
+    pub python_distributions: Vec<LockedPythonDistribution>,
+
+    /// Python package archives consumed by the build.
+    pub artifacts: Vec<LockedArtifact>,
+}
+
+impl Default for LockFile {
+    fn default() -> Self {
+        Self {
+            version: LOCK_FILE_VERSION,
+            python_distributions: vec![],
+            artifacts: vec![],
+        }
+    }
+}
+
+impl LockFile {
+    /// Read a lock file from a filesystem path.
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let data =
+            std::fs::read(path).with_context(|| format!("reading lock file {}", path.display()))?;
+        let lock: Self = serde_json::from_slice(&data)
+            .with_context(|| format!("parsing lock file {}", path.display()))?;
+
+        if lock.version != LOCK_FILE_VERSION {
+            return Err(anyhow!(
+                "unsupported lock file version {} in {}; expected {}",
+                lock.version,
+                path.display(),
+                LOCK_FILE_VERSION
+            ));
+        }
+
+        Ok(lock)
+    }
+
+    /// Write this lock file to a filesystem path.
+    pub fn write_path(&self, path: &Path) -> Result<()> {
+        let mut data = serde_json::to_string_pretty(self)?;
+        data.push('\n');
+
+        std::fs::write(path, data).with_context(|| format!("writing {}", path.display()))
+    }
+
+    /// Record a Python package archive.
+    pub fn add_artifact(&mut self, artifact: LockedArtifact) {
+        if !self.artifacts.contains(&artifact) {
+            self.artifacts.push(artifact);
+            self.artifacts.sort();
+        }
+    }
+
+    /// Record a Python distribution.
+    pub fn add_python_distribution(&mut self, location: &PythonDistributionLocation) {
+        let distribution = LockedPythonDistribution::from(location);
+
+        if !self.python_distributions.contains(&distribution) {
+            self.python_distributions.push(distribution);
+            self.python_distributions.sort();
+        }
+    }
+
+    /// Whether a Python distribution is recorded in this lock file.
+    pub fn has_python_distribution(&self, location: &PythonDistributionLocation) -> bool {
+        self.python_distributions
+            .contains(&LockedPythonDistribution::from(location))
+    }
+
+    /// Copy every recorded archive from an artifact cache into a directory.
+    ///
+    /// The content of each archive is verified against its recorded hash.
+    pub fn materialize_artifacts(&self, cache_dir: &Path, dest_dir: &Path) -> Result<()> {
+        std::fs::create_dir_all(dest_dir)
+            .with_context(|| format!("creating {}", dest_dir.display()))?;
+
+        for artifact in &self.artifacts {
+            let path = artifact_cache_path(cache_dir, &artifact.sha256, &artifact.filename);
+
+            if !path.exists() {
+                return Err(anyhow!(
+                    "{} is not in the artifact cache at {}; run `pyoxidizer lock` to populate it",
+                    artifact.filename,
+                    cache_dir.display()
+                ));
+            }
+
+            let data =
+                std::fs::read(&path).with_context(|| format!("reading {}", path.display()))?;
+
+            if sha256_hex(&data) != artifact.sha256 {
+                return Err(anyhow!(
+                    "{} does not match the SHA-256 recorded in the lock file",
+                    path.display()
+                ));
+            }
+
+            std::fs::write(dest_dir.join(&artifact.filename), data)
+                .with_context(|| format!("copying {}", artifact.filename))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// How a build consumes external artifacts.
+#[derive(Clone, Debug, Default)]
+pub enum LockMode {
+    /// Artifacts are resolved without consulting a lock file.
+    #[default]
+    Unlocked,
+
+    /// Artifacts are resolved normally and recorded in a lock file.
+    Record(Arc<Mutex<LockFile>>),
+
+    /// Only artifacts recorded in a lock file may be used.
+    Locked(Arc<LockFile>),
+}
+
+impl LockMode {
+    /// Record or verify the use of a Python distribution.
+    pub fn use_python_distribution(&self, location: &PythonDistributionLocation) -> Result<()> {
+        match self {
+            Self::Unlocked => Ok(()),
+            Self::Record(lock) => {
+                lock.lock()
+                    .map_err(|e| anyhow!("unable to lock lock file: {}", e))?
+                    .add_python_distribution(location);
+
+                Ok(())
+            }
+            Self::Locked(lock) => {
+                if lock.has_python_distribution(location) {
+                    Ok(())
+                } else {
+                    Err(anyhow!(
+                        "Python distribution {} is not in the lock file; run `pyoxidizer lock` to update it",
+                        location
+                    ))
+                }
+            }
+        }
+    }
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(data);
+
+    hex::encode(hasher.finalize())
+}
+
+/// Path of an archive in an artifact cache directory.
+pub fn artifact_cache_path(cache_dir: &Path, sha256: &str, filename: &str) -> PathBuf {
+    cache_dir.join(sha256).join(filename)
+}
+
+/// Ensure the archive at a URL is present in an artifact cache directory.
+///
+/// If `expected_sha256` is defined and a verified copy of the archive is
+/// already cached, nothing is downloaded.
+///
+/// Returns the hex encoded SHA-256 of the archive.
+pub fn fetch_artifact(
+    cache_dir: &Path,
+    url: &str,
+    filename: &str,
+    expected_sha256: Option<&str>,
+) -> Result<String> {
+    if let Some(sha256) = expected_sha256 {
+        let path = artifact_cache_path(cache_dir, sha256, filename);
+
+        if path.exists() && sha256_hex(&std::fs::read(&path)?) == sha256 {
+            return Ok(sha256.to_string());
+        }
+    }
+
+    let u = Url::parse(url).with_context(|| format!("parsing URL {}", url))?;
+
+    let mut data = vec![];
+
+    if u.scheme() == "file" {
+        let path = u
+            .to_file_path()
+            .map_err(|_| anyhow!("invalid file URL: {}", url))?;
+        data = std::fs::read(&path).with_context(|| format!("reading {}", path.display()))?;
+    } else {
+        println!("downloading {}", u);
+        get_http_client()?
+            .get(u.as_str())
+            .send()?
+            .error_for_status()?
+            .read_to_end(&mut data)?;
+    }
+
+    let sha256 = sha256_hex(&data);
+
+    if let Some(expected) = expected_sha256 {
+        if sha256 != expected {
+            return Err(anyhow!(
+                "SHA-256 of {} does not validate: expected {}, got {}",
+                url,
+                expected,
+                sha256
+            ));
+        }
+    }
+
+    let path = artifact_cache_path(cache_dir, &sha256, filename);
+    let parent = path.parent().expect("cache path should have parent");
+    std::fs::create_dir_all(parent).with_context(|| format!("creating {}", parent.display()))?;
+
+    let mut temp_path = path.clone();
+    temp_path.set_file_name(format!("{}.tmp", uuid::Uuid::new_v4()));
+
+    std::fs::write(&temp_path, data).with_context(|| format!("writing {}", temp_path.display()))?;
+    std::fs::rename(&temp_path, &path)
+        .with_context(|| format!("renaming {} to {}", temp_path.display(), path.display()))?;
+
+    Ok(sha256)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock_file_round_trip() -> Result<()> {
+        let temp_dir = tempfile::Builder::new()
+            .prefix("pyoxidizer-test")
+            .tempdir()?;
+
+        let cache_dir = temp_dir.path().join("cache");
+        let source = temp_dir.path().join("foo-1.0-py3-none-any.whl");
+        std::fs::write(&source, b"wheel content")?;
+
+        let origin = Url::from_file_path(&source).unwrap().to_string();
+        let sha256 = fetch_artifact(&cache_dir, &origin, "foo-1.0-py3-none-any.whl", None)?;
+        assert_eq!(sha256, sha256_hex(b"wheel content"));
+
+        assert!(fetch_artifact(
+            &cache_dir,
+            &origin,
+            "foo-1.0-py3-none-any.whl",
+            Some("0".repeat(64).as_str())
+        )
+        .is_err());
+
+        let mut lock = LockFile::default();
+        lock.add_artifact(LockedArtifact {
+            name: "foo".to_string(),
+            version: "1.0".to_string(),
+            filename: "foo-1.0-py3-none-any.whl".to_string(),
+            sha256: sha256.clone(),
+            origin,
+        });
+        let location = PythonDistributionLocation::Url {
+            url: "https://example.com/cpython.tar.zst".to_string(),
+            sha256: "0".repeat(64),
+        };
+        lock.add_python_distribution(&location);
+        lock.add_python_distribution(&location);
+        assert_eq!(lock.python_distributions.len(), 1);
+
+        let lock_path = temp_dir.path().join(LOCK_FILE_NAME);
+        lock.write_path(&lock_path)?;
+        let lock = LockFile::from_path(&lock_path)?;
+        assert_eq!(lock.artifacts.len(), 1);
+
+        let mode = LockMode::Locked(Arc::new(lock.clone()));
+        mode.use_python_distribution(&location)?;
+        assert!(mode
+            .use_python_distribution(&PythonDistributionLocation::Url {
+                url: "https://example.com/other.tar.zst".to_string(),
+                sha256: "1".repeat(64),
+            })
+            .is_err());
+
+        let links_dir = temp_dir.path().join("links");
+        lock.materialize_artifacts(&cache_dir, &links_dir)?;
+        assert_eq!(
+            std::fs::read(links_dir.join("foo-1.0-py3-none-any.whl"))?,
+            b"wheel content"
+        );
+
+        std::fs::write(
+            artifact_cache_path(&cache_dir, &sha256, "foo-1.0-py3-none-any.whl"),
+            b"tampered",
+        )?;
+        assert!(lock.materialize_artifacts(&cache_dir, &links_dir).is_err());
+
+        Ok(())
+    }
+}
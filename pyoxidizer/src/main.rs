@@ -31,6 +31,7 @@ mod cli;
 mod default_python_distributions;
 mod environment;
 mod licensing;
+mod lockfile;
 mod project_building;
 mod project_layout;
 mod projectmgmt;
@@ -0,0 +1,83 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+"""Invoke PEP 517 build backend hooks.
+
+Usage:
+
+    python -S hooks.py unpack ARCHIVE DEST_DIR
+    python -S hooks.py HOOK SOURCE_DIR BACKEND OUTPUT_DIR RESULT_PATH [BACKEND_PATH...]
+
+The interpreter should be started without site-packages. The build environment
+named by the PYOXIDIZER_PEP517_BUILD_ENV environment variable is added as a
+site directory so .pth files in it are processed.
+
+The JSON encoded return value of the hook is written to RESULT_PATH.
+"""
+
+import importlib
+import json
+import os
+import site
+import sys
+import tarfile
+import zipfile
+
+
+def unpack(archive, dest_dir):
+    if zipfile.is_zipfile(archive):
+        with zipfile.ZipFile(archive) as zf:
+            zf.extractall(dest_dir)
+    else:
+        with tarfile.open(archive) as tf:
+            if hasattr(tarfile, "data_filter"):
+                tf.extractall(dest_dir, filter="data")
+            else:
+                tf.extractall(dest_dir)
+
+
+def load_backend(spec, backend_path):
+    # Per PEP 517, backend-path entries take precedence over everything else.
+    sys.path[0:0] = backend_path
+
+    module_name, _, attrs = spec.partition(":")
+    backend = importlib.import_module(module_name.strip())
+
+    if attrs:
+        for attr in attrs.strip().split("."):
+            backend = getattr(backend, attr)
+
+    return backend
+
+
+def main(args):
+    if args[0] == "unpack":
+        unpack(args[1], args[2])
+        return
+
+    hook, source_dir, spec, output_dir, result_path = args[0:5]
+    backend_path = [os.path.join(source_dir, p) for p in args[5:]]
+
+    build_env = os.environ.get("PYOXIDIZER_PEP517_BUILD_ENV")
+    if build_env:
+        site.addsitedir(build_env)
+
+    os.chdir(source_dir)
+    backend = load_backend(spec, backend_path)
+
+    if hook == "get_requires_for_build_wheel":
+        # This hook is optional.
+        f = getattr(backend, hook, None)
+        result = f() if f else []
+    elif hook == "build_wheel":
+        result = backend.build_wheel(output_dir)
+    else:
+        raise Exception("unsupported hook: %s" % hook)
+
+    with open(result_path, "w", encoding="utf-8") as fh:
+        json.dump(result, fh)
+
+
+if __name__ == "__main__":
+    main(sys.argv[1:])
@@ -8,6 +8,7 @@ use {
     crate::{
         environment::{canonicalize_path, default_target_triple, Environment, PyOxidizerSource},
         licensing::{licenses_from_cargo_manifest, log_licensing_info},
+        lockfile::{LockFile, LockMode, LOCK_FILE_NAME},
         project_building::find_pyoxidizer_config_file_env,
         project_layout::{initialize_project, write_new_pyoxidizer_config_file},
         py_packaging::{
@@ -16,6 +17,11 @@ use {
                 resolve_python_distribution_archive, BinaryLibpythonLinkMode, DistributionCache,
                 DistributionFlavor, PythonDistribution,
             },
+            filtering::{read_resource_access_reports, write_resource_names_file},
+            packed_resources::{
+                convert_packed_resources, diff_packed_resources, extract_resource,
+                find_packed_resources, parse_packed_resources, summarize_resource,
+            },
             standalone_distribution::StandaloneDistribution,
         },
         python_distributions::PYTHON_DISTRIBUTIONS,
@@ -35,6 +41,7 @@ use {
         fs::create_dir_all,
         io::{Cursor, Read},
         path::{Path, PathBuf},
+        sync::{Arc, Mutex},
     },
 };
 
@@ -101,6 +108,37 @@ pub fn list_targets(env: &Environment, project_path: &Path) -> Result<()> {
     Ok(())
 }
 
+/// Path of the lock file for a configuration file.
+fn lock_file_path(config_path: &Path) -> PathBuf {
+    config_path
+        .parent()
+        .unwrap_or_else(|| Path::new("."))
+        .join(LOCK_FILE_NAME)
+}
+
+/// Resolve the environment to evaluate a configuration file with.
+///
+/// If `locked` is true, builds may only use artifacts recorded in the
+/// configuration file's lock file.
+fn project_environment(env: &Environment, config_path: &Path, locked: bool) -> Result<Environment> {
+    let mut env = env.clone();
+
+    if locked {
+        let lock_path = lock_file_path(config_path);
+
+        if !lock_path.exists() {
+            return Err(anyhow!(
+                "no lock file at {}; run `pyoxidizer lock` to create one",
+                lock_path.display()
+            ));
+        }
+
+        env.set_lock_mode(LockMode::Locked(Arc::new(LockFile::from_path(&lock_path)?)));
+    }
+
+    Ok(env)
+}
+
 /// Build a PyOxidizer enabled project.
 ///
 /// This is a glorified wrapper around `cargo build`. Our goal is to get the
@@ -114,6 +152,7 @@ pub fn build(
     extra_vars: HashMap<String, Option<String>>,
     release: bool,
     verbose: bool,
+    locked: bool,
 ) -> Result<()> {
     let config_path = find_pyoxidizer_config_file_env(project_path).ok_or_else(|| {
         anyhow!(
@@ -122,8 +161,9 @@ pub fn build(
         )
     })?;
     let target_triple = resolve_target(target_triple)?;
+    let env = project_environment(env, &config_path, locked)?;
 
-    let mut context = EvaluationContextBuilder::new(env, config_path.clone(), target_triple)
+    let mut context = EvaluationContextBuilder::new(&env, config_path.clone(), target_triple)
         .extra_vars(extra_vars)
         .release(release)
         .verbose(verbose)
@@ -139,6 +179,53 @@ pub fn build(
     Ok(())
 }
 
+/// Build a PyOxidizer enabled project and write a lock file.
+///
+/// The lock file records the Python distributions and package archives
+/// consumed by the build. Package archives are also stored in PyOxidizer's
+/// cache so subsequent locked builds can run offline.
+pub fn lock(
+    env: &Environment,
+    project_path: &Path,
+    target_triple: Option<&str>,
+    resolve_targets: Option<Vec<String>>,
+    extra_vars: HashMap<String, Option<String>>,
+    verbose: bool,
+) -> Result<()> {
+    let config_path = find_pyoxidizer_config_file_env(project_path).ok_or_else(|| {
+        anyhow!(
+            "unable to find PyOxidizer config file at {}",
+            project_path.display()
+        )
+    })?;
+
+    let lock = Arc::new(Mutex::new(LockFile::default()));
+
+    let mut env = env.clone();
+    env.set_lock_mode(LockMode::Record(lock.clone()));
+
+    build(
+        &env,
+        project_path,
+        target_triple,
+        resolve_targets,
+        extra_vars,
+        false,
+        verbose,
+        false,
+    )?;
+
+    let lock_path = lock_file_path(&config_path);
+
+    lock.lock()
+        .map_err(|e| anyhow!("unable to lock lock file: {}", e))?
+        .write_path(&lock_path)?;
+
+    println!("wrote {}", lock_path.display());
+
+    Ok(())
+}
+
 #[allow(clippy::too_many_arguments)]
 pub fn run(
     env: &Environment,
@@ -149,6 +236,7 @@ pub fn run(
     extra_vars: HashMap<String, Option<String>>,
     _extra_args: &[&str],
     verbose: bool,
+    locked: bool,
 ) -> Result<()> {
     let config_path = find_pyoxidizer_config_file_env(project_path).ok_or_else(|| {
         anyhow!(
@@ -157,8 +245,9 @@ pub fn run(
         )
     })?;
     let target_triple = resolve_target(target_triple)?;
+    let env = project_environment(env, &config_path, locked)?;
 
-    let mut context = EvaluationContextBuilder::new(env, config_path.clone(), target_triple)
+    let mut context = EvaluationContextBuilder::new(&env, config_path.clone(), target_triple)
         .extra_vars(extra_vars)
         .release(release)
         .verbose(verbose)
@@ -179,6 +268,30 @@ pub fn cache_clear(env: &Environment) -> Result<()> {
     Ok(())
 }
 
+/// Convert between packed resources data and a JSON or YAML description of it.
+pub fn convert_resources(input: &Path, output: &Path) -> Result<()> {
+    convert_packed_resources(input, output)?;
+
+    println!("wrote {}", output.display());
+
+    Ok(())
+}
+
+/// Write a resources filter file from resource access reports.
+pub fn generate_resources_filter(reports: &[&Path], output: &Path) -> Result<()> {
+    let names = read_resource_access_reports(reports)?;
+
+    write_resource_names_file(output, &names)?;
+
+    println!(
+        "wrote {} resource names to {}",
+        names.len(),
+        output.display()
+    );
+
+    Ok(())
+}
+
 /// Find resources given a source path.
 pub fn find_resources(
     env: &Environment,
@@ -377,6 +490,91 @@ for configuration changes to take effect.
     Ok(())
 }
 
+/// Inspect the content of packed resources data.
+///
+/// `path` can be a file holding packed resources data or a binary having
+/// packed resources data embedded within it.
+pub fn inspect_resources(
+    path: &Path,
+    diff_path: Option<&Path>,
+    extract: Option<(&str, &Path)>,
+    json: bool,
+) -> Result<()> {
+    let data = std::fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+    let resources = parse_packed_resources(
+        find_packed_resources(&data).with_context(|| format!("inspecting {}", path.display()))?,
+    )?;
+
+    if let Some((name, dest_dir)) = extract {
+        let resource = resources
+            .iter()
+            .find(|r| r.name == name)
+            .ok_or_else(|| anyhow!("resource {} not found in {}", name, path.display()))?;
+
+        for written in extract_resource(resource, dest_dir)? {
+            println!("wrote {}", written.display());
+        }
+
+        return Ok(());
+    }
+
+    if let Some(diff_path) = diff_path {
+        let other_data =
+            std::fs::read(diff_path).with_context(|| format!("reading {}", diff_path.display()))?;
+        let other = parse_packed_resources(
+            find_packed_resources(&other_data)
+                .with_context(|| format!("inspecting {}", diff_path.display()))?,
+        )?;
+
+        let diff = diff_packed_resources(&resources, &other);
+
+        if json {
+            println!("{}", serde_json::to_string_pretty(&diff)?);
+        } else if diff.is_empty() {
+            println!("no differences");
+        } else {
+            for name in &diff.added {
+                println!("+ {}", name);
+            }
+            for name in &diff.removed {
+                println!("- {}", name);
+            }
+            for change in &diff.changed {
+                println!("~ {} ({})", change.name, change.fields.join(", "));
+            }
+        }
+
+        return Ok(());
+    }
+
+    let summaries = resources.iter().map(summarize_resource).collect::<Vec<_>>();
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&summaries)?);
+    } else {
+        for summary in summaries {
+            println!("{}", summary.name);
+            if !summary.flags.is_empty() {
+                println!("    flags: {}", summary.flags.join(", "));
+            }
+            for (field, value) in &summary.data {
+                println!("    {}: {} bytes", field, value.size);
+            }
+            for (field, path) in &summary.relative_paths {
+                println!("    {}: {}", field, path);
+            }
+            if !summary.shared_library_dependency_names.is_empty() {
+                println!(
+                    "    shared_library_dependency_names: {}",
+                    summary.shared_library_dependency_names.join(", ")
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
 pub fn python_distribution_extract(
     download_default: bool,
     archive_path: Option<&str>,
@@ -13,6 +13,7 @@ use {
     },
     anyhow::Result,
     python_packaging::{
+        import_graph::TreeShakeReport,
         licensing::{LicensedComponent, LicensedComponents},
         policy::PythonPackagingPolicy,
         resource::{
@@ -282,9 +283,33 @@ pub trait PythonBinaryBuilder {
         packages: &[String],
     ) -> Result<Vec<PythonResource>>;
 
+    /// Install a `pyproject.toml` project and its locked dependencies.
+    ///
+    /// `path` is the directory holding `pyproject.toml` and a lock file.
+    ///
+    /// Returns resources discovered as part of performing an install.
+    fn read_pyproject(
+        &mut self,
+        env: &Environment,
+        verbose: bool,
+        path: &Path,
+        extras: &[String],
+        groups: &[String],
+    ) -> Result<Vec<PythonResource>>;
+
     /// Read Python resources from a populated virtualenv directory.
     fn read_virtualenv(&mut self, path: &Path) -> Result<Vec<PythonResource>>;
 
+    /// Install pinned requirements from wheels in a local wheelhouse directory.
+    ///
+    /// Wheels are selected for compatibility with the target distribution.
+    /// `pip` is not used.
+    fn wheelhouse_install(
+        &mut self,
+        wheelhouse: &Path,
+        requirements: &[String],
+    ) -> Result<Vec<PythonResource>>;
+
     /// Runs `python setup.py install` using the binary builder's settings.
     ///
     /// Returns resources discovered as part of performing an install.
@@ -297,6 +322,17 @@ pub trait PythonBinaryBuilder {
         extra_global_arguments: &[String],
     ) -> Result<Vec<PythonResource>>;
 
+    /// Build a wheel from a source tree or sdist via PEP 517.
+    ///
+    /// Returns resources from the built wheel.
+    fn pep517_build(
+        &mut self,
+        env: &Environment,
+        source_path: &Path,
+        verbose: bool,
+        extra_envs: &HashMap<String, String>,
+    ) -> Result<Vec<PythonResource>>;
+
     /// Add resources from the Python distribution to the builder.
     ///
     /// This method should likely be called soon after object construction
@@ -384,6 +420,16 @@ pub trait PythonBinaryBuilder {
         glob_patterns: &[&str],
     ) -> Result<()>;
 
+    /// Filter embedded resources to Python modules reachable from entry points.
+    ///
+    /// Entry points are derived from the `run_module` and `run_command` of the
+    /// interpreter configuration. Modules are reachable if they are imported,
+    /// directly or indirectly, by an entry point.
+    ///
+    /// `keep` is names of modules to always keep, along with their submodules.
+    /// Use it for modules that are imported dynamically.
+    fn filter_resources_from_import_graph(&mut self, keep: &[&str]) -> Result<TreeShakeReport>;
+
     /// Whether the binary requires the jemalloc library.
     fn requires_jemalloc(&self) -> bool;
 
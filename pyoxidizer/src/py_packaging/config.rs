@@ -127,6 +127,7 @@ pub struct PyembedPythonInterpreterConfig {
     pub oxidized_importer: bool,
     pub filesystem_importer: bool,
     pub packed_resources: Vec<PyembedPackedResourcesSource>,
+    pub startup_hooks: Vec<String>,
     pub argvb: bool,
     pub multiprocessing_auto_dispatch: bool,
     pub multiprocessing_start_method: MultiprocessingStartMethod,
@@ -162,6 +163,7 @@ impl Default for PyembedPythonInterpreterConfig {
             oxidized_importer: true,
             filesystem_importer: false,
             packed_resources: vec![],
+            startup_hooks: vec![],
             argvb: false,
             multiprocessing_auto_dispatch: true,
             multiprocessing_start_method: MultiprocessingStartMethod::Auto,
@@ -251,6 +253,16 @@ impl PyembedPythonInterpreterConfig {
             oxidized_importer: {},\n    \
             filesystem_importer: {},\n    \
             packed_resources: {},\n    \
+            packed_resources_layers: vec![],\n    \
+            packed_resources_strict_layers: false,\n    \
+            packed_resources_public_key: None,\n    \
+            packed_resources_key_provider: None,\n    \
+            packed_resources_lazy_index: false,\n    \
+            packed_resources_hot_reload: false,\n    \
+            zip_archives: vec![],\n    \
+            wheel_extension_cache_dir: None,\n    \
+            resources_cache_dir: None,\n    \
+            startup_hooks: {},\n    \
             extra_extension_modules: None,\n    \
             argv: None,\n    \
             argvb: {},\n    \
@@ -389,6 +401,13 @@ impl PyembedPythonInterpreterConfig {
                     .map(|e| e.to_string())
                     .join(", ")
             ),
+            format!(
+                "vec![{}]",
+                self.startup_hooks
+                    .iter()
+                    .map(|x| format!("\"{}\".to_string()", x.escape_default()))
+                    .join(", ")
+            ),
             self.argvb,
             self.multiprocessing_auto_dispatch,
             match self.multiprocessing_start_method {
@@ -597,6 +616,7 @@ mod tests {
                     "$ORIGIN/packed-resources",
                 )),
             ],
+            startup_hooks: vec!["import os".into()],
             argvb: true,
             sys_frozen: false,
             sys_meipass: true,
@@ -12,7 +12,7 @@ use {
     std::{
         collections::{BTreeMap, BTreeSet},
         fs::File,
-        io::{BufRead, BufReader},
+        io::{BufRead, BufReader, Write},
         path::Path,
     },
 };
@@ -65,6 +65,56 @@ pub fn resolve_resource_names_from_files(
     Ok(include_names)
 }
 
+/// Resolve the names of resources accessed according to resource access reports.
+///
+/// Reports are the `resources-*.json` files written by an embedded interpreter
+/// when `write_modules_directory_env` is active. The names of parent packages
+/// of accessed resources are included, as a module can't be imported without
+/// its parent packages.
+pub fn read_resource_access_reports(paths: &[&Path]) -> Result<BTreeSet<String>> {
+    let mut names = BTreeSet::new();
+
+    for path in paths {
+        let report: serde_json::Value = serde_json::from_reader(BufReader::new(
+            File::open(path).map_err(|e| anyhow!("error opening {}: {}", path.display(), e))?,
+        ))
+        .map_err(|e| anyhow!("error parsing {}: {}", path.display(), e))?;
+
+        let accessed = report
+            .get("accessed")
+            .and_then(|accessed| accessed.as_object())
+            .ok_or_else(|| {
+                anyhow!(
+                    "{} is not a resource access report: missing accessed object",
+                    path.display()
+                )
+            })?;
+
+        for name in accessed.keys() {
+            let mut parts = name.split('.').collect::<Vec<_>>();
+
+            while !parts.is_empty() {
+                names.insert(parts.join("."));
+                parts.pop();
+            }
+        }
+    }
+
+    Ok(names)
+}
+
+/// Write a file of resource names readable by [read_resource_names_file()].
+pub fn write_resource_names_file(path: &Path, names: &BTreeSet<String>) -> Result<()> {
+    let mut fh = File::create(path)?;
+
+    fh.write_all(b"# Resource names generated from resource access reports.\n")?;
+    for name in names {
+        fh.write_all(format!("{}\n", name).as_bytes())?;
+    }
+
+    Ok(())
+}
+
 pub fn filter_btreemap<V>(m: &mut BTreeMap<String, V>, f: &BTreeSet<String>) {
     let keys: Vec<String> = m.keys().cloned().collect();
 
@@ -75,3 +125,47 @@ pub fn filter_btreemap<V>(m: &mut BTreeMap<String, V>, f: &BTreeSet<String>) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resource_access_reports_round_trip() -> Result<()> {
+        let td = tempfile::Builder::new()
+            .prefix("pyoxidizer-test")
+            .tempdir()?;
+
+        let report_path = td.path().join("resources-1.json");
+        std::fs::write(
+            &report_path,
+            r#"{"accessed": {"foo.bar.baz": {"module": 1}, "json": {"module": 2}}, "unused": ["other"]}"#,
+        )?;
+
+        let names = read_resource_access_reports(&[&report_path])?;
+        assert_eq!(
+            names.iter().map(|x| x.as_str()).collect::<Vec<_>>(),
+            vec!["foo", "foo.bar", "foo.bar.baz", "json"]
+        );
+
+        let names_path = td.path().join("names");
+        write_resource_names_file(&names_path, &names)?;
+        assert_eq!(read_resource_names_file(&names_path)?, names);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_resource_access_report_invalid() -> Result<()> {
+        let td = tempfile::Builder::new()
+            .prefix("pyoxidizer-test")
+            .tempdir()?;
+
+        let report_path = td.path().join("modules");
+        std::fs::write(&report_path, "[]")?;
+
+        assert!(read_resource_access_reports(&[&report_path]).is_err());
+
+        Ok(())
+    }
+}
@@ -16,6 +16,8 @@ pub mod embedding;
 pub mod filtering;
 pub mod libpython;
 pub mod packaging_tool;
+pub mod packed_resources;
+pub mod pyproject;
 pub mod resource;
 pub mod standalone_builder;
 pub mod standalone_distribution;
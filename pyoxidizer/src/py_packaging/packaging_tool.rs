@@ -8,25 +8,42 @@ Interaction with Python packaging tools (pip, setuptools, etc).
 
 use {
     super::{
-        binary::LibpythonLinkMode, distribution::PythonDistribution,
-        distutils::read_built_extensions, standalone_distribution::resolve_python_paths,
+        binary::LibpythonLinkMode,
+        distribution::PythonDistribution,
+        distutils::read_built_extensions,
+        pyproject::{lock_constraints, read_project_lock, PyProject},
+        standalone_distribution::resolve_python_paths,
+    },
+    crate::{
+        environment::Environment,
+        lockfile::{artifact_cache_path, fetch_artifact, LockMode, LockedArtifact},
     },
-    crate::environment::Environment,
     anyhow::{anyhow, Context, Result},
     duct::{cmd, ReaderHandle},
     log::warn,
     python_packaging::{
-        filesystem_scanning::find_python_resources, policy::PythonPackagingPolicy,
-        resource::PythonResource, wheel::WheelArchive,
+        filesystem_scanning::find_python_resources,
+        policy::PythonPackagingPolicy,
+        pyproject::BuildSystem,
+        resource::PythonResource,
+        sdist::SdistArchive,
+        wheel::WheelArchive,
+        wheelhouse::{canonicalize_name, PinnedRequirement, WheelCompatibility, Wheelhouse},
     },
+    serde::Deserialize,
+    simple_file_manifest::FileData,
     std::{
-        collections::{hash_map::RandomState, HashMap},
+        collections::{hash_map::RandomState, BTreeSet, HashMap, HashSet},
         hash::BuildHasher,
         io::{BufRead, BufReader},
         path::{Path, PathBuf},
     },
+    url::Url,
 };
 
+/// Script invoking PEP 517 build backend hooks.
+const PEP517_HOOKS_PY: &str = include_str!("../pep517/hooks.py");
+
 fn log_command_output(handle: &ReaderHandle) {
     let reader = BufReader::new(handle);
     for line in reader.lines() {
@@ -42,6 +59,10 @@ fn log_command_output(handle: &ReaderHandle) {
 }
 
 /// Find resources installed as part of a packaging operation.
+///
+/// Zipped .egg files are flattened into the resources they contain. Directories
+/// outside `path` that are added to `sys.path` by .pth files in `path` are also
+/// scanned for resources.
 pub fn find_resources<'a>(
     dist: &dyn PythonDistribution,
     policy: &PythonPackagingPolicy,
@@ -59,16 +80,33 @@ pub fn find_resources<'a>(
         HashMap::new()
     };
 
+    let suffixes = dist.python_module_suffixes()?;
+    let mut extra_paths = vec![];
+
     for r in find_python_resources(
         path,
         dist.cache_tag(),
-        &dist.python_module_suffixes()?,
+        &suffixes,
         policy.file_scanner_emit_files(),
         policy.file_scanner_classify_files(),
     )? {
-        let r = r?.to_memory()?;
+        let r = r?;
+
+        // .pth files are only evaluated in site directories. So we only follow
+        // the ones at the root of the scanned path.
+        if let PythonResource::PathExtension(extension) = &r {
+            if let FileData::Path(pth_path) = &extension.data {
+                if pth_path.parent() == Some(path) {
+                    for p in extension.resolve_directories(path)? {
+                        if !p.starts_with(path) && !extra_paths.contains(&p) {
+                            extra_paths.push(p);
+                        }
+                    }
+                }
+            }
+        }
 
-        match r {
+        match r.to_memory()? {
             PythonResource::ExtensionModule(e) => {
                 // Use a built extension if present, as it will contain more metadata.
                 res.push(if let Some(built) = built_extensions.get(&e.name) {
@@ -77,15 +115,274 @@ pub fn find_resources<'a>(
                     PythonResource::ExtensionModule(e)
                 });
             }
-            _ => {
+            PythonResource::EggFile(egg) => {
+                res.extend(
+                    egg.python_resources(
+                        dist.cache_tag(),
+                        &suffixes,
+                        policy.file_scanner_emit_files(),
+                        policy.file_scanner_classify_files(),
+                    )
+                    .context("reading egg file")?,
+                );
+            }
+            r => {
                 res.push(r);
             }
         }
     }
 
+    for p in extra_paths {
+        warn!("scanning {} (added to sys.path by .pth file)", p.display());
+
+        for r in find_python_resources(
+            &p,
+            dist.cache_tag(),
+            &suffixes,
+            policy.file_scanner_emit_files(),
+            policy.file_scanner_classify_files(),
+        )? {
+            res.push(r?.to_memory()?);
+        }
+    }
+
+    Ok(res)
+}
+
+/// Subset of the JSON document written by `pip install --report`.
+#[derive(Deserialize)]
+struct PipReport {
+    install: Vec<PipReportInstall>,
+}
+
+#[derive(Deserialize)]
+struct PipReportInstall {
+    download_info: PipReportDownloadInfo,
+    metadata: PipReportMetadata,
+}
+
+#[derive(Deserialize)]
+struct PipReportDownloadInfo {
+    url: String,
+    archive_info: Option<PipReportArchiveInfo>,
+}
+
+#[derive(Deserialize)]
+struct PipReportArchiveInfo {
+    #[serde(default)]
+    hashes: HashMap<String, String>,
+    hash: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct PipReportMetadata {
+    name: String,
+    version: String,
+}
+
+/// Resolve the archives a `pip install` would use and add them to the artifact cache.
+///
+/// pip is run with `--dry-run` and `--report`, so nothing is installed.
+fn pip_report_artifacts(
+    env: &Environment,
+    python_exe: &Path,
+    envs: &HashMap<String, String, RandomState>,
+    install_args: &[String],
+    lock_dir: &Path,
+) -> Result<Vec<LockedArtifact>> {
+    std::fs::create_dir_all(lock_dir)?;
+    let report_path = lock_dir.join("report.json");
+
+    let mut pip_args = vec![
+        "-m".to_string(),
+        "pip".to_string(),
+        "--disable-pip-version-check".to_string(),
+        "install".to_string(),
+        "--dry-run".to_string(),
+        "--ignore-installed".to_string(),
+        "--report".to_string(),
+        format!("{}", report_path.display()),
+    ];
+    pip_args.extend(install_args.iter().cloned());
+
+    warn!("resolving artifacts to lock with python {:?}", pip_args);
+
+    let command = cmd(python_exe, &pip_args)
+        .full_env(envs)
+        .stderr_to_stdout()
+        .unchecked()
+        .reader()?;
+
+    log_command_output(&command);
+
+    let output = command
+        .try_wait()?
+        .ok_or_else(|| anyhow!("unable to wait on command"))?;
+    if !output.status.success() {
+        return Err(anyhow!("error running pip"));
+    }
+
+    let report: PipReport = serde_json::from_slice(&std::fs::read(&report_path)?)
+        .context("parsing pip installation report")?;
+
+    let mut res = vec![];
+
+    for install in report.install {
+        let url = install.download_info.url;
+
+        let archive = if let Some(archive) = install.download_info.archive_info {
+            archive
+        } else {
+            warn!(
+                "{} from {} is not an archive and cannot be locked",
+                install.metadata.name, url
+            );
+            continue;
+        };
+
+        let filename = Url::parse(&url)?
+            .path_segments()
+            .and_then(|segments| segments.last())
+            .filter(|s| !s.is_empty())
+            .ok_or_else(|| anyhow!("unable to resolve filename from {}", url))?
+            .to_string();
+
+        let expected_sha256 = archive.hashes.get("sha256").cloned().or_else(|| {
+            archive
+                .hash
+                .as_deref()
+                .and_then(|h| h.strip_prefix("sha256="))
+                .map(|h| h.to_string())
+        });
+
+        let sha256 = fetch_artifact(
+            &env.python_artifacts_dir(),
+            &url,
+            &filename,
+            expected_sha256.as_deref(),
+        )
+        .with_context(|| format!("fetching {}", url))?;
+
+        res.push(LockedArtifact {
+            name: install.metadata.name,
+            version: install.metadata.version,
+            filename,
+            sha256,
+            origin: url,
+        });
+    }
+
     Ok(res)
 }
 
+/// Resolve archives like [pip_report_artifacts], including build requirements of sdists.
+///
+/// pip builds sdists in an isolated environment having their build
+/// requirements installed. When locked, these can only come from the lock
+/// file. So the archives build requirements resolve to are resolved as well,
+/// recursively.
+fn pip_report_artifacts_with_build_requires(
+    env: &Environment,
+    python_exe: &Path,
+    envs: &HashMap<String, String, RandomState>,
+    install_args: &[String],
+    lock_dir: &Path,
+) -> Result<Vec<LockedArtifact>> {
+    let mut res = vec![];
+    let mut seen = HashSet::new();
+    let mut pending = pip_report_artifacts(env, python_exe, envs, install_args, lock_dir)?;
+    let mut round = 0;
+
+    loop {
+        let mut build_requires = BTreeSet::new();
+
+        for artifact in pending {
+            if !seen.insert(artifact.filename.clone()) {
+                continue;
+            }
+
+            if !artifact.filename.ends_with(".whl") {
+                let path = artifact_cache_path(
+                    &env.python_artifacts_dir(),
+                    &artifact.sha256,
+                    &artifact.filename,
+                );
+                let sdist = SdistArchive::from_path(&path)
+                    .with_context(|| format!("reading sdist {}", artifact.filename))?;
+                build_requires.extend(sdist.build_requires().with_context(|| {
+                    format!("resolving build requirements of {}", artifact.filename)
+                })?);
+            }
+
+            res.push(artifact);
+        }
+
+        if build_requires.is_empty() {
+            return Ok(res);
+        }
+
+        round += 1;
+        pending = pip_report_artifacts(
+            env,
+            python_exe,
+            envs,
+            &build_requires.into_iter().collect::<Vec<_>>(),
+            &lock_dir.join(format!("build-requires-{}", round)),
+        )
+        .context("resolving build requirements of sdists")?;
+    }
+}
+
+/// Resolve extra pip arguments enforcing the environment's lock mode.
+///
+/// `install_args` are `pip install` arguments resolving the same packages as
+/// the pip invocation being locked. When recording a lock file, the archives
+/// they resolve to and the archives needed to build sdists among them are
+/// added to it. Archives in the lock file are then copied
+/// into `lock_dir` and the returned arguments make pip use only these, without
+/// accessing a package index.
+fn pip_lock_args(
+    env: &Environment,
+    python_exe: &Path,
+    envs: &HashMap<String, String, RandomState>,
+    install_args: &[String],
+    lock_dir: &Path,
+) -> Result<Vec<String>> {
+    let lock = match env.lock_mode() {
+        LockMode::Unlocked => return Ok(vec![]),
+        LockMode::Record(lock) => {
+            let artifacts = pip_report_artifacts_with_build_requires(
+                env,
+                python_exe,
+                envs,
+                install_args,
+                lock_dir,
+            )?;
+
+            let mut lock = lock
+                .lock()
+                .map_err(|e| anyhow!("unable to lock lock file: {}", e))?;
+            for artifact in artifacts {
+                lock.add_artifact(artifact);
+            }
+
+            lock.clone()
+        }
+        LockMode::Locked(lock) => lock.as_ref().clone(),
+    };
+
+    let links_dir = lock_dir.join("artifacts");
+    lock.materialize_artifacts(&env.python_artifacts_dir(), &links_dir)?;
+
+    Ok(vec![
+        // Ignore pip configuration, which could define other package sources.
+        "--isolated".to_string(),
+        "--no-index".to_string(),
+        "--find-links".to_string(),
+        format!("{}", links_dir.display()),
+    ])
+}
+
 /// Run `pip download` and collect resources found from downloaded packages.
 ///
 /// `host_dist` is the Python distribution to use to run `pip`.
@@ -109,7 +406,44 @@ pub fn pip_download<'a>(
 
     host_dist.ensure_pip()?;
 
-    let target_dir = temp_dir.path();
+    let target_dir = temp_dir.path().join("download");
+    std::fs::create_dir_all(&target_dir)?;
+
+    // Only download wheels compatible with the distribution we're targeting.
+    let mut target_args = vec![
+        "--only-binary=:all:".to_string(),
+        format!(
+            "--platform={}",
+            taget_dist.python_platform_compatibility_tag()
+        ),
+        format!("--python-version={}", taget_dist.python_version()),
+        format!(
+            "--implementation={}",
+            taget_dist.python_implementation_short()
+        ),
+    ];
+
+    if let Some(abi) = taget_dist.python_abi_tag() {
+        target_args.push(format!("--abi={}", abi));
+    }
+
+    let lock_dir = temp_dir.path().join("lock");
+
+    let mut report_args = target_args.clone();
+    report_args.extend([
+        "--target".to_string(),
+        format!("{}", lock_dir.join("target").display()),
+    ]);
+    report_args.extend(args.iter().cloned());
+
+    let lock_args = pip_lock_args(
+        env,
+        host_dist.python_exe_path(),
+        &std::env::vars().collect(),
+        &report_args,
+        &lock_dir,
+    )
+    .context("resolving locked artifacts")?;
 
     warn!("pip downloading to {}", target_dir.display());
 
@@ -128,24 +462,10 @@ pub fn pip_download<'a>(
         // Download packages to our temporary directory.
         "--dest".to_string(),
         format!("{}", target_dir.display()),
-        // Only download wheels.
-        "--only-binary=:all:".to_string(),
-        // We download files compatible with the distribution we're targeting.
-        format!(
-            "--platform={}",
-            taget_dist.python_platform_compatibility_tag()
-        ),
-        format!("--python-version={}", taget_dist.python_version()),
-        format!(
-            "--implementation={}",
-            taget_dist.python_implementation_short()
-        ),
     ]);
 
-    if let Some(abi) = taget_dist.python_abi_tag() {
-        pip_args.push(format!("--abi={}", abi));
-    }
-
+    pip_args.extend(target_args);
+    pip_args.extend(lock_args);
     pip_args.extend(args.iter().cloned());
 
     warn!("running python {:?}", pip_args);
@@ -168,7 +488,7 @@ pub fn pip_download<'a>(
     // in the destination directory. Iterate over them and collect resources
     // from each.
 
-    let mut files = std::fs::read_dir(target_dir)?
+    let mut files = std::fs::read_dir(&target_dir)?
         .map(|entry| Ok(entry?.path()))
         .collect::<Result<Vec<_>>>()?;
     files.sort();
@@ -192,6 +512,82 @@ pub fn pip_download<'a>(
     Ok(res)
 }
 
+/// Collect resources from wheels in a local wheelhouse directory.
+///
+/// Each entry in `requirements` must be pinned to an exact version (e.g.
+/// `foo==1.0`). For each, the most preferred wheel compatible with the
+/// tags of `dist` is selected from `wheelhouse` and its resources are
+/// collected. Dependencies are not resolved: `requirements` must list the
+/// full set of distributions to install. `pip` is not used.
+pub fn wheelhouse_install<'a>(
+    dist: &dyn PythonDistribution,
+    policy: &PythonPackagingPolicy,
+    wheelhouse: &Path,
+    requirements: &[String],
+) -> Result<Vec<PythonResource<'a>>> {
+    let requirements = requirements
+        .iter()
+        .map(|s| PinnedRequirement::parse(s))
+        .collect::<Result<Vec<_>>>()?;
+
+    let compatibility = WheelCompatibility::new(
+        dist.python_tag(),
+        dist.python_abi_tag(),
+        dist.python_platform_compatibility_tag(),
+    )?;
+
+    let wheelhouse = Wheelhouse::from_directory(wheelhouse)?;
+
+    let pinned = requirements
+        .iter()
+        .map(|r| canonicalize_name(&r.name))
+        .collect::<HashSet<_>>();
+
+    let mut res = Vec::new();
+
+    for requirement in &requirements {
+        let path = wheelhouse.find_wheel(requirement, &compatibility)?;
+
+        warn!("installing {} from {}", requirement, path.display());
+
+        let wheel = WheelArchive::from_path(path)?;
+
+        // Requirements without environment markers always apply. Missing ones
+        // are likely a mistake. But we don't evaluate markers, so we can't be
+        // sure and only warn.
+        for dependency in wheel.metadata()?.find_all_headers("Requires-Dist") {
+            if dependency.contains(';') {
+                continue;
+            }
+
+            let name = dependency
+                .split(|c: char| !(c.is_alphanumeric() || matches!(c, '-' | '_' | '.')))
+                .next()
+                .unwrap_or_default();
+
+            if !name.is_empty() && !pinned.contains(&canonicalize_name(name)) {
+                warn!(
+                    "{} requires {}, which is not in the requirements",
+                    requirement, dependency
+                );
+            }
+        }
+
+        res.extend(
+            wheel
+                .python_resources(
+                    dist.cache_tag(),
+                    &dist.python_module_suffixes()?,
+                    policy.file_scanner_emit_files(),
+                    policy.file_scanner_classify_files(),
+                )
+                .with_context(|| format!("reading {}", path.display()))?,
+        );
+    }
+
+    Ok(res)
+}
+
 /// Run `pip install` and return found resources.
 pub fn pip_install<'a, S: BuildHasher>(
     env: &Environment,
@@ -206,15 +602,24 @@ pub fn pip_install<'a, S: BuildHasher>(
 
     dist.ensure_pip()?;
 
-    let mut env: HashMap<String, String, RandomState> = std::env::vars().collect();
+    let mut envs: HashMap<String, String, RandomState> = std::env::vars().collect();
     for (k, v) in dist.resolve_distutils(libpython_link_mode, temp_dir.path(), &[])? {
-        env.insert(k, v);
+        envs.insert(k, v);
     }
 
     for (key, value) in extra_envs.iter() {
-        env.insert(key.clone(), value.clone());
+        envs.insert(key.clone(), value.clone());
     }
 
+    let lock_args = pip_lock_args(
+        env,
+        dist.python_exe_path(),
+        &envs,
+        install_args,
+        &temp_dir.path().join("lock"),
+    )
+    .context("resolving locked artifacts")?;
+
     let target_dir = temp_dir.path().join("install");
 
     warn!("pip installing to {}", target_dir.display());
@@ -235,10 +640,11 @@ pub fn pip_install<'a, S: BuildHasher>(
         format!("{}", target_dir.display()),
     ]);
 
+    pip_args.extend(lock_args);
     pip_args.extend(install_args.iter().cloned());
 
     let command = cmd(dist.python_exe_path(), &pip_args)
-        .full_env(&env)
+        .full_env(&envs)
         .stderr_to_stdout()
         .unchecked()
         .reader()?;
@@ -252,7 +658,9 @@ pub fn pip_install<'a, S: BuildHasher>(
         return Err(anyhow!("error running pip"));
     }
 
-    let state_dir = env.get("PYOXIDIZER_DISTUTILS_STATE_DIR").map(PathBuf::from);
+    let state_dir = envs
+        .get("PYOXIDIZER_DISTUTILS_STATE_DIR")
+        .map(PathBuf::from);
 
     let resources =
         find_resources(dist, policy, &target_dir, state_dir).context("scanning for resources")?;
@@ -262,6 +670,59 @@ pub fn pip_install<'a, S: BuildHasher>(
     Ok(resources)
 }
 
+/// Install a `pyproject.toml` project with versions pinned by its lock file.
+///
+/// `path` is the directory holding `pyproject.toml` and the lock file. The
+/// project (or only its dependencies if it can't be built) is installed with
+/// `pip install`, constrained to the versions in the lock file.
+#[allow(clippy::too_many_arguments)]
+pub fn pyproject_install<'a>(
+    env: &Environment,
+    dist: &dyn PythonDistribution,
+    policy: &PythonPackagingPolicy,
+    libpython_link_mode: LibpythonLinkMode,
+    verbose: bool,
+    path: &Path,
+    extras: &[String],
+    groups: &[String],
+) -> Result<Vec<PythonResource<'a>>> {
+    let project = PyProject::from_directory(path)?;
+    let (lock_path, packages) = read_project_lock(path)?;
+
+    warn!(
+        "installing {} with versions locked by {}",
+        project.name(),
+        lock_path.display()
+    );
+
+    let temp_dir = env.temporary_directory("pyoxidizer-pyproject")?;
+
+    let constraints_path = temp_dir.path().join("constraints.txt");
+    let mut constraints = lock_constraints(project.name(), &packages).join("\n");
+    constraints.push('\n');
+    std::fs::write(&constraints_path, constraints).context("writing constraints file")?;
+
+    let mut install_args = vec![
+        "--constraint".to_string(),
+        format!("{}", constraints_path.display()),
+    ];
+    install_args.extend(project.install_requirements(extras, groups)?);
+
+    let resources = pip_install(
+        env,
+        dist,
+        policy,
+        libpython_link_mode,
+        verbose,
+        &install_args,
+        &HashMap::<String, String>::new(),
+    )?;
+
+    temp_dir.close().context("closing temporary directory")?;
+
+    Ok(resources)
+}
+
 /// Discover Python resources from a populated virtualenv directory.
 pub fn read_virtualenv<'a>(
     dist: &dyn PythonDistribution,
@@ -363,6 +824,282 @@ pub fn setup_py_install<'a, S: BuildHasher>(
     Ok(resources)
 }
 
+/// Run the PEP 517 hooks script with site-packages disabled.
+fn run_pep517_hooks(
+    dist: &dyn PythonDistribution,
+    envs: &HashMap<String, String, RandomState>,
+    hooks_path: &Path,
+    args: &[String],
+) -> Result<()> {
+    let mut python_args = vec!["-S".to_string(), format!("{}", hooks_path.display())];
+    python_args.extend(args.iter().cloned());
+
+    let command = cmd(dist.python_exe_path(), &python_args)
+        .full_env(envs)
+        .stderr_to_stdout()
+        .unchecked()
+        .reader()?;
+
+    log_command_output(&command);
+
+    let output = command
+        .try_wait()?
+        .ok_or_else(|| anyhow!("unable to wait on command"))?;
+    if !output.status.success() {
+        return Err(anyhow!("error running PEP 517 {}", args[0]));
+    }
+
+    Ok(())
+}
+
+/// Install requirements of a PEP 517 build into a build environment directory.
+fn install_build_requirements(
+    env: &Environment,
+    dist: &dyn PythonDistribution,
+    envs: &HashMap<String, String, RandomState>,
+    verbose: bool,
+    build_env: &Path,
+    requirements: &[String],
+    lock_dir: &Path,
+) -> Result<()> {
+    if requirements.is_empty() {
+        return Ok(());
+    }
+
+    let lock_args = pip_lock_args(env, dist.python_exe_path(), envs, requirements, lock_dir)
+        .context("resolving locked build requirements")?;
+
+    warn!(
+        "installing build requirements {:?} to {}",
+        requirements,
+        build_env.display()
+    );
+
+    let mut pip_args: Vec<String> = vec![
+        "-m".to_string(),
+        "pip".to_string(),
+        "--disable-pip-version-check".to_string(),
+    ];
+
+    if verbose {
+        pip_args.push("--verbose".to_string());
+    }
+
+    pip_args.extend(vec![
+        "install".to_string(),
+        "--target".to_string(),
+        format!("{}", build_env.display()),
+    ]);
+
+    pip_args.extend(lock_args);
+    pip_args.extend(requirements.iter().cloned());
+
+    let command = cmd(dist.python_exe_path(), &pip_args)
+        .full_env(envs)
+        .stderr_to_stdout()
+        .unchecked()
+        .reader()?;
+
+    log_command_output(&command);
+
+    let output = command
+        .try_wait()?
+        .ok_or_else(|| anyhow!("unable to wait on command"))?;
+    if !output.status.success() {
+        return Err(anyhow!("error running pip"));
+    }
+
+    Ok(())
+}
+
+/// Build a wheel from a source tree or sdist with PEP 517 and return found resources.
+///
+/// `source_path` is either a directory holding a Python project or a
+/// `.tar.gz` or `.zip` source distribution.
+///
+/// The build requirements declared by the project's `pyproject.toml` are
+/// installed into an isolated build environment. The project's build backend
+/// is then invoked from it to build a wheel, without the site-packages of the
+/// distribution. Resources are collected from the built wheel.
+///
+/// The build backend runs with the environment variables from
+/// `PythonDistribution.resolve_distutils()`. So extension modules built with
+/// setuptools when linking libpython statically come with the object files
+/// needed to link them.
+#[allow(clippy::too_many_arguments)]
+pub fn pep517_build<'a, S: BuildHasher>(
+    env: &Environment,
+    dist: &dyn PythonDistribution,
+    policy: &PythonPackagingPolicy,
+    libpython_link_mode: LibpythonLinkMode,
+    source_path: &Path,
+    verbose: bool,
+    extra_envs: &HashMap<String, String, S>,
+) -> Result<Vec<PythonResource<'a>>> {
+    if !source_path.is_absolute() {
+        return Err(anyhow!(
+            "source_path must be absolute: got {:?}",
+            source_path.display()
+        ));
+    }
+
+    let temp_dir = env.temporary_directory("pyoxidizer-pep517-build")?;
+
+    dist.ensure_pip()?;
+
+    // Build requirements run on the host. So they are installed without our
+    // distutils modifications.
+    let mut host_envs: HashMap<String, String, RandomState> = std::env::vars().collect();
+    for (key, value) in extra_envs.iter() {
+        host_envs.insert(key.clone(), value.clone());
+    }
+
+    let hooks_path = temp_dir.path().join("pep517_hooks.py");
+    std::fs::write(&hooks_path, PEP517_HOOKS_PY).context("writing PEP 517 hooks script")?;
+
+    let source_dir = if source_path.is_dir() {
+        source_path.to_path_buf()
+    } else {
+        let unpack_dir = temp_dir.path().join("source");
+
+        warn!(
+            "unpacking {} to {}",
+            source_path.display(),
+            unpack_dir.display()
+        );
+        run_pep517_hooks(
+            dist,
+            &host_envs,
+            &hooks_path,
+            &[
+                "unpack".to_string(),
+                format!("{}", source_path.display()),
+                format!("{}", unpack_dir.display()),
+            ],
+        )
+        .with_context(|| format!("unpacking {}", source_path.display()))?;
+
+        // Source distributions have a single top-level directory.
+        let entries = std::fs::read_dir(&unpack_dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<std::io::Result<Vec<_>>>()?;
+
+        match entries.as_slice() {
+            [p] if p.is_dir() => p.clone(),
+            _ => unpack_dir,
+        }
+    };
+
+    let build_system = BuildSystem::from_directory(&source_dir)?;
+
+    let build_env = temp_dir.path().join("build-env");
+    std::fs::create_dir_all(&build_env)?;
+
+    install_build_requirements(
+        env,
+        dist,
+        &host_envs,
+        verbose,
+        &build_env,
+        &build_system.requires,
+        &temp_dir.path().join("lock-build-system"),
+    )?;
+
+    let mut envs = host_envs.clone();
+    for (k, v) in dist.resolve_distutils(libpython_link_mode, temp_dir.path(), &[])? {
+        envs.insert(k, v);
+    }
+    for (key, value) in extra_envs.iter() {
+        envs.insert(key.clone(), value.clone());
+    }
+    envs.insert(
+        "PYOXIDIZER_PEP517_BUILD_ENV".to_string(),
+        format!("{}", build_env.display()),
+    );
+
+    let wheel_dir = temp_dir.path().join("wheel");
+    std::fs::create_dir_all(&wheel_dir)?;
+
+    let run_hook = |hook: &str| -> Result<serde_json::Value> {
+        let result_path = temp_dir.path().join(format!("{}.json", hook));
+
+        let mut args = vec![
+            hook.to_string(),
+            format!("{}", source_dir.display()),
+            build_system.build_backend.clone(),
+            format!("{}", wheel_dir.display()),
+            format!("{}", result_path.display()),
+        ];
+        args.extend(build_system.backend_path.iter().cloned());
+
+        run_pep517_hooks(dist, &envs, &hooks_path, &args)?;
+
+        serde_json::from_slice(&std::fs::read(&result_path)?)
+            .with_context(|| format!("parsing result of {}", hook))
+    };
+
+    warn!(
+        "building wheel from {} with {}",
+        source_dir.display(),
+        build_system.build_backend
+    );
+
+    let requires: Vec<String> = serde_json::from_value(run_hook("get_requires_for_build_wheel")?)
+        .context("parsing requirements for building wheel")?;
+
+    install_build_requirements(
+        env,
+        dist,
+        &host_envs,
+        verbose,
+        &build_env,
+        &requires,
+        &temp_dir.path().join("lock-build-wheel"),
+    )?;
+
+    let wheel_name: String =
+        serde_json::from_value(run_hook("build_wheel")?).context("parsing name of built wheel")?;
+    let wheel_path = wheel_dir.join(wheel_name);
+
+    warn!("collecting resources from {}", wheel_path.display());
+
+    let wheel = WheelArchive::from_path(&wheel_path)?;
+
+    // Use built extensions if present, as they contain more metadata. The
+    // wheel may not contain files for them.
+    let built_extensions = if let Some(p) = envs.get("PYOXIDIZER_DISTUTILS_STATE_DIR") {
+        read_built_extensions(Path::new(p))?
+    } else {
+        vec![]
+    };
+
+    let mut res = vec![];
+
+    for r in wheel
+        .python_resources(
+            dist.cache_tag(),
+            &dist.python_module_suffixes()?,
+            policy.file_scanner_emit_files(),
+            policy.file_scanner_classify_files(),
+        )
+        .with_context(|| format!("reading {}", wheel_path.display()))?
+    {
+        match r {
+            PythonResource::ExtensionModule(e)
+                if built_extensions.iter().any(|built| built.name == e.name) => {}
+            r => res.push(r),
+        }
+    }
+
+    for built in built_extensions {
+        res.push(PythonResource::from(built.to_memory()?));
+    }
+
+    temp_dir.close().context("closing temporary directory")?;
+
+    Ok(res)
+}
+
 #[cfg(test)]
 mod tests {
     use {
@@ -392,6 +1129,57 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_pep517_build_in_tree_backend() -> Result<()> {
+        let env = get_env()?;
+        let distribution = get_default_distribution(None)?;
+
+        let temp_dir = env.temporary_directory("pyoxidizer-test")?;
+        let root = temp_dir.path();
+
+        std::fs::create_dir_all(root.join("backend"))?;
+        std::fs::write(
+            root.join("pyproject.toml"),
+            "[build-system]\nrequires = []\nbuild-backend = \"simple_backend\"\nbackend-path = [\"backend\"]\n",
+        )?;
+        std::fs::write(root.join("foo.py"), "VALUE = 42\n")?;
+        std::fs::write(
+            root.join("backend").join("simple_backend.py"),
+            r#"import os
+import zipfile
+
+
+def build_wheel(wheel_directory, config_settings=None, metadata_directory=None):
+    name = "foo-1.0-py3-none-any.whl"
+    with zipfile.ZipFile(os.path.join(wheel_directory, name), "w") as zf:
+        zf.write("foo.py")
+        zf.writestr("foo-1.0.dist-info/METADATA", "Metadata-Version: 2.1\nName: foo\nVersion: 1.0\n")
+        zf.writestr("foo-1.0.dist-info/WHEEL", "Wheel-Version: 1.0\nRoot-Is-Purelib: true\nTag: py3-none-any\n")
+        zf.writestr("foo-1.0.dist-info/RECORD", "")
+    return name
+"#,
+        )?;
+
+        let resources: Vec<PythonResource> = pep517_build(
+            &env,
+            distribution.deref(),
+            &distribution.create_packaging_policy()?,
+            LibpythonLinkMode::Dynamic,
+            root,
+            false,
+            &HashMap::<String, String>::new(),
+        )?;
+
+        assert!(resources
+            .iter()
+            .any(|r| matches!(r, PythonResource::ModuleSource(m) if m.name == "foo")));
+        assert!(resources
+            .iter()
+            .any(|r| matches!(r, PythonResource::PackageDistributionResource(_))));
+
+        Ok(())
+    }
+
     #[test]
     #[cfg(windows)]
     fn test_install_cffi() -> Result<()> {
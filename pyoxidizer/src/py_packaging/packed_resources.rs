@@ -0,0 +1,675 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Inspection and conversion of serialized packed resources data.
+*/
+
+use {
+    anyhow::{anyhow, Context, Result},
+    python_packed_resources::{
+        load_resources, PackedResourcesDescription, Resource, HEADER_V3, HEADER_V4,
+    },
+    serde::Serialize,
+    sha2::{Digest, Sha256},
+    std::{
+        collections::BTreeMap,
+        fs::File,
+        io::{BufWriter, Write},
+        path::{Component, Path, PathBuf},
+    },
+};
+
+/// Describes a single data field of a packed resource.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct PackedResourceField {
+    /// Length in bytes of the (decompressed) field data.
+    pub size: usize,
+    /// Hex encoded SHA-256 digest of the field data.
+    pub sha256: String,
+}
+
+impl PackedResourceField {
+    fn new(data: &[u8]) -> Self {
+        Self {
+            size: data.len(),
+            sha256: hex::encode(Sha256::digest(data)),
+        }
+    }
+}
+
+/// A summary of a resource in packed resources data.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct PackedResourceSummary {
+    /// Name of the resource.
+    pub name: String,
+    /// Names of boolean flags set on the resource.
+    pub flags: Vec<&'static str>,
+    /// Data fields, keyed by field name.
+    ///
+    /// Fields holding a mapping of data are keyed by `<field>/<key>`.
+    pub data: BTreeMap<String, PackedResourceField>,
+    /// Filesystem relative paths, keyed by field name.
+    ///
+    /// Fields holding a mapping of paths are keyed by `<field>/<key>`.
+    pub relative_paths: BTreeMap<String, String>,
+    /// Names of shared libraries this resource depends on.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub shared_library_dependency_names: Vec<String>,
+}
+
+impl PackedResourceSummary {
+    /// Names of fields whose values differ from those in another summary.
+    pub fn changed_fields(&self, other: &Self) -> Vec<String> {
+        let mut changed = vec![];
+
+        if self.flags != other.flags {
+            changed.push("flags".to_string());
+        }
+
+        for (key, value) in &self.data {
+            if other.data.get(key) != Some(value) {
+                changed.push(key.clone());
+            }
+        }
+        for key in other.data.keys() {
+            if !self.data.contains_key(key) {
+                changed.push(key.clone());
+            }
+        }
+
+        for (key, value) in &self.relative_paths {
+            if other.relative_paths.get(key) != Some(value) {
+                changed.push(key.clone());
+            }
+        }
+        for key in other.relative_paths.keys() {
+            if !self.relative_paths.contains_key(key) {
+                changed.push(key.clone());
+            }
+        }
+
+        if self.shared_library_dependency_names != other.shared_library_dependency_names {
+            changed.push("shared_library_dependency_names".to_string());
+        }
+
+        changed.sort();
+        changed.dedup();
+
+        changed
+    }
+}
+
+/// A resource present in both sides of a diff whose content differs.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct PackedResourceChange {
+    pub name: String,
+    /// Names of fields that differ.
+    pub fields: Vec<String>,
+}
+
+/// Describes differences between 2 sets of packed resources.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct PackedResourcesDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<PackedResourceChange>,
+}
+
+impl PackedResourcesDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Iterate over data fields of a resource.
+///
+/// Yields the field key as used by [PackedResourceSummary::data] and the data.
+fn resource_data_fields<'r>(
+    resource: &'r Resource<u8>,
+) -> impl Iterator<Item = (String, &'r [u8])> + 'r {
+    let single = [
+        ("in_memory_source", &resource.in_memory_source),
+        ("in_memory_bytecode", &resource.in_memory_bytecode),
+        ("in_memory_bytecode_opt1", &resource.in_memory_bytecode_opt1),
+        ("in_memory_bytecode_opt2", &resource.in_memory_bytecode_opt2),
+        (
+            "in_memory_extension_module_shared_library",
+            &resource.in_memory_extension_module_shared_library,
+        ),
+        (
+            "in_memory_shared_library",
+            &resource.in_memory_shared_library,
+        ),
+        ("file_data_embedded", &resource.file_data_embedded),
+    ];
+
+    let mapped = [
+        (
+            "in_memory_package_resources",
+            &resource.in_memory_package_resources,
+        ),
+        (
+            "in_memory_distribution_resources",
+            &resource.in_memory_distribution_resources,
+        ),
+    ];
+
+    single
+        .into_iter()
+        .filter_map(|(field, value)| {
+            value
+                .as_ref()
+                .map(|data| (field.to_string(), data.as_ref()))
+        })
+        .chain(mapped.into_iter().flat_map(|(field, value)| {
+            value.iter().flat_map(move |map| {
+                map.iter()
+                    .map(move |(key, data)| (format!("{}/{}", field, key), data.as_ref()))
+            })
+        }))
+}
+
+/// Summarize a parsed resource.
+pub fn summarize_resource(resource: &Resource<u8>) -> PackedResourceSummary {
+    let flags = [
+        ("is_python_module", resource.is_python_module),
+        (
+            "is_python_builtin_extension_module",
+            resource.is_python_builtin_extension_module,
+        ),
+        ("is_python_frozen_module", resource.is_python_frozen_module),
+        (
+            "is_python_extension_module",
+            resource.is_python_extension_module,
+        ),
+        ("is_shared_library", resource.is_shared_library),
+        ("is_utf8_filename_data", resource.is_utf8_filename_data),
+        ("is_python_package", resource.is_python_package),
+        (
+            "is_python_namespace_package",
+            resource.is_python_namespace_package,
+        ),
+        ("file_executable", resource.file_executable),
+        ("extract_to_cache", resource.extract_to_cache),
+    ]
+    .into_iter()
+    .filter_map(|(name, value)| if value { Some(name) } else { None })
+    .collect::<Vec<_>>();
+
+    let data = resource_data_fields(resource)
+        .map(|(key, data)| (key, PackedResourceField::new(data)))
+        .collect::<BTreeMap<_, _>>();
+
+    let mut relative_paths = BTreeMap::new();
+
+    for (field, value) in [
+        (
+            "relative_path_module_source",
+            &resource.relative_path_module_source,
+        ),
+        (
+            "relative_path_module_bytecode",
+            &resource.relative_path_module_bytecode,
+        ),
+        (
+            "relative_path_module_bytecode_opt1",
+            &resource.relative_path_module_bytecode_opt1,
+        ),
+        (
+            "relative_path_module_bytecode_opt2",
+            &resource.relative_path_module_bytecode_opt2,
+        ),
+        (
+            "relative_path_extension_module_shared_library",
+            &resource.relative_path_extension_module_shared_library,
+        ),
+    ] {
+        if let Some(path) = value {
+            relative_paths.insert(field.to_string(), path.display().to_string());
+        }
+    }
+
+    for (field, value) in [
+        (
+            "relative_path_package_resources",
+            &resource.relative_path_package_resources,
+        ),
+        (
+            "relative_path_distribution_resources",
+            &resource.relative_path_distribution_resources,
+        ),
+    ] {
+        if let Some(map) = value {
+            for (key, path) in map {
+                relative_paths.insert(format!("{}/{}", field, key), path.display().to_string());
+            }
+        }
+    }
+
+    if let Some(path) = &resource.file_data_utf8_relative_path {
+        relative_paths.insert("file_data_utf8_relative_path".to_string(), path.to_string());
+    }
+
+    PackedResourceSummary {
+        name: resource.name.to_string(),
+        flags,
+        data,
+        relative_paths,
+        shared_library_dependency_names: resource
+            .shared_library_dependency_names
+            .iter()
+            .flatten()
+            .map(|x| x.to_string())
+            .collect::<Vec<_>>(),
+    }
+}
+
+/// Parse all resources in packed resources data.
+pub fn parse_packed_resources(data: &[u8]) -> Result<Vec<Resource<'_, u8>>> {
+    load_resources(data)
+        .map_err(|e| anyhow!("error parsing packed resources: {}", e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| anyhow!("error parsing packed resources: {}", e))
+}
+
+/// Resolve packed resources data from file content.
+///
+/// If `data` begins with a packed resources header, it is returned as-is.
+/// Otherwise `data` is assumed to be a binary (such as an executable) having
+/// packed resources data embedded within it. The first region of `data`
+/// beginning with a packed resources header and parsing successfully is
+/// returned.
+pub fn find_packed_resources(data: &[u8]) -> Result<&[u8]> {
+    if data.starts_with(HEADER_V3) || data.starts_with(HEADER_V4) {
+        return Ok(data);
+    }
+
+    // Both headers share everything but the trailing version byte.
+    let prefix = &HEADER_V3[0..HEADER_V3.len() - 1];
+
+    let mut offset = 0;
+    while let Some(position) = find_subslice(&data[offset..], prefix) {
+        let start = offset + position;
+        let candidate = &data[start..];
+        offset = start + 1;
+
+        if !(candidate.starts_with(HEADER_V3) || candidate.starts_with(HEADER_V4)) {
+            continue;
+        }
+
+        let parser = match load_resources(candidate) {
+            Ok(parser) => parser,
+            Err(_) => continue,
+        };
+
+        let payload_length = parser.payload_length();
+        if payload_length > candidate.len() {
+            continue;
+        }
+
+        if parser.collect::<Result<Vec<_>, _>>().is_ok() {
+            return Ok(&candidate[0..payload_length]);
+        }
+    }
+
+    Err(anyhow!("unable to find packed resources data"))
+}
+
+fn find_subslice(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Compute the differences between 2 sets of resources.
+pub fn diff_packed_resources(a: &[Resource<u8>], b: &[Resource<u8>]) -> PackedResourcesDiff {
+    let a = a
+        .iter()
+        .map(|r| (r.name.to_string(), summarize_resource(r)))
+        .collect::<BTreeMap<_, _>>();
+    let b = b
+        .iter()
+        .map(|r| (r.name.to_string(), summarize_resource(r)))
+        .collect::<BTreeMap<_, _>>();
+
+    let mut diff = PackedResourcesDiff::default();
+
+    for (name, summary) in &a {
+        match b.get(name) {
+            Some(other) => {
+                let fields = summary.changed_fields(other);
+                if !fields.is_empty() {
+                    diff.changed.push(PackedResourceChange {
+                        name: name.clone(),
+                        fields,
+                    });
+                }
+            }
+            None => diff.removed.push(name.clone()),
+        }
+    }
+
+    diff.added = b
+        .keys()
+        .filter(|name| !a.contains_key(*name))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    diff
+}
+
+/// Write data fields of a resource to files in a directory.
+///
+/// Each data field is written to a file whose path is the field key as used
+/// by [PackedResourceSummary::data]. Returns the paths of written files.
+pub fn extract_resource(resource: &Resource<u8>, dest_dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut written = vec![];
+
+    for (key, data) in resource_data_fields(resource) {
+        let relative = Path::new(&key);
+        if !relative
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)))
+        {
+            return Err(anyhow!(
+                "refusing to extract {} of {}: path is not a normalized relative path",
+                key,
+                resource.name
+            ));
+        }
+
+        let path = dest_dir.join(relative);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("creating {}", parent.display()))?;
+        }
+        std::fs::write(&path, data).with_context(|| format!("writing {}", path.display()))?;
+
+        written.push(path);
+    }
+
+    Ok(written)
+}
+
+fn is_yaml_path(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|x| x.to_str()),
+        Some("yaml") | Some("yml")
+    )
+}
+
+/// Convert between packed resources data and a JSON or YAML description of it.
+///
+/// If `input` holds packed resources data, a description of it is written
+/// to `output`. Otherwise `input` is parsed as a description and packed
+/// resources data is written to `output`. Descriptions are YAML if the path
+/// has a `.yaml` or `.yml` extension and JSON otherwise. File references in
+/// descriptions are resolved relative to the directory containing `input`.
+pub fn convert_packed_resources(input: &Path, output: &Path) -> Result<()> {
+    let data = std::fs::read(input).with_context(|| format!("reading {}", input.display()))?;
+
+    if data.starts_with(HEADER_V3) || data.starts_with(HEADER_V4) {
+        let description = PackedResourcesDescription::from_packed_resources(&data)?;
+
+        let mut fh = BufWriter::new(
+            File::create(output).with_context(|| format!("creating {}", output.display()))?,
+        );
+        if is_yaml_path(output) {
+            serde_yaml::to_writer(&mut fh, &description)?;
+        } else {
+            serde_json::to_writer_pretty(&mut fh, &description)?;
+            fh.write_all(b"\n")?;
+        }
+        fh.flush()?;
+    } else {
+        let description: PackedResourcesDescription = if is_yaml_path(input) {
+            serde_yaml::from_slice(&data)
+                .with_context(|| format!("parsing {} as YAML", input.display()))?
+        } else {
+            serde_json::from_slice(&data)
+                .with_context(|| format!("parsing {} as JSON", input.display()))?
+        };
+
+        let base_dir = input
+            .parent()
+            .ok_or_else(|| anyhow!("unable to resolve parent directory of input"))?;
+
+        let mut fh = BufWriter::new(
+            File::create(output).with_context(|| format!("creating {}", output.display()))?,
+        );
+        description.write_packed_resources(&mut fh, Some(base_dir))?;
+        fh.flush()?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        python_packed_resources::{write_packed_resources_v3, write_packed_resources_v4},
+        std::{borrow::Cow, collections::HashMap},
+    };
+
+    fn serialize(resources: &[Resource<u8>]) -> Vec<u8> {
+        let mut data = vec![];
+        write_packed_resources_v4(resources, &mut data, &Default::default()).unwrap();
+        data
+    }
+
+    fn package_resources() -> Vec<Resource<'static, u8>> {
+        let mut package_resources = HashMap::new();
+        package_resources.insert(Cow::from("data/foo.txt"), Cow::from(b"foo".to_vec()));
+
+        vec![
+            Resource {
+                name: Cow::from("foo"),
+                is_python_module: true,
+                is_python_package: true,
+                in_memory_source: Some(Cow::from(b"import os".to_vec())),
+                in_memory_package_resources: Some(package_resources),
+                ..Default::default()
+            },
+            Resource {
+                name: Cow::from("foo.bar"),
+                is_python_module: true,
+                relative_path_module_source: Some(Cow::from(Path::new("foo/bar.py"))),
+                ..Default::default()
+            },
+        ]
+    }
+
+    #[test]
+    fn test_summarize() {
+        let resources = package_resources();
+        let summary = summarize_resource(&resources[0]);
+
+        assert_eq!(summary.name, "foo");
+        assert_eq!(summary.flags, vec!["is_python_module", "is_python_package"]);
+        assert_eq!(
+            summary.data.keys().collect::<Vec<_>>(),
+            vec![
+                "in_memory_package_resources/data/foo.txt",
+                "in_memory_source"
+            ]
+        );
+        assert_eq!(summary.data["in_memory_source"].size, 9);
+        assert!(summary.relative_paths.is_empty());
+
+        let summary = summarize_resource(&resources[1]);
+        assert!(summary.data.is_empty());
+        assert_eq!(
+            summary.relative_paths["relative_path_module_source"],
+            Path::new("foo/bar.py").display().to_string()
+        );
+    }
+
+    #[test]
+    fn test_find_packed_resources() -> Result<()> {
+        let data = serialize(&package_resources());
+        assert_eq!(find_packed_resources(&data)?, data.as_slice());
+
+        // A header without valid data following it is ignored.
+        let mut binary = b"\x7fELF\0\0pyembed\x04garbage".to_vec();
+        binary.extend_from_slice(&data);
+        binary.extend_from_slice(b"trailing\0data");
+
+        assert_eq!(find_packed_resources(&binary)?, data.as_slice());
+        assert!(find_packed_resources(b"\x7fELF\0\0pyembed\x04garbage").is_err());
+
+        let mut v3 = vec![];
+        write_packed_resources_v3(&package_resources(), &mut v3, None)?;
+        let mut binary = b"prefix".to_vec();
+        binary.extend_from_slice(&v3);
+        assert_eq!(find_packed_resources(&binary)?, v3.as_slice());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff() {
+        let a = package_resources();
+        let mut b = package_resources();
+
+        assert!(diff_packed_resources(&a, &b).is_empty());
+
+        b[0].in_memory_source = Some(Cow::from(b"import io".to_vec()));
+        b[0].is_python_package = false;
+        b.remove(1);
+        b.push(Resource {
+            name: Cow::from("baz"),
+            is_python_module: true,
+            ..Default::default()
+        });
+
+        let diff = diff_packed_resources(&a, &b);
+        assert_eq!(diff.added, vec!["baz".to_string()]);
+        assert_eq!(diff.removed, vec!["foo.bar".to_string()]);
+        assert_eq!(
+            diff.changed,
+            vec![PackedResourceChange {
+                name: "foo".to_string(),
+                fields: vec!["flags".to_string(), "in_memory_source".to_string()],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_convert() -> Result<()> {
+        let temp_dir = tempfile::Builder::new()
+            .prefix("pyoxidizer-test")
+            .tempdir()?;
+
+        let blob_path = temp_dir.path().join("packed-resources");
+        std::fs::write(&blob_path, serialize(&package_resources()))?;
+
+        let description_path = temp_dir.path().join("resources.json");
+        convert_packed_resources(&blob_path, &description_path)?;
+
+        let roundtrip_path = temp_dir.path().join("roundtrip");
+        convert_packed_resources(&description_path, &roundtrip_path)?;
+
+        assert_eq!(
+            parse_packed_resources(&std::fs::read(&roundtrip_path)?)?,
+            package_resources()
+        );
+
+        // Data can reference files relative to the description.
+        std::fs::write(temp_dir.path().join("foo.py"), b"import os")?;
+        let description_path = temp_dir.path().join("external.json");
+        std::fs::write(
+            &description_path,
+            r#"{"format_version": 4, "resources": [{"name": "foo", "is_python_module": true, "in_memory_source": {"path": "foo.py"}}]}"#,
+        )?;
+        convert_packed_resources(&description_path, &blob_path)?;
+
+        let data = std::fs::read(&blob_path)?;
+        assert!(data.starts_with(HEADER_V4));
+        let resources = parse_packed_resources(&data)?;
+        assert_eq!(
+            resources[0].in_memory_source.as_deref(),
+            Some(b"import os".as_ref())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_convert_yaml() -> Result<()> {
+        let temp_dir = tempfile::Builder::new()
+            .prefix("pyoxidizer-test")
+            .tempdir()?;
+
+        let blob_path = temp_dir.path().join("packed-resources");
+        std::fs::write(&blob_path, serialize(&package_resources()))?;
+
+        let description_path = temp_dir.path().join("resources.yaml");
+        convert_packed_resources(&blob_path, &description_path)?;
+        assert!(std::fs::read_to_string(&description_path)?.starts_with("format_version: 4\n"));
+
+        let roundtrip_path = temp_dir.path().join("roundtrip");
+        convert_packed_resources(&description_path, &roundtrip_path)?;
+
+        assert_eq!(
+            parse_packed_resources(&std::fs::read(&roundtrip_path)?)?,
+            package_resources()
+        );
+
+        std::fs::write(temp_dir.path().join("foo.py"), b"import os")?;
+        let description_path = temp_dir.path().join("external.yml");
+        std::fs::write(
+            &description_path,
+            "format_version: 4\nresources:\n- name: foo\n  is_python_module: true\n  in_memory_source:\n    path: foo.py\n",
+        )?;
+        convert_packed_resources(&description_path, &blob_path)?;
+
+        let resources = parse_packed_resources(&std::fs::read(&blob_path)?)?;
+        assert_eq!(
+            resources[0].in_memory_source.as_deref(),
+            Some(b"import os".as_ref())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_extract() -> Result<()> {
+        let temp_dir = tempfile::Builder::new()
+            .prefix("pyoxidizer-test")
+            .tempdir()?;
+
+        let data = serialize(&package_resources());
+        let resources = parse_packed_resources(&data)?;
+        let written = extract_resource(&resources[0], temp_dir.path())?;
+        assert_eq!(written.len(), 2);
+
+        assert_eq!(
+            std::fs::read(temp_dir.path().join("in_memory_source"))?,
+            b"import os"
+        );
+        assert_eq!(
+            std::fs::read(
+                temp_dir
+                    .path()
+                    .join("in_memory_package_resources")
+                    .join("data")
+                    .join("foo.txt")
+            )?,
+            b"foo"
+        );
+
+        let mut package_resources = HashMap::new();
+        package_resources.insert(Cow::from("../escape"), Cow::from(b"foo".to_vec()));
+        let resource = Resource {
+            name: Cow::from("evil"),
+            in_memory_package_resources: Some(package_resources),
+            ..Default::default()
+        };
+        assert!(extract_resource(&resource, temp_dir.path()).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,406 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Reading project metadata from `pyproject.toml` and versions from lock files.
+
+Build systems are resolved by [python_packaging::pyproject::BuildSystem].
+*/
+
+use {
+    anyhow::{anyhow, Context, Result},
+    log::warn,
+    python_packaging::wheelhouse::canonicalize_name,
+    serde::Deserialize,
+    std::{
+        collections::BTreeMap,
+        path::{Path, PathBuf},
+    },
+};
+
+/// Names of lock files we know how to read, in order of preference.
+const LOCK_FILE_NAMES: &[&str] = &["pylock.toml", "uv.lock", "poetry.lock", "pdm.lock"];
+
+#[derive(Deserialize)]
+struct PyProjectToml {
+    project: Option<ProjectTable>,
+    #[serde(rename = "build-system")]
+    build_system: Option<toml::Table>,
+    #[serde(rename = "dependency-groups", default)]
+    dependency_groups: BTreeMap<String, Vec<DependencyGroupEntry>>,
+}
+
+#[derive(Deserialize)]
+struct ProjectTable {
+    name: String,
+    #[serde(default)]
+    dependencies: Vec<String>,
+    #[serde(rename = "optional-dependencies", default)]
+    optional_dependencies: BTreeMap<String, Vec<String>>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum DependencyGroupEntry {
+    Requirement(String),
+    Include {
+        #[serde(rename = "include-group")]
+        include_group: String,
+    },
+}
+
+/// A Python project defined by a `pyproject.toml` file.
+pub struct PyProject {
+    /// Directory holding `pyproject.toml`.
+    root: PathBuf,
+
+    /// Normalized name of the project.
+    name: String,
+
+    /// Requirements from `project.dependencies`.
+    dependencies: Vec<String>,
+
+    /// Requirements of extras, keyed by normalized extra name.
+    optional_dependencies: BTreeMap<String, Vec<String>>,
+
+    /// Dependency groups, keyed by normalized group name.
+    dependency_groups: BTreeMap<String, Vec<DependencyGroupEntry>>,
+
+    /// Whether the project defines how to build it.
+    has_build_system: bool,
+}
+
+impl PyProject {
+    /// Read the `pyproject.toml` in a directory.
+    pub fn from_directory(root: &Path) -> Result<Self> {
+        let path = root.join("pyproject.toml");
+        let data = std::fs::read_to_string(&path)
+            .with_context(|| format!("reading {}", path.display()))?;
+        let doc: PyProjectToml =
+            toml::from_str(&data).with_context(|| format!("parsing {}", path.display()))?;
+
+        let project = doc
+            .project
+            .ok_or_else(|| anyhow!("{} has no [project] table", path.display()))?;
+
+        Ok(Self {
+            root: root.to_path_buf(),
+            name: canonicalize_name(&project.name),
+            dependencies: project.dependencies,
+            optional_dependencies: project
+                .optional_dependencies
+                .into_iter()
+                .map(|(k, v)| (canonicalize_name(&k), v))
+                .collect(),
+            dependency_groups: doc
+                .dependency_groups
+                .into_iter()
+                .map(|(k, v)| (canonicalize_name(&k), v))
+                .collect(),
+            has_build_system: doc.build_system.is_some(),
+        })
+    }
+
+    /// The normalized name of the project.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Resolve the requirements of a dependency group, expanding included groups.
+    fn group_requirements(&self, group: &str, stack: &mut Vec<String>) -> Result<Vec<String>> {
+        let group = canonicalize_name(group);
+
+        if stack.contains(&group) {
+            return Err(anyhow!("dependency group {} includes itself", group));
+        }
+
+        let entries = self
+            .dependency_groups
+            .get(&group)
+            .ok_or_else(|| anyhow!("project {} has no dependency group {}", self.name, group))?;
+
+        stack.push(group);
+
+        let mut res = vec![];
+        for entry in entries {
+            match entry {
+                DependencyGroupEntry::Requirement(s) => res.push(s.clone()),
+                DependencyGroupEntry::Include { include_group } => {
+                    res.extend(self.group_requirements(include_group, stack)?)
+                }
+            }
+        }
+
+        stack.pop();
+
+        Ok(res)
+    }
+
+    /// Derive `pip install` requirement arguments for the project.
+    ///
+    /// If the project has a `[build-system]` table, the project itself is
+    /// installed with the requested extras. Otherwise only its dependencies
+    /// are. Requirements of the requested dependency groups are always added.
+    pub fn install_requirements(
+        &self,
+        extras: &[String],
+        groups: &[String],
+    ) -> Result<Vec<String>> {
+        for extra in extras {
+            if !self
+                .optional_dependencies
+                .contains_key(&canonicalize_name(extra))
+            {
+                return Err(anyhow!("project {} has no extra {}", self.name, extra));
+            }
+        }
+
+        let mut res = vec![];
+
+        if self.has_build_system {
+            res.push(if extras.is_empty() {
+                format!("{}", self.root.display())
+            } else {
+                format!("{}[{}]", self.root.display(), extras.join(","))
+            });
+        } else {
+            res.extend(self.dependencies.iter().cloned());
+
+            for extra in extras {
+                res.extend(self.optional_dependencies[&canonicalize_name(extra)].clone());
+            }
+        }
+
+        for group in groups {
+            res.extend(self.group_requirements(group, &mut vec![])?);
+        }
+
+        Ok(res)
+    }
+}
+
+#[derive(Deserialize)]
+struct LockFileToml {
+    /// Packages in `pylock.toml` files.
+    #[serde(default)]
+    packages: Vec<LockedPackage>,
+
+    /// Packages in `uv.lock`, `poetry.lock` and `pdm.lock` files.
+    #[serde(default)]
+    package: Vec<LockedPackage>,
+}
+
+/// A package in a lock file.
+#[derive(Clone, Debug, Deserialize)]
+pub struct LockedPackage {
+    pub name: String,
+    pub version: Option<String>,
+    pub marker: Option<String>,
+}
+
+impl LockedPackage {
+    /// The environment marker to attach to a pip constraint for this package.
+    ///
+    /// `pylock.toml` markers can reference the `extras` and `dependency_groups`
+    /// variables, which pip doesn't understand. These markers are ignored.
+    fn constraint_marker(&self) -> Option<&str> {
+        self.marker
+            .as_deref()
+            .filter(|m| !(m.contains("extras") || m.contains("dependency_groups")))
+    }
+}
+
+/// Find and read the lock file in a project directory.
+///
+/// Returns the path of the lock file and the packages it contains.
+pub fn read_project_lock(root: &Path) -> Result<(PathBuf, Vec<LockedPackage>)> {
+    let path = LOCK_FILE_NAMES
+        .iter()
+        .map(|name| root.join(name))
+        .find(|p| p.exists())
+        .ok_or_else(|| {
+            anyhow!(
+                "no lock file in {}; expected one of {}",
+                root.display(),
+                LOCK_FILE_NAMES.join(", ")
+            )
+        })?;
+
+    let data =
+        std::fs::read_to_string(&path).with_context(|| format!("reading {}", path.display()))?;
+    let doc: LockFileToml =
+        toml::from_str(&data).with_context(|| format!("parsing {}", path.display()))?;
+
+    let mut packages = doc.packages;
+    packages.extend(doc.package);
+
+    Ok((path, packages))
+}
+
+/// Derive pip constraints pinning the versions of locked packages.
+///
+/// The project itself and packages without a version are not constrained.
+/// Neither are packages locked to multiple versions without markers telling
+/// them apart.
+pub fn lock_constraints(project_name: &str, packages: &[LockedPackage]) -> Vec<String> {
+    let mut by_name: BTreeMap<String, Vec<&LockedPackage>> = BTreeMap::new();
+
+    for package in packages {
+        if package.version.is_some() {
+            by_name
+                .entry(canonicalize_name(&package.name))
+                .or_default()
+                .push(package);
+        }
+    }
+
+    let mut res = vec![];
+
+    for (name, entries) in by_name {
+        if name == project_name {
+            continue;
+        }
+
+        if entries.len() > 1 && entries.iter().any(|p| p.constraint_marker().is_none()) {
+            warn!(
+                "{} is locked to multiple versions; not constraining its version",
+                name
+            );
+            continue;
+        }
+
+        for package in entries {
+            let version = package.version.as_ref().expect("filtered above");
+
+            res.push(if let Some(marker) = package.constraint_marker() {
+                format!("{}=={} ; {}", name, version, marker)
+            } else {
+                format!("{}=={}", name, version)
+            });
+        }
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_install_requirements() -> Result<()> {
+        let temp_dir = tempfile::Builder::new()
+            .prefix("pyoxidizer-test")
+            .tempdir()?;
+        let root = temp_dir.path();
+
+        std::fs::write(
+            root.join("pyproject.toml"),
+            r#"
+[project]
+name = "My.App"
+dependencies = ["requests>=2"]
+
+[project.optional-dependencies]
+Fast = ["ujson"]
+
+[dependency-groups]
+test = ["pytest", {include-group = "lint"}]
+lint = ["flake8"]
+cycle = [{include-group = "cycle"}]
+"#,
+        )?;
+
+        let project = PyProject::from_directory(root)?;
+        assert_eq!(project.name(), "my-app");
+
+        assert_eq!(
+            project.install_requirements(&["fast".to_string()], &["test".to_string()])?,
+            vec!["requests>=2", "ujson", "pytest", "flake8"]
+        );
+        assert!(project
+            .install_requirements(&["missing".to_string()], &[])
+            .is_err());
+        assert!(project
+            .install_requirements(&[], &["cycle".to_string()])
+            .is_err());
+
+        std::fs::write(
+            root.join("pyproject.toml"),
+            "[build-system]\nrequires = [\"setuptools\"]\n\n[project]\nname = \"app\"\n\n[project.optional-dependencies]\nfast = []\n",
+        )?;
+        let project = PyProject::from_directory(root)?;
+        assert_eq!(
+            project.install_requirements(&["fast".to_string()], &[])?,
+            vec![format!("{}[fast]", root.display())]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lock_constraints() -> Result<()> {
+        let temp_dir = tempfile::Builder::new()
+            .prefix("pyoxidizer-test")
+            .tempdir()?;
+        let root = temp_dir.path();
+
+        assert!(read_project_lock(root).is_err());
+
+        std::fs::write(
+            root.join("pylock.toml"),
+            r#"
+lock-version = "1.0"
+created-by = "test"
+
+[[packages]]
+name = "app"
+directory = { path = "." }
+
+[[packages]]
+name = "Requests"
+version = "2.32.3"
+
+[[packages]]
+name = "numpy"
+version = "1.24.4"
+marker = "python_version < '3.9'"
+
+[[packages]]
+name = "numpy"
+version = "2.1.0"
+marker = "python_version >= '3.9'"
+
+[[packages]]
+name = "pytest"
+version = "8.3.3"
+marker = "'test' in dependency_groups"
+
+[[packages]]
+name = "six"
+version = "1.16.0"
+
+[[packages]]
+name = "six"
+version = "1.17.0"
+"#,
+        )?;
+
+        let (path, packages) = read_project_lock(root)?;
+        assert_eq!(path, root.join("pylock.toml"));
+        assert_eq!(packages.len(), 7);
+
+        assert_eq!(
+            lock_constraints("app", &packages),
+            vec![
+                "numpy==1.24.4 ; python_version < '3.9'",
+                "numpy==2.1.0 ; python_version >= '3.9'",
+                "pytest==8.3.3",
+                "requests==2.32.3",
+            ]
+        );
+
+        Ok(())
+    }
+}
@@ -17,7 +17,8 @@ use {
         filtering::{filter_btreemap, resolve_resource_names_from_files},
         libpython::link_libpython,
         packaging_tool::{
-            find_resources, pip_download, pip_install, read_virtualenv, setup_py_install,
+            find_resources, pep517_build, pip_download, pip_install, pyproject_install,
+            read_virtualenv, setup_py_install, wheelhouse_install,
         },
         standalone_distribution::StandaloneDistribution,
     },
@@ -28,6 +29,7 @@ use {
     pyo3_build_config::{BuildFlag, BuildFlags, PythonImplementation, PythonVersion},
     python_packaging::{
         bytecode::BytecodeCompiler,
+        import_graph::{TreeShakeReport, INTERPRETER_STARTUP_MODULES},
         interpreter::MemoryAllocatorBackend,
         libpython::LibPythonBuildContext,
         licensing::{
@@ -35,6 +37,7 @@ use {
         },
         location::AbstractResourceLocation,
         policy::PythonPackagingPolicy,
+        python_source::find_imports,
         resource::{
             PythonExtensionModule, PythonModuleSource, PythonPackageDistributionResource,
             PythonPackageResource, PythonResource,
@@ -373,6 +376,24 @@ impl StandalonePythonExecutableBuilder {
     }
 
     /// Resolves Windows runtime DLLs file needed for this binary given current settings.
+    /// Evaluate Python path extension (.pth) files in installed resources.
+    ///
+    /// .pth files can't be represented in Starlark. So we register their
+    /// startup hooks as soon as they are encountered.
+    fn add_path_extensions_from_resources<'a>(
+        &mut self,
+        resources: &[PythonResource<'a>],
+    ) -> Result<()> {
+        for resource in resources {
+            if let PythonResource::PathExtension(extension) = resource {
+                self.resources_collector
+                    .add_python_path_extension(extension)?;
+            }
+        }
+
+        Ok(())
+    }
+
     fn resolve_windows_runtime_dll_files(&self) -> Result<FileManifest> {
         let mut manifest = FileManifest::default();
 
@@ -582,6 +603,8 @@ impl PythonBinaryBuilder for StandalonePythonExecutableBuilder {
 
         self.index_package_license_info_from_resources(&resources)
             .context("indexing package license metadata")?;
+        self.add_path_extensions_from_resources(&resources)
+            .context("evaluating .pth files")?;
 
         Ok(resources)
     }
@@ -614,6 +637,34 @@ impl PythonBinaryBuilder for StandalonePythonExecutableBuilder {
         Ok(resources)
     }
 
+    fn read_pyproject(
+        &mut self,
+        env: &Environment,
+        verbose: bool,
+        path: &Path,
+        extras: &[String],
+        groups: &[String],
+    ) -> Result<Vec<PythonResource>> {
+        let resources = pyproject_install(
+            env,
+            &*self.target_distribution,
+            self.python_packaging_policy(),
+            self.link_mode,
+            verbose,
+            path,
+            extras,
+            groups,
+        )
+        .context("installing pyproject.toml project")?;
+
+        self.index_package_license_info_from_resources(&resources)
+            .context("indexing package license metadata")?;
+        self.add_path_extensions_from_resources(&resources)
+            .context("evaluating .pth files")?;
+
+        Ok(resources)
+    }
+
     fn read_virtualenv(&mut self, path: &Path) -> Result<Vec<PythonResource>> {
         let resources = read_virtualenv(
             &*self.target_distribution,
@@ -624,6 +675,29 @@ impl PythonBinaryBuilder for StandalonePythonExecutableBuilder {
 
         self.index_package_license_info_from_resources(&resources)
             .context("indexing package license metadata")?;
+        self.add_path_extensions_from_resources(&resources)
+            .context("evaluating .pth files")?;
+
+        Ok(resources)
+    }
+
+    fn wheelhouse_install(
+        &mut self,
+        wheelhouse: &Path,
+        requirements: &[String],
+    ) -> Result<Vec<PythonResource>> {
+        let resources = wheelhouse_install(
+            &*self.target_distribution,
+            self.python_packaging_policy(),
+            wheelhouse,
+            requirements,
+        )
+        .context("installing from wheelhouse")?;
+
+        self.index_package_license_info_from_resources(&resources)
+            .context("indexing package license metadata")?;
+        self.add_path_extensions_from_resources(&resources)
+            .context("evaluating .pth files")?;
 
         Ok(resources)
     }
@@ -650,6 +724,34 @@ impl PythonBinaryBuilder for StandalonePythonExecutableBuilder {
 
         self.index_package_license_info_from_resources(&resources)
             .context("indexing package license metadata")?;
+        self.add_path_extensions_from_resources(&resources)
+            .context("evaluating .pth files")?;
+
+        Ok(resources)
+    }
+
+    fn pep517_build(
+        &mut self,
+        env: &Environment,
+        source_path: &Path,
+        verbose: bool,
+        extra_envs: &HashMap<String, String>,
+    ) -> Result<Vec<PythonResource>> {
+        let resources = pep517_build(
+            env,
+            &*self.target_distribution,
+            self.python_packaging_policy(),
+            self.link_mode,
+            source_path,
+            verbose,
+            extra_envs,
+        )
+        .context("building wheel via PEP 517")?;
+
+        self.index_package_license_info_from_resources(&resources)
+            .context("indexing package license metadata")?;
+        self.add_path_extensions_from_resources(&resources)
+            .context("evaluating .pth files")?;
 
         Ok(resources)
     }
@@ -860,6 +962,62 @@ impl PythonBinaryBuilder for StandalonePythonExecutableBuilder {
         Ok(())
     }
 
+    fn filter_resources_from_import_graph(&mut self, keep: &[&str]) -> Result<TreeShakeReport> {
+        let mut entry_points = vec![];
+
+        if let Some(module) = &self.config.config.run_module {
+            entry_points.push(module.clone());
+        }
+
+        if let Some(command) = &self.config.config.run_command {
+            entry_points.extend(
+                find_imports(command.as_bytes())
+                    .into_iter()
+                    .filter(|import| import.level == 0)
+                    .map(|import| import.module),
+            );
+        }
+
+        if entry_points.is_empty() {
+            return Err(anyhow!(
+                "cannot filter resources from import graph: interpreter config does not define run_module or run_command"
+            ));
+        }
+
+        let entry_points = entry_points.iter().map(|x| x.as_str()).collect::<Vec<_>>();
+        let keep = keep
+            .iter()
+            .chain(INTERPRETER_STARTUP_MODULES.iter())
+            .copied()
+            .collect::<Vec<_>>();
+
+        warn!(
+            "filtering module entries reachable from {}",
+            entry_points.join(", ")
+        );
+
+        let report = self
+            .resources_collector
+            .filter_resources_from_import_graph(&entry_points, &keep)?;
+
+        for (name, reason) in &report.removed {
+            warn!("removing {}: {}", name, reason);
+        }
+
+        for name in &report.unanalyzed {
+            warn!(
+                "imports of {} could not be determined; modules it imports may have been removed",
+                name
+            );
+        }
+
+        warn!("filtering embedded extension modules");
+        self.extension_build_contexts
+            .retain(|name, _| !report.removed.contains_key(name));
+
+        Ok(report)
+    }
+
     fn requires_jemalloc(&self) -> bool {
         self.config.allocator_backend == MemoryAllocatorBackend::Jemalloc
     }
@@ -900,7 +1058,8 @@ impl PythonBinaryBuilder for StandalonePythonExecutableBuilder {
         let compiled_resources = {
             let temp_dir = env.temporary_directory("pyoxidizer-bytecode-compile")?;
             let mut compiler = BytecodeCompiler::new(self.host_python_exe_path(), temp_dir.path())?;
-            let resources = self.resources_collector.compile_resources(&mut compiler)?;
+            let mut resources = self.resources_collector.compile_resources(&mut compiler)?;
+            resources.writer_options = self.packaging_policy.packed_resources_writer_options()?;
 
             temp_dir.close().context("closing temporary directory")?;
 
@@ -912,6 +1071,9 @@ impl PythonBinaryBuilder for StandalonePythonExecutableBuilder {
         let mut extra_files = compiled_resources.extra_files_manifest()?;
 
         let mut config = self.config.clone();
+        config
+            .startup_hooks
+            .extend(self.resources_collector.startup_hooks().iter().cloned());
 
         match &self.resources_load_mode {
             PackedResourcesLoadMode::None => {}
@@ -1276,6 +1438,12 @@ pub mod tests {
                 entry.in_memory_extension_module_shared_library =
                     Some(extension.shared_library.as_ref().unwrap().clone());
             }
+            ConcreteResourceLocation::ExtractToCache => {
+                assert!(extension.shared_library.is_some());
+                entry.in_memory_extension_module_shared_library =
+                    Some(extension.shared_library.as_ref().unwrap().clone());
+                entry.extract_to_cache = true;
+            }
             ConcreteResourceLocation::RelativePath(prefix) => {
                 assert!(extension.shared_library.is_some());
                 entry.relative_path_extension_module_shared_library = Some((
@@ -1618,6 +1786,77 @@ pub mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_linux_extension_in_memory_shared_library_loading() -> Result<()> {
+        for libpython_link_mode in vec![
+            BinaryLibpythonLinkMode::Static,
+            BinaryLibpythonLinkMode::Dynamic,
+        ] {
+            let options = StandalonePythonExecutableBuilderOptions {
+                target_triple: "x86_64-unknown-linux-gnu".to_string(),
+                extension_module_filter: Some(ExtensionModuleFilter::Minimal),
+                libpython_link_mode: libpython_link_mode.clone(),
+                resources_location: Some(ConcreteResourceLocation::InMemory),
+                resources_location_fallback: Some(None),
+                allow_in_memory_shared_library_loading: Some(true),
+                ..StandalonePythonExecutableBuilderOptions::default()
+            };
+
+            let mut builder = options.new_builder()?;
+
+            builder.add_python_extension_module(&EXTENSION_MODULE_SHARED_LIBRARY_ONLY, None)?;
+            assert_extension_shared_library(
+                &builder,
+                &EXTENSION_MODULE_SHARED_LIBRARY_ONLY,
+                ConcreteResourceLocation::InMemory,
+            );
+
+            let res =
+                builder.add_python_extension_module(&EXTENSION_MODULE_OBJECT_FILES_ONLY, None);
+            match libpython_link_mode {
+                BinaryLibpythonLinkMode::Static => {
+                    assert!(res.is_ok());
+                    assert_extension_builtin(&builder, &EXTENSION_MODULE_OBJECT_FILES_ONLY);
+                }
+                BinaryLibpythonLinkMode::Dynamic => {
+                    assert!(res.is_err());
+                    assert_eq!(
+                        res.err().unwrap().to_string(),
+                        "no shared library data present"
+                    );
+                }
+                BinaryLibpythonLinkMode::Default => {
+                    panic!("should not get here");
+                }
+            }
+
+            builder.add_python_extension_module(
+                &EXTENSION_MODULE_SHARED_LIBRARY_AND_OBJECT_FILES,
+                None,
+            )?;
+            match libpython_link_mode {
+                BinaryLibpythonLinkMode::Static => {
+                    assert_extension_builtin(
+                        &builder,
+                        &EXTENSION_MODULE_SHARED_LIBRARY_AND_OBJECT_FILES,
+                    );
+                }
+                BinaryLibpythonLinkMode::Dynamic => {
+                    assert_extension_shared_library(
+                        &builder,
+                        &EXTENSION_MODULE_SHARED_LIBRARY_AND_OBJECT_FILES,
+                        ConcreteResourceLocation::InMemory,
+                    );
+                }
+                BinaryLibpythonLinkMode::Default => {
+                    panic!("should not get here");
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     #[test]
     fn test_linux_distribution_extension_filesystem_relative_only() -> Result<()> {
         for libpython_link_mode in vec![
@@ -72,6 +72,18 @@ impl PythonDistributionValue {
 
             let dest_dir = pyoxidizer_context.python_distributions_path()?;
 
+            pyoxidizer_context
+                .env()
+                .lock_mode()
+                .use_python_distribution(&self.source)
+                .map_err(|e| {
+                    ValueError::from(RuntimeError {
+                        code: "PYOXIDIZER_BUILD",
+                        message: format!("{:?}", e),
+                        label: label.to_string(),
+                    })
+                })?;
+
             self.distribution = Some(
                 pyoxidizer_context
                     .distribution_cache
@@ -31,6 +31,7 @@ use {
         environment::TypeValues,
         eval::call_stack::CallStack,
         values::{
+            dict::Dictionary,
             error::{
                 RuntimeError, UnsupportedOperation, ValueError, INCORRECT_PARAMETER_TYPE_ERROR_CODE,
             },
@@ -492,6 +493,65 @@ impl PythonExecutableValue {
         Ok(Value::from(resources))
     }
 
+    /// PythonExecutable.read_pyproject(path, extras=None, groups=None)
+    pub fn read_pyproject(
+        &mut self,
+        type_values: &TypeValues,
+        call_stack: &mut CallStack,
+        path: String,
+        extras: &Value,
+        groups: &Value,
+    ) -> ValueResult {
+        const LABEL: &str = "PythonExecutable.read_pyproject()";
+
+        optional_list_arg("extras", "string", extras)?;
+        optional_list_arg("groups", "string", groups)?;
+
+        let extras: Vec<String> = match extras.get_type() {
+            "list" => extras.iter()?.iter().map(|x| x.to_string()).collect(),
+            _ => vec![],
+        };
+        let groups: Vec<String> = match groups.get_type() {
+            "list" => groups.iter()?.iter().map(|x| x.to_string()).collect(),
+            _ => vec![],
+        };
+
+        let pyoxidizer_context_value = get_context(type_values)?;
+        let pyoxidizer_context = pyoxidizer_context_value
+            .downcast_ref::<PyOxidizerEnvironmentContext>()
+            .ok_or(ValueError::IncorrectParameterType)?;
+
+        let python_packaging_policy = self.python_packaging_policy();
+
+        let mut exe = self.inner(LABEL)?;
+
+        let resources = error_context(LABEL, || {
+            exe.read_pyproject(
+                pyoxidizer_context.env(),
+                pyoxidizer_context.verbose,
+                Path::new(&path),
+                &extras,
+                &groups,
+            )
+        })?;
+
+        let resources = resources
+            .iter()
+            .filter(|r| is_resource_starlark_compatible(r))
+            .map(|r| {
+                python_resource_to_value(
+                    LABEL,
+                    type_values,
+                    call_stack,
+                    r,
+                    &python_packaging_policy,
+                )
+            })
+            .collect::<Result<Vec<Value>, ValueError>>()?;
+
+        Ok(Value::from(resources))
+    }
+
     /// PythonExecutable.read_virtualenv(path)
     pub fn read_virtualenv(
         &mut self,
@@ -524,6 +584,46 @@ impl PythonExecutableValue {
         Ok(Value::from(resources))
     }
 
+    /// PythonExecutable.wheelhouse_install(path, requirements)
+    pub fn wheelhouse_install(
+        &mut self,
+        type_values: &TypeValues,
+        call_stack: &mut CallStack,
+        path: String,
+        requirements: &Value,
+    ) -> ValueResult {
+        const LABEL: &str = "PythonExecutable.wheelhouse_install()";
+
+        required_list_arg("requirements", "string", requirements)?;
+
+        let requirements: Vec<String> =
+            requirements.iter()?.iter().map(|x| x.to_string()).collect();
+
+        let python_packaging_policy = self.python_packaging_policy();
+
+        let mut exe = self.inner(LABEL)?;
+
+        let resources = error_context(LABEL, || {
+            exe.wheelhouse_install(Path::new(&path), &requirements)
+        })?;
+
+        let resources = resources
+            .iter()
+            .filter(|r| is_resource_starlark_compatible(r))
+            .map(|r| {
+                python_resource_to_value(
+                    LABEL,
+                    type_values,
+                    call_stack,
+                    r,
+                    &python_packaging_policy,
+                )
+            })
+            .collect::<Result<Vec<Value>, ValueError>>()?;
+
+        Ok(Value::from(resources))
+    }
+
     /// PythonExecutable.setup_py_install(package_path, extra_envs=None, extra_global_arguments=None)
     pub fn setup_py_install(
         &mut self,
@@ -610,6 +710,77 @@ impl PythonExecutableValue {
         Ok(Value::from(resources))
     }
 
+    /// PythonExecutable.pep517_build(path, extra_envs=None)
+    pub fn pep517_build(
+        &mut self,
+        type_values: &TypeValues,
+        call_stack: &mut CallStack,
+        path: String,
+        extra_envs: &Value,
+    ) -> ValueResult {
+        const LABEL: &str = "PythonExecutable.pep517_build()";
+
+        optional_dict_arg("extra_envs", "string", "string", extra_envs)?;
+
+        let extra_envs = match extra_envs.get_type() {
+            "dict" => extra_envs
+                .iter()?
+                .iter()
+                .map(|key| {
+                    let k = key.to_string();
+                    let v = extra_envs.at(key).unwrap().to_string();
+                    (k, v)
+                })
+                .collect(),
+            "NoneType" => HashMap::new(),
+            _ => panic!("should have validated type above"),
+        };
+
+        let path = PathBuf::from(path);
+
+        let pyoxidizer_context_value = get_context(type_values)?;
+        let pyoxidizer_context = pyoxidizer_context_value
+            .downcast_ref::<PyOxidizerEnvironmentContext>()
+            .ok_or(ValueError::IncorrectParameterType)?;
+
+        let path = if path.is_absolute() {
+            path
+        } else {
+            PathBuf::from(&pyoxidizer_context.cwd).join(path)
+        };
+
+        let python_packaging_policy = self.python_packaging_policy();
+
+        let mut exe = self.inner(LABEL)?;
+
+        let resources = error_context(LABEL, || {
+            exe.pep517_build(
+                pyoxidizer_context.env(),
+                &path,
+                pyoxidizer_context.verbose,
+                &extra_envs,
+            )
+        })?;
+
+        let resources = resources
+            .iter()
+            .filter(|r| is_resource_starlark_compatible(r))
+            .map(|r| {
+                python_resource_to_value(
+                    LABEL,
+                    type_values,
+                    call_stack,
+                    r,
+                    &python_packaging_policy,
+                )
+            })
+            .collect::<Result<Vec<Value>, ValueError>>()?;
+
+        warn!("collected {} resources from PEP 517 build", resources.len());
+
+        Ok(Value::from(resources))
+    }
+
     pub fn add_python_module_source(
         &mut self,
         label: &str,
@@ -1003,6 +1174,35 @@ impl PythonExecutableValue {
 
         Ok(Value::new(NoneType::None))
     }
+
+    /// PythonExecutable.filter_resources_from_import_graph(keep=None)
+    pub fn filter_resources_from_import_graph(&mut self, keep: &Value) -> ValueResult {
+        const LABEL: &str = "PythonExecutable.filter_resources_from_import_graph()";
+
+        optional_list_arg("keep", "string", keep)?;
+
+        let keep = match keep.get_type() {
+            "list" => keep.iter()?.iter().map(|x| x.to_string()).collect(),
+            "NoneType" => Vec::new(),
+            _ => panic!("type should have been validated above"),
+        };
+
+        let keep_refs = keep.iter().map(|x| x.as_str()).collect::<Vec<&str>>();
+
+        let mut exe = self.inner(LABEL)?;
+
+        let report = error_context(LABEL, || exe.filter_resources_from_import_graph(&keep_refs))?;
+
+        let mut removed = Dictionary::default();
+
+        for (name, reason) in report.removed {
+            removed
+                .insert(Value::from(name), Value::from(reason))
+                .expect("error inserting value; this should not happen");
+        }
+
+        Ok(Value::try_from(removed.get_content().clone()).unwrap())
+    }
 }
 
 starlark_module! { python_executable_env =>
@@ -1055,6 +1255,18 @@ starlark_module! { python_executable_env =>
         this.read_package_root(env, cs, path, &packages)
     }
 
+    PythonExecutable.read_pyproject(
+        env env,
+        call_stack cs,
+        this,
+        path: String,
+        extras=NoneType::None,
+        groups=NoneType::None
+    ) {
+        let mut this = this.downcast_mut::<PythonExecutableValue>().unwrap().unwrap();
+        this.read_pyproject(env, cs, path, &extras, &groups)
+    }
+
     PythonExecutable.read_virtualenv(
         env env,
         call_stack cs,
@@ -1065,6 +1277,17 @@ starlark_module! { python_executable_env =>
         this.read_virtualenv(env, cs, path)
     }
 
+    PythonExecutable.wheelhouse_install(
+        env env,
+        call_stack cs,
+        this,
+        path: String,
+        requirements
+    ) {
+        let mut this = this.downcast_mut::<PythonExecutableValue>().unwrap().unwrap();
+        this.wheelhouse_install(env, cs, path, &requirements)
+    }
+
     PythonExecutable.setup_py_install(
         env env,
         call_stack cs,
@@ -1077,6 +1300,17 @@ starlark_module! { python_executable_env =>
         this.setup_py_install(env, cs, package_path, &extra_envs, &extra_global_arguments)
     }
 
+    PythonExecutable.pep517_build(
+        env env,
+        call_stack cs,
+        this,
+        path: String,
+        extra_envs=NoneType::None
+    ) {
+        let mut this = this.downcast_mut::<PythonExecutableValue>().unwrap().unwrap();
+        this.pep517_build(env, cs, path, &extra_envs)
+    }
+
     PythonExecutable.add_python_resource(
         this,
         resource
@@ -1118,6 +1352,11 @@ starlark_module! { python_executable_env =>
         this.filter_resources_from_files(&files, &glob_files)
     }
 
+    PythonExecutable.filter_resources_from_import_graph(this, keep=NoneType::None) {
+        let mut this = this.downcast_mut::<PythonExecutableValue>().unwrap().unwrap();
+        this.filter_resources_from_import_graph(&keep)
+    }
+
     PythonExecutable.to_embedded_resources(this) {
         let this = this.downcast_ref::<PythonExecutableValue>().unwrap();
         this.to_embedded_resources()
@@ -1170,6 +1409,8 @@ mod tests {
         super::super::testutil::*,
         super::*,
         crate::{python_distributions::PYTHON_DISTRIBUTIONS, testutil::*},
+        python_packaging::wheel_builder::WheelBuilder,
+        simple_file_manifest::FileEntry,
     };
 
     #[test]
@@ -1192,6 +1433,49 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_wheelhouse_install() -> Result<()> {
+        let temp_dir = tempfile::Builder::new()
+            .prefix("pyoxidizer-test")
+            .tempdir()?;
+
+        let mut builder = WheelBuilder::new("foo", "1.0");
+        builder.add_file(
+            "foo/__init__.py",
+            FileEntry::new_from_data(b"import os\n".to_vec(), false),
+        )?;
+        builder.write_wheel_into_directory(temp_dir.path())?;
+
+        let mut env = test_evaluation_context_builder()?.into_context()?;
+        add_exe(&mut env)?;
+
+        let resources = env.eval(&format!(
+            "exe.wheelhouse_install({:?}, ['foo==1.0'])",
+            temp_dir.path().display().to_string()
+        ))?;
+        assert_eq!(resources.get_type(), "list");
+
+        let modules = resources
+            .iter()
+            .unwrap()
+            .iter()
+            .filter_map(|v| {
+                v.downcast_ref::<PythonModuleSourceValue>()
+                    .map(|x| x.inner("ignored").unwrap().m.name.clone())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(modules, vec!["foo".to_string()]);
+
+        assert!(env
+            .eval(&format!(
+                "exe.wheelhouse_install({:?}, ['foo==2.0'])",
+                temp_dir.path().display().to_string()
+            ))
+            .is_err());
+
+        Ok(())
+    }
+
     #[test]
     fn test_no_sources() -> Result<()> {
         let mut env = test_evaluation_context_builder()?.into_context()?;
@@ -216,11 +216,23 @@ mod tests {
             "filesystem-relative:lib"
         );
 
+        m.set_attr("add_location", Value::from("extract-to-cache"))
+            .unwrap();
+        assert_eq!(
+            m.get_attr("add_location").unwrap().to_str(),
+            "extract-to-cache"
+        );
+
         assert!(m.has_attr("add_location_fallback").unwrap());
 
         assert_eq!(
             m.get_attr("add_location_fallback").unwrap().get_type(),
-            if dist.supports_in_memory_shared_library_loading() {
+            if dist
+                .create_packaging_policy()
+                .unwrap()
+                .resources_location_fallback()
+                .is_some()
+            {
                 "string"
             } else {
                 "NoneType"
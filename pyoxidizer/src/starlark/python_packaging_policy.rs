@@ -4,6 +4,7 @@
 
 use {
     super::python_resource::ResourceCollectionContext,
+    anyhow::anyhow,
     linked_hash_map::LinkedHashMap,
     python_packaging::{
         location::ConcreteResourceLocation,
@@ -137,6 +138,9 @@ impl TypedValue for PythonPackagingPolicyValue {
                 Value::from(inner.include_non_distribution_sources())
             }
             "include_test" => Value::from(inner.include_test()),
+            "packed_resources_compress" => Value::from(inner.packed_resources_compress()),
+            "packed_resources_deduplicate" => Value::from(inner.packed_resources_deduplicate()),
+            "packed_resources_version" => Value::from(inner.packed_resources_version() as i64),
             "preferred_extension_module_variants" => {
                 Value::try_from(inner.preferred_extension_module_variants().clone())?
             }
@@ -174,6 +178,9 @@ impl TypedValue for PythonPackagingPolicyValue {
                 | "include_file_resources"
                 | "include_non_distribution_sources"
                 | "include_test"
+                | "packed_resources_compress"
+                | "packed_resources_deduplicate"
+                | "packed_resources_version"
                 | "preferred_extension_module_variants"
                 | "resources_location"
                 | "resources_location_fallback"
@@ -235,6 +242,26 @@ impl TypedValue for PythonPackagingPolicyValue {
             "include_test" => {
                 inner.set_include_test(value.to_bool());
             }
+            "packed_resources_compress" => {
+                inner.set_packed_resources_compress(value.to_bool());
+            }
+            "packed_resources_deduplicate" => {
+                inner.set_packed_resources_deduplicate(value.to_bool());
+            }
+            "packed_resources_version" => {
+                let version = value.to_int()?;
+
+                u8::try_from(version)
+                    .map_err(|_| anyhow!("invalid packed resources version: {}", version))
+                    .and_then(|version| inner.set_packed_resources_version(version))
+                    .map_err(|e| {
+                        ValueError::from(RuntimeError {
+                            code: "PYOXIDIZER_BUILD",
+                            message: e.to_string(),
+                            label: format!("{}.{} = {}", Self::TYPE, attribute, value),
+                        })
+                    })?;
+            }
             "resources_location" => {
                 inner.set_resources_location(
                     ConcreteResourceLocation::try_from(value.to_string().as_str()).map_err(
@@ -487,7 +514,7 @@ mod tests {
         assert_eq!(value.to_string(), "filesystem-relative:lib");
 
         let value = env.eval("policy.resources_location_fallback")?;
-        if dist_ref.supports_in_memory_shared_library_loading() {
+        if policy.resources_location_fallback().is_some() {
             assert_eq!(value.get_type(), "string");
             assert_eq!(value.to_string(), "filesystem-relative:lib");
         } else {
@@ -566,6 +593,44 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_packed_resources() -> Result<()> {
+        let mut env = test_evaluation_context_builder()?.into_context()?;
+
+        env.eval("dist = default_python_distribution()")?;
+        env.eval("policy = dist.make_python_packaging_policy()")?;
+
+        let value = env.eval("policy.packed_resources_version")?;
+        assert_eq!(value.get_type(), "int");
+        assert_eq!(value.to_int().unwrap(), 3);
+
+        let value =
+            env.eval("policy.packed_resources_version = 4; policy.packed_resources_version")?;
+        assert_eq!(value.to_int().unwrap(), 4);
+
+        assert!(env.eval("policy.packed_resources_version = 5").is_err());
+        assert!(env.eval("policy.packed_resources_version = -1").is_err());
+
+        let value = env.eval("policy.packed_resources_compress")?;
+        assert_eq!(value.get_type(), "bool");
+        assert!(!value.to_bool());
+
+        let value =
+            env.eval("policy.packed_resources_compress = True; policy.packed_resources_compress")?;
+        assert!(value.to_bool());
+
+        let value = env.eval("policy.packed_resources_deduplicate")?;
+        assert_eq!(value.get_type(), "bool");
+        assert!(!value.to_bool());
+
+        let value = env.eval(
+            "policy.packed_resources_deduplicate = True; policy.packed_resources_deduplicate",
+        )?;
+        assert!(value.to_bool());
+
+        Ok(())
+    }
+
     #[test]
     fn test_preferred_extension_module_variants() -> Result<()> {
         let mut env = test_evaluation_context_builder()?.into_context()?;
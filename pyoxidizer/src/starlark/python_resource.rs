@@ -39,6 +39,7 @@ impl From<&OptionalResourceLocation> for Value {
             Some(ConcreteResourceLocation::RelativePath(prefix)) => {
                 Value::from(format!("filesystem-relative:{}", prefix))
             }
+            Some(ConcreteResourceLocation::ExtractToCache) => Value::from("extract-to-cache"),
             None => Value::from(NoneType::None),
         }
     }
@@ -54,6 +55,10 @@ impl TryFrom<&str> for OptionalResourceLocation {
             Ok(OptionalResourceLocation {
                 inner: Some(ConcreteResourceLocation::InMemory),
             })
+        } else if s == "extract-to-cache" {
+            Ok(OptionalResourceLocation {
+                inner: Some(ConcreteResourceLocation::ExtractToCache),
+            })
         } else if s.starts_with("filesystem-relative:") {
             let prefix = s.split_at("filesystem-relative:".len()).1;
             Ok(OptionalResourceLocation {
@@ -64,7 +69,7 @@ impl TryFrom<&str> for OptionalResourceLocation {
                 code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
                 message: format!("unable to convert value {} to a resource location", s),
                 label: format!(
-                    "expected `default`, `in-memory`, or `filesystem-relative:*`; got {}",
+                    "expected `default`, `in-memory`, `extract-to-cache`, or `filesystem-relative:*`; got {}",
                     s
                 ),
             }))
@@ -0,0 +1,292 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*! Extracting embedded resources to a cache directory. */
+
+use {
+    sha2::{Digest, Sha256},
+    std::{
+        borrow::Cow,
+        collections::HashMap,
+        io::Write,
+        path::{Path, PathBuf},
+        sync::Mutex,
+        time::{Duration, SystemTime},
+    },
+};
+
+/// Name of the file recording when an extraction directory was last used.
+const LAST_USED_FILE: &str = ".last-used";
+
+/// How long an extraction directory must be unused before it is removed.
+///
+/// Processes running other builds of the executable may still be using their
+/// extraction directory. So only directories no process has used for a while
+/// are removed.
+const STALE_DIRECTORY_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Filename suffix given to extracted extension modules.
+///
+/// This is a suffix every CPython build on the platform recognizes.
+#[cfg(windows)]
+pub const EXTENSION_MODULE_SUFFIX: &str = ".pyd";
+#[cfg(not(windows))]
+pub const EXTENSION_MODULE_SUFFIX: &str = ".so";
+
+/// Obtain the path of a module's source file relative to the extraction directory.
+pub fn module_source_path(name: &str, is_package: bool) -> PathBuf {
+    let mut path = name.split('.').collect::<PathBuf>();
+
+    if is_package {
+        path.push("__init__.py");
+    } else {
+        path.set_extension("py");
+    }
+
+    path
+}
+
+/// Obtain the path of an extension module relative to the extraction directory.
+pub fn extension_module_path(name: &str, is_package: bool) -> PathBuf {
+    let mut path = name.split('.').collect::<PathBuf>();
+
+    if is_package {
+        path.push(format!("__init__{}", EXTENSION_MODULE_SUFFIX));
+    } else {
+        let leaf = format!(
+            "{}{}",
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .unwrap_or_default(),
+            EXTENSION_MODULE_SUFFIX
+        );
+        path.set_file_name(leaf);
+    }
+
+    path
+}
+
+/// Obtain the default root directory of the extraction cache.
+///
+/// This is a directory named after the executable in the per-user cache
+/// directory. The shared temporary directory isn't used as a fallback, as
+/// other users could place files there that would then be imported.
+fn default_cache_root(current_exe: &Path) -> Result<PathBuf, String> {
+    let exe_name = current_exe
+        .file_stem()
+        .and_then(|name| name.to_str())
+        .unwrap_or("python");
+
+    let cache_dir = dirs::cache_dir().ok_or_else(|| {
+        "unable to resolve per-user cache directory; set the resources cache directory".to_string()
+    })?;
+
+    Ok(cache_dir.join("pyoxidizer").join(exe_name))
+}
+
+/// Obtain the name of a content-addressed cache directory.
+///
+/// The name is `name` followed by a digest of the content fed to `hasher`,
+/// so different builds having the same name don't share extracted files.
+pub(crate) fn cache_directory_name(name: &str, hasher: Sha256) -> String {
+    let digest = hasher.finalize();
+    let digest = digest[0..8]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<String>();
+
+    format!("{}-{}", name, digest)
+}
+
+/// Write a file atomically, unless it already exists with the same content.
+///
+/// The file is written to a temporary file that is renamed into place, so
+/// concurrent processes extracting the same file don't see partial content.
+/// An existing file having different content, e.g. because a previous write
+/// was interrupted or the file was modified, is replaced.
+pub(crate) fn write_file(path: &Path, data: &[u8]) -> Result<(), String> {
+    if let Ok(existing) = std::fs::read(path) {
+        if existing == data {
+            return Ok(());
+        }
+    }
+
+    let parent = path
+        .parent()
+        .ok_or_else(|| format!("unable to resolve parent of {}", path.display()))?;
+    std::fs::create_dir_all(parent)
+        .map_err(|e| format!("error creating {}: {}", parent.display(), e))?;
+
+    let mut temp = tempfile::NamedTempFile::new_in(parent).map_err(|e| {
+        format!(
+            "error creating temporary file in {}: {}",
+            parent.display(),
+            e
+        )
+    })?;
+    temp.write_all(data)
+        .map_err(|e| format!("error writing {}: {}", path.display(), e))?;
+    temp.persist(path)
+        .map_err(|e| format!("error writing {}: {}", path.display(), e))?;
+
+    Ok(())
+}
+
+/// Record that an extraction directory is in use.
+fn mark_directory_used(dir: &Path) -> Result<(), String> {
+    let path = dir.join(LAST_USED_FILE);
+
+    std::fs::write(&path, std::process::id().to_string())
+        .map_err(|e| format!("error writing {}: {}", path.display(), e))
+}
+
+/// Whether an extraction directory hasn't been used for [STALE_DIRECTORY_AGE].
+///
+/// Directories without a record of their last use are judged by their own
+/// modification time.
+fn is_unused_directory(dir: &Path, now: SystemTime) -> bool {
+    std::fs::metadata(dir.join(LAST_USED_FILE))
+        .or_else(|_| std::fs::metadata(dir))
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| now.duration_since(modified).ok())
+        .map(|age| age >= STALE_DIRECTORY_AGE)
+        .unwrap_or(false)
+}
+
+/// Remove unused extraction directories of a package other than `current`.
+///
+/// These were produced by other builds of the executable. Directories used
+/// within [STALE_DIRECTORY_AGE] are kept, as a process running another build
+/// may still be using them. Errors are ignored.
+fn remove_stale_directories(root: &Path, package: &str, current: &str) {
+    let prefix = format!("{}-", package);
+    let now = SystemTime::now();
+
+    let entries = match std::fs::read_dir(root) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let name = match name.to_str() {
+            Some(name) => name,
+            None => continue,
+        };
+
+        // Digests are hex, so a package whose name has the prefix of another
+        // package followed by `-` can't be confused with it.
+        let is_stale = name != current
+            && name
+                .strip_prefix(&prefix)
+                .map(|digest| digest.chars().all(|c| c.is_ascii_hexdigit()))
+                .unwrap_or(false);
+
+        if is_stale
+            && entry.file_type().map(|t| t.is_dir()).unwrap_or(false)
+            && is_unused_directory(&entry.path(), now)
+        {
+            let _ = std::fs::remove_dir_all(entry.path());
+        }
+    }
+}
+
+/// Tracks top-level packages whose resources were extracted to a cache directory.
+#[derive(Debug, Default)]
+pub struct ExtractCache {
+    /// Root directory of the cache.
+    ///
+    /// If [None], a directory in the per-user cache directory is used. It is
+    /// an error if the platform doesn't define one.
+    cache_dir: Option<PathBuf>,
+
+    /// Extraction directory of each extracted top-level package.
+    packages: Mutex<HashMap<String, PathBuf>>,
+}
+
+impl ExtractCache {
+    /// Set the root directory of the cache.
+    pub fn set_cache_dir(&mut self, path: Option<PathBuf>) {
+        self.cache_dir = path;
+    }
+
+    /// Obtain the extraction directory of a top-level package, if it was extracted.
+    pub fn package_dir(&self, package: &str) -> Option<PathBuf> {
+        self.packages
+            .lock()
+            .expect("extract cache lock poisoned")
+            .get(package)
+            .cloned()
+    }
+
+    /// Resolve a path within an extraction directory to a path relative to it.
+    pub fn relative_path<'p>(&self, path: &'p Path) -> Option<&'p Path> {
+        self.packages
+            .lock()
+            .expect("extract cache lock poisoned")
+            .values()
+            .find_map(|dir| path.strip_prefix(dir).ok())
+    }
+
+    /// Extract files of a top-level package.
+    ///
+    /// `files` holds paths relative to the extraction directory and their content.
+    /// Files are written to a directory named after the package and a digest of
+    /// `files` under the cache root. Existing files having the expected content
+    /// are reused. Extraction directories of the package having a different
+    /// digest are removed once they haven't been used for a while.
+    ///
+    /// Returns the extraction directory.
+    pub fn extract_package(
+        &self,
+        current_exe: &Path,
+        package: &str,
+        mut files: Vec<(PathBuf, Cow<[u8]>)>,
+    ) -> Result<PathBuf, String> {
+        let mut packages = self
+            .packages
+            .lock()
+            .map_err(|_| "extract cache lock poisoned".to_string())?;
+
+        if let Some(dir) = packages.get(package) {
+            return Ok(dir.clone());
+        }
+
+        let root = match &self.cache_dir {
+            Some(path) => path.clone(),
+            None => default_cache_root(current_exe)?,
+        };
+
+        files.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut hasher = Sha256::new();
+        for (path, data) in &files {
+            let path = path.to_string_lossy();
+            hasher.update((path.len() as u64).to_le_bytes());
+            hasher.update(path.as_bytes());
+            hasher.update((data.len() as u64).to_le_bytes());
+            hasher.update(data);
+        }
+
+        let name = cache_directory_name(package, hasher);
+        let dir = root.join(&name);
+
+        for (relative_path, data) in &files {
+            write_file(&dir.join(relative_path), data)?;
+        }
+
+        // Ensure the package directory exists, even if nothing was extracted,
+        // so `__path__` refers to a real directory.
+        std::fs::create_dir_all(dir.join(package))
+            .map_err(|e| format!("error creating {}: {}", dir.display(), e))?;
+
+        mark_directory_used(&dir)?;
+        remove_stale_directories(&root, package, &name);
+
+        packages.insert(package.to_string(), dir.clone());
+
+        Ok(dir)
+    }
+}
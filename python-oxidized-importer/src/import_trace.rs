@@ -0,0 +1,146 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*! Recording of import activity performed by `OxidizedFinder`. */
+
+use {
+    pyo3::{prelude::*, types::PyDict},
+    std::{
+        sync::Mutex,
+        time::{Duration, Instant},
+    },
+};
+
+/// An importer operation recorded in an import trace.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImportOperation {
+    /// `find_spec()`.
+    FindSpec,
+    /// `exec_module()`.
+    ExecModule,
+}
+
+impl ImportOperation {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::FindSpec => "find_spec",
+            Self::ExecModule => "exec_module",
+        }
+    }
+}
+
+/// Where the code of an imported module comes from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImportSource {
+    /// Source, bytecode, or an extension module held in memory.
+    InMemory,
+    /// Source, bytecode, or an extension module in a path relative to the origin.
+    FilesystemRelative,
+    /// A built-in extension module.
+    Builtin,
+    /// A frozen module.
+    Frozen,
+    /// A namespace package, which has no code.
+    Namespace,
+}
+
+impl ImportSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::InMemory => "in-memory",
+            Self::FilesystemRelative => "filesystem-relative",
+            Self::Builtin => "builtin",
+            Self::Frozen => "frozen",
+            Self::Namespace => "namespace",
+        }
+    }
+}
+
+/// A single recorded importer operation.
+#[derive(Clone, Debug)]
+pub struct ImportTraceRecord {
+    /// The operation performed.
+    pub operation: ImportOperation,
+    /// Name of the module the operation was performed on.
+    pub name: String,
+    /// Where the module comes from.
+    ///
+    /// `None` if the importer does not know about the module.
+    pub source: Option<ImportSource>,
+    /// When the operation started, relative to the start of the trace.
+    pub start: Duration,
+    /// How long the operation took.
+    ///
+    /// For `exec_module()`, this includes imports performed by the module.
+    pub elapsed: Duration,
+}
+
+impl ImportTraceRecord {
+    /// Whether the importer knew about the module.
+    pub fn hit(&self) -> bool {
+        self.source.is_some()
+    }
+
+    /// Convert to a Python `dict`.
+    pub fn to_dict<'p>(&self, py: Python<'p>) -> PyResult<&'p PyDict> {
+        let dict = PyDict::new(py);
+
+        dict.set_item("operation", self.operation.as_str())?;
+        dict.set_item("name", &self.name)?;
+        dict.set_item("hit", self.hit())?;
+        dict.set_item("source", self.source.map(|source| source.as_str()))?;
+        dict.set_item("start", self.start.as_secs_f64())?;
+        dict.set_item("elapsed", self.elapsed.as_secs_f64())?;
+
+        Ok(dict)
+    }
+}
+
+/// Records importer operations in the order they complete.
+#[derive(Debug)]
+pub struct ImportTrace {
+    start: Instant,
+    records: Mutex<Vec<ImportTraceRecord>>,
+}
+
+impl Default for ImportTrace {
+    fn default() -> Self {
+        Self {
+            start: Instant::now(),
+            records: Mutex::new(vec![]),
+        }
+    }
+}
+
+impl ImportTrace {
+    /// Record an operation that started at `started` and just finished.
+    pub fn record(
+        &self,
+        operation: ImportOperation,
+        name: &str,
+        source: Option<ImportSource>,
+        started: Instant,
+    ) {
+        let record = ImportTraceRecord {
+            operation,
+            name: name.to_string(),
+            source,
+            start: started.saturating_duration_since(self.start),
+            elapsed: started.elapsed(),
+        };
+
+        self.records
+            .lock()
+            .expect("import trace lock should not be poisoned")
+            .push(record);
+    }
+
+    /// Obtain a copy of all recorded operations.
+    pub fn records(&self) -> Vec<ImportTraceRecord> {
+        self.records
+            .lock()
+            .expect("import trace lock should not be poisoned")
+            .clone()
+    }
+}
@@ -10,15 +10,14 @@ for importing Python modules from memory.
 */
 
 #[cfg(windows)]
-use {
-    crate::memory_dll::{free_library_memory, get_proc_address_memory, load_library_memory},
-    pyo3::exceptions::PySystemError,
-    std::ffi::{c_void, CString},
-};
+use crate::memory_dll::{free_library_memory, get_proc_address_memory, load_library_memory};
+#[cfg(target_os = "linux")]
+use crate::memory_so::{free_library_memory, get_proc_address_memory, load_library_memory};
 use {
     crate::{
         conversion::pyobject_to_pathbuf,
         get_module_state,
+        import_trace::{ImportOperation, ImportSource, ImportTrace},
         path_entry_finder::OxidizedPathEntryFinder,
         pkg_resources::register_pkg_resources_with_module,
         python_resources::{
@@ -35,10 +34,15 @@ use {
         AsPyPointer, FromPyPointer, PyNativeType, PyTraverseError, PyVisit,
     },
     python_packaging::resource::BytecodeOptimizationLevel,
-    std::sync::Arc,
+    std::{sync::Arc, time::Instant},
+};
+#[cfg(any(windows, target_os = "linux"))]
+use {
+    pyo3::exceptions::PySystemError,
+    std::ffi::{c_void, CString},
 };
 
-#[cfg(windows)]
+#[cfg(any(windows, target_os = "linux"))]
 #[allow(non_camel_case_types)]
 type py_init_fn = extern "C" fn() -> *mut pyffi::PyObject;
 
@@ -57,7 +61,7 @@ type py_init_fn = extern "C" fn() -> *mut pyffi::PyObject;
 /// `_PyImport_LoadDynamicModuleWithSpec()` is more interesting. It takes a
 /// `FILE*` for the extension location, so we can't call it. So we need to
 /// reimplement it. Documentation of that is inline.
-#[cfg(windows)]
+#[cfg(any(windows, target_os = "linux"))]
 fn extension_module_shared_library_create_module(
     resources_state: &PythonResourcesState<u8>,
     py: Python,
@@ -67,25 +71,33 @@ fn extension_module_shared_library_create_module(
     name: &str,
     library_data: &[u8],
 ) -> PyResult<Py<PyAny>> {
-    let origin = PyString::new(py, "memory");
-
-    let existing_module =
-        unsafe { pyffi::_PyImport_FindExtensionObject(name_py.as_ptr(), origin.as_ptr()) };
-
-    // We found an existing module object. Return it.
-    if !existing_module.is_null() {
-        return Ok(unsafe { PyObject::from_owned_ptr(py, existing_module) });
-    }
+    // `_PyImport_FindExtensionObject()` is no longer exported as of Python 3.11.
+    // There, we rely on the import machinery having consulted `sys.modules`.
+    #[cfg(not(Py_3_11))]
+    {
+        let origin = PyString::new(py, "memory");
+
+        let existing_module =
+            unsafe { pyffi::_PyImport_FindExtensionObject(name_py.as_ptr(), origin.as_ptr()) };
+
+        // We found an existing module object. Return it.
+        if !existing_module.is_null() {
+            return Ok(unsafe { PyObject::from_owned_ptr(py, existing_module) });
+        }
 
-    // An error occurred calling _PyImport_FindExtensionObjectEx(). Raise it.
-    if !unsafe { pyffi::PyErr_Occurred() }.is_null() {
-        return Err(PyErr::fetch(py));
+        // An error occurred calling _PyImport_FindExtensionObjectEx(). Raise it.
+        if !unsafe { pyffi::PyErr_Occurred() }.is_null() {
+            return Err(PyErr::fetch(py));
+        }
     }
 
     // New module load request. Proceed to _PyImport_LoadDynamicModuleWithSpec()
     // functionality.
 
+    #[cfg(windows)]
     let module = unsafe { load_library_memory(resources_state, library_data) };
+    #[cfg(target_os = "linux")]
+    let module = unsafe { load_library_memory(resources_state, name, library_data) };
 
     if module.is_null() {
         return Err(PyImportError::new_err((
@@ -94,18 +106,15 @@ fn extension_module_shared_library_create_module(
         )));
     }
 
-    // Any error past this point should call `MemoryFreeLibrary()` to unload the
+    // Any error past this point should call `free_library_memory()` to unload the
     // library.
 
-    load_dynamic_library(py, sys_modules, spec, name_py, name, module).map_err(|e| {
-        unsafe {
-            free_library_memory(module);
-        }
-        e
+    load_dynamic_library(py, sys_modules, spec, name_py, name, module).inspect_err(|_| unsafe {
+        free_library_memory(module);
     })
 }
 
-#[cfg(unix)]
+#[cfg(not(any(windows, target_os = "linux")))]
 fn extension_module_shared_library_create_module(
     _resources_state: &PythonResourcesState<u8>,
     _py: Python,
@@ -115,11 +124,11 @@ fn extension_module_shared_library_create_module(
     _name: &str,
     _library_data: &[u8],
 ) -> PyResult<Py<PyAny>> {
-    panic!("should only be called on Windows");
+    panic!("should only be called on Windows or Linux");
 }
 
 /// Reimplementation of `_PyImport_LoadDynamicModuleWithSpec()`.
-#[cfg(windows)]
+#[cfg(any(windows, target_os = "linux"))]
 fn load_dynamic_library(
     py: Python,
     sys_modules: &PyAny,
@@ -130,7 +139,7 @@ fn load_dynamic_library(
 ) -> PyResult<Py<PyAny>> {
     // The init function is `PyInit_<stem>`.
     let last_name_part = if name.contains('.') {
-        name.split('.').last().unwrap()
+        name.split('.').next_back().unwrap()
     } else {
         name
     };
@@ -194,7 +203,10 @@ fn load_dynamic_library(
     // module by calling PyModule_FromDefAndSpec(). py_module is a borrowed reference. And
     // PyModule_FromDefAndSpec() returns a new reference. So we don't need to worry about refcounts
     // of py_module.
-    if unsafe { pyffi::PyObject_TypeCheck(py_module, &mut pyffi::PyModuleDef_Type) } != 0 {
+    if unsafe {
+        pyffi::PyObject_TypeCheck(py_module, std::ptr::addr_of_mut!(pyffi::PyModuleDef_Type))
+    } != 0
+    {
         let py_module = unsafe {
             pyffi::PyModule_FromDefAndSpec(py_module as *mut pyffi::PyModuleDef, spec.as_ptr())
         };
@@ -211,7 +223,7 @@ fn load_dynamic_library(
     // leak it.
     let py_module = unsafe { PyObject::from_owned_ptr(py, py_module) };
 
-    let mut module_def = unsafe { pyffi::PyModule_GetDef(py_module.as_ptr()) };
+    let module_def = unsafe { pyffi::PyModule_GetDef(py_module.as_ptr()) };
     if module_def.is_null() {
         return Err(PySystemError::new_err(format!(
             "initialization of {} did not return an extension module",
@@ -271,6 +283,8 @@ pub struct ImporterState {
     pub(crate) multiprocessing_set_start_method: Option<String>,
     /// Whether to automatically register ourself with `pkg_resources` when it is imported.
     pub(crate) pkg_resources_import_auto_register: bool,
+    /// Records `find_spec()` and `exec_module()` calls, if import tracing is enabled.
+    pub(crate) import_trace: Option<ImportTrace>,
     /// Holds state about importable resources.
     ///
     /// This field is a PyCapsule and is a glorified wrapper around
@@ -293,7 +307,7 @@ impl ImporterState {
         py: Python,
         importer_module: &PyModule,
         bootstrap_module: &PyModule,
-        resources_state: Box<PythonResourcesState<'a, u8>>,
+        #[allow(unused_mut)] mut resources_state: Box<PythonResourcesState<'a, u8>>,
     ) -> Result<Self, PyErr> {
         let decode_source = importer_module.getattr("decode_source")?.into_py(py);
 
@@ -301,7 +315,7 @@ impl ImporterState {
         let marshal_module = py.import("marshal")?;
 
         let imp_module = bootstrap_module.getattr("_imp")?;
-        let imp_module = imp_module.downcast::<PyModule>()?.into_py(py);
+        let imp_module: Py<PyModule> = imp_module.downcast::<PyModule>()?.into_py(py);
         let sys_module = bootstrap_module.getattr("sys")?;
         let sys_module = sys_module.downcast::<PyModule>()?;
         let meta_path_object = sys_module.getattr("meta_path")?;
@@ -339,6 +353,26 @@ impl ImporterState {
         }
         .into_py(py);
 
+        // Wheels embedded as file data can only be indexed once we know how the
+        // interpreter names bytecode and extension module files.
+        #[cfg(feature = "zipimport")]
+        {
+            let cache_tag = sys_module
+                .getattr("implementation")?
+                .getattr("cache_tag")?
+                .extract::<Option<String>>()?;
+            let extension_suffixes = imp_module
+                .getattr(py, "extension_suffixes")?
+                .call0(py)?
+                .extract::<Vec<String>>(py)?;
+
+            if let Some(cache_tag) = cache_tag {
+                resources_state
+                    .index_embedded_wheels(&cache_tag, &extension_suffixes)
+                    .map_err(PyValueError::new_err)?;
+            }
+        }
+
         let sys_flags = sys_module.getattr("flags")?;
         let sys_module = sys_module.into_py(py);
 
@@ -389,6 +423,7 @@ impl ImporterState {
             multiprocessing_set_start_method: None,
             // TODO value should come from config.
             pkg_resources_import_auto_register: true,
+            import_trace: None,
             resources_state: capsule,
         })
     }
@@ -448,6 +483,35 @@ impl ImporterState {
     pub fn set_multiprocessing_set_start_method(&mut self, value: Option<String>) {
         self.multiprocessing_set_start_method = value;
     }
+
+    /// Set whether to record `find_spec()` and `exec_module()` calls.
+    ///
+    /// Enabling tracing starts a new, empty trace.
+    pub fn set_trace_imports(&mut self, enabled: bool) {
+        self.import_trace = if enabled {
+            Some(ImportTrace::default())
+        } else {
+            None
+        };
+    }
+
+    /// Obtain the import trace, if import tracing is enabled.
+    pub fn import_trace(&self) -> Option<&ImportTrace> {
+        self.import_trace.as_ref()
+    }
+
+    /// Record an importer operation in the import trace, if enabled.
+    fn trace_import(
+        &self,
+        operation: ImportOperation,
+        name: &str,
+        source: Option<ImportSource>,
+        started: Instant,
+    ) {
+        if let Some(trace) = &self.import_trace {
+            trace.record(operation, name, source, started);
+        }
+    }
 }
 
 impl Drop for ImporterState {
@@ -521,19 +585,35 @@ impl OxidizedFinder {
         path: &PyAny,
         target: Option<&PyAny>,
     ) -> PyResult<&'p PyAny> {
+        let started = Instant::now();
         let py = slf.py();
         let finder = slf.borrow();
 
+        // Packages extracted to a cache directory are extracted when first found,
+        // so the spec can refer to the extracted files.
+        finder
+            .state
+            .get_resources_state()
+            .extract_module_to_cache(&fullname)
+            .map_err(|e| PyImportError::new_err((e, fullname.clone())))?;
+
         let module = match finder
             .state
             .get_resources_state()
             .resolve_importable_module(&fullname, finder.state.optimize_level)
         {
             Some(module) => module,
-            None => return Ok(py.None().into_ref(py)),
+            None => {
+                finder
+                    .state
+                    .trace_import(ImportOperation::FindSpec, &fullname, None, started);
+                return Ok(py.None().into_ref(py));
+            }
         };
 
-        match module.flavor {
+        let source = module.import_source(finder.state.optimize_level);
+
+        let spec = match module.flavor {
             ModuleFlavor::Extension | ModuleFlavor::SourceBytecode => module.resolve_module_spec(
                 py,
                 finder.state.module_spec_type.clone_ref(py).into_ref(py),
@@ -546,15 +626,22 @@ impl OxidizedFinder {
                 Ok(finder
                     .state
                     .builtin_importer
-                    .call_method(py, "find_spec", (fullname,), None)?
+                    .call_method(py, "find_spec", (&fullname,), None)?
                     .into_ref(py))
             }
             ModuleFlavor::Frozen => Ok(finder
                 .state
                 .frozen_importer
-                .call_method(py, "find_spec", (fullname, path, target), None)?
+                .call_method(py, "find_spec", (&fullname, path, target), None)?
                 .into_ref(py)),
-        }
+            ModuleFlavor::Namespace => Self::resolve_namespace_spec(slf, &fullname, path),
+        };
+
+        finder
+            .state
+            .trace_import(ImportOperation::FindSpec, &fullname, Some(source), started);
+
+        spec
     }
 
     fn find_module<'p>(
@@ -572,7 +659,19 @@ impl OxidizedFinder {
         }
     }
 
-    fn invalidate_caches(&self) -> PyResult<()> {
+    fn invalidate_caches(&self, py: Python) -> PyResult<()> {
+        if !cfg!(debug_assertions) {
+            return Ok(());
+        }
+
+        // `importlib.invalidate_caches()` calls every finder. So failing to
+        // re-index a watched file is logged instead of raised.
+        if let Err(e) = self.state.get_resources_state_mut().reload_watched_paths() {
+            py.import("logging")?
+                .call_method1("getLogger", ("oxidized_importer",))?
+                .call_method1("warning", (e,))?;
+        }
+
         Ok(())
     }
 
@@ -606,7 +705,10 @@ impl OxidizedFinder {
             // If we ever implement our own lazy module importer, we could
             // potentially work around this and move all extension module
             // initialization into `exec_module()`.
-            if let Some(library_data) = &module.in_memory_extension_module_shared_library() {
+            if let Some(library_data) = &module
+                .in_memory_extension_module_shared_library()
+                .map_err(|e| PyImportError::new_err((e, key.clone())))?
+            {
                 let sys_modules = state.sys_module.getattr(py, "modules")?;
 
                 extension_module_shared_library_create_module(
@@ -619,6 +721,13 @@ impl OxidizedFinder {
                     library_data,
                 )
             } else {
+                // Extension modules from wheels are extracted on first import.
+                #[cfg(feature = "zipimport")]
+                state
+                    .get_resources_state()
+                    .extract_wheel_extension_module(&key)
+                    .map_err(|e| PyImportError::new_err((e, key.clone())))?;
+
                 // Call `imp.create_dynamic()` for dynamic extension modules.
                 let create_dynamic = state.imp_module.getattr(py, "create_dynamic")?;
 
@@ -632,6 +741,7 @@ impl OxidizedFinder {
     }
 
     fn exec_module(slf: &PyCell<Self>, module: &PyAny) -> PyResult<Py<PyAny>> {
+        let started = Instant::now();
         let py = slf.py();
         let finder = slf.borrow();
         let state = &finder.state;
@@ -639,23 +749,28 @@ impl OxidizedFinder {
         let name = module.getattr("__name__")?;
         let key = name.extract::<String>()?;
 
-        let mut entry = match state
+        let entry = match state
             .get_resources_state()
             .resolve_importable_module(&key, state.optimize_level)
         {
             Some(entry) => entry,
             None => {
+                state.trace_import(ImportOperation::ExecModule, &key, None, started);
+
                 // Raising here might make more sense, as `find_spec()` shouldn't have returned
                 // an entry for something that we don't know how to handle.
                 return Ok(py.None());
             }
         };
 
-        if let Some(bytecode) = entry.resolve_bytecode(
+        let source = entry.import_source(state.optimize_level);
+
+        let result = if let Some(bytecode) = entry.resolve_bytecode(
             py,
             state.optimize_level,
             state.decode_source.as_ref(py),
             state.io_module.as_ref(py),
+            state.imp_module.as_ref(py),
         )? {
             let code = state.marshal_loads.call(py, (bytecode,), None)?;
             let dict = module.getattr("__dict__")?;
@@ -680,7 +795,10 @@ impl OxidizedFinder {
                 .call(py, (&exec_dynamic, module), None)
         } else {
             Ok(py.None())
-        }?;
+        };
+
+        state.trace_import(ImportOperation::ExecModule, &key, Some(source), started);
+        result?;
 
         // Perform import time side-effects for special modules.
         match key.as_str() {
@@ -732,7 +850,7 @@ impl OxidizedFinder {
 
         let key = fullname.to_string();
 
-        let mut module = match state
+        let module = match state
             .get_resources_state()
             .resolve_importable_module(&key, state.optimize_level)
         {
@@ -745,6 +863,7 @@ impl OxidizedFinder {
             state.optimize_level,
             state.decode_source.as_ref(py),
             state.io_module.as_ref(py),
+            state.imp_module.as_ref(py),
         )? {
             state.marshal_loads.call(py, (bytecode,), None)
         } else if module.flavor == ModuleFlavor::Frozen {
@@ -902,10 +1021,17 @@ impl OxidizedFinder {
 
     // Additional methods provided for convenience.
 
-    /// OxidizedFinder.__new__(relative_path_origin=None))
+    /// OxidizedFinder.__new__(relative_path_origin=None, lazy_index=False, trace_imports=False, track_resource_access=False, strict_layers=False))
     #[new]
-    #[pyo3(signature=(relative_path_origin=None))]
-    fn new(py: Python, relative_path_origin: Option<&PyAny>) -> PyResult<Self> {
+    #[pyo3(signature=(relative_path_origin=None, lazy_index=false, trace_imports=false, track_resource_access=false, strict_layers=false))]
+    fn new(
+        py: Python,
+        relative_path_origin: Option<&PyAny>,
+        lazy_index: bool,
+        trace_imports: bool,
+        track_resource_access: bool,
+        strict_layers: bool,
+    ) -> PyResult<Self> {
         // We need to obtain an ImporterState instance. This requires handles on a
         // few items...
 
@@ -921,13 +1047,15 @@ impl OxidizedFinder {
             resources_state.set_origin(pyobject_to_pathbuf(py, py_origin)?);
         }
 
+        resources_state.set_lazy_index(lazy_index);
+        resources_state.set_track_resource_access(track_resource_access);
+        resources_state.set_strict_layers(strict_layers);
+
+        let mut state = ImporterState::new(py, m, bootstrap_module, resources_state)?;
+        state.set_trace_imports(trace_imports);
+
         Ok(OxidizedFinder {
-            state: Arc::new(ImporterState::new(
-                py,
-                m,
-                bootstrap_module,
-                resources_state,
-            )?),
+            state: Arc::new(state),
         })
     }
 
@@ -963,6 +1091,75 @@ impl OxidizedFinder {
         Ok(self.state.pkg_resources_import_auto_register)
     }
 
+    /// Obtain recorded `find_spec()` and `exec_module()` calls.
+    ///
+    /// Returns `None` if import tracing is not enabled.
+    fn import_trace<'p>(&self, py: Python<'p>) -> PyResult<Option<&'p PyList>> {
+        if let Some(trace) = self.state.import_trace() {
+            let records = trace
+                .records()
+                .iter()
+                .map(|record| record.to_dict(py))
+                .collect::<PyResult<Vec<_>>>()?;
+
+            Ok(Some(PyList::new(py, records)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Obtain which indexed resources were accessed.
+    ///
+    /// Returns a dict whose `accessed` key maps names of accessed resources to
+    /// per-kind access counts and whose `unused` key holds the names of resources
+    /// that were never accessed. Returns `None` if resource access tracking is
+    /// not enabled.
+    fn resource_access<'p>(&self, py: Python<'p>) -> PyResult<Option<&'p PyDict>> {
+        let resources_state = self.state.get_resources_state();
+
+        let (counts, unused) = match (
+            resources_state.resource_access_counts(),
+            resources_state.unused_resource_names(),
+        ) {
+            (Some(counts), Some(unused)) => (counts, unused),
+            _ => return Ok(None),
+        };
+
+        let accessed = PyDict::new(py);
+        for (name, counts) in counts {
+            accessed.set_item(name, counts.to_dict(py)?)?;
+        }
+
+        let result = PyDict::new(py);
+        result.set_item("accessed", accessed)?;
+        result.set_item("unused", PyList::new(py, unused))?;
+
+        Ok(Some(result))
+    }
+
+    /// Obtain the name of the layer that provided a resource.
+    ///
+    /// Returns `None` if the resource isn't known or wasn't indexed from a layer.
+    fn resource_layer(&self, name: &str) -> Option<String> {
+        self.state
+            .get_resources_state()
+            .resource_layer(name)
+            .map(|layer| layer.to_string())
+    }
+
+    /// Obtain resources that were provided by multiple layers.
+    fn layer_conflicts<'p>(&self, py: Python<'p>) -> PyResult<&'p PyList> {
+        let conflicts = self
+            .state
+            .get_resources_state()
+            .layer_conflicts()
+            .iter()
+            .map(|conflict| conflict.to_dict(py))
+            .collect::<PyResult<Vec<_>>>()?;
+
+        Ok(PyList::new(py, conflicts))
+    }
+
     fn path_hook(slf: &PyCell<Self>, path: &PyAny) -> PyResult<OxidizedPathEntryFinder> {
         Self::path_hook_inner(slf, path).map_err(|inner| {
             let err = PyImportError::new_err("error running OxidizedFinder.path_hook");
@@ -980,25 +1177,94 @@ impl OxidizedFinder {
         })
     }
 
-    fn index_bytes(&self, py: Python, data: &PyAny) -> PyResult<()> {
-        self.state
-            .get_resources_state_mut()
-            .index_pyobject(py, data)?;
+    #[pyo3(signature=(data, layer=None, priority=0))]
+    fn index_bytes(
+        &self,
+        py: Python,
+        data: &PyAny,
+        layer: Option<&str>,
+        priority: i32,
+    ) -> PyResult<()> {
+        let resources_state = self.state.get_resources_state_mut();
+
+        if let Some(layer) = layer {
+            resources_state.index_layer_pyobject(py, layer, priority, data)?;
+        } else {
+            resources_state.index_pyobject(py, data)?;
+        }
 
         Ok(())
     }
 
-    fn index_file_memory_mapped(&self, py: Python, path: &PyAny) -> PyResult<()> {
+    #[pyo3(signature=(path, layer=None, priority=0))]
+    fn index_file_memory_mapped(
+        &self,
+        py: Python,
+        path: &PyAny,
+        layer: Option<&str>,
+        priority: i32,
+    ) -> PyResult<()> {
+        let path = pyobject_to_pathbuf(py, path)?;
+        let resources_state = self.state.get_resources_state_mut();
+
+        if let Some(layer) = layer {
+            resources_state.index_layer_path_memory_mapped(layer, priority, path)
+        } else {
+            resources_state.index_path_memory_mapped(path)
+        }
+        .map_err(PyValueError::new_err)?;
+
+        Ok(())
+    }
+
+    fn index_file_watched(&self, py: Python, path: &PyAny) -> PyResult<()> {
+        if !cfg!(debug_assertions) {
+            return Err(PyValueError::new_err(
+                "watching packed resources files requires a build with debug assertions",
+            ));
+        }
+
         let path = pyobject_to_pathbuf(py, path)?;
 
         self.state
             .get_resources_state_mut()
-            .index_path_memory_mapped(path)
+            .index_path_watched(path)
             .map_err(PyValueError::new_err)?;
 
         Ok(())
     }
 
+    /// Re-index watched files that changed since they were indexed.
+    ///
+    /// If `invalidate_modules` is true, modules from changed files are removed
+    /// from `sys.modules` so subsequent imports load the new versions.
+    ///
+    /// Returns the names of resources in changed files.
+    #[pyo3(signature=(invalidate_modules=false))]
+    fn reload_watched_resources<'p>(
+        &self,
+        py: Python<'p>,
+        invalidate_modules: bool,
+    ) -> PyResult<&'p PyList> {
+        let names = self
+            .state
+            .get_resources_state_mut()
+            .reload_watched_paths()
+            .map_err(PyValueError::new_err)?;
+
+        if invalidate_modules {
+            let sys_modules = self.state.sys_module.getattr(py, "modules")?.into_ref(py);
+
+            for name in &names {
+                if sys_modules.contains(name)? {
+                    sys_modules.del_item(name)?;
+                }
+            }
+        }
+
+        Ok(PyList::new(py, names))
+    }
+
     fn index_interpreter_builtins(&self) -> PyResult<()> {
         self.state
             .get_resources_state_mut()
@@ -1075,6 +1341,91 @@ impl OxidizedFinder {
 }
 
 impl OxidizedFinder {
+    /// Resolve the `ModuleSpec` for a namespace package.
+    ///
+    /// Per PEP 420, a namespace package may be split into portions provided by
+    /// multiple distributions. The returned spec searches for submodules in the
+    /// virtual location of the package followed by portions `PathFinder` finds
+    /// on `path`, or `sys.path` if not defined. Entries served by our path hook
+    /// aren't searched by `PathFinder`, as they would resolve back to us.
+    ///
+    /// Returns `None` if `PathFinder` finds a regular package of the same name, as
+    /// regular packages take precedence over namespace packages.
+    fn resolve_namespace_spec<'p>(
+        slf: &'p PyCell<Self>,
+        fullname: &str,
+        path: &PyAny,
+    ) -> PyResult<&'p PyAny> {
+        let py = slf.py();
+        let finder = slf.borrow();
+        let state = &finder.state;
+
+        let path_hook_base = finder.path_hook_base_str(py);
+        let path_hook_prefixes = PyTuple::new(
+            py,
+            [
+                path_hook_base.call_method1("__add__", ("/",))?,
+                path_hook_base.call_method1("__add__", ("\\",))?,
+            ],
+        );
+
+        let search_path = if path.is_none() {
+            state.sys_module.getattr(py, "path")?.into_ref(py)
+        } else {
+            path
+        };
+
+        let portions_path = PyList::empty(py);
+        for entry in search_path.iter()? {
+            let entry = entry?;
+
+            let is_ours = if let Ok(entry) = entry.downcast::<PyString>() {
+                entry.compare(path_hook_base)? == std::cmp::Ordering::Equal
+                    || entry
+                        .call_method1("startswith", (path_hook_prefixes,))?
+                        .extract::<bool>()?
+            } else {
+                false
+            };
+
+            if !is_ours {
+                portions_path.append(entry)?;
+            }
+        }
+
+        let mut location = state.get_resources_state().current_exe().to_path_buf();
+        location.extend(fullname.split('.'));
+
+        let locations = PyList::new(py, [location.into_py(py)]);
+
+        let path_finder = py.import("importlib.machinery")?.getattr("PathFinder")?;
+        let portions_spec = path_finder.call_method1("find_spec", (fullname, portions_path))?;
+
+        if !portions_spec.is_none() {
+            if !portions_spec.getattr("loader")?.is_none() {
+                return Ok(py.None().into_ref(py));
+            }
+
+            let portions = portions_spec.getattr("submodule_search_locations")?;
+            if !portions.is_none() {
+                for portion in portions.iter()? {
+                    locations.append(portion?)?;
+                }
+            }
+        }
+
+        let kwargs = PyDict::new(py);
+        kwargs.set_item("is_package", true)?;
+
+        let spec = state
+            .module_spec_type
+            .call(py, (fullname, py.None()), Some(kwargs))?
+            .into_ref(py);
+        spec.setattr("submodule_search_locations", locations)?;
+
+        Ok(spec)
+    }
+
     fn path_hook_inner(
         slf: &PyCell<Self>,
         path_original: &PyAny,
@@ -5,31 +5,43 @@
 //! oxidized_importer Python extension.
 
 mod conversion;
+mod extract_cache;
+mod import_trace;
 #[allow(clippy::needless_option_as_deref)]
 mod importer;
 #[cfg(windows)]
 mod memory_dll;
+#[cfg(target_os = "linux")]
+mod memory_so;
 mod package_metadata;
 #[allow(clippy::needless_option_as_deref)]
 mod path_entry_finder;
 mod pkg_resources;
+mod pyc;
 #[allow(clippy::needless_option_as_deref)]
 mod python_resource_collector;
 mod python_resource_types;
 mod python_resources;
+mod resource_access;
+mod resource_layers;
 mod resource_reader;
 mod resource_scanning;
 #[cfg(feature = "zipimport")]
+mod wheel_import;
+#[cfg(feature = "zipimport")]
 #[allow(clippy::needless_option_as_deref)]
 mod zip_import;
 
 pub use crate::{
+    import_trace::{ImportOperation, ImportSource, ImportTrace, ImportTraceRecord},
     importer::{
         install_path_hook, remove_external_importers, replace_meta_path_importers, ImporterState,
         OxidizedFinder,
     },
     python_resource_collector::PyTempDir,
-    python_resources::{PackedResourcesSource, PythonResourcesState},
+    python_resources::{PackedResourcesLayer, PackedResourcesSource, PythonResourcesState},
+    resource_access::{ResourceAccessCounts, ResourceAccessKind, ResourceAccessTracker},
+    resource_layers::{LayerConflict, ResourceLayers, ResourcesLayer},
 };
 
 #[cfg(feature = "zipimport")]
@@ -211,6 +223,8 @@ fn module_init(py: Python, m: &PyModule) -> PyResult<()> {
 #[cfg(feature = "zipimport")]
 fn init_zipimport(m: &PyModule) -> PyResult<()> {
     m.add_class::<crate::zip_import::OxidizedZipFinder>()?;
+    m.add_class::<crate::zip_import::OxidizedZipResourceReader>()?;
+    m.add_class::<crate::zip_import::OxidizedZipTraversable>()?;
 
     Ok(())
 }
@@ -0,0 +1,124 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*! Functionality for loading Linux shared libraries from memory.
+
+Library data is written to an anonymous file created with `memfd_create()`
+and loaded by passing its `/proc/self/fd/<N>` path to `dlopen()`. Nothing
+is written to a real filesystem.
+*/
+
+use {
+    crate::python_resources::PythonResourcesState,
+    once_cell::sync::Lazy,
+    std::{
+        collections::HashSet,
+        ffi::{c_void, CStr, CString},
+        fs::File,
+        io::Write,
+        os::unix::io::FromRawFd,
+        sync::Mutex,
+    },
+};
+
+/// Names of shared libraries loaded from memory as dependencies of other libraries.
+///
+/// These libraries are never unloaded: once loaded, they stay loaded for the
+/// lifetime of the process.
+static MEMORY_LIBRARIES: Lazy<Mutex<HashSet<String>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+
+/// Load a library from memory, loading in-memory dependencies from resources state first.
+///
+/// Dependencies come from the `shared_library_dependency_names` of the resource
+/// named `name`, recursively. Since the dynamic linker matches libraries that are
+/// already loaded by their `SONAME`, the `DT_NEEDED` entries of the library then
+/// resolve to the libraries loaded from memory. Dependencies not available in
+/// memory are left for the dynamic linker to find.
+///
+/// Returns NULL on failure.
+pub(crate) unsafe fn load_library_memory(
+    resources_state: &PythonResourcesState<u8>,
+    name: &str,
+    data: &[u8],
+) -> *const c_void {
+    let mut loading = vec![name.to_string()];
+
+    if !load_dependencies_memory(resources_state, name, &mut loading) {
+        return std::ptr::null();
+    }
+
+    dlopen_memory(name, data)
+}
+
+/// Free a library that was loaded from memory.
+pub(crate) unsafe fn free_library_memory(module: *const c_void) {
+    libc::dlclose(module as *mut c_void);
+}
+
+/// Find the address of a symbol in a memory loaded module.
+pub(crate) unsafe fn get_proc_address_memory(module: *const c_void, name: &CStr) -> *mut c_void {
+    libc::dlsym(module as *mut c_void, name.as_ptr())
+}
+
+/// Load the in-memory dependencies of the resource named `name`.
+///
+/// `loading` holds the names of libraries being loaded and guards against cycles.
+unsafe fn load_dependencies_memory(
+    resources_state: &PythonResourcesState<u8>,
+    name: &str,
+    loading: &mut Vec<String>,
+) -> bool {
+    for dependency in resources_state.resolve_shared_library_dependency_names(name) {
+        if loading.contains(&dependency) || MEMORY_LIBRARIES.lock().unwrap().contains(&dependency) {
+            continue;
+        }
+
+        let library_data = match resources_state.resolve_in_memory_shared_library_data(&dependency)
+        {
+            Ok(Some(data)) => data,
+            Ok(None) => continue,
+            Err(_) => return false,
+        };
+
+        loading.push(dependency.clone());
+
+        if !load_dependencies_memory(resources_state, &dependency, loading) {
+            return false;
+        }
+
+        if dlopen_memory(&dependency, &library_data).is_null() {
+            return false;
+        }
+
+        MEMORY_LIBRARIES.lock().unwrap().insert(dependency);
+    }
+
+    true
+}
+
+/// Write library data to a `memfd` and `dlopen()` it.
+unsafe fn dlopen_memory(name: &str, data: &[u8]) -> *const c_void {
+    // The name is only used for display purposes, e.g. in `/proc/self/maps`.
+    let name = match CString::new(name) {
+        Ok(name) => name,
+        Err(_) => return std::ptr::null(),
+    };
+
+    let fd = libc::syscall(libc::SYS_memfd_create, name.as_ptr(), libc::MFD_CLOEXEC) as libc::c_int;
+    if fd < 0 {
+        return std::ptr::null();
+    }
+
+    // The descriptor is closed when the file is dropped. The loaded library
+    // holds its own mapping of the data, so closing it afterwards is safe.
+    let mut file = File::from_raw_fd(fd);
+
+    if file.write_all(data).is_err() {
+        return std::ptr::null();
+    }
+
+    let path = CString::new(format!("/proc/self/fd/{}", fd)).unwrap();
+
+    libc::dlopen(path.as_ptr(), libc::RTLD_NOW)
+}
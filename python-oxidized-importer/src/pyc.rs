@@ -0,0 +1,108 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*! Validation of `.pyc` data against module source. */
+
+use {
+    pyo3::{exceptions::PyValueError, ffi as pyffi, prelude::*, types::PyBytes},
+    python_packaging::interpreter::CheckHashPycsMode,
+};
+
+/// Provides the module source that `.pyc` data is validated against.
+pub(crate) trait PycSource {
+    /// Difference in seconds tolerated between the modification time of the
+    /// source and the time recorded in timestamp-based `.pyc` data.
+    const MTIME_TOLERANCE: i64 = 0;
+
+    /// Obtain the content of the source.
+    fn content(&mut self, py: Python) -> PyResult<Vec<u8>>;
+
+    /// Obtain the modification time of the source in seconds since the UNIX
+    /// epoch and its size in bytes.
+    ///
+    /// `None` means the modification time isn't known, in which case
+    /// timestamp-based data isn't validated.
+    fn mtime_and_size(&mut self, py: Python) -> PyResult<Option<(i64, u64)>>;
+}
+
+/// Determine why `.pyc` data cannot be used, if it can't.
+///
+/// This follows `SourceFileLoader.get_code()` and `zipimport`. The magic number
+/// and flags are always validated. If `source` is defined, hash-based data is
+/// validated against the hash of the source according to
+/// `_imp.check_hash_based_pycs` and timestamp-based data is validated against
+/// the modification time and size of the source.
+pub(crate) fn invalid_pyc_reason<S: PycSource>(
+    py: Python,
+    imp_module: &PyModule,
+    data: &[u8],
+    source: Option<S>,
+) -> PyResult<Option<&'static str>> {
+    if data.len() < 16 {
+        return Ok(Some("truncated bytecode header"));
+    }
+
+    let magic = unsafe { pyffi::PyImport_GetMagicNumber() } as u32;
+    if data[0..4] != magic.to_le_bytes() {
+        return Ok(Some("bad magic number"));
+    }
+
+    let flags = u32::from_le_bytes([data[4], data[5], data[6], data[7]]);
+    if flags & !0b11 != 0 {
+        return Ok(Some("invalid bytecode flags"));
+    }
+
+    let mut source = match source {
+        Some(source) => source,
+        None => return Ok(None),
+    };
+
+    if flags & 0b1 != 0 {
+        let check_source = flags & 0b10 != 0;
+
+        let mode = imp_module
+            .getattr("check_hash_based_pycs")?
+            .extract::<String>()?;
+        let mode = CheckHashPycsMode::try_from(mode.as_str()).map_err(PyValueError::new_err)?;
+
+        let validate = match mode {
+            CheckHashPycsMode::Always => true,
+            CheckHashPycsMode::Never => false,
+            CheckHashPycsMode::Default => check_source,
+        };
+
+        if !validate {
+            return Ok(None);
+        }
+
+        let source_hash = imp_module
+            .getattr("source_hash")?
+            .call((magic, PyBytes::new(py, &source.content(py)?)), None)?
+            .extract::<Vec<u8>>()?;
+
+        if data[8..16] == source_hash[..] {
+            Ok(None)
+        } else {
+            Ok(Some("bytecode hash does not match source"))
+        }
+    } else {
+        let (mtime, size) = match source.mtime_and_size(py)? {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+
+        let expected_mtime = u32::from_le_bytes([data[8], data[9], data[10], data[11]]);
+        let expected_size = u32::from_le_bytes([data[12], data[13], data[14], data[15]]);
+
+        // Like CPython, only the low 32 bits of the modification time and size
+        // are recorded.
+        if (mtime as u32 as i64 - expected_mtime as i64).abs() <= S::MTIME_TOLERANCE
+            && size as u32 == expected_size
+        {
+            Ok(None)
+        } else {
+            Ok(Some("stale bytecode"))
+        }
+    }
+}
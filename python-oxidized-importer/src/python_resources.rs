@@ -7,12 +7,21 @@ Management of Python resources.
 */
 
 use {
-    crate::conversion::{
-        path_to_pathlib_path, pyobject_optional_resources_map_to_owned_bytes,
-        pyobject_optional_resources_map_to_pathbuf, pyobject_to_owned_bytes_optional,
-        pyobject_to_pathbuf_optional,
+    crate::{
+        conversion::{
+            path_to_pathlib_path, pyobject_optional_resources_map_to_owned_bytes,
+            pyobject_optional_resources_map_to_pathbuf, pyobject_to_owned_bytes_optional,
+            pyobject_to_pathbuf_optional,
+        },
+        extract_cache::{extension_module_path, module_source_path, ExtractCache},
+        import_trace::ImportSource,
+        pyc::{invalid_pyc_reason, PycSource},
+        resource_access::{ResourceAccessCounts, ResourceAccessKind, ResourceAccessTracker},
+        resource_layers::{LayerConflict, ResourceLayers},
     },
-    anyhow::Result,
+    anyhow::{anyhow, Result},
+    elsa::sync::FrozenMap,
+    once_cell::sync::OnceCell,
     pyo3::{
         buffer::PyBuffer,
         exceptions::{PyImportError, PyOSError, PyValueError},
@@ -22,17 +31,21 @@ use {
         PyTypeInfo,
     },
     python_packaging::resource::BytecodeOptimizationLevel,
-    python_packed_resources::Resource,
+    python_packed_resources::{BlobCompression, Resource, ResourceField, ResourceParserIterator},
     std::{
         borrow::Cow,
         cell::RefCell,
-        collections::{hash_map::Entry, BTreeSet, HashMap},
+        collections::{hash_map::Entry, BTreeMap, BTreeSet, HashMap},
         ffi::CStr,
         os::raw::c_int,
         path::{Path, PathBuf},
+        time::SystemTime,
     },
 };
 
+#[cfg(feature = "zipimport")]
+use crate::wheel_import::{WheelImports, WheelResources};
+
 const ENOENT: c_int = 2;
 
 /// Determines whether an entry represents an importable Python module.
@@ -57,6 +70,330 @@ where
         }
 }
 
+/// Resource fields that can hold compressed data.
+const COMPRESSIBLE_FIELDS: [ResourceField; 9] = [
+    ResourceField::InMemorySource,
+    ResourceField::InMemoryBytecode,
+    ResourceField::InMemoryBytecodeOpt1,
+    ResourceField::InMemoryBytecodeOpt2,
+    ResourceField::InMemoryExtensionModuleSharedLibrary,
+    ResourceField::InMemoryResourcesData,
+    ResourceField::InMemoryDistributionResource,
+    ResourceField::InMemorySharedLibrary,
+    ResourceField::FileDataEmbedded,
+];
+
+/// Whether a resource has a value for a compressible field.
+fn resource_has_field<X>(resource: &Resource<X>, field: ResourceField) -> bool
+where
+    [X]: ToOwned<Owned = Vec<X>>,
+{
+    match field {
+        ResourceField::InMemorySource => resource.in_memory_source.is_some(),
+        ResourceField::InMemoryBytecode => resource.in_memory_bytecode.is_some(),
+        ResourceField::InMemoryBytecodeOpt1 => resource.in_memory_bytecode_opt1.is_some(),
+        ResourceField::InMemoryBytecodeOpt2 => resource.in_memory_bytecode_opt2.is_some(),
+        ResourceField::InMemoryExtensionModuleSharedLibrary => {
+            resource.in_memory_extension_module_shared_library.is_some()
+        }
+        ResourceField::InMemoryResourcesData => resource.in_memory_package_resources.is_some(),
+        ResourceField::InMemoryDistributionResource => {
+            resource.in_memory_distribution_resources.is_some()
+        }
+        ResourceField::InMemorySharedLibrary => resource.in_memory_shared_library.is_some(),
+        ResourceField::FileDataEmbedded => resource.file_data_embedded.is_some(),
+        _ => false,
+    }
+}
+
+/// A mapping of names to data, as used by resource fields holding multiple entries.
+type DataMap<'a> = HashMap<Cow<'a, str>, Cow<'a, [u8]>>;
+
+/// Records which fields of a resource hold compressed data.
+///
+/// Indexed resources reference compressed data as-is so indexing doesn't
+/// incur decompression overhead. Data is decompressed when it is accessed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct CompressedFields(u32);
+
+impl CompressedFields {
+    fn set(&mut self, field: ResourceField, compression: BlobCompression) {
+        let mask = 1 << (field as u32);
+
+        match compression {
+            BlobCompression::None => {
+                self.0 &= !mask;
+            }
+            BlobCompression::Zstd => {
+                self.0 |= mask;
+            }
+        }
+    }
+
+    fn compression(&self, field: ResourceField) -> BlobCompression {
+        if self.0 & (1 << (field as u32)) != 0 {
+            BlobCompression::Zstd
+        } else {
+            BlobCompression::None
+        }
+    }
+
+    /// Update state for the fields defined by a resource read from a blob.
+    ///
+    /// `blob` is the compression state of the blob the resource came from.
+    /// Fields set on an incoming resource replace fields on an existing
+    /// resource. So their compression state is replaced as well.
+    fn merge_from(&mut self, resource: &Resource<u8>, blob: CompressedFields) {
+        for field in COMPRESSIBLE_FIELDS {
+            if resource_has_field(resource, field) {
+                self.set(field, blob.compression(field));
+            }
+        }
+    }
+
+    /// Resolve the data for a field, decompressing if necessary.
+    pub(crate) fn resolve<'d>(
+        &self,
+        field: ResourceField,
+        data: &'d [u8],
+    ) -> Result<Cow<'d, [u8]>, &'static str> {
+        match self.compression(field) {
+            BlobCompression::None => Ok(Cow::Borrowed(data)),
+            compression => Ok(Cow::Owned(python_packed_resources::decompress_blob(
+                compression,
+                data,
+            )?)),
+        }
+    }
+
+    /// Obtain a copy of a resource with all compressed fields decompressed.
+    fn decompress_resource<'r>(
+        &self,
+        resource: &Resource<'r, u8>,
+    ) -> Result<Resource<'r, u8>, &'static str> {
+        if self.0 == 0 {
+            return Ok(resource.clone());
+        }
+
+        let field = |field: ResourceField,
+                     data: &Option<Cow<'r, [u8]>>|
+         -> Result<Option<Cow<'r, [u8]>>, &'static str> {
+            match data {
+                Some(data) if self.compression(field) != BlobCompression::None => {
+                    Ok(Some(Cow::Owned(self.resolve(field, data)?.into_owned())))
+                }
+                data => Ok(data.clone()),
+            }
+        };
+
+        let map = |field: ResourceField,
+                   data: &Option<DataMap<'r>>|
+         -> Result<Option<DataMap<'r>>, &'static str> {
+            match data {
+                Some(data) if self.compression(field) != BlobCompression::None => Ok(Some(
+                    data.iter()
+                        .map(|(k, v)| {
+                            Ok((k.clone(), Cow::Owned(self.resolve(field, v)?.into_owned())))
+                        })
+                        .collect::<Result<_, &'static str>>()?,
+                )),
+                data => Ok(data.clone()),
+            }
+        };
+
+        Ok(Resource {
+            in_memory_source: field(ResourceField::InMemorySource, &resource.in_memory_source)?,
+            in_memory_bytecode: field(
+                ResourceField::InMemoryBytecode,
+                &resource.in_memory_bytecode,
+            )?,
+            in_memory_bytecode_opt1: field(
+                ResourceField::InMemoryBytecodeOpt1,
+                &resource.in_memory_bytecode_opt1,
+            )?,
+            in_memory_bytecode_opt2: field(
+                ResourceField::InMemoryBytecodeOpt2,
+                &resource.in_memory_bytecode_opt2,
+            )?,
+            in_memory_extension_module_shared_library: field(
+                ResourceField::InMemoryExtensionModuleSharedLibrary,
+                &resource.in_memory_extension_module_shared_library,
+            )?,
+            in_memory_package_resources: map(
+                ResourceField::InMemoryResourcesData,
+                &resource.in_memory_package_resources,
+            )?,
+            in_memory_distribution_resources: map(
+                ResourceField::InMemoryDistributionResource,
+                &resource.in_memory_distribution_resources,
+            )?,
+            in_memory_shared_library: field(
+                ResourceField::InMemorySharedLibrary,
+                &resource.in_memory_shared_library,
+            )?,
+            file_data_embedded: field(
+                ResourceField::FileDataEmbedded,
+                &resource.file_data_embedded,
+            )?,
+            ..resource.clone()
+        })
+    }
+}
+
+/// State of a resource before a watched file was merged into it.
+///
+/// `None` records that the resource didn't exist.
+type WatchedResourceBase<'a> = Option<(Resource<'a, u8>, CompressedFields)>;
+
+/// A packed resources file that is re-indexed when it changes.
+#[derive(Clone, Debug)]
+struct WatchedResourcesFile<'a> {
+    /// Path of the file.
+    path: PathBuf,
+
+    /// Modification time and size of the file when it was last indexed.
+    stamp: Option<(SystemTime, u64)>,
+
+    /// Names of resources that were indexed from the file.
+    names: Vec<String>,
+
+    /// State of resources in the file before the file was merged into them.
+    ///
+    /// Re-indexing merges the new content of the file onto these, so fields
+    /// provided by other packed resources data are retained.
+    base: HashMap<String, WatchedResourceBase<'a>>,
+}
+
+/// Obtain the modification time and size of a file.
+fn file_stamp(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = std::fs::metadata(path).ok()?;
+
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// A source of resources that are resolved on demand.
+#[derive(Debug)]
+enum LazyResourcesSource<'a> {
+    /// Packed resources data whose resources are found via its name index.
+    Packed {
+        resources: ResourceParserIterator<'a>,
+        compressed_fields: CompressedFields,
+    },
+    /// A wheel archive whose members are read when resolved.
+    #[cfg(feature = "zipimport")]
+    Wheel(WheelResources),
+}
+
+impl<'a> LazyResourcesSource<'a> {
+    /// Find a named resource and the compression state of its fields.
+    fn find_resource(
+        &self,
+        name: &str,
+    ) -> Result<Option<(Resource<'a, u8>, CompressedFields)>, String> {
+        match self {
+            Self::Packed {
+                resources,
+                compressed_fields,
+            } => Ok(resources
+                .find_resource(name)?
+                .map(|resource| (resource, *compressed_fields))),
+            #[cfg(feature = "zipimport")]
+            Self::Wheel(wheel) => Ok(wheel
+                .find_resource(name)?
+                .map(|resource| (resource, CompressedFields::default()))),
+        }
+    }
+
+    /// Obtain the names of all resources.
+    fn names(&self) -> Vec<String> {
+        match self {
+            Self::Packed { resources, .. } => resources
+                .names()
+                .unwrap_or_default()
+                .into_iter()
+                .map(|name| name.to_string())
+                .collect(),
+            #[cfg(feature = "zipimport")]
+            Self::Wheel(wheel) => wheel.names().map(|name| name.to_string()).collect(),
+        }
+    }
+}
+
+/// A resource resolved from lazily indexed packed resources data.
+#[derive(Debug)]
+struct LazyResource<'a> {
+    resource: Resource<'a, u8>,
+    compressed_fields: CompressedFields,
+}
+
+/// Holds resources resolved from lazily indexed packed resources data.
+///
+/// Entries are only added through `&self` and only removed through `&mut self`.
+/// So references to resolved resources remain valid for as long as the cache
+/// is borrowed.
+#[derive(Default)]
+struct LazyResourcesCache<'a> {
+    /// Resolved resources by name. `None` records that a resource doesn't exist.
+    resources: FrozenMap<String, Box<Option<LazyResource<'a>>>>,
+
+    /// Names of all resources in lazily indexed data, once enumerated.
+    names: OnceCell<BTreeSet<String>>,
+}
+
+impl<'a> std::fmt::Debug for LazyResourcesCache<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LazyResourcesCache")
+            .field("names", &self.names)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'a> LazyResourcesCache<'a> {
+    /// Forget all resolved resources.
+    fn clear(&mut self) {
+        self.resources.as_mut().clear();
+        self.names.take();
+    }
+}
+
+/// Resolve a resource from lazily indexed sources.
+///
+/// The resource is merged across all sources having it, in order.
+///
+/// Entries failing to parse or read are treated as missing.
+fn resolve_lazy_resource<'a>(
+    sources: &[LazyResourcesSource<'a>],
+    name: &str,
+) -> Option<LazyResource<'a>> {
+    let mut result: Option<LazyResource<'a>> = None;
+
+    for source in sources {
+        let (resource, blob) = match source.find_resource(name) {
+            Ok(Some(value)) => value,
+            _ => continue,
+        };
+
+        match &mut result {
+            Some(existing) => {
+                existing.compressed_fields.merge_from(&resource, blob);
+                // Names are identical, so merging can't fail.
+                let _ = existing.resource.merge_from(resource);
+            }
+            None => {
+                let mut compressed_fields = CompressedFields::default();
+                compressed_fields.merge_from(&resource, blob);
+
+                result = Some(LazyResource {
+                    resource,
+                    compressed_fields,
+                });
+            }
+        }
+    }
+
+    result
+}
+
 /// Whether a resource name matches a package target.
 ///
 /// This function is used for filtering through resources at a specific
@@ -101,6 +438,42 @@ pub enum ModuleFlavor {
     Frozen,
     Extension,
     SourceBytecode,
+    Namespace,
+}
+
+/// A filesystem-relative module source file that `.pyc` data is validated against.
+struct FilesystemPycSource<'p> {
+    /// Name of the module.
+    name: &'p str,
+    path: &'p Path,
+    metadata: std::fs::Metadata,
+}
+
+impl<'p> PycSource for FilesystemPycSource<'p> {
+    fn content(&mut self, py: Python) -> PyResult<Vec<u8>> {
+        std::fs::read(self.path).map_err(|e| {
+            PyErr::from_type(
+                PyImportError::type_object(py),
+                (
+                    format!(
+                        "error reading module source from {}: {}",
+                        self.path.display(),
+                        e
+                    ),
+                    self.name.to_string(),
+                ),
+            )
+        })
+    }
+
+    fn mtime_and_size(&mut self, _py: Python) -> PyResult<Option<(i64, u64)>> {
+        Ok(self
+            .metadata
+            .modified()
+            .ok()
+            .and_then(|mtime| mtime.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|duration| (duration.as_secs() as i64, self.metadata.len())))
+    }
 }
 
 /// Holds state for an importable Python module.
@@ -124,6 +497,12 @@ where
     pub flavor: ModuleFlavor,
     /// Whether this module is a package.
     pub is_package: bool,
+
+    /// Which fields of the backing resource hold compressed data.
+    compressed_fields: CompressedFields,
+
+    /// Directory the module's top-level package was extracted to.
+    extracted_dir: Option<PathBuf>,
 }
 
 impl<'a> ImportablePythonModule<'a, u8> {
@@ -139,7 +518,17 @@ impl<'a> ImportablePythonModule<'a, u8> {
         io_module: &PyAny,
     ) -> PyResult<Option<&'p PyAny>> {
         let bytes = if let Some(data) = &self.resource.in_memory_source {
-            Some(PyBytes::new(py, data))
+            let data = self
+                .compressed_fields
+                .resolve(ResourceField::InMemorySource, data)
+                .map_err(|e| {
+                    PyErr::from_type(
+                        PyImportError::type_object(py),
+                        (e, self.resource.name.clone().into_py(py)),
+                    )
+                })?;
+
+            Some(PyBytes::new(py, &data))
         } else if let Some(relative_path) = &self.resource.relative_path_module_source {
             let path = self.origin.join(relative_path);
 
@@ -173,18 +562,48 @@ impl<'a> ImportablePythonModule<'a, u8> {
     /// (e.g. the case of frozen modules).
     ///
     /// The returned `PyObject` will be an instance of `memoryview`.
+    ///
+    /// Filesystem-relative bytecode is validated against the module's
+    /// filesystem-relative source, if present, the same way `importlib`'s
+    /// `SourceFileLoader` validates `.pyc` files. Stale bytecode is ignored and
+    /// the source is compiled instead.
     pub fn resolve_bytecode(
-        &mut self,
+        &self,
         py: Python,
         optimize_level: BytecodeOptimizationLevel,
         decode_source: &PyAny,
         io_module: &PyModule,
+        imp_module: &PyModule,
     ) -> PyResult<Option<Py<PyAny>>> {
-        if let Some(data) = match optimize_level {
-            BytecodeOptimizationLevel::Zero => &self.resource.in_memory_bytecode,
-            BytecodeOptimizationLevel::One => &self.resource.in_memory_bytecode_opt1,
-            BytecodeOptimizationLevel::Two => &self.resource.in_memory_bytecode_opt2,
-        } {
+        let (field, data) = match optimize_level {
+            BytecodeOptimizationLevel::Zero => (
+                ResourceField::InMemoryBytecode,
+                &self.resource.in_memory_bytecode,
+            ),
+            BytecodeOptimizationLevel::One => (
+                ResourceField::InMemoryBytecodeOpt1,
+                &self.resource.in_memory_bytecode_opt1,
+            ),
+            BytecodeOptimizationLevel::Two => (
+                ResourceField::InMemoryBytecodeOpt2,
+                &self.resource.in_memory_bytecode_opt2,
+            ),
+        };
+
+        if let Some(data) = data {
+            // Compressed bytecode needs to be decompressed into a new buffer.
+            // Uncompressed bytecode can be referenced without copying.
+            if self.compressed_fields.compression(field) != BlobCompression::None {
+                let bytecode = self.compressed_fields.resolve(field, data).map_err(|e| {
+                    PyErr::from_type(
+                        PyImportError::type_object(py),
+                        (e, self.resource.name.clone().into_py(py)),
+                    )
+                })?;
+
+                return Ok(Some(PyBytes::new(py, &bytecode).into_py(py)));
+            }
+
             let ptr = unsafe {
                 pyffi::PyMemoryView_FromMemory(
                     data.as_ptr() as _,
@@ -218,9 +637,27 @@ impl<'a> ImportablePythonModule<'a, u8> {
                 ));
             }
 
-            // First 16 bytes of .pyc files are a header.
-            Ok(Some(PyBytes::new(py, &bytecode[16..]).into_py(py)))
-        } else if let Some(source) = self.resolve_source(py, decode_source, io_module)? {
+            if self.is_bytecode_current(py, imp_module, &bytecode)? {
+                // First 16 bytes of .pyc files are a header.
+                Ok(Some(PyBytes::new(py, &bytecode[16..]).into_py(py)))
+            } else {
+                self.compile_source(py, decode_source, io_module)
+            }
+        } else {
+            self.compile_source(py, decode_source, io_module)
+        }
+    }
+
+    /// Compile this module's source to marshalled bytecode.
+    ///
+    /// Returns `Ok(None)` if the module has no source.
+    fn compile_source(
+        &self,
+        py: Python,
+        decode_source: &PyAny,
+        io_module: &PyModule,
+    ) -> PyResult<Option<Py<PyAny>>> {
+        if let Some(source) = self.resolve_source(py, decode_source, io_module)? {
             let builtins = py.import("builtins")?;
             let marshal = py.import("marshal")?;
 
@@ -235,6 +672,79 @@ impl<'a> ImportablePythonModule<'a, u8> {
         }
     }
 
+    /// Whether filesystem-relative `.pyc` data is current with respect to its source.
+    ///
+    /// See [invalid_pyc_reason()]. Hash-based data is checked according to
+    /// `_imp.check_hash_based_pycs`, which reflects the interpreter's
+    /// `CheckHashPycsMode`.
+    ///
+    /// Bytecode without a filesystem-relative source file is always current, as
+    /// there is nothing to validate or recompile it against.
+    fn is_bytecode_current(
+        &self,
+        py: Python,
+        imp_module: &PyModule,
+        data: &[u8],
+    ) -> PyResult<bool> {
+        let source_path = match &self.resource.relative_path_module_source {
+            Some(path) => self.origin.join(path),
+            None => return Ok(true),
+        };
+
+        let metadata = match std::fs::metadata(&source_path) {
+            Ok(metadata) => metadata,
+            Err(_) => return Ok(true),
+        };
+
+        let source = FilesystemPycSource {
+            name: &self.resource.name,
+            path: &source_path,
+            metadata,
+        };
+
+        Ok(invalid_pyc_reason(py, imp_module, data, Some(source))?.is_none())
+    }
+
+    /// Describe where the code of this module comes from.
+    ///
+    /// This mirrors the preference order of [Self::resolve_bytecode()].
+    pub fn import_source(&self, optimize_level: BytecodeOptimizationLevel) -> ImportSource {
+        match self.flavor {
+            ModuleFlavor::Builtin => ImportSource::Builtin,
+            ModuleFlavor::Frozen => ImportSource::Frozen,
+            ModuleFlavor::Namespace => ImportSource::Namespace,
+            ModuleFlavor::Extension => {
+                if self
+                    .resource
+                    .in_memory_extension_module_shared_library
+                    .is_some()
+                    && self.extracted_dir.is_none()
+                {
+                    ImportSource::InMemory
+                } else {
+                    ImportSource::FilesystemRelative
+                }
+            }
+            ModuleFlavor::SourceBytecode => {
+                let in_memory_bytecode = match optimize_level {
+                    BytecodeOptimizationLevel::Zero => &self.resource.in_memory_bytecode,
+                    BytecodeOptimizationLevel::One => &self.resource.in_memory_bytecode_opt1,
+                    BytecodeOptimizationLevel::Two => &self.resource.in_memory_bytecode_opt2,
+                };
+
+                if in_memory_bytecode.is_some() {
+                    ImportSource::InMemory
+                } else if self.bytecode_path(optimize_level).is_some() {
+                    ImportSource::FilesystemRelative
+                } else if self.resource.in_memory_source.is_some() {
+                    ImportSource::InMemory
+                } else {
+                    ImportSource::FilesystemRelative
+                }
+            }
+        }
+    }
+
     /// Resolve the `importlib.machinery.ModuleSpec` for this module.
     pub fn resolve_module_spec<'p>(
         &self,
@@ -352,6 +862,18 @@ impl<'a> ImportablePythonModule<'a, u8> {
 
     /// Obtain the filesystem path to this resource to be used for `ModuleSpec.origin`.
     fn origin_path(&self) -> Option<PathBuf> {
+        if let Some(dir) = &self.extracted_dir {
+            return match self.flavor {
+                ModuleFlavor::SourceBytecode => {
+                    Some(dir.join(module_source_path(&self.resource.name, self.is_package)))
+                }
+                ModuleFlavor::Extension => {
+                    Some(dir.join(extension_module_path(&self.resource.name, self.is_package)))
+                }
+                _ => None,
+            };
+        }
+
         match self.flavor {
             ModuleFlavor::SourceBytecode => self
                 .resource
@@ -380,8 +902,24 @@ impl<'a> ImportablePythonModule<'a, u8> {
             .map(|bytecode_path| self.origin.join(bytecode_path))
     }
 
-    pub fn in_memory_extension_module_shared_library(&self) -> &'a Option<Cow<'a, [u8]>> {
-        &self.resource.in_memory_extension_module_shared_library
+    /// Resolve the data for an extension module shared library to load from memory.
+    ///
+    /// Extension modules extracted to a cache directory are loaded from the
+    /// extracted file instead.
+    pub fn in_memory_extension_module_shared_library(
+        &self,
+    ) -> Result<Option<Cow<'a, [u8]>>, &'static str> {
+        if self.extracted_dir.is_some() {
+            return Ok(None);
+        }
+
+        match &self.resource.in_memory_extension_module_shared_library {
+            Some(data) => Ok(Some(
+                self.compressed_fields
+                    .resolve(ResourceField::InMemoryExtensionModuleSharedLibrary, data)?,
+            )),
+            None => Ok(None),
+        }
     }
 }
 
@@ -402,123 +940,724 @@ impl<'a> From<&'a [u8]> for PackedResourcesSource<'a> {
     }
 }
 
-/// Defines Python resources available for import.
-#[derive(Debug)]
-pub struct PythonResourcesState<'a, X>
-where
-    [X]: ToOwned<Owned = Vec<X>>,
-{
-    /// Path to currently running executable.
-    current_exe: PathBuf,
+/// A named source for packed resources data having a priority.
+///
+/// See [PythonResourcesState::index_layer_data] for how layers interact.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PackedResourcesLayer<'a> {
+    /// Name of the layer.
+    pub name: String,
+
+    /// Priority of the layer. Higher values take precedence.
+    pub priority: i32,
+
+    /// Where the packed resources data comes from.
+    pub source: PackedResourcesSource<'a>,
+}
+
+/// Defines Python resources available for import.
+#[derive(Debug)]
+pub struct PythonResourcesState<'a, X>
+where
+    [X]: ToOwned<Owned = Vec<X>>,
+{
+    /// Path to currently running executable.
+    current_exe: PathBuf,
+
+    /// Directory from which relative paths should be evaluated.
+    ///
+    /// Probably the directory of `current_exe`.
+    origin: PathBuf,
+
+    /// Named resources available for loading.
+    resources: HashMap<Cow<'a, str>, Resource<'a, X>>,
+
+    /// List of `PyObject` that back indexed data.
+    ///
+    /// Holding a reference to these prevents them from being gc'd and for
+    /// memory referenced by `self.resources` from being freed.
+    backing_py_objects: Vec<Py<PyAny>>,
+
+    /// Holds memory mapped file instances that resources data came from.
+    backing_mmaps: Vec<memmap2::Mmap>,
+
+    /// Resources having fields referencing compressed data.
+    compressed_fields: HashMap<Cow<'a, str>, CompressedFields>,
+
+    /// Whether to lazily index packed resources data having a name index.
+    lazy_index: bool,
+
+    /// Packed resources data and wheels that were indexed lazily.
+    ///
+    /// Resources in `resources` take precedence over resources in these sources.
+    lazy_sources: Vec<LazyResourcesSource<'a>>,
+
+    /// Resources resolved from `lazy_sources`.
+    lazy_resources: LazyResourcesCache<'a>,
+
+    /// ed25519 public key that indexed packed resources data must be signed with.
+    required_public_key: Option<[u8; 32]>,
+
+    /// AES-256-GCM key used to decrypt encrypted packed resources data.
+    decryption_key: Option<[u8; 32]>,
+
+    /// Records accesses to resources, if enabled.
+    resource_access: Option<ResourceAccessTracker>,
+
+    /// Holds owned buffers that watched resources data came from.
+    ///
+    /// Buffers are retained after their file is re-indexed, as the base state
+    /// of other watched files and objects handed out to Python may still
+    /// reference their data. So memory grows by the size of a watched file
+    /// each time it is successfully re-indexed. This is accepted as watching
+    /// files is only intended for development.
+    backing_buffers: Vec<Vec<u8>>,
+
+    /// Packed resources files that are re-indexed when they change.
+    watched_files: Vec<WatchedResourcesFile<'a>>,
+
+    /// Tracks which named layer provided resources.
+    layers: ResourceLayers,
+
+    /// Tracks native files from wheels whose resources were indexed.
+    #[cfg(feature = "zipimport")]
+    wheels: WheelImports,
+
+    /// Tracks packages whose resources were extracted to a cache directory.
+    extract_cache: ExtractCache,
+}
+
+impl<'a> Default for PythonResourcesState<'a, u8> {
+    fn default() -> Self {
+        Self {
+            current_exe: PathBuf::new(),
+            origin: PathBuf::new(),
+            resources: HashMap::new(),
+            backing_py_objects: vec![],
+            backing_mmaps: vec![],
+            compressed_fields: HashMap::new(),
+            lazy_index: false,
+            lazy_sources: vec![],
+            lazy_resources: LazyResourcesCache::default(),
+            required_public_key: None,
+            decryption_key: None,
+            resource_access: None,
+            backing_buffers: vec![],
+            watched_files: vec![],
+            layers: ResourceLayers::default(),
+            #[cfg(feature = "zipimport")]
+            wheels: WheelImports::default(),
+            extract_cache: ExtractCache::default(),
+        }
+    }
+}
+
+impl<'a> PythonResourcesState<'a, u8> {
+    /// Construct an instance from environment state.
+    pub fn new_from_env() -> Result<Self, &'static str> {
+        let exe = std::env::current_exe().map_err(|_| "unable to obtain current executable")?;
+        let origin = exe
+            .parent()
+            .ok_or("unable to get executable parent")?
+            .to_path_buf();
+
+        Ok(Self {
+            current_exe: exe,
+            origin,
+            ..Default::default()
+        })
+    }
+
+    /// Obtain the path of the current executable.
+    pub fn current_exe(&self) -> &Path {
+        &self.current_exe
+    }
+
+    /// Set the path of the current executable.
+    pub fn set_current_exe(&mut self, path: PathBuf) {
+        self.current_exe = path;
+    }
+
+    /// Obtain the source path that relative paths are relative to.
+    pub fn origin(&self) -> &Path {
+        &self.origin
+    }
+
+    /// Set the source path that relative paths are relative to.
+    pub fn set_origin(&mut self, path: PathBuf) {
+        self.origin = path;
+    }
+
+    /// Set the ed25519 public key that packed resources data must be signed with.
+    ///
+    /// When set, data passed to [Self::index_data()] and friends must have an
+    /// integrity trailer with a valid signature from the corresponding secret
+    /// key. Otherwise it is rejected before anything is indexed.
+    pub fn set_required_public_key(&mut self, key: Option<[u8; 32]>) {
+        self.required_public_key = key;
+    }
+
+    /// Set the key used to decrypt encrypted packed resources data.
+    ///
+    /// Data in encrypted blob sections is decrypted when resources are indexed.
+    /// For lazily indexed data, this is when a resource is first accessed.
+    /// Compressed data is still decompressed when accessed. Indexing encrypted
+    /// data fails if this isn't set.
+    pub fn set_decryption_key(&mut self, key: Option<[u8; 32]>) {
+        self.decryption_key = key;
+    }
+
+    /// Set whether packed resources data having a name index is indexed lazily.
+    ///
+    /// When enabled, [Self::index_data()] and friends don't parse data having a
+    /// name index. Instead, resources are found via the name index when they are
+    /// first accessed. This makes indexing cost independent of the number of
+    /// resources. Operations enumerating all resources, such as resolving
+    /// `iter_modules()`, still need to resolve every resource.
+    pub fn set_lazy_index(&mut self, value: bool) {
+        self.lazy_index = value;
+    }
+
+    /// Set whether layer conflicts that aren't resolved by priority are an error.
+    ///
+    /// See [Self::index_layer_data()].
+    pub fn set_strict_layers(&mut self, value: bool) {
+        self.layers.set_strict(value);
+    }
+
+    /// Obtain the name of the layer that provided a resource.
+    ///
+    /// Returns `None` if the resource isn't known or wasn't indexed from a layer.
+    pub fn resource_layer(&self, name: &str) -> Option<&str> {
+        self.layers.layer_name(name)
+    }
+
+    /// Obtain resources that were provided by multiple layers.
+    pub fn layer_conflicts(&self) -> &[LayerConflict] {
+        self.layers.conflicts()
+    }
+
+    /// Set whether to record which resources are accessed.
+    ///
+    /// When enabled, resolving a module for importing and reading package
+    /// resources, distribution metadata, or in-memory shared libraries are
+    /// counted per resource. See [Self::resource_access_counts()].
+    pub fn set_track_resource_access(&mut self, enabled: bool) {
+        self.resource_access = if enabled {
+            Some(ResourceAccessTracker::default())
+        } else {
+            None
+        };
+    }
+
+    /// Record an access to a resource, if resource access tracking is enabled.
+    fn record_access(&self, name: &str, kind: ResourceAccessKind) {
+        if let Some(tracker) = &self.resource_access {
+            tracker.record(name, kind);
+        }
+    }
+
+    /// Obtain access counts of accessed resources.
+    ///
+    /// Returns `None` if resource access tracking is not enabled.
+    pub fn resource_access_counts(&self) -> Option<BTreeMap<String, ResourceAccessCounts>> {
+        self.resource_access
+            .as_ref()
+            .map(|tracker| tracker.counts())
+    }
+
+    /// Obtain the sorted names of indexed resources that were never accessed.
+    ///
+    /// Built-in extension modules and frozen modules are part of the interpreter
+    /// and are not reported.
+    ///
+    /// Returns `None` if resource access tracking is not enabled.
+    pub fn unused_resource_names(&self) -> Option<Vec<String>> {
+        let counts = self.resource_access_counts()?;
+
+        let mut names = self
+            .all_resources()
+            .into_iter()
+            .filter(|resource| {
+                !resource.is_python_builtin_extension_module && !resource.is_python_frozen_module
+            })
+            .map(|resource| resource.name.to_string())
+            .filter(|name| !counts.contains_key(name))
+            .collect::<Vec<_>>();
+
+        names.sort();
+        names.dedup();
+
+        Some(names)
+    }
+
+    /// Parse packed resources data according to the configured keys.
+    ///
+    /// Compressed data is not decompressed by the returned parser.
+    fn load_resources(&self, data: &'a [u8]) -> Result<ResourceParserIterator<'a>, &'static str> {
+        let mut resources = if let Some(key) = &self.required_public_key {
+            python_packed_resources::load_resources_verified(data, Some(key))?
+        } else {
+            python_packed_resources::load_resources(data)?
+        };
+        resources.set_decompress(false);
+        resources.set_decryption_key(self.decryption_key);
+
+        Ok(resources)
+    }
+
+    /// Load resources by parsing a blob.
+    ///
+    /// If an existing entry exists, the new entry will be merged into it. Set fields
+    /// on the incoming entry will overwrite fields on the existing entry.
+    ///
+    /// If an entry doesn't exist, the resource will be inserted as-is.
+    ///
+    /// Data in compressed blob sections is not decompressed during indexing.
+    /// Instead, it is decompressed when accessed. Data in encrypted blob sections
+    /// is decrypted during indexing. See [Self::set_decryption_key()].
+    pub fn index_data(&mut self, data: &'a [u8]) -> Result<(), &'static str> {
+        let resources = self.load_resources(data)?;
+
+        let mut compressed_fields = CompressedFields::default();
+        for field in COMPRESSIBLE_FIELDS {
+            compressed_fields.set(field, resources.field_compression(field));
+        }
+
+        if self.lazy_index && resources.has_name_index() {
+            return self.index_lazy_source(resources, compressed_fields);
+        }
+
+        // Reserve space for expected number of incoming items so we can avoid extra
+        // allocations.
+        self.resources.reserve(resources.expected_resources_count());
+
+        for resource in resources {
+            let resource = resource?;
+
+            self.promote_lazy_resource(&resource.name);
+            self.record_compressed_fields(&resource, compressed_fields);
+
+            match self.resources.entry(resource.name.clone()) {
+                Entry::Occupied(existing) => {
+                    existing.into_mut().merge_from(resource)?;
+                }
+                Entry::Vacant(vacant) => {
+                    vacant.insert(resource);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Load resources by parsing a blob as a named layer.
+    ///
+    /// Unlike [Self::index_data], resources aren't merged. A resource in a layer
+    /// replaces an existing resource of the same name unless the existing resource
+    /// came from a layer having a higher priority, in which case the incoming
+    /// resource is ignored. When layers have the same priority, the layer indexed
+    /// last wins, unless strict mode is enabled via [Self::set_strict_layers], in
+    /// which case an error is returned. Resources provided by multiple layers are
+    /// recorded and available via [Self::layer_conflicts].
+    ///
+    /// Layer data is always indexed eagerly.
+    pub fn index_layer_data(
+        &mut self,
+        name: &str,
+        priority: i32,
+        data: &'a [u8],
+    ) -> Result<(), String> {
+        let layer = self.layers.add_layer(name, priority)?;
+
+        let resources = self.load_resources(data)?;
+
+        let mut compressed_fields = CompressedFields::default();
+        for field in COMPRESSIBLE_FIELDS {
+            compressed_fields.set(field, resources.field_compression(field));
+        }
+
+        for resource in resources {
+            let resource = resource?;
+
+            self.promote_lazy_resource(&resource.name);
+
+            let exists = self.resources.contains_key(&resource.name);
+            if !self
+                .layers
+                .resolve(&resource.name, layer, exists)
+                .map_err(|e| format!("error indexing layer {}: {}", name, e))?
+            {
+                continue;
+            }
+
+            self.compressed_fields.remove(&resource.name);
+            self.record_compressed_fields(&resource, compressed_fields);
+            self.resources.insert(resource.name.clone(), resource);
+        }
+
+        Ok(())
+    }
+
+    /// Register packed resources data having a name index for lazy resolution.
+    fn index_lazy_source(
+        &mut self,
+        resources: ResourceParserIterator<'a>,
+        compressed_fields: CompressedFields,
+    ) -> Result<(), &'static str> {
+        // Eagerly indexed resources take precedence over lazily resolved ones.
+        // So merge the new data into them to preserve merge semantics.
+        let names = self.resources.keys().cloned().collect::<Vec<_>>();
+        for name in names {
+            if let Some(resource) = resources.find_resource(&name)? {
+                self.record_compressed_fields(&resource, compressed_fields);
+                self.resources
+                    .get_mut(&name)
+                    .expect("resource should exist")
+                    .merge_from(resource)?;
+            }
+        }
+
+        self.add_lazy_source(LazyResourcesSource::Packed {
+            resources,
+            compressed_fields,
+        });
+
+        Ok(())
+    }
+
+    /// Register a source of resources that are resolved on demand.
+    fn add_lazy_source(&mut self, source: LazyResourcesSource<'a>) {
+        self.lazy_sources.push(source);
+
+        // Previously resolved resources may have changed.
+        self.lazy_resources.clear();
+    }
+
+    /// Obtain a named resource.
+    ///
+    /// Resources in lazily indexed data are resolved on demand.
+    fn resource(&self, name: &str) -> Option<&Resource<'a, u8>> {
+        if let Some(resource) = self.resources.get(name) {
+            Some(resource)
+        } else {
+            self.lazy_resource(name).map(|lazy| &lazy.resource)
+        }
+    }
+
+    /// Obtain a named resource from lazily indexed data.
+    fn lazy_resource(&self, name: &str) -> Option<&LazyResource<'a>> {
+        if self.lazy_sources.is_empty() {
+            return None;
+        }
+
+        let lazy = match self.lazy_resources.resources.get(name) {
+            Some(lazy) => lazy,
+            None => self.lazy_resources.resources.insert(
+                name.to_string(),
+                Box::new(resolve_lazy_resource(&self.lazy_sources, name)),
+            ),
+        };
+
+        lazy.as_ref()
+    }
+
+    /// Obtain all resources.
+    ///
+    /// This resolves every resource in lazily indexed data.
+    fn all_resources(&self) -> Vec<&Resource<'a, u8>> {
+        let mut resources = self.resources.values().collect::<Vec<_>>();
+
+        if self.lazy_sources.is_empty() {
+            return resources;
+        }
+
+        for name in self.lazy_resource_names() {
+            if !self.resources.contains_key(name.as_str()) {
+                if let Some(lazy) = self.lazy_resource(name) {
+                    resources.push(&lazy.resource);
+                }
+            }
+        }
+
+        resources
+    }
+
+    /// Obtain the names of all resources in lazily indexed data.
+    fn lazy_resource_names(&self) -> &BTreeSet<String> {
+        self.lazy_resources.names.get_or_init(|| {
+            let mut names = BTreeSet::new();
+            for source in &self.lazy_sources {
+                names.extend(source.names());
+            }
+
+            names
+        })
+    }
+
+    /// Move a resource from lazily indexed data into the eagerly indexed resources.
+    ///
+    /// This allows the resource to be modified in place. It is a no-op if the
+    /// resource is already eagerly indexed or isn't in lazily indexed data.
+    fn promote_lazy_resource(&mut self, name: &str) {
+        if self.lazy_sources.is_empty() || self.resources.contains_key(name) {
+            return;
+        }
+
+        let lazy = match self.lazy_resources.resources.as_mut().remove(name) {
+            Some(lazy) => *lazy,
+            None => resolve_lazy_resource(&self.lazy_sources, name),
+        };
+
+        if let Some(LazyResource {
+            resource,
+            compressed_fields,
+        }) = lazy
+        {
+            if compressed_fields != CompressedFields::default() {
+                self.compressed_fields
+                    .insert(resource.name.clone(), compressed_fields);
+            }
+            self.resources.insert(resource.name.clone(), resource);
+        }
+    }
+
+    /// Record the compression state of fields defined by an incoming resource.
+    ///
+    /// Fields set on the incoming resource replace fields on an existing
+    /// resource. So their compression state is replaced as well.
+    fn record_compressed_fields(&mut self, resource: &Resource<'a, u8>, blob: CompressedFields) {
+        if blob == CompressedFields::default()
+            && !self.compressed_fields.contains_key(&resource.name)
+        {
+            return;
+        }
+
+        let entry = self
+            .compressed_fields
+            .entry(resource.name.clone())
+            .or_default();
+
+        entry.merge_from(resource, blob);
+
+        if *entry == CompressedFields::default() {
+            self.compressed_fields.remove(&resource.name);
+        }
+    }
+
+    /// Obtain the compression state of fields of a named resource.
+    fn resource_compressed_fields(&self, name: &str) -> CompressedFields {
+        if let Some(fields) = self.compressed_fields.get(name) {
+            *fields
+        } else if self.resources.contains_key(name) {
+            CompressedFields::default()
+        } else if let Some(lazy) = self.lazy_resource(name) {
+            lazy.compressed_fields
+        } else {
+            CompressedFields::default()
+        }
+    }
+
+    /// Load resources data from a filesystem path using memory mapped I/O.
+    pub fn index_path_memory_mapped(&mut self, path: impl AsRef<Path>) -> Result<(), String> {
+        let path = path.as_ref();
+        let f = std::fs::File::open(path).map_err(|e| e.to_string())?;
+
+        let mapped = unsafe { memmap2::Mmap::map(&f) }.map_err(|e| e.to_string())?;
+
+        let data = unsafe { std::slice::from_raw_parts::<u8>(mapped.as_ptr(), mapped.len()) };
+
+        self.index_data(data)?;
+        self.backing_mmaps.push(mapped);
+
+        Ok(())
+    }
+
+    /// Load resources data for a named layer from a filesystem path using memory mapped I/O.
+    ///
+    /// See [Self::index_layer_data] for layering semantics.
+    pub fn index_layer_path_memory_mapped(
+        &mut self,
+        name: &str,
+        priority: i32,
+        path: impl AsRef<Path>,
+    ) -> Result<(), String> {
+        let path = path.as_ref();
+        let f = std::fs::File::open(path).map_err(|e| e.to_string())?;
+
+        let mapped = unsafe { memmap2::Mmap::map(&f) }.map_err(|e| e.to_string())?;
+
+        let data = unsafe { std::slice::from_raw_parts::<u8>(mapped.as_ptr(), mapped.len()) };
+
+        self.backing_mmaps.push(mapped);
+        self.index_layer_data(name, priority, data)?;
+
+        Ok(())
+    }
 
-    /// Directory from which relative paths should be evaluated.
+    /// Load resources data from a filesystem path and re-index it when it changes.
     ///
-    /// Probably the directory of `current_exe`.
-    origin: PathBuf,
+    /// Unlike [Self::index_path_memory_mapped], the file is read into memory. This
+    /// allows it to be rewritten while the process is running. Data is always
+    /// indexed eagerly, regardless of [Self::set_lazy_index].
+    ///
+    /// Changes are picked up by [Self::reload_watched_paths].
+    pub fn index_path_watched(&mut self, path: impl AsRef<Path>) -> Result<(), String> {
+        let path = path.as_ref();
 
-    /// Named resources available for loading.
-    resources: HashMap<Cow<'a, str>, Resource<'a, X>>,
+        let mut file = WatchedResourcesFile {
+            path: path.to_path_buf(),
+            stamp: file_stamp(path),
+            names: vec![],
+            base: HashMap::new(),
+        };
+        self.index_watched_file(&mut file)?;
+        self.watched_files.push(file);
 
-    /// List of `PyObject` that back indexed data.
+        Ok(())
+    }
+
+    /// Re-index watched packed resources files that changed since they were indexed.
     ///
-    /// Holding a reference to these prevents them from being gc'd and for
-    /// memory referenced by `self.resources` from being freed.
-    backing_py_objects: Vec<Py<PyAny>>,
+    /// The new content of a changed file replaces what the file previously
+    /// contributed to resources. Fields provided by other packed resources
+    /// data are retained.
+    ///
+    /// If a file fails to re-index, resources are left as they were and an
+    /// error is returned. The file is retried on the next call.
+    ///
+    /// Returns the sorted names of resources in changed files, before and after
+    /// the change.
+    pub fn reload_watched_paths(&mut self) -> Result<Vec<String>, String> {
+        let mut changed = BTreeSet::new();
+        let mut watched_files = std::mem::take(&mut self.watched_files);
+        let mut res = Ok(());
+
+        for file in watched_files.iter_mut() {
+            let stamp = file_stamp(&file.path);
+
+            if stamp == file.stamp {
+                continue;
+            }
 
-    /// Holds memory mapped file instances that resources data came from.
-    backing_mmaps: Vec<memmap2::Mmap>,
-}
+            let previous_names = file.names.clone();
 
-impl<'a> Default for PythonResourcesState<'a, u8> {
-    fn default() -> Self {
-        Self {
-            current_exe: PathBuf::new(),
-            origin: PathBuf::new(),
-            resources: HashMap::new(),
-            backing_py_objects: vec![],
-            backing_mmaps: vec![],
+            if let Err(e) = self.index_watched_file(file) {
+                res = Err(format!("error re-indexing {}: {}", file.path.display(), e));
+                break;
+            }
+
+            file.stamp = stamp;
+            changed.extend(previous_names);
+            changed.extend(file.names.iter().cloned());
         }
-    }
-}
 
-impl<'a> PythonResourcesState<'a, u8> {
-    /// Construct an instance from environment state.
-    pub fn new_from_env() -> Result<Self, &'static str> {
-        let exe = std::env::current_exe().map_err(|_| "unable to obtain current executable")?;
-        let origin = exe
-            .parent()
-            .ok_or("unable to get executable parent")?
-            .to_path_buf();
+        self.watched_files = watched_files;
+        res?;
 
-        Ok(Self {
-            current_exe: exe,
-            origin,
-            ..Default::default()
-        })
+        Ok(changed.into_iter().collect())
     }
 
-    /// Obtain the path of the current executable.
-    pub fn current_exe(&self) -> &Path {
-        &self.current_exe
-    }
+    /// Index the current content of a watched file.
+    ///
+    /// The content is merged onto the state resources had before the file was
+    /// first merged into them. Nothing is changed unless the whole file is
+    /// read, parsed, and merged successfully.
+    fn index_watched_file(&mut self, file: &mut WatchedResourcesFile<'a>) -> Result<(), String> {
+        let buffer = std::fs::read(&file.path).map_err(|e| e.to_string())?;
 
-    /// Set the path of the current executable.
-    pub fn set_current_exe(&mut self, path: PathBuf) {
-        self.current_exe = path;
-    }
+        // The buffer's heap allocation doesn't move when the buffer does. So it is
+        // safe to hold references to it for as long as we retain the buffer.
+        let data = unsafe { std::slice::from_raw_parts::<u8>(buffer.as_ptr(), buffer.len()) };
 
-    /// Obtain the source path that relative paths are relative to.
-    pub fn origin(&self) -> &Path {
-        &self.origin
-    }
+        let resources = self.load_resources(data)?;
 
-    /// Set the source path that relative paths are relative to.
-    pub fn set_origin(&mut self, path: PathBuf) {
-        self.origin = path;
-    }
+        let mut compressed_fields = CompressedFields::default();
+        for field in COMPRESSIBLE_FIELDS {
+            compressed_fields.set(field, resources.field_compression(field));
+        }
 
-    /// Load resources by parsing a blob.
-    ///
-    /// If an existing entry exists, the new entry will be merged into it. Set fields
-    /// on the incoming entry will overwrite fields on the existing entry.
-    ///
-    /// If an entry doesn't exist, the resource will be inserted as-is.
-    pub fn index_data(&mut self, data: &'a [u8]) -> Result<(), &'static str> {
-        let resources = python_packed_resources::load_resources(data)?;
+        let resources = resources.collect::<Result<Vec<_>, _>>()?;
 
-        // Reserve space for expected number of incoming items so we can avoid extra
-        // allocations.
-        self.resources.reserve(resources.expected_resources_count());
+        // Resources new to the file are merged onto their current state.
+        let mut base = file.base.clone();
+        for resource in &resources {
+            if !base.contains_key(resource.name.as_ref()) {
+                self.promote_lazy_resource(&resource.name);
+
+                let current = self.resources.get(&resource.name).map(|existing| {
+                    (
+                        existing.clone(),
+                        self.compressed_fields
+                            .get(&resource.name)
+                            .copied()
+                            .unwrap_or_default(),
+                    )
+                });
+                base.insert(resource.name.to_string(), current);
+            }
+        }
 
+        let mut merged: BTreeMap<String, (Resource<'a, u8>, CompressedFields)> = BTreeMap::new();
         for resource in resources {
-            let resource = resource?;
+            let name = resource.name.to_string();
 
-            match self.resources.entry(resource.name.clone()) {
-                Entry::Occupied(existing) => {
-                    existing.into_mut().merge_from(resource)?;
+            let current = match merged.remove(&name) {
+                Some(current) => Some(current),
+                None => base.get(&name).cloned().flatten(),
+            };
+
+            let resource = match current {
+                Some((mut existing, mut fields)) => {
+                    fields.merge_from(&resource, compressed_fields);
+                    existing.merge_from(resource)?;
+                    (existing, fields)
                 }
-                Entry::Vacant(vacant) => {
-                    vacant.insert(resource);
+                None => {
+                    let mut fields = CompressedFields::default();
+                    fields.merge_from(&resource, compressed_fields);
+                    (resource, fields)
+                }
+            };
+
+            merged.insert(name, resource);
+        }
+
+        // Everything was merged successfully. So swap in the new state.
+        self.backing_buffers.push(buffer);
+
+        // Resources no longer in the file revert to their state before it.
+        for name in &file.names {
+            if !merged.contains_key(name) {
+                match base.remove(name).flatten() {
+                    Some((resource, fields)) => self.insert_watched_resource(resource, fields),
+                    None => {
+                        self.resources.remove(name.as_str());
+                        self.compressed_fields.remove(name.as_str());
+                        self.layers.remove(name);
+                    }
                 }
             }
         }
 
+        file.names = merged.keys().cloned().collect();
+        for (resource, fields) in merged.into_values() {
+            self.insert_watched_resource(resource, fields);
+        }
+        file.base = base;
+
         Ok(())
     }
 
-    /// Load resources data from a filesystem path using memory mapped I/O.
-    pub fn index_path_memory_mapped(&mut self, path: impl AsRef<Path>) -> Result<(), String> {
-        let path = path.as_ref();
-        let f = std::fs::File::open(path).map_err(|e| e.to_string())?;
-
-        let mapped = unsafe { memmap2::Mmap::map(&f) }.map_err(|e| e.to_string())?;
-
-        let data = unsafe { std::slice::from_raw_parts::<u8>(mapped.as_ptr(), mapped.len()) };
-
-        self.index_data(data)?;
-        self.backing_mmaps.push(mapped);
+    /// Replace a resource and the compression state of its fields.
+    fn insert_watched_resource(&mut self, resource: Resource<'a, u8>, fields: CompressedFields) {
+        if fields == CompressedFields::default() {
+            self.compressed_fields.remove(&resource.name);
+        } else {
+            self.compressed_fields.insert(resource.name.clone(), fields);
+        }
 
-        Ok(())
+        self.resources.insert(resource.name.clone(), resource);
     }
 
     /// Load resources from packed data stored in a PyObject.
@@ -537,6 +1676,30 @@ impl<'a> PythonResourcesState<'a, u8> {
         Ok(())
     }
 
+    /// Load resources for a named layer from packed data stored in a PyObject.
+    ///
+    /// The `PyObject` must conform to the buffer protocol. See
+    /// [Self::index_layer_data] for layering semantics.
+    pub fn index_layer_pyobject(
+        &mut self,
+        py: Python,
+        name: &str,
+        priority: i32,
+        obj: &PyAny,
+    ) -> PyResult<()> {
+        let buffer = PyBuffer::<u8>::get(obj)?;
+
+        let data = unsafe {
+            std::slice::from_raw_parts::<u8>(buffer.buf_ptr() as *const _, buffer.len_bytes())
+        };
+
+        self.backing_py_objects.push(obj.to_object(py));
+        self.index_layer_data(name, priority, data)
+            .map_err(PyValueError::new_err)?;
+
+        Ok(())
+    }
+
     /// Load `builtin` modules from the Python interpreter.
     pub fn index_interpreter_builtin_extension_modules(&mut self) -> Result<(), &'static str> {
         for i in 0.. {
@@ -554,6 +1717,7 @@ impl<'a> PythonResourcesState<'a, u8> {
                 }
             };
 
+            self.promote_lazy_resource(name_str);
             self.resources
                 .entry(name_str.into())
                 .and_modify(|r| {
@@ -586,6 +1750,7 @@ impl<'a> PythonResourcesState<'a, u8> {
                 }
             };
 
+            self.promote_lazy_resource(name_str);
             self.resources
                 .entry(name_str.into())
                 .and_modify(|r| {
@@ -613,9 +1778,208 @@ impl<'a> PythonResourcesState<'a, u8> {
         Ok(())
     }
 
+    /// Set the directory under which native files from wheels are extracted.
+    ///
+    /// Each wheel's files are extracted to a subdirectory named after the wheel
+    /// and a digest of its content. If [None], a private temporary directory
+    /// is created when needed and deleted when this instance is dropped.
+    #[cfg(feature = "zipimport")]
+    pub fn set_wheel_extension_cache_dir(&mut self, path: Option<PathBuf>) {
+        self.wheels.set_cache_dir(path);
+    }
+
+    /// Index resources from a wheel archive.
+    ///
+    /// `basename` is the file name of the wheel. `cache_tag` and
+    /// `extension_suffixes` should be `sys.implementation.cache_tag` and
+    /// `importlib.machinery.EXTENSION_SUFFIXES` of the running interpreter.
+    ///
+    /// Module source, package resources and `.dist-info` metadata are indexed
+    /// as in-memory resources and merged into existing resources. Like lazily
+    /// indexed packed resources data, their content is read from the archive
+    /// when a resource is first accessed. Extension modules and shared
+    /// libraries can't be loaded from the archive. So they are extracted to a
+    /// cache directory when an extension module from the wheel is first
+    /// imported. See [Self::set_wheel_extension_cache_dir].
+    #[cfg(feature = "zipimport")]
+    pub fn index_wheel_data(
+        &mut self,
+        basename: &str,
+        data: Vec<u8>,
+        cache_tag: &str,
+        extension_suffixes: &[String],
+    ) -> Result<(), String> {
+        let wheel = self
+            .wheels
+            .wheel_resources(basename, data, cache_tag, extension_suffixes)?;
+
+        // Eagerly indexed resources take precedence over lazily resolved ones.
+        // So merge the wheel into them to preserve merge semantics.
+        let names = wheel
+            .names()
+            .filter(|name| self.resources.contains_key(*name))
+            .map(|name| name.to_string())
+            .collect::<Vec<_>>();
+        for name in names {
+            if let Some(resource) = wheel.find_resource(&name)? {
+                self.record_compressed_fields(&resource, CompressedFields::default());
+                self.resources
+                    .get_mut(name.as_str())
+                    .expect("resource should exist")
+                    .merge_from(resource)?;
+            }
+        }
+
+        self.add_lazy_source(LazyResourcesSource::Wheel(wheel));
+
+        Ok(())
+    }
+
+    /// Index resources from wheels embedded as file data.
+    ///
+    /// Resources defining embedded data for a filename ending in `.whl` are
+    /// indexed via [Self::index_wheel_data]. This includes resources in lazily
+    /// indexed data.
+    #[cfg(feature = "zipimport")]
+    pub fn index_embedded_wheels(
+        &mut self,
+        cache_tag: &str,
+        extension_suffixes: &[String],
+    ) -> Result<(), String> {
+        let mut wheels = vec![];
+
+        let lazy_wheels = self
+            .lazy_resource_names()
+            .iter()
+            .filter(|name| name.ends_with(".whl") && !self.resources.contains_key(name.as_str()))
+            .filter_map(|name| self.resource(name));
+
+        for resource in self.resources.values().chain(lazy_wheels) {
+            if !resource.is_utf8_filename_data || !resource.name.ends_with(".whl") {
+                continue;
+            }
+
+            if let Some(data) = &resource.file_data_embedded {
+                let data = self
+                    .resource_compressed_fields(&resource.name)
+                    .resolve(ResourceField::FileDataEmbedded, data)?;
+
+                let basename = Path::new(resource.name.as_ref())
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .unwrap_or(resource.name.as_ref())
+                    .to_string();
+
+                wheels.push((basename, data.into_owned()));
+            }
+        }
+
+        for (basename, data) in wheels {
+            self.index_wheel_data(&basename, data, cache_tag, extension_suffixes)?;
+        }
+
+        Ok(())
+    }
+
+    /// Ensure the native files needed to import an extension module from a wheel exist.
+    ///
+    /// Does nothing if the extension module wasn't provided by a wheel.
+    #[cfg(feature = "zipimport")]
+    pub fn extract_wheel_extension_module(&self, name: &str) -> Result<(), String> {
+        self.wheels.extract_extension_module(name)
+    }
+
+    /// Set the root directory resources are extracted to.
+    ///
+    /// Each top-level package is extracted to a subdirectory named after the
+    /// package and a digest of its extracted files. If [None], a directory
+    /// named after the current executable in the per-user cache directory is
+    /// used. Extraction fails if the platform doesn't define one.
+    pub fn set_resources_cache_dir(&mut self, path: Option<PathBuf>) {
+        self.extract_cache.set_cache_dir(path);
+    }
+
+    /// Extract the resources of a module's top-level package to a cache directory.
+    ///
+    /// Does nothing unless the named module is marked as extracted to a cache.
+    /// Otherwise, module source, package resources, extension modules and
+    /// shared libraries of all resources in the module's top-level package
+    /// marked as extracted to a cache are written to a directory under the
+    /// cache root. Modules resolved afterwards refer to the extracted files
+    /// via `__file__`, `__path__` and `ModuleSpec.origin`.
+    pub fn extract_module_to_cache(&self, name: &str) -> Result<(), String> {
+        let name = name.strip_suffix(".__init__").unwrap_or(name);
+
+        match self.resource(name) {
+            Some(resource) if resource.extract_to_cache => {}
+            _ => return Ok(()),
+        }
+
+        let package = name.split('.').next().unwrap_or(name);
+
+        if self.extract_cache.package_dir(package).is_some() {
+            return Ok(());
+        }
+
+        let package_prefix = format!("{}.", package);
+        let mut files = vec![];
+        let mut library_names = BTreeSet::new();
+
+        for resource in self.all_resources() {
+            if !resource.extract_to_cache
+                || (resource.name != package && !resource.name.starts_with(&package_prefix))
+            {
+                continue;
+            }
+
+            let resource = self.decompressed_resource(resource)?;
+
+            if let Some(source) = resource.in_memory_source {
+                files.push((
+                    module_source_path(&resource.name, resource.is_python_package),
+                    source,
+                ));
+            }
+
+            if let Some(resources) = resource.in_memory_package_resources {
+                let package_dir = resource.name.split('.').collect::<PathBuf>();
+
+                for (relative_name, data) in resources {
+                    files.push((package_dir.join(relative_name.as_ref()), data));
+                }
+            }
+
+            if let Some(library) = resource.in_memory_extension_module_shared_library {
+                let path = extension_module_path(&resource.name, resource.is_python_package);
+
+                // Shared libraries are placed next to the extension module so
+                // the dynamic linker can find them.
+                for dependency in resource.shared_library_dependency_names.unwrap_or_default() {
+                    library_names.insert((
+                        path.parent().map(|p| p.to_path_buf()).unwrap_or_default(),
+                        dependency.to_string(),
+                    ));
+                }
+
+                files.push((path, library));
+            }
+        }
+
+        for (dir, library_name) in library_names {
+            if let Some(data) = self.resolve_in_memory_shared_library_data(&library_name)? {
+                files.push((dir.join(&library_name), data));
+            }
+        }
+
+        self.extract_cache
+            .extract_package(&self.current_exe, package, files)?;
+
+        Ok(())
+    }
+
     /// Says whether a named resource exists.
     pub fn has_resource(&self, name: &str) -> bool {
-        self.resources.contains_key(name)
+        self.resource(name).is_some()
     }
 
     /// Add a resource to the instance.
@@ -626,6 +1990,12 @@ impl<'a> PythonResourcesState<'a, u8> {
         &mut self,
         resource: Resource<'resource, u8>,
     ) -> Result<(), &'static str> {
+        self.compressed_fields.remove(&resource.name);
+        self.lazy_resources
+            .resources
+            .as_mut()
+            .remove(resource.name.as_ref());
+        self.layers.remove(&resource.name);
         self.resources.insert(resource.name.clone(), resource);
 
         Ok(())
@@ -662,11 +2032,22 @@ impl<'a> PythonResourcesState<'a, u8> {
         // for recognizing `__init__` because Python code in the wild relies on it.
         let name = name.strip_suffix(".__init__").unwrap_or(name);
 
-        let resource = match self.resources.get(name) {
+        let resource = match self.resource(name) {
             Some(entry) => entry,
             None => return None,
         };
 
+        self.record_access(name, ResourceAccessKind::Module);
+
+        let compressed_fields = self.resource_compressed_fields(name);
+
+        let extracted_dir = if resource.extract_to_cache {
+            self.extract_cache
+                .package_dir(name.split('.').next().unwrap_or(name))
+        } else {
+            None
+        };
+
         // Since resources can exist as multiple types and it is possible
         // that a single resource will express itself as multiple types
         // (e.g. we have both bytecode and an extension module available),
@@ -691,7 +2072,7 @@ impl<'a> PythonResourcesState<'a, u8> {
         // 1. built-in extension modules
         // 2. frozen modules
         // 3. extension modules
-        // 4. module (covers both source and bytecode)
+        // 4. module (covers namespace packages, source, and bytecode)
 
         if resource.is_python_builtin_extension_module {
             Some(ImportablePythonModule {
@@ -700,6 +2081,8 @@ impl<'a> PythonResourcesState<'a, u8> {
                 origin: &self.origin,
                 flavor: ModuleFlavor::Builtin,
                 is_package: resource.is_python_package,
+                compressed_fields,
+                extracted_dir,
             })
         } else if resource.is_python_frozen_module {
             Some(ImportablePythonModule {
@@ -708,6 +2091,8 @@ impl<'a> PythonResourcesState<'a, u8> {
                 origin: &self.origin,
                 flavor: ModuleFlavor::Frozen,
                 is_package: resource.is_python_package,
+                compressed_fields,
+                extracted_dir,
             })
         } else if resource.is_python_extension_module {
             Some(ImportablePythonModule {
@@ -716,15 +2101,29 @@ impl<'a> PythonResourcesState<'a, u8> {
                 origin: &self.origin,
                 flavor: ModuleFlavor::Extension,
                 is_package: resource.is_python_package,
+                compressed_fields,
+                extracted_dir,
             })
         } else if resource.is_python_module {
-            if is_module_importable(resource, optimize_level) {
+            if resource.is_python_namespace_package {
+                Some(ImportablePythonModule {
+                    resource,
+                    current_exe: &self.current_exe,
+                    origin: &self.origin,
+                    flavor: ModuleFlavor::Namespace,
+                    is_package: true,
+                    compressed_fields,
+                    extracted_dir,
+                })
+            } else if is_module_importable(resource, optimize_level) {
                 Some(ImportablePythonModule {
                     resource,
                     current_exe: &self.current_exe,
                     origin: &self.origin,
                     flavor: ModuleFlavor::SourceBytecode,
                     is_package: resource.is_python_package,
+                    compressed_fields,
+                    extracted_dir,
                 })
             } else {
                 None
@@ -745,23 +2144,31 @@ impl<'a> PythonResourcesState<'a, u8> {
         package: &str,
         resource_name: &str,
     ) -> PyResult<Option<&'p PyAny>> {
-        let entry = match self.resources.get(package) {
+        let entry = match self.resource(package) {
             Some(entry) => entry,
             None => return Ok(None),
         };
 
         if let Some(resources) = &entry.in_memory_package_resources {
             if let Some(data) = resources.get(resource_name) {
+                self.record_access(package, ResourceAccessKind::PackageResource);
+
                 let io_module = py.import("io")?;
                 let bytes_io = io_module.getattr("BytesIO")?;
 
-                let data = PyBytes::new(py, data);
+                let data = self
+                    .resource_compressed_fields(package)
+                    .resolve(ResourceField::InMemoryResourcesData, data)
+                    .map_err(PyValueError::new_err)?;
+                let data = PyBytes::new(py, &data);
                 return Ok(Some(bytes_io.call((data,), None)?));
             }
         }
 
         if let Some(resources) = &entry.relative_path_package_resources {
             if let Some(path) = resources.get(resource_name) {
+                self.record_access(package, ResourceAccessKind::PackageResource);
+
                 let path = self.origin.join(path);
                 let io_module = py.import("io")?;
 
@@ -778,7 +2185,7 @@ impl<'a> PythonResourcesState<'a, u8> {
 
     /// Determines whether a specific package + name pair is a known Python package resource.
     pub fn is_package_resource(&self, package: &str, resource_name: &str) -> bool {
-        if let Some(entry) = self.resources.get(package) {
+        if let Some(entry) = self.resource(package) {
             if let Some(resources) = &entry.in_memory_package_resources {
                 if resources.contains_key(resource_name) {
                     return true;
@@ -799,7 +2206,7 @@ impl<'a> PythonResourcesState<'a, u8> {
     ///
     /// The names are returned in sorted order.
     pub fn package_resource_names<'p>(&self, py: Python<'p>, package: &str) -> PyResult<&'p PyAny> {
-        let entry = match self.resources.get(package) {
+        let entry = match self.resource(package) {
             Some(entry) => entry,
             None => return Ok(PyList::empty(py).into()),
         };
@@ -833,7 +2240,7 @@ impl<'a> PythonResourcesState<'a, u8> {
             format!("{}/", name)
         };
 
-        if let Some(entry) = self.resources.get(package) {
+        if let Some(entry) = self.resource(package) {
             if let Some(resources) = &entry.in_memory_package_resources {
                 if resources.keys().any(|path| path.starts_with(&prefix)) {
                     return true;
@@ -890,7 +2297,7 @@ impl<'a> PythonResourcesState<'a, u8> {
 
         let mut entries = BTreeSet::new();
 
-        if let Some(entry) = self.resources.get(package) {
+        if let Some(entry) = self.resource(package) {
             if let Some(resources) = &entry.in_memory_package_resources {
                 entries.extend(resources.keys().filter_map(filter_map_resource));
             }
@@ -955,6 +2362,9 @@ impl<'a> PythonResourcesState<'a, u8> {
         let (relative_path, check_in_memory, check_relative_path) =
             if let Ok(relative_path) = native_path.strip_prefix(&self.current_exe) {
                 (relative_path, true, false)
+            } else if let Some(relative_path) = self.extract_cache.relative_path(&native_path) {
+                // Extracted files are copies of in-memory resources.
+                (relative_path, true, false)
             } else if let Ok(relative_path) = native_path.strip_prefix(&self.origin) {
                 (relative_path, false, true)
             } else {
@@ -1008,11 +2418,21 @@ impl<'a> PythonResourcesState<'a, u8> {
             let resource_name = name_parts.join("/");
             let resource_name_ref: &str = &resource_name;
 
-            if let Some(entry) = self.resources.get(package_name_ref) {
+            if let Some(entry) = self.resource(package_name_ref) {
                 if check_in_memory {
                     if let Some(resources) = &entry.in_memory_package_resources {
                         if let Some(data) = resources.get(resource_name_ref) {
-                            return Ok(PyBytes::new(py, data).into());
+                            self.record_access(
+                                package_name_ref,
+                                ResourceAccessKind::PackageResource,
+                            );
+
+                            let data = self
+                                .resource_compressed_fields(package_name_ref)
+                                .resolve(ResourceField::InMemoryResourcesData, data)
+                                .map_err(PyValueError::new_err)?;
+
+                            return Ok(PyBytes::new(py, &data).into());
                         }
                     }
                 }
@@ -1020,6 +2440,11 @@ impl<'a> PythonResourcesState<'a, u8> {
                 if check_relative_path {
                     if let Some(resources) = &entry.relative_path_package_resources {
                         if let Some(resource_relative_path) = resources.get(resource_name_ref) {
+                            self.record_access(
+                                package_name_ref,
+                                ResourceAccessKind::PackageResource,
+                            );
+
                             let resource_path = self.origin.join(resource_relative_path);
 
                             let io_module = py.import("io")?;
@@ -1063,8 +2488,8 @@ impl<'a> PythonResourcesState<'a, u8> {
         optimize_level: BytecodeOptimizationLevel,
     ) -> PyResult<&'p PyList> {
         let infos: PyResult<Vec<_>> = self
-            .resources
-            .values()
+            .all_resources()
+            .into_iter()
             .filter(|r| {
                 r.is_python_extension_module
                     || (r.is_python_module && is_module_importable(r, optimize_level))
@@ -1094,8 +2519,8 @@ impl<'a> PythonResourcesState<'a, u8> {
 
     /// Resolve the names of package distributions matching a name filter.
     pub fn package_distribution_names(&self, filter: impl Fn(&str) -> bool) -> Vec<&'_ str> {
-        self.resources
-            .values()
+        self.all_resources()
+            .into_iter()
             .filter(|r| {
                 r.is_python_package
                     && (r.in_memory_distribution_resources.is_some()
@@ -1112,15 +2537,23 @@ impl<'a> PythonResourcesState<'a, u8> {
         package: &str,
         name: &str,
     ) -> Result<Option<Cow<'_, [u8]>>> {
-        if let Some(entry) = self.resources.get(package) {
+        if let Some(entry) = self.resource(package) {
             if let Some(resources) = &entry.in_memory_distribution_resources {
                 if let Some(data) = resources.get(name) {
-                    return Ok(Some(Cow::Borrowed(data.as_ref())));
+                    self.record_access(package, ResourceAccessKind::DistributionResource);
+
+                    return Ok(Some(
+                        self.resource_compressed_fields(package)
+                            .resolve(ResourceField::InMemoryDistributionResource, data)
+                            .map_err(|e| anyhow!(e))?,
+                    ));
                 }
             }
 
             if let Some(resources) = &entry.relative_path_distribution_resources {
                 if let Some(path) = resources.get(name) {
+                    self.record_access(package, ResourceAccessKind::DistributionResource);
+
                     let path = &self.origin.join(path);
                     let data = std::fs::read(path)?;
 
@@ -1148,7 +2581,7 @@ impl<'a> PythonResourcesState<'a, u8> {
             format!("{}/", name)
         };
 
-        if let Some(entry) = &self.resources.get(package) {
+        if let Some(entry) = &self.resource(package) {
             if let Some(resources) = &entry.in_memory_distribution_resources {
                 if resources.keys().any(|path| path.starts_with(&prefix)) {
                     return true;
@@ -1185,9 +2618,9 @@ impl<'a> PythonResourcesState<'a, u8> {
 
         let filter_map_resource = |path: &'slf Cow<'slf, str>| -> Option<&'slf str> {
             match &prefix {
-                Some(prefix) => {
-                    path.strip_prefix(prefix).filter(|&name| !name.contains('/'))
-                }
+                Some(prefix) => path
+                    .strip_prefix(prefix)
+                    .filter(|&name| !name.contains('/')),
                 None => {
                     // Empty string input matches root directory.
                     if path.contains('/') {
@@ -1201,7 +2634,7 @@ impl<'a> PythonResourcesState<'a, u8> {
 
         let mut entries = BTreeSet::new();
 
-        if let Some(entry) = self.resources.get(package) {
+        if let Some(entry) = self.resource(package) {
             if let Some(resources) = &entry.in_memory_distribution_resources {
                 entries.extend(resources.keys().filter_map(filter_map_resource));
             }
@@ -1215,26 +2648,57 @@ impl<'a> PythonResourcesState<'a, u8> {
     }
 
     /// Resolve content of a shared library to load from memory.
-    pub fn resolve_in_memory_shared_library_data(&self, name: &str) -> Option<&[u8]> {
-        if let Some(entry) = &self.resources.get(name) {
+    pub fn resolve_in_memory_shared_library_data(
+        &self,
+        name: &str,
+    ) -> Result<Option<Cow<'_, [u8]>>, &'static str> {
+        if let Some(entry) = &self.resource(name) {
             if let Some(library_data) = &entry.in_memory_shared_library {
-                Some(library_data.as_ref())
+                self.record_access(name, ResourceAccessKind::SharedLibrary);
+
+                Ok(Some(self.resource_compressed_fields(name).resolve(
+                    ResourceField::InMemorySharedLibrary,
+                    library_data,
+                )?))
             } else {
-                None
+                Ok(None)
             }
         } else {
-            None
+            Ok(None)
         }
     }
 
+    /// Resolve the names of shared libraries a resource depends on.
+    pub fn resolve_shared_library_dependency_names(&self, name: &str) -> Vec<String> {
+        self.resource(name)
+            .and_then(|entry| entry.shared_library_dependency_names.as_ref())
+            .map(|names| names.iter().map(|name| name.to_string()).collect())
+            .unwrap_or_default()
+    }
+
+    /// Obtain a copy of a resource with compressed data decompressed.
+    fn decompressed_resource<'r>(
+        &self,
+        resource: &Resource<'r, u8>,
+    ) -> Result<Resource<'r, u8>, &'static str> {
+        self.resource_compressed_fields(&resource.name)
+            .decompress_resource(resource)
+    }
+
     /// Convert indexed resources to a [PyList].
     pub fn resources_as_py_list<'p>(&self, py: Python<'p>) -> PyResult<&'p PyList> {
-        let mut resources = self.resources.values().collect::<Vec<_>>();
+        let mut resources = self.all_resources();
         resources.sort_by_key(|r| &r.name);
 
         let objects = resources
             .iter()
-            .map(|r| resource_to_pyobject(py, r))
+            .map(|r| {
+                let r = self
+                    .decompressed_resource(r)
+                    .map_err(PyValueError::new_err)?;
+
+                resource_to_pyobject(py, &r)
+            })
             .collect::<Result<Vec<_>, _>>()?;
 
         Ok(PyList::new(py, objects))
@@ -1250,17 +2714,19 @@ impl<'a> PythonResourcesState<'a, u8> {
         ignore_frozen: bool,
     ) -> Result<Vec<u8>> {
         let mut resources = self
-            .resources
-            .values()
+            .all_resources()
+            .into_iter()
             .filter(|resource| {
                 // This assumes builtins and frozen are mutually exclusive with other types.
                 !((resource.is_python_builtin_extension_module && ignore_builtin)
                     || (resource.is_python_frozen_module && ignore_frozen))
             })
-            .collect::<Vec<&Resource<u8>>>();
+            .map(|resource| self.decompressed_resource(resource))
+            .collect::<Result<Vec<Resource<u8>>, &'static str>>()
+            .map_err(|e| anyhow!(e))?;
 
         // Sort so behavior is deterministic.
-        resources.sort_by_key(|v| &v.name);
+        resources.sort_by(|a, b| a.name.cmp(&b.name));
 
         let mut buffer = Vec::new();
 
@@ -1747,6 +3213,18 @@ impl OxidizedResource {
 
         Ok(())
     }
+
+    #[getter]
+    fn get_extract_to_cache(&self) -> bool {
+        self.resource.borrow().extract_to_cache
+    }
+
+    #[setter]
+    fn set_extract_to_cache(&self, value: bool) -> PyResult<()> {
+        self.resource.borrow_mut().extract_to_cache = value;
+
+        Ok(())
+    }
 }
 
 /// Convert a Resource to an OxidizedResource.
@@ -0,0 +1,93 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*! Recording of accesses to indexed resources. */
+
+use {
+    pyo3::{prelude::*, types::PyDict},
+    std::{collections::BTreeMap, sync::Mutex},
+};
+
+/// A kind of resource data that was accessed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResourceAccessKind {
+    /// A Python module was resolved for importing.
+    Module,
+    /// A resource file in a Python package was read.
+    PackageResource,
+    /// A file in a Python package distribution's metadata was read.
+    DistributionResource,
+    /// A shared library was loaded.
+    SharedLibrary,
+}
+
+/// Counts of accesses to a single resource, by kind of data accessed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ResourceAccessCounts {
+    /// Number of times the resource was resolved as a Python module.
+    pub module: u64,
+    /// Number of times a package resource file of the resource was read.
+    pub package_resource: u64,
+    /// Number of times a distribution metadata file of the resource was read.
+    pub distribution_resource: u64,
+    /// Number of times the resource was loaded as a shared library.
+    pub shared_library: u64,
+}
+
+impl ResourceAccessCounts {
+    fn increment(&mut self, kind: ResourceAccessKind) {
+        let count = match kind {
+            ResourceAccessKind::Module => &mut self.module,
+            ResourceAccessKind::PackageResource => &mut self.package_resource,
+            ResourceAccessKind::DistributionResource => &mut self.distribution_resource,
+            ResourceAccessKind::SharedLibrary => &mut self.shared_library,
+        };
+
+        *count += 1;
+    }
+
+    /// Convert to a Python `dict`.
+    pub fn to_dict<'p>(&self, py: Python<'p>) -> PyResult<&'p PyDict> {
+        let dict = PyDict::new(py);
+
+        dict.set_item("module", self.module)?;
+        dict.set_item("package_resource", self.package_resource)?;
+        dict.set_item("distribution_resource", self.distribution_resource)?;
+        dict.set_item("shared_library", self.shared_library)?;
+
+        Ok(dict)
+    }
+}
+
+/// Records which resources were accessed and how often.
+#[derive(Debug, Default)]
+pub struct ResourceAccessTracker {
+    counts: Mutex<BTreeMap<String, ResourceAccessCounts>>,
+}
+
+impl ResourceAccessTracker {
+    /// Record an access to the resource named `name`.
+    pub fn record(&self, name: &str, kind: ResourceAccessKind) {
+        let mut counts = self
+            .counts
+            .lock()
+            .expect("resource access lock should not be poisoned");
+
+        if let Some(entry) = counts.get_mut(name) {
+            entry.increment(kind);
+        } else {
+            let mut entry = ResourceAccessCounts::default();
+            entry.increment(kind);
+            counts.insert(name.to_string(), entry);
+        }
+    }
+
+    /// Obtain a copy of the access counts of every accessed resource.
+    pub fn counts(&self) -> BTreeMap<String, ResourceAccessCounts> {
+        self.counts
+            .lock()
+            .expect("resource access lock should not be poisoned")
+            .clone()
+    }
+}
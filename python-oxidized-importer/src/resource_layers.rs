@@ -0,0 +1,154 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*! Layering of packed resources data sources. */
+
+use {
+    pyo3::{prelude::*, types::PyDict},
+    std::collections::HashMap,
+};
+
+/// A named source of resources having a priority.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ResourcesLayer {
+    /// Name of the layer.
+    pub name: String,
+
+    /// Priority of the layer.
+    ///
+    /// Resources in layers having a higher priority replace resources of
+    /// the same name in layers having a lower priority.
+    pub priority: i32,
+}
+
+/// A resource that was provided by multiple layers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LayerConflict {
+    /// Name of the resource.
+    pub name: String,
+
+    /// Name of the layer whose resource is used.
+    pub layer: String,
+
+    /// Name of the layer whose resource was replaced or ignored.
+    pub shadowed_layer: String,
+}
+
+impl LayerConflict {
+    /// Convert to a Python `dict`.
+    pub fn to_dict<'p>(&self, py: Python<'p>) -> PyResult<&'p PyDict> {
+        let dict = PyDict::new(py);
+
+        dict.set_item("name", &self.name)?;
+        dict.set_item("layer", &self.layer)?;
+        dict.set_item("shadowed_layer", &self.shadowed_layer)?;
+
+        Ok(dict)
+    }
+}
+
+/// Tracks which layer provided each resource.
+#[derive(Debug, Default)]
+pub struct ResourceLayers {
+    /// Registered layers, in the order they were added.
+    layers: Vec<ResourcesLayer>,
+
+    /// Index into `layers` of the layer providing each resource.
+    ///
+    /// Resources not provided by a layer don't have an entry.
+    providers: HashMap<String, usize>,
+
+    /// Resources provided by multiple layers.
+    conflicts: Vec<LayerConflict>,
+
+    /// Whether conflicts that aren't resolved by priority are an error.
+    strict: bool,
+}
+
+impl ResourceLayers {
+    /// Set whether conflicts that aren't resolved by priority are an error.
+    ///
+    /// When a resource is provided by multiple layers having the same priority,
+    /// the layer added last wins. In strict mode, this is an error instead.
+    pub fn set_strict(&mut self, value: bool) {
+        self.strict = value;
+    }
+
+    /// Register a new layer, returning its identifier.
+    pub fn add_layer(&mut self, name: &str, priority: i32) -> Result<usize, String> {
+        if self.layers.iter().any(|layer| layer.name == name) {
+            return Err(format!("resources layer {} is already registered", name));
+        }
+
+        self.layers.push(ResourcesLayer {
+            name: name.to_string(),
+            priority,
+        });
+
+        Ok(self.layers.len() - 1)
+    }
+
+    /// Resolve whether a resource from a layer should replace an existing resource.
+    ///
+    /// `exists` denotes whether a resource of the same name is already indexed.
+    /// Resources not provided by a layer are always replaced.
+    ///
+    /// Returns whether the incoming resource should be indexed.
+    pub fn resolve(&mut self, name: &str, layer: usize, exists: bool) -> Result<bool, String> {
+        let existing = match self.providers.get(name) {
+            Some(existing) if exists && *existing != layer => *existing,
+            _ => {
+                self.providers.insert(name.to_string(), layer);
+                return Ok(true);
+            }
+        };
+
+        let incoming_layer = &self.layers[layer];
+        let existing_layer = &self.layers[existing];
+
+        if incoming_layer.priority == existing_layer.priority && self.strict {
+            return Err(format!(
+                "resource {} is provided by layers {} and {} having the same priority",
+                name, existing_layer.name, incoming_layer.name
+            ));
+        }
+
+        let replace = incoming_layer.priority >= existing_layer.priority;
+
+        let (winner, loser) = if replace {
+            (incoming_layer, existing_layer)
+        } else {
+            (existing_layer, incoming_layer)
+        };
+
+        self.conflicts.push(LayerConflict {
+            name: name.to_string(),
+            layer: winner.name.clone(),
+            shadowed_layer: loser.name.clone(),
+        });
+
+        if replace {
+            self.providers.insert(name.to_string(), layer);
+        }
+
+        Ok(replace)
+    }
+
+    /// Record that a resource is no longer provided by a layer.
+    pub fn remove(&mut self, name: &str) {
+        self.providers.remove(name);
+    }
+
+    /// Obtain the name of the layer that provided a resource.
+    pub fn layer_name(&self, name: &str) -> Option<&str> {
+        self.providers
+            .get(name)
+            .map(|layer| self.layers[*layer].name.as_str())
+    }
+
+    /// Obtain resources that were provided by multiple layers.
+    pub fn conflicts(&self) -> &[LayerConflict] {
+        &self.conflicts
+    }
+}
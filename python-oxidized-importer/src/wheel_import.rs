@@ -0,0 +1,416 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*! Importing Python resources from wheel archives. */
+
+use {
+    crate::{
+        extract_cache::{cache_directory_name, write_file},
+        zip_import::ZipIndex,
+    },
+    python_packaging::{
+        module_util::PythonModuleSuffixes, resource::PythonResource, wheel::WheelArchive,
+    },
+    python_packed_resources::Resource,
+    sha2::{Digest, Sha256},
+    simple_file_manifest::{FileData, FileEntry, FileManifest},
+    std::{
+        borrow::Cow,
+        collections::{BTreeMap, HashMap},
+        io::Cursor,
+        path::{Path, PathBuf},
+        sync::{Arc, Mutex},
+    },
+};
+
+/// Whether a wheel member holds native code that must exist on the filesystem.
+///
+/// This covers extension modules as well as shared libraries they depend on,
+/// such as those `auditwheel` places in a `<package>.libs` directory.
+fn is_native_file(path: &Path) -> bool {
+    let name = match path.file_name().and_then(|name| name.to_str()) {
+        Some(name) => name,
+        None => return false,
+    };
+
+    name.ends_with(".so")
+        || name.contains(".so.")
+        || name.ends_with(".pyd")
+        || name.ends_with(".dll")
+        || name.ends_with(".dylib")
+}
+
+/// Whether a wheel member's content is needed to classify wheel members.
+///
+/// Distribution metadata is read to determine the package a `.dist-info`
+/// directory belongs to.
+fn is_classification_file(path: &Path) -> bool {
+    let mut components = path.iter().map(|component| component.to_str());
+
+    matches!(
+        (components.next(), components.next(), components.next()),
+        (Some(Some(directory)), Some(Some("METADATA")), None) if directory.ends_with(".dist-info")
+    )
+}
+
+/// An indexed wheel archive, shared by its resources and native files.
+type SharedWheelArchive = Arc<Mutex<ZipIndex<Cursor<Vec<u8>>>>>;
+
+/// Read the content of wheel member data.
+///
+/// Data that isn't in memory refers to a member of `archive` by its path.
+fn read_member(archive: &SharedWheelArchive, data: &FileData) -> Result<Vec<u8>, String> {
+    match data {
+        FileData::Memory(data) => Ok(data.clone()),
+        FileData::Path(path) => archive
+            .lock()
+            .map_err(|_| "wheel archive lock poisoned".to_string())?
+            .resolve_path_content(path)
+            .map_err(|e| format!("error reading {}: {}", path.display(), e)),
+    }
+}
+
+/// Wheel members providing a resource.
+#[derive(Default)]
+struct WheelResourceMembers {
+    is_package: bool,
+    is_extension_module: bool,
+    source: Option<FileData>,
+    package_resources: Vec<(String, FileData)>,
+    distribution_resources: Vec<(String, FileData)>,
+    /// Where the extension module is extracted to.
+    extension_module_path: Option<PathBuf>,
+}
+
+/// Resources of a wheel archive.
+///
+/// Only the archive index is read when the wheel is indexed. Like the zip
+/// importer, member content is read from the archive when a resource is
+/// resolved.
+pub struct WheelResources {
+    archive: SharedWheelArchive,
+    resources: BTreeMap<String, WheelResourceMembers>,
+}
+
+impl std::fmt::Debug for WheelResources {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WheelResources")
+            .field("resources", &self.resources.keys())
+            .finish_non_exhaustive()
+    }
+}
+
+impl WheelResources {
+    /// Obtain the names of resources in the wheel.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.resources.keys().map(|name| name.as_str())
+    }
+
+    /// Resolve a resource, reading its content from the archive.
+    pub fn find_resource(&self, name: &str) -> Result<Option<Resource<'static, u8>>, String> {
+        let members = match self.resources.get(name) {
+            Some(members) => members,
+            None => return Ok(None),
+        };
+
+        let read_map = |entries: &[(String, FileData)]| -> Result<_, String> {
+            if entries.is_empty() {
+                return Ok(None);
+            }
+
+            Ok(Some(
+                entries
+                    .iter()
+                    .map(|(name, data)| {
+                        Ok((
+                            Cow::Owned(name.clone()),
+                            Cow::Owned(read_member(&self.archive, data)?),
+                        ))
+                    })
+                    .collect::<Result<HashMap<_, _>, String>>()?,
+            ))
+        };
+
+        Ok(Some(Resource {
+            name: Cow::Owned(name.to_string()),
+            is_python_module: true,
+            is_python_package: members.is_package,
+            is_python_extension_module: members.is_extension_module,
+            in_memory_source: members
+                .source
+                .as_ref()
+                .map(|data| read_member(&self.archive, data).map(Cow::Owned))
+                .transpose()?,
+            in_memory_package_resources: read_map(&members.package_resources)?,
+            in_memory_distribution_resources: read_map(&members.distribution_resources)?,
+            relative_path_extension_module_shared_library: members
+                .extension_module_path
+                .clone()
+                .map(Cow::Owned),
+            ..Resource::default()
+        }))
+    }
+}
+
+/// Native files from a wheel that are extracted to a cache directory on first import.
+pub struct WheelExtensionCache {
+    /// Directory files are extracted to.
+    directory: PathBuf,
+
+    /// Archive holding the files.
+    archive: SharedWheelArchive,
+
+    /// Files to extract, as paths relative to `directory` and their data.
+    ///
+    /// Emptied once the files have been extracted.
+    files: Mutex<Vec<(PathBuf, FileData)>>,
+}
+
+impl std::fmt::Debug for WheelExtensionCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WheelExtensionCache")
+            .field("directory", &self.directory)
+            .field("files", &self.files)
+            .finish_non_exhaustive()
+    }
+}
+
+impl WheelExtensionCache {
+    /// Extract files to the cache directory, if this hasn't been done already.
+    ///
+    /// Files that already exist with the expected content are left alone.
+    /// Others are written atomically. See [write_file].
+    pub fn extract(&self) -> Result<(), String> {
+        let mut files = self
+            .files
+            .lock()
+            .map_err(|_| "wheel extension cache lock poisoned".to_string())?;
+
+        for (relative_path, data) in files.iter() {
+            write_file(
+                &self.directory.join(relative_path),
+                &read_member(&self.archive, data)?,
+            )?;
+        }
+
+        *files = vec![];
+
+        Ok(())
+    }
+}
+
+/// Tracks native files from indexed wheels.
+#[derive(Debug, Default)]
+pub struct WheelImports {
+    /// Directory under which native files are extracted.
+    ///
+    /// If [None], a private temporary directory is used.
+    cache_dir: Option<PathBuf>,
+
+    /// Private temporary directory native files are extracted to.
+    ///
+    /// Created on demand and deleted when this instance is dropped.
+    temp_dir: Option<tempfile::TempDir>,
+
+    /// Native files of indexed wheels.
+    caches: Vec<WheelExtensionCache>,
+
+    /// Index into `caches` for each extension module provided by a wheel.
+    extension_modules: HashMap<String, usize>,
+}
+
+impl WheelImports {
+    /// Set the directory under which native files are extracted.
+    pub fn set_cache_dir(&mut self, path: Option<PathBuf>) {
+        self.cache_dir = path;
+    }
+
+    /// Obtain the directory under which native files are extracted.
+    ///
+    /// Creates a temporary directory if no directory is configured.
+    fn cache_root(&mut self) -> Result<PathBuf, String> {
+        if let Some(path) = &self.cache_dir {
+            return Ok(path.clone());
+        }
+
+        if self.temp_dir.is_none() {
+            self.temp_dir = Some(
+                tempfile::Builder::new()
+                    .prefix("oxidized-wheels-")
+                    .tempdir()
+                    .map_err(|e| format!("error creating wheel extension cache: {}", e))?,
+            );
+        }
+
+        Ok(self
+            .temp_dir
+            .as_ref()
+            .expect("temp dir should be defined")
+            .path()
+            .to_path_buf())
+    }
+
+    /// Index resources of a wheel archive.
+    ///
+    /// `basename` is the file name of the wheel, which must conform to the wheel
+    /// naming convention. `cache_tag` and `extension_suffixes` come from the
+    /// running interpreter and are used to classify wheel members.
+    ///
+    /// Only the archive index and distribution metadata are read. Content of
+    /// other members is read when a resource is resolved.
+    ///
+    /// Extension modules are given an absolute path in a directory under the
+    /// cache directory. Native files are only written there when an extension
+    /// module from the wheel is first imported. See
+    /// [Self::extract_extension_module].
+    pub fn wheel_resources(
+        &mut self,
+        basename: &str,
+        data: Vec<u8>,
+        cache_tag: &str,
+        extension_suffixes: &[String],
+    ) -> Result<WheelResources, String> {
+        let read_error =
+            |e: &dyn std::fmt::Display| format!("error reading wheel {}: {}", basename, e);
+
+        let directory_name = cache_directory_name(
+            basename.strip_suffix(".whl").unwrap_or(basename),
+            Sha256::new_with_prefix(&data),
+        );
+
+        let mut archive = ZipIndex::new(Cursor::new(data), None).map_err(|e| read_error(&e))?;
+
+        // Members are classified by path. Their data refers to the member in the
+        // archive so content can be read on demand.
+        let mut manifest = FileManifest::default();
+        for path in archive
+            .file_paths()
+            .map(|path| path.to_path_buf())
+            .collect::<Vec<_>>()
+        {
+            let entry = if is_classification_file(&path) {
+                FileEntry::new_from_data(
+                    archive
+                        .resolve_path_content(&path)
+                        .map_err(|e| read_error(&e))?,
+                    false,
+                )
+            } else {
+                FileEntry::new_from_path(&path, false)
+            };
+
+            manifest
+                .add_file_entry(&path, entry)
+                .map_err(|e| read_error(&e))?;
+        }
+
+        let wheel = WheelArchive::from_manifest(manifest, basename).map_err(|e| read_error(&e))?;
+
+        let suffixes = PythonModuleSuffixes {
+            source: vec![".py".to_string()],
+            bytecode: vec![".pyc".to_string()],
+            debug_bytecode: vec![],
+            optimized_bytecode: vec![],
+            extension: extension_suffixes.to_vec(),
+        };
+
+        let mut resources = BTreeMap::<String, WheelResourceMembers>::new();
+        let mut extension_paths = vec![];
+
+        for resource in wheel
+            .python_resources(cache_tag, &suffixes, false, true)
+            .map_err(|e| read_error(&e))?
+        {
+            match resource {
+                PythonResource::ModuleSource(module) => {
+                    let entry = resources.entry(module.name.clone()).or_default();
+                    entry.is_package |= module.is_package;
+                    entry.source = Some(module.source.clone());
+                }
+                PythonResource::PackageResource(resource) => {
+                    let entry = resources.entry(resource.leaf_package.clone()).or_default();
+                    entry.is_package = true;
+                    entry
+                        .package_resources
+                        .push((resource.relative_name.clone(), resource.data.clone()));
+                }
+                PythonResource::PackageDistributionResource(resource) => {
+                    let entry = resources.entry(resource.package.clone()).or_default();
+                    entry.is_package = true;
+                    entry
+                        .distribution_resources
+                        .push((resource.name.clone(), resource.data.clone()));
+                }
+                PythonResource::ExtensionModule(module) => {
+                    let entry = resources.entry(module.name.clone()).or_default();
+                    entry.is_extension_module = true;
+                    entry.is_package |= module.is_package;
+                    extension_paths.push((module.name.clone(), module.resolve_path("")));
+                }
+                // Bytecode in wheels is rare and would need to be validated against
+                // its source. Source is compiled instead.
+                _ => {}
+            }
+        }
+
+        // Native files are extracted using their installed layout, so references
+        // between them via relative paths continue to work.
+        let files = wheel
+            .regular_files()
+            .into_iter()
+            .chain(wheel.purelib_files())
+            .chain(wheel.platlib_files())
+            .filter(|file| is_native_file(file.path()))
+            .map(|file| (file.path().to_path_buf(), file.entry().file_data().clone()))
+            .collect::<Vec<_>>();
+
+        let archive = Arc::new(Mutex::new(archive));
+
+        if !files.is_empty() {
+            let directory = self.cache_root()?.join(directory_name);
+
+            for (name, path) in &extension_paths {
+                if let Some(entry) = resources.get_mut(name) {
+                    entry.extension_module_path = Some(directory.join(path));
+                }
+            }
+
+            self.add_extension_cache(
+                WheelExtensionCache {
+                    directory,
+                    archive: archive.clone(),
+                    files: Mutex::new(files),
+                },
+                extension_paths.into_iter().map(|(name, _)| name),
+            );
+        }
+
+        Ok(WheelResources { archive, resources })
+    }
+
+    /// Register native files of a wheel providing the named extension modules.
+    fn add_extension_cache(
+        &mut self,
+        cache: WheelExtensionCache,
+        extension_modules: impl Iterator<Item = String>,
+    ) {
+        let index = self.caches.len();
+        self.caches.push(cache);
+
+        for name in extension_modules {
+            self.extension_modules.insert(name, index);
+        }
+    }
+
+    /// Ensure the native files needed to import an extension module exist.
+    ///
+    /// Does nothing if the module wasn't provided by a wheel.
+    pub fn extract_extension_module(&self, name: &str) -> Result<(), String> {
+        if let Some(index) = self.extension_modules.get(name) {
+            self.caches[*index].extract()
+        } else {
+            Ok(())
+        }
+    }
+}
@@ -5,22 +5,26 @@
 /*! Support for importing from zip archives. */
 
 use {
-    crate::{conversion::pyobject_to_pathbuf, decode_source},
+    crate::{
+        conversion::pyobject_to_pathbuf,
+        decode_source,
+        pyc::{invalid_pyc_reason, PycSource},
+    },
     anyhow::{anyhow, Result},
     pyo3::{
         buffer::PyBuffer,
-        exceptions::{PyImportError, PyValueError},
+        exceptions::{PyFileNotFoundError, PyImportError, PyNotADirectoryError, PyValueError},
         ffi as pyffi,
         prelude::*,
-        types::{PyBytes, PyDict, PyType},
+        types::{PyBytes, PyDict, PyList, PyTuple, PyType},
         PyNativeType, PyTraverseError, PyVisit,
     },
     std::{
-        collections::HashMap,
+        collections::{BTreeMap, BTreeSet, HashMap, HashSet},
         io::{BufReader, Cursor, Read, Seek},
         path::{Path, PathBuf},
     },
-    zip::read::ZipArchive,
+    zip::{read::ZipArchive, DateTime},
 };
 
 /// Represents a handle on a Python module within a [ZipImporter].
@@ -44,7 +48,10 @@ pub struct ZipIndex<R: Read + Seek> {
     /// Handle on zip archive that we're reading from.
     archive: ZipArchive<R>,
     prefix: Option<PathBuf>,
+    /// Files in the archive and their index in the archive.
     members: HashMap<PathBuf, usize>,
+    /// Directories in the archive, explicit or implied by the paths of files.
+    directories: HashSet<PathBuf>,
 }
 
 impl<R: Read + Seek> ZipIndex<R> {
@@ -58,6 +65,8 @@ impl<R: Read + Seek> ZipIndex<R> {
         let mut archive = ZipArchive::new(reader)?;
 
         let mut members = HashMap::with_capacity(archive.len());
+        let mut directories = HashSet::new();
+        directories.insert(PathBuf::new());
 
         for index in 0..archive.len() {
             let zf = archive.by_index_raw(index)?;
@@ -76,7 +85,17 @@ impl<R: Read + Seek> ZipIndex<R> {
                     name.as_path()
                 };
 
-                members.insert(name.to_path_buf(), index);
+                directories.extend(
+                    name.ancestors()
+                        .skip(1)
+                        .map(|ancestor| ancestor.to_path_buf()),
+                );
+
+                if zf.is_dir() {
+                    directories.insert(name.to_path_buf());
+                } else {
+                    members.insert(name.to_path_buf(), index);
+                }
             }
         }
 
@@ -84,9 +103,54 @@ impl<R: Read + Seek> ZipIndex<R> {
             archive,
             prefix,
             members,
+            directories,
         })
     }
 
+    /// Obtain the paths of files in the archive, in no particular order.
+    pub fn file_paths(&self) -> impl Iterator<Item = &Path> {
+        self.members.keys().map(|path| path.as_path())
+    }
+
+    /// Whether a path is a file in the archive.
+    pub fn is_file(&self, path: &Path) -> bool {
+        self.members.contains_key(path)
+    }
+
+    /// Whether a path is a directory in the archive.
+    ///
+    /// The empty path denotes the root directory.
+    pub fn is_dir(&self, path: &Path) -> bool {
+        self.directories.contains(path)
+    }
+
+    /// Obtain the sorted names of files and directories directly within a directory.
+    pub fn list_directory(&self, path: &Path) -> Vec<String> {
+        self.members
+            .keys()
+            .chain(self.directories.iter())
+            .filter(|entry| entry.parent() == Some(path))
+            .filter_map(|entry| entry.file_name())
+            .map(|name| name.to_string_lossy().to_string())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+
+    /// Obtain the last modified time and uncompressed size of a file.
+    ///
+    /// Errors if the path does not exist.
+    pub fn resolve_path_metadata(&mut self, path: &Path) -> Result<(Option<DateTime>, u64)> {
+        let index = self
+            .members
+            .get(path)
+            .ok_or_else(|| anyhow!("path {} not present in archive", path.display()))?;
+
+        let zf = self.archive.by_index_raw(*index)?;
+
+        Ok((zf.last_modified(), zf.size()))
+    }
+
     /// Attempt to locate a Python module within the zip archive.
     ///
     /// `full_name` is the fully qualified / dotted Python module name.
@@ -163,14 +227,71 @@ pub trait SeekableReader: Read + Seek + Send {}
 impl SeekableReader for Cursor<Vec<u8>> {}
 impl SeekableReader for Cursor<&[u8]> {}
 impl SeekableReader for BufReader<std::fs::File> {}
+impl SeekableReader for Cursor<memmap2::Mmap> {}
+
+/// Module source in a zip archive that `.pyc` members are validated against.
+///
+/// Mirrors the validation performed by `zipimport`.
+struct ZipPycSource<'i> {
+    index: &'i mut ZipIndex<Box<dyn SeekableReader>>,
+    path: &'i Path,
+}
+
+impl<'i> ZipPycSource<'i> {
+    fn source_error(e: anyhow::Error) -> PyErr {
+        PyImportError::new_err(format!("error reading module source from zip: {}", e))
+    }
+}
+
+impl<'i> PycSource for ZipPycSource<'i> {
+    // Zip archives record local time with a 2 second resolution. Like `zipimport`,
+    // tolerate a difference of 1 second.
+    const MTIME_TOLERANCE: i64 = 1;
+
+    fn content(&mut self, _py: Python) -> PyResult<Vec<u8>> {
+        self.index
+            .resolve_path_content(self.path)
+            .map_err(Self::source_error)
+    }
+
+    fn mtime_and_size(&mut self, py: Python) -> PyResult<Option<(i64, u64)>> {
+        let (modified, size) = self
+            .index
+            .resolve_path_metadata(self.path)
+            .map_err(Self::source_error)?;
+
+        // Like `zipimport`, nothing is validated if the source has no modification time.
+        let modified = match modified {
+            Some(modified) => modified,
+            None => return Ok(None),
+        };
+
+        // Like `zipimport`, convert the local time with `time.mktime()`.
+        let mtime = py
+            .import("time")?
+            .getattr("mktime")?
+            .call1(((
+                modified.year(),
+                modified.month(),
+                modified.day(),
+                modified.hour(),
+                modified.minute(),
+                modified.second(),
+                0,
+                0,
+                -1,
+            ),))?
+            .extract::<f64>()? as i64;
+
+        Ok(Some((mtime, size)))
+    }
+}
 
 /// A meta path finder that reads from zip archives.
 ///
 /// Known incompatibilities with `zipimporter`:
 ///
-/// * ResourceReader interface not implemented.
-/// * ResourceLoader interface not implemented.
-/// * Bytecode isn't validated.
+/// * Namespace packages are not supported.
 #[pyclass(module = "oxidized_importer")]
 pub struct OxidizedZipFinder {
     /// A PyObject backing storage of data.
@@ -199,6 +320,9 @@ pub struct OxidizedZipFinder {
     /// `_io` Python module.
     io_module: Py<PyModule>,
 
+    /// `_imp` Python module.
+    imp_module: Py<PyModule>,
+
     /// `marshal.loads` function.
     marshal_loads: Py<PyAny>,
 
@@ -248,7 +372,8 @@ impl OxidizedZipFinder {
 
     /// Construct a new instance from a reader.
     ///
-    /// The full content of the reader will be read to an in-memory buffer.
+    /// Only the central directory of the zip archive is read when indexing.
+    /// Members are read from the reader and decompressed when they are accessed.
     pub fn new_from_reader(
         py: Python,
         zip_path: PathBuf,
@@ -262,6 +387,41 @@ impl OxidizedZipFinder {
         Self::new_internal(py, index, zip_path, None)
     }
 
+    /// Construct a new instance from a path to a zip archive.
+    ///
+    /// If `memory_map` is true, the file is memory mapped and members are read
+    /// from the mapping. Otherwise members are read through a buffered file handle.
+    /// In both cases members are decompressed when they are accessed.
+    ///
+    /// The zip archive may be preceded by other data, such as when it is appended
+    /// to an executable.
+    pub fn new_from_path(
+        py: Python,
+        zip_path: PathBuf,
+        memory_map: bool,
+        prefix: Option<&Path>,
+    ) -> PyResult<Self> {
+        let f = std::fs::File::open(&zip_path).map_err(|e| {
+            PyValueError::new_err(format!("failed to open path {}: {}", zip_path.display(), e))
+        })?;
+
+        let reader: Box<dyn SeekableReader> = if memory_map {
+            let mapped = unsafe { memmap2::Mmap::map(&f) }.map_err(|e| {
+                PyValueError::new_err(format!(
+                    "failed to memory map path {}: {}",
+                    zip_path.display(),
+                    e
+                ))
+            })?;
+
+            Box::new(Cursor::new(mapped))
+        } else {
+            Box::new(BufReader::new(f))
+        };
+
+        Self::new_from_reader(py, zip_path, reader, prefix)
+    }
+
     fn new_internal(
         py: Python,
         index: ZipIndex<Box<dyn SeekableReader>>,
@@ -271,6 +431,7 @@ impl OxidizedZipFinder {
         let importlib_bootstrap = py.import("_frozen_importlib")?;
         let module_spec_type = importlib_bootstrap.getattr("ModuleSpec")?.into_py(py);
         let io_module = py.import("_io")?.into_py(py);
+        let imp_module = py.import("_imp")?.into_py(py);
         let marshal_module = py.import("marshal")?;
         let marshal_loads = marshal_module.getattr("loads")?.into_py(py);
         let builtins_module = py.import("builtins")?;
@@ -283,6 +444,7 @@ impl OxidizedZipFinder {
             zip_path,
             module_spec_type,
             io_module,
+            imp_module,
             marshal_loads,
             builtins_compile,
             builtins_exec,
@@ -302,6 +464,22 @@ impl OxidizedZipFinder {
             )))
         }
     }
+
+    /// Path to advertise for the root of the indexed content.
+    fn root_path(&self) -> PathBuf {
+        if let Some(prefix) = &self.index.prefix {
+            self.zip_path.join(prefix)
+        } else {
+            self.zip_path.clone()
+        }
+    }
+
+    /// Resolve the path of an archive member from a path that may be prefixed by [Self::root_path()].
+    fn member_path(&self, path: &Path) -> PathBuf {
+        path.strip_prefix(self.root_path())
+            .unwrap_or(path)
+            .to_path_buf()
+    }
 }
 
 #[pymethods]
@@ -313,6 +491,7 @@ impl OxidizedZipFinder {
 
         visit.call(&self.module_spec_type)?;
         visit.call(&self.io_module)?;
+        visit.call(&self.imp_module)?;
         visit.call(&self.marshal_loads)?;
         visit.call(&self.builtins_compile)?;
         visit.call(&self.builtins_exec)?;
@@ -321,15 +500,12 @@ impl OxidizedZipFinder {
     }
 
     #[classmethod]
+    #[pyo3(signature=(path, memory_map=false))]
     #[allow(unused)]
-    fn from_path(cls: &PyType, py: Python, path: &PyAny) -> PyResult<Self> {
+    fn from_path(cls: &PyType, py: Python, path: &PyAny, memory_map: bool) -> PyResult<Self> {
         let path = pyobject_to_pathbuf(py, path)?;
 
-        let f = Box::new(BufReader::new(std::fs::File::open(&path).map_err(|e| {
-            PyValueError::new_err(format!("failed to open path {}: {}", path.display(), e))
-        })?));
-
-        Self::new_from_reader(py, path, f, None)
+        Self::new_from_path(py, path, memory_map, None)
     }
 
     #[classmethod]
@@ -379,10 +555,7 @@ impl OxidizedZipFinder {
         kwargs.set_item("is_package", module.is_package)?;
 
         // origin is the path to the zip archive + the path within the archive.
-        let mut origin = importer.zip_path.clone();
-        if let Some(prefix) = &importer.index.prefix {
-            origin = origin.join(prefix);
-        }
+        let mut origin = importer.root_path();
 
         if let Some(path) = module.source_path {
             origin = origin.join(path);
@@ -476,20 +649,41 @@ impl OxidizedZipFinder {
 
         let module: ZipPythonModule = Self::resolve_python_module(&mut importer, fullname)?;
 
-        if let Some(path) = module.bytecode_path {
-            let bytecode_data = importer.index.resolve_path_content(&path).map_err(|e| {
+        let bytecode_data = if let Some(path) = &module.bytecode_path {
+            let bytecode_data = importer.index.resolve_path_content(path).map_err(|e| {
                 PyImportError::new_err((
                     format!("error reading module bytecode from zip: {}", e),
                     fullname.to_string(),
                 ))
             })?;
 
+            let imp_module = importer.imp_module.clone_ref(py);
+
+            let source = module.source_path.as_deref().map(|path| ZipPycSource {
+                index: &mut importer.index,
+                path,
+            });
+
+            match invalid_pyc_reason(py, imp_module.as_ref(py), &bytecode_data, source)? {
+                None => Some(bytecode_data),
+                // Like `zipimport`, fall back to the source if the bytecode can't be used.
+                Some(_) if module.source_path.is_some() => None,
+                Some(reason) => {
+                    return Err(PyImportError::new_err((
+                        format!("{} in {}", reason, path.display()),
+                        fullname.to_string(),
+                    )));
+                }
+            }
+        } else {
+            None
+        };
+
+        if let Some(bytecode_data) = bytecode_data {
             // Minimize potential for nested borrow by dropping borrow as soon as possible.
             let marshal_loads = importer.marshal_loads.clone_ref(py);
             std::mem::drop(importer);
 
-            // TODO validate the .pyc header.
-
             let bytecode = &bytecode_data[16..];
             let ptr = unsafe {
                 pyffi::PyMemoryView_FromMemory(
@@ -578,4 +772,408 @@ impl OxidizedZipFinder {
     }
 
     // End of importlib.abc.InspectLoader interface.
+
+    // Start of importlib.abc.ResourceLoader interface.
+
+    fn get_data<'p>(slf: &'p PyCell<Self>, path: &PyAny) -> PyResult<&'p PyAny> {
+        let py = slf.py();
+        let mut importer = slf.try_borrow_mut()?;
+
+        let path = pyobject_to_pathbuf(py, path)?;
+        let member_path = importer.member_path(&path);
+
+        let data = importer
+            .index
+            .resolve_path_content(&member_path)
+            .map_err(|_| {
+                PyFileNotFoundError::new_err(format!("{} not found in zip archive", path.display()))
+            })?;
+
+        Ok(PyBytes::new(py, &data))
+    }
+
+    // End of importlib.abc.ResourceLoader interface.
+
+    // Support obtaining ResourceReader instances.
+
+    fn get_resource_reader(slf: &PyCell<Self>, fullname: &str) -> PyResult<Py<PyAny>> {
+        let py = slf.py();
+        let mut importer = slf.try_borrow_mut()?;
+
+        let module = match importer.index.find_python_module(fullname) {
+            Some(module) => module,
+            None => return Ok(py.None()),
+        };
+
+        // Resources are only available on packages.
+        if module.is_package {
+            std::mem::drop(importer);
+
+            let package_path = fullname.split('.').collect::<PathBuf>();
+
+            Ok(
+                PyCell::new(py, OxidizedZipResourceReader::new(slf.into(), package_path))?
+                    .into_py(py),
+            )
+        } else {
+            Ok(py.None())
+        }
+    }
+
+    // importlib.metadata interface.
+
+    /// def find_distributions(context=DistributionFinder.Context()):
+    ///
+    /// Return an iterable of `importlib.metadata.PathDistribution` for the
+    /// `.dist-info` directories at the root of the archive matching `context.name`.
+    #[pyo3(signature=(context=None))]
+    fn find_distributions<'p>(
+        slf: &'p PyCell<Self>,
+        context: Option<&PyAny>,
+    ) -> PyResult<&'p PyAny> {
+        let py = slf.py();
+
+        let name = if let Some(context) = context {
+            let name = context.getattr("name")?;
+            if name.is_none() {
+                None
+            } else {
+                Some(name.to_string())
+            }
+        } else {
+            None
+        };
+
+        // Python normalizes the name. We do the same.
+        let normalize = |name: &str| name.to_lowercase().replace('-', "_");
+        let name = name.map(|name| normalize(&name));
+
+        let importer = slf.try_borrow()?;
+
+        let directories = importer
+            .index
+            .list_directory(Path::new(""))
+            .into_iter()
+            .filter(|entry| importer.index.is_dir(Path::new(entry)))
+            .filter(|entry| {
+                if let Some(stem) = entry.strip_suffix(".dist-info") {
+                    if let Some(name) = &name {
+                        stem.split('-').next().map(normalize).as_ref() == Some(name)
+                    } else {
+                        true
+                    }
+                } else {
+                    false
+                }
+            })
+            .collect::<Vec<_>>();
+
+        std::mem::drop(importer);
+
+        let path_distribution = py
+            .import("importlib.metadata")?
+            .getattr("PathDistribution")?;
+
+        let distributions = directories
+            .into_iter()
+            .map(|entry| {
+                let path = PyCell::new(
+                    py,
+                    OxidizedZipTraversable::new(slf.into(), PathBuf::from(entry)),
+                )?;
+
+                path_distribution.call1((path,))
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+
+        PyList::new(py, distributions).call_method0("__iter__")
+    }
+
+    // pkgutil methods.
+
+    /// def iter_modules(prefix="")
+    #[pyo3(signature=(prefix=None))]
+    fn iter_modules<'p>(slf: &'p PyCell<Self>, prefix: Option<&str>) -> PyResult<&'p PyList> {
+        let py = slf.py();
+        let importer = slf.try_borrow()?;
+
+        let mut modules = BTreeMap::new();
+
+        for entry in importer.index.list_directory(Path::new("")) {
+            let path = Path::new(&entry);
+
+            if importer.index.is_dir(path) {
+                if importer.index.is_file(&path.join("__init__.py"))
+                    || importer.index.is_file(&path.join("__init__.pyc"))
+                {
+                    modules.insert(entry, true);
+                }
+            } else if let Some(stem) = entry
+                .strip_suffix(".py")
+                .or_else(|| entry.strip_suffix(".pyc"))
+            {
+                if stem != "__init__" && !stem.contains('.') {
+                    modules.entry(stem.to_string()).or_insert(false);
+                }
+            }
+        }
+
+        let infos = modules
+            .into_iter()
+            .map(|(name, is_package)| {
+                let name = format!("{}{}", prefix.unwrap_or_default(), name);
+
+                PyTuple::new(py, &[name.to_object(py), is_package.to_object(py)])
+            })
+            .collect::<Vec<_>>();
+
+        Ok(PyList::new(py, &infos))
+    }
+}
+
+/// Implements reading of resource data from a zip archive.
+///
+/// Implements importlib.abc.ResourceReader and the `files()` method of
+/// importlib.abc.TraversableResources.
+#[pyclass(module = "oxidized_importer")]
+pub(crate) struct OxidizedZipResourceReader {
+    finder: Py<OxidizedZipFinder>,
+    /// Path of the package directory within the archive.
+    package_path: PathBuf,
+}
+
+impl OxidizedZipResourceReader {
+    fn new(finder: Py<OxidizedZipFinder>, package_path: PathBuf) -> Self {
+        Self {
+            finder,
+            package_path,
+        }
+    }
+
+    fn traversable(&self, py: Python) -> OxidizedZipTraversable {
+        OxidizedZipTraversable::new(self.finder.clone_ref(py), self.package_path.clone())
+    }
+}
+
+#[pymethods]
+impl OxidizedZipResourceReader {
+    /// Returns an opened, file-like object for binary reading of the resource.
+    ///
+    /// If the resource cannot be found, FileNotFoundError is raised.
+    fn open_resource<'p>(&self, py: Python<'p>, resource: &str) -> PyResult<&'p PyAny> {
+        self.traversable(py).join(resource).open_binary(py)
+    }
+
+    /// Returns the file system path to the resource.
+    ///
+    /// Resources in zip archives don't exist on the file system, so this always
+    /// raises FileNotFoundError.
+    #[allow(unused)]
+    fn resource_path(&self, resource: &PyAny) -> PyResult<()> {
+        Err(PyFileNotFoundError::new_err(
+            "zip archive resources do not have filesystem paths",
+        ))
+    }
+
+    /// Returns True if the named name is considered a resource. FileNotFoundError
+    /// is raised if name does not exist.
+    fn is_resource(&self, py: Python, name: &str) -> PyResult<bool> {
+        let path = self.package_path.join(name);
+        let finder = self.finder.as_ref(py).try_borrow()?;
+
+        if finder.index.is_file(&path) {
+            Ok(true)
+        } else if finder.index.is_dir(&path) {
+            Ok(false)
+        } else {
+            Err(PyFileNotFoundError::new_err("resource not found"))
+        }
+    }
+
+    /// Returns an iterable of strings over the contents of the package.
+    fn contents<'p>(&self, py: Python<'p>) -> PyResult<&'p PyList> {
+        let finder = self.finder.as_ref(py).try_borrow()?;
+
+        Ok(PyList::new(
+            py,
+            finder.index.list_directory(&self.package_path),
+        ))
+    }
+
+    /// Returns an importlib.abc.Traversable for the package directory.
+    fn files(&self, py: Python) -> OxidizedZipTraversable {
+        self.traversable(py)
+    }
+}
+
+/// A path within a zip archive.
+///
+/// Implements importlib.abc.Traversable.
+#[pyclass(module = "oxidized_importer")]
+pub(crate) struct OxidizedZipTraversable {
+    finder: Py<OxidizedZipFinder>,
+    /// Path within the archive. The empty path denotes the root.
+    path: PathBuf,
+}
+
+impl OxidizedZipTraversable {
+    fn new(finder: Py<OxidizedZipFinder>, path: PathBuf) -> Self {
+        Self { finder, path }
+    }
+
+    fn join(&self, child: &str) -> Self {
+        let mut path = self.path.clone();
+        path.extend(child.split('/').filter(|part| !part.is_empty()));
+
+        Self {
+            finder: self.finder.clone(),
+            path,
+        }
+    }
+
+    fn read(&self, py: Python) -> PyResult<Vec<u8>> {
+        let mut finder = self.finder.as_ref(py).try_borrow_mut()?;
+
+        if !finder.index.is_file(&self.path) {
+            return Err(PyFileNotFoundError::new_err(format!(
+                "{} not found in zip archive",
+                self.path.display()
+            )));
+        }
+
+        finder
+            .index
+            .resolve_path_content(&self.path)
+            .map_err(|e| PyImportError::new_err(format!("error reading zip archive: {}", e)))
+    }
+
+    fn open_binary<'p>(&self, py: Python<'p>) -> PyResult<&'p PyAny> {
+        let data = self.read(py)?;
+        let io_module = self.finder.as_ref(py).try_borrow()?.io_module.clone_ref(py);
+
+        io_module
+            .into_ref(py)
+            .getattr("BytesIO")?
+            .call1((PyBytes::new(py, &data),))
+    }
+}
+
+#[pymethods]
+impl OxidizedZipTraversable {
+    fn __str__(&self, py: Python) -> PyResult<String> {
+        let finder = self.finder.as_ref(py).try_borrow()?;
+
+        Ok(finder.root_path().join(&self.path).display().to_string())
+    }
+
+    fn __repr__(&self, py: Python) -> PyResult<String> {
+        Ok(format!("OxidizedZipTraversable('{}')", self.__str__(py)?))
+    }
+
+    #[getter]
+    fn name(&self) -> String {
+        self.path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default()
+    }
+
+    #[getter]
+    fn parent(&self) -> Self {
+        Self {
+            finder: self.finder.clone(),
+            path: self.path.parent().unwrap_or(&self.path).to_path_buf(),
+        }
+    }
+
+    fn iterdir<'p>(&self, py: Python<'p>) -> PyResult<&'p PyAny> {
+        let finder = self.finder.as_ref(py).try_borrow()?;
+
+        if !finder.index.is_dir(&self.path) {
+            return Err(PyNotADirectoryError::new_err(format!(
+                "{} is not a directory in zip archive",
+                self.path.display()
+            )));
+        }
+
+        let children = finder
+            .index
+            .list_directory(&self.path)
+            .into_iter()
+            .map(|name| PyCell::new(py, self.join(&name)))
+            .collect::<PyResult<Vec<_>>>()?;
+
+        PyList::new(py, children).call_method0("__iter__")
+    }
+
+    fn is_dir(&self, py: Python) -> PyResult<bool> {
+        Ok(self
+            .finder
+            .as_ref(py)
+            .try_borrow()?
+            .index
+            .is_dir(&self.path))
+    }
+
+    fn is_file(&self, py: Python) -> PyResult<bool> {
+        Ok(self
+            .finder
+            .as_ref(py)
+            .try_borrow()?
+            .index
+            .is_file(&self.path))
+    }
+
+    #[pyo3(signature=(*descendants))]
+    fn joinpath(&self, descendants: Vec<&str>) -> Self {
+        descendants
+            .into_iter()
+            .fold(self.join(""), |traversable, child| traversable.join(child))
+    }
+
+    fn __truediv__(&self, child: &str) -> Self {
+        self.join(child)
+    }
+
+    fn read_bytes<'p>(&self, py: Python<'p>) -> PyResult<&'p PyBytes> {
+        Ok(PyBytes::new(py, &self.read(py)?))
+    }
+
+    #[pyo3(signature=(encoding=None))]
+    fn read_text<'p>(&self, py: Python<'p>, encoding: Option<&str>) -> PyResult<&'p PyAny> {
+        let kwargs = PyDict::new(py);
+        kwargs.set_item("encoding", encoding)?;
+
+        self.open(py, "r", PyTuple::empty(py), Some(kwargs))?
+            .call_method0("read")
+    }
+
+    #[pyo3(signature=(mode="r", *py_args, **py_kwargs))]
+    fn open<'p>(
+        &self,
+        py: Python<'p>,
+        mode: &str,
+        py_args: &PyTuple,
+        py_kwargs: Option<&PyDict>,
+    ) -> PyResult<&'p PyAny> {
+        match mode {
+            "rb" => self.open_binary(py),
+            "r" => {
+                let fh = self.open_binary(py)?;
+                let io_module = self.finder.as_ref(py).try_borrow()?.io_module.clone_ref(py);
+
+                let mut args = vec![fh];
+                args.extend(py_args.iter());
+
+                io_module
+                    .into_ref(py)
+                    .getattr("TextIOWrapper")?
+                    .call(PyTuple::new(py, args), py_kwargs)
+            }
+            _ => Err(PyValueError::new_err(format!(
+                "unsupported mode for zip archive resources: {}",
+                mode
+            ))),
+        }
+    }
 }
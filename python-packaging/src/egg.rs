@@ -0,0 +1,191 @@
+// Copyright 2022 Gregory Szorc.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/*! Functionality for reading Python .egg files. */
+
+use {
+    crate::{
+        filesystem_scanning::PythonResourceIterator,
+        module_util::PythonModuleSuffixes,
+        package_metadata::PythonPackageMetadata,
+        resource::{PythonEggFile, PythonResource},
+    },
+    anyhow::{Context, Result},
+    simple_file_manifest::{File, FileEntry},
+    std::{
+        io::{Cursor, Read},
+        path::{Path, PathBuf},
+    },
+    zip::ZipArchive,
+};
+
+/// Directory in .egg files holding packaging metadata.
+const EGG_INFO: &str = "EGG-INFO";
+
+impl PythonEggFile {
+    /// Obtain the files within the .egg archive.
+    ///
+    /// Packaging metadata in the `EGG-INFO` directory is remapped to a
+    /// `<name>-<version>.egg-info` directory, mirroring the layout of an
+    /// installed distribution. It is dropped if the name and version can't
+    /// be determined from its `PKG-INFO` file.
+    pub fn files(&self) -> Result<Vec<File>> {
+        let data = self.data.resolve_content().context("reading egg data")?;
+        let mut archive = ZipArchive::new(Cursor::new(data)).context("opening egg archive")?;
+
+        let mut files = vec![];
+
+        for i in 0..archive.len() {
+            let mut file = archive.by_index(i)?;
+
+            if file.is_dir() {
+                continue;
+            }
+
+            let mut buffer = Vec::with_capacity(file.size() as usize);
+            file.read_to_end(&mut buffer)?;
+
+            files.push(File::new(
+                file.name(),
+                FileEntry::new_from_data(buffer, file.unix_mode().unwrap_or(0) & 0o100 != 0),
+            ));
+        }
+
+        let egg_info_prefix = format!("{}/", EGG_INFO);
+
+        let egg_info_dir = files
+            .iter()
+            .find(|f| f.path() == Path::new(EGG_INFO).join("PKG-INFO"))
+            .and_then(|f| f.entry().resolve_content().ok())
+            .and_then(|data| PythonPackageMetadata::from_metadata(&data).ok())
+            .and_then(|metadata| {
+                Some(format!(
+                    "{}-{}.egg-info",
+                    metadata.name()?,
+                    metadata.version()?
+                ))
+            });
+
+        Ok(files
+            .into_iter()
+            .filter_map(|f| {
+                let path = f.path().to_string_lossy().to_string();
+
+                if let Some(name) = path.strip_prefix(&egg_info_prefix) {
+                    egg_info_dir
+                        .as_ref()
+                        .map(|dir| File::new(PathBuf::from(dir).join(name), f.entry().clone()))
+                } else {
+                    Some(f)
+                }
+            })
+            .collect())
+    }
+
+    /// Obtain `PythonResource` for files within the .egg archive.
+    ///
+    /// This flattens the egg into the resources it would provide if it were
+    /// installed into a site-packages directory.
+    pub fn python_resources<'a>(
+        &self,
+        cache_tag: &str,
+        suffixes: &PythonModuleSuffixes,
+        emit_files: bool,
+        classify_files: bool,
+    ) -> Result<Vec<PythonResource<'a>>> {
+        PythonResourceIterator::from_data_locations(
+            &self.files()?,
+            cache_tag,
+            suffixes,
+            emit_files,
+            classify_files,
+        )?
+        .collect::<Result<Vec<_>>>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::resource::{
+            PythonModuleSource, PythonPackageDistributionResource,
+            PythonPackageDistributionResourceFlavor,
+        },
+        simple_file_manifest::FileData,
+        std::io::Write,
+        zip::{write::SimpleFileOptions, ZipWriter},
+    };
+
+    const DEFAULT_CACHE_TAG: &str = "cpython-39";
+
+    #[test]
+    fn test_egg_python_resources() -> Result<()> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::<u8>::new()));
+
+        for (path, data) in [
+            ("EGG-INFO/PKG-INFO", "Name: foo\nVersion: 1.0\n"),
+            ("foo/__init__.py", "import foo.bar\n"),
+            ("foo/bar.py", ""),
+        ] {
+            writer.start_file(path, SimpleFileOptions::default())?;
+            writer.write_all(data.as_bytes())?;
+        }
+
+        let egg = PythonEggFile {
+            data: FileData::Memory(writer.finish()?.into_inner()),
+        };
+
+        let resources = egg.python_resources(
+            DEFAULT_CACHE_TAG,
+            &PythonModuleSuffixes {
+                source: vec![".py".to_string()],
+                bytecode: vec![".pyc".to_string()],
+                debug_bytecode: vec![],
+                optimized_bytecode: vec![],
+                extension: vec![],
+            },
+            false,
+            true,
+        )?;
+
+        assert_eq!(
+            resources,
+            vec![
+                PythonModuleSource {
+                    name: "foo".to_string(),
+                    source: FileData::Memory(b"import foo.bar\n".to_vec()),
+                    is_package: true,
+                    cache_tag: DEFAULT_CACHE_TAG.to_string(),
+                    is_stdlib: false,
+                    is_test: false,
+                }
+                .into(),
+                PythonModuleSource {
+                    name: "foo.bar".to_string(),
+                    source: FileData::Memory(vec![]),
+                    is_package: false,
+                    cache_tag: DEFAULT_CACHE_TAG.to_string(),
+                    is_stdlib: false,
+                    is_test: false,
+                }
+                .into(),
+                PythonPackageDistributionResource {
+                    location: PythonPackageDistributionResourceFlavor::EggInfo,
+                    package: "foo".to_string(),
+                    version: "1.0".to_string(),
+                    name: "PKG-INFO".to_string(),
+                    data: FileData::Memory(b"Name: foo\nVersion: 1.0\n".to_vec()),
+                }
+                .into(),
+            ]
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,405 @@
+// Copyright 2022 Gregory Szorc.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/*! Static analysis of imports between Python modules. */
+
+use {
+    crate::{
+        module_util::packages_from_module_name,
+        python_source::{find_imports, PythonImport},
+        resource_collection::{PrePackagedResource, PythonModuleBytecodeProvider},
+    },
+    anyhow::{Context, Result},
+    simple_file_manifest::FileData,
+    std::collections::{BTreeMap, BTreeSet, VecDeque},
+};
+
+/// Names of modules the interpreter may import during startup.
+///
+/// Names denote modules and all their submodules. These modules are not
+/// necessarily imported by application code but are needed to run it.
+/// `encodings` is here because codecs are imported dynamically by name.
+pub const INTERPRETER_STARTUP_MODULES: &[&str] = &[
+    "_collections_abc",
+    "_sitebuiltins",
+    "abc",
+    "codecs",
+    "encodings",
+    "genericpath",
+    "importlib",
+    "io",
+    "ntpath",
+    "os",
+    "posixpath",
+    "site",
+    "stat",
+    "zipimport",
+];
+
+/// Why a module is reachable from entry points.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ModuleReachability {
+    /// The module is an entry point.
+    EntryPoint,
+    /// The module is imported by the named reachable module.
+    ImportedBy(String),
+    /// The module is a parent package of the named reachable module.
+    ParentOf(String),
+}
+
+impl std::fmt::Display for ModuleReachability {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::EntryPoint => f.write_str("entry point"),
+            Self::ImportedBy(name) => write!(f, "imported by {}", name),
+            Self::ParentOf(name) => write!(f, "parent package of {}", name),
+        }
+    }
+}
+
+/// Obtain the source code of a module resource, if available.
+fn module_source(resource: &PrePackagedResource) -> Option<&FileData> {
+    if let Some(source) = &resource.in_memory_source {
+        return Some(source);
+    }
+
+    if let Some((_, source)) = &resource.relative_path_module_source {
+        return Some(source);
+    }
+
+    [
+        resource.in_memory_bytecode.as_ref(),
+        resource.in_memory_bytecode_opt1.as_ref(),
+        resource.in_memory_bytecode_opt2.as_ref(),
+        resource.relative_path_bytecode.as_ref().map(|(_, _, p)| p),
+        resource
+            .relative_path_bytecode_opt1
+            .as_ref()
+            .map(|(_, _, p)| p),
+        resource
+            .relative_path_bytecode_opt2
+            .as_ref()
+            .map(|(_, _, p)| p),
+    ]
+    .into_iter()
+    .flatten()
+    .find_map(|provider| match provider {
+        PythonModuleBytecodeProvider::FromSource(source) => Some(source),
+        PythonModuleBytecodeProvider::Provided(_) => None,
+    })
+}
+
+/// Resolve the name of the package a relative import is relative to.
+///
+/// Returns `None` if the import reaches beyond the top-level package.
+fn resolve_relative_base(importer: &str, is_package: bool, level: usize) -> Option<String> {
+    let mut parts = importer.split('.').collect::<Vec<_>>();
+
+    // Relative imports in a module are relative to its package. In a package,
+    // they are relative to the package itself.
+    let strip = if is_package { level - 1 } else { level };
+
+    if strip >= parts.len() {
+        return None;
+    }
+
+    parts.truncate(parts.len() - strip);
+
+    Some(parts.join("."))
+}
+
+/// Imports between Python modules in a set of resources.
+#[derive(Clone, Debug, Default)]
+pub struct ImportGraph {
+    /// Names of all modules in the graph.
+    modules: BTreeSet<String>,
+
+    /// Modules in the graph imported by each analyzed module.
+    imports: BTreeMap<String, BTreeSet<String>>,
+
+    /// Modules whose imports could not be determined.
+    ///
+    /// These are extension modules, built-in and frozen modules, and modules
+    /// only having bytecode.
+    unanalyzed: BTreeSet<String>,
+}
+
+impl ImportGraph {
+    /// Construct an instance by analyzing the source code of Python module resources.
+    ///
+    /// Resources that aren't Python modules are ignored.
+    pub fn from_resources<'a>(
+        resources: impl Iterator<Item = &'a PrePackagedResource> + Clone,
+    ) -> Result<Self> {
+        let modules = resources
+            .clone()
+            .filter(|r| r.is_python_resource())
+            .map(|r| r.name.clone())
+            .collect::<BTreeSet<_>>();
+
+        let mut imports = BTreeMap::new();
+        let mut unanalyzed = BTreeSet::new();
+
+        for resource in resources.filter(|r| r.is_python_resource()) {
+            let source = match module_source(resource) {
+                Some(source) => source,
+                None => {
+                    unanalyzed.insert(resource.name.clone());
+                    continue;
+                }
+            };
+
+            let source = source
+                .resolve_content()
+                .with_context(|| format!("reading source of {}", resource.name))?;
+
+            let targets = find_imports(&source)
+                .iter()
+                .flat_map(|import| {
+                    resolve_import_targets(&modules, &resource.name, resource.is_package, import)
+                })
+                .filter(|target| target != &resource.name)
+                .collect::<BTreeSet<_>>();
+
+            imports.insert(resource.name.clone(), targets);
+        }
+
+        Ok(Self {
+            modules,
+            imports,
+            unanalyzed,
+        })
+    }
+
+    /// Whether a module is in the graph.
+    pub fn contains(&self, name: &str) -> bool {
+        self.modules.contains(name)
+    }
+
+    /// Obtain the names of modules in the graph imported by a module.
+    ///
+    /// Returns `None` for modules whose imports could not be determined.
+    pub fn imports(&self, name: &str) -> Option<&BTreeSet<String>> {
+        self.imports.get(name)
+    }
+
+    /// Whether the imports of a module could not be determined.
+    pub fn is_unanalyzed(&self, name: &str) -> bool {
+        self.unanalyzed.contains(name)
+    }
+
+    /// Obtain the sorted names of modules that import a module.
+    pub fn importers(&self, name: &str) -> Vec<&str> {
+        self.imports
+            .iter()
+            .filter(|(_, targets)| targets.contains(name))
+            .map(|(importer, _)| importer.as_str())
+            .collect()
+    }
+
+    /// Resolve modules reachable from entry point modules and why they are reachable.
+    ///
+    /// A module is reachable if it is an entry point, is imported by a reachable
+    /// module, or is a parent package of a reachable module. Entry points not in
+    /// the graph are ignored.
+    pub fn reachable<'a>(
+        &self,
+        entry_points: impl Iterator<Item = &'a str>,
+    ) -> BTreeMap<String, ModuleReachability> {
+        let mut reachable = BTreeMap::new();
+        let mut queue = VecDeque::new();
+
+        for name in entry_points {
+            if self.modules.contains(name) && !reachable.contains_key(name) {
+                reachable.insert(name.to_string(), ModuleReachability::EntryPoint);
+                queue.push_back(name.to_string());
+            }
+        }
+
+        while let Some(name) = queue.pop_front() {
+            let parents = packages_from_module_name(&name)
+                .into_iter()
+                .map(|parent| (parent, ModuleReachability::ParentOf(name.clone())));
+
+            let imported = self
+                .imports
+                .get(&name)
+                .into_iter()
+                .flatten()
+                .map(|target| (target.clone(), ModuleReachability::ImportedBy(name.clone())));
+
+            for (target, reason) in parents.chain(imported).collect::<Vec<_>>() {
+                if self.modules.contains(&target) && !reachable.contains_key(&target) {
+                    reachable.insert(target.clone(), reason);
+                    queue.push_back(target);
+                }
+            }
+        }
+
+        reachable
+    }
+}
+
+/// Resolve the modules in `modules` that an import performed by `importer` refers to.
+///
+/// Imported names that are submodules are included. If an imported module isn't
+/// known, its nearest known parent package is used instead.
+fn resolve_import_targets(
+    modules: &BTreeSet<String>,
+    importer: &str,
+    is_package: bool,
+    import: &PythonImport,
+) -> Vec<String> {
+    let base = if import.level == 0 {
+        import.module.clone()
+    } else {
+        match resolve_relative_base(importer, is_package, import.level) {
+            Some(base) if import.module.is_empty() => base,
+            Some(base) => format!("{}.{}", base, import.module),
+            None => return vec![],
+        }
+    };
+
+    let mut targets = vec![];
+
+    let mut candidate = base.as_str();
+    loop {
+        if modules.contains(candidate) {
+            targets.push(candidate.to_string());
+            break;
+        }
+
+        match candidate.rfind('.') {
+            Some(idx) => candidate = &candidate[0..idx],
+            None => break,
+        }
+    }
+
+    for name in &import.names {
+        let submodule = format!("{}.{}", base, name);
+
+        if modules.contains(&submodule) {
+            targets.push(submodule);
+        }
+    }
+
+    targets
+}
+
+/// Describes the result of removing modules unreachable from entry points.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TreeShakeReport {
+    /// Kept modules and why they are reachable.
+    pub kept: BTreeMap<String, ModuleReachability>,
+
+    /// Removed modules and why they were removed.
+    pub removed: BTreeMap<String, String>,
+
+    /// Top-level packages and modules that were removed entirely.
+    pub removed_top_level_names: BTreeSet<String>,
+
+    /// Kept modules whose imports could not be determined.
+    ///
+    /// Modules only imported by these modules may have been removed. They
+    /// should be kept explicitly if they are needed.
+    pub unanalyzed: BTreeSet<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn module(name: &str, is_package: bool, source: &str) -> PrePackagedResource {
+        PrePackagedResource {
+            name: name.to_string(),
+            is_module: true,
+            is_package,
+            in_memory_source: Some(FileData::Memory(source.as_bytes().to_vec())),
+            ..PrePackagedResource::default()
+        }
+    }
+
+    #[test]
+    fn test_import_graph() -> Result<()> {
+        let resources = vec![
+            module("app", false, "import foo.bar\nfrom baz import thing\n"),
+            module("foo", true, "from . import helper\n"),
+            module(
+                "foo.bar",
+                false,
+                "from .helper import x\nimport qux, os.path\n",
+            ),
+            module("foo.helper", false, ""),
+            module("baz", true, "from .sub import *\n"),
+            module("baz.sub", false, ""),
+            module("qux", false, ""),
+            module("os", false, ""),
+            module("unused", false, "import qux\n"),
+            PrePackagedResource {
+                name: "_ext".to_string(),
+                is_extension_module: true,
+                ..PrePackagedResource::default()
+            },
+        ];
+
+        let graph = ImportGraph::from_resources(resources.iter())?;
+
+        assert_eq!(
+            graph.imports("app").unwrap().iter().collect::<Vec<_>>(),
+            vec!["baz", "foo.bar"]
+        );
+        assert_eq!(
+            graph.imports("foo").unwrap().iter().collect::<Vec<_>>(),
+            vec!["foo.helper"]
+        );
+        assert_eq!(
+            graph.imports("foo.bar").unwrap().iter().collect::<Vec<_>>(),
+            vec!["foo.helper", "os", "qux"]
+        );
+        assert!(graph.is_unanalyzed("_ext"));
+        assert_eq!(graph.importers("qux"), vec!["foo.bar", "unused"]);
+
+        let reachable = graph.reachable(["app"].into_iter());
+
+        assert_eq!(
+            reachable.keys().collect::<Vec<_>>(),
+            vec![
+                "app",
+                "baz",
+                "baz.sub",
+                "foo",
+                "foo.bar",
+                "foo.helper",
+                "os",
+                "qux"
+            ]
+        );
+        assert_eq!(reachable["app"], ModuleReachability::EntryPoint);
+        assert_eq!(
+            reachable["foo"],
+            ModuleReachability::ParentOf("foo.bar".to_string())
+        );
+        assert_eq!(
+            reachable["baz.sub"],
+            ModuleReachability::ImportedBy("baz".to_string())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_relative_import_beyond_top_level() -> Result<()> {
+        let resources = [module("top", false, "from .. import other\n")];
+
+        let graph = ImportGraph::from_resources(resources.iter())?;
+
+        assert!(graph.imports("top").unwrap().is_empty());
+
+        Ok(())
+    }
+}
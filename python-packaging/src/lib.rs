@@ -13,7 +13,10 @@ and packaging facilities.
 */
 
 pub mod bytecode;
+#[cfg(feature = "zip")]
+pub mod egg;
 pub mod filesystem_scanning;
+pub mod import_graph;
 pub mod interpreter;
 pub mod libpython;
 pub mod licensing;
@@ -21,14 +24,20 @@ pub mod location;
 pub mod module_util;
 pub mod package_metadata;
 pub mod policy;
+#[cfg(feature = "pyproject")]
+pub mod pyproject;
 pub mod python_source;
 pub mod resource;
 pub mod resource_collection;
+#[cfg(feature = "sdist")]
+pub mod sdist;
 #[cfg(test)]
 mod testutil;
 #[cfg(feature = "wheel")]
 pub mod wheel;
 #[cfg(feature = "wheel")]
 pub mod wheel_builder;
+#[cfg(feature = "wheel")]
+pub mod wheelhouse;
 #[cfg(feature = "zip")]
 pub mod zip_app_builder;
@@ -18,6 +18,8 @@ pub enum AbstractResourceLocation {
     InMemory,
     /// Resource is loaded from a relative filesystem path.
     RelativePath,
+    /// Resource is embedded and extracted to a cache directory when accessed.
+    ExtractToCache,
 }
 
 impl ToString for &AbstractResourceLocation {
@@ -25,6 +27,7 @@ impl ToString for &AbstractResourceLocation {
         match self {
             AbstractResourceLocation::InMemory => "in-memory".to_string(),
             AbstractResourceLocation::RelativePath => "filesystem-relative".to_string(),
+            AbstractResourceLocation::ExtractToCache => "extract-to-cache".to_string(),
         }
     }
 }
@@ -36,6 +39,7 @@ impl TryFrom<&str> for AbstractResourceLocation {
         match value {
             "in-memory" => Ok(Self::InMemory),
             "filesystem-relative" => Ok(Self::RelativePath),
+            "extract-to-cache" => Ok(Self::ExtractToCache),
             _ => Err(format!("{} is not a valid resource location", value)),
         }
     }
@@ -48,6 +52,11 @@ pub enum ConcreteResourceLocation {
     InMemory,
     /// Reosurce is loaded from a relative filesystem path.
     RelativePath(String),
+    /// Resource is embedded and extracted to a cache directory when accessed.
+    ///
+    /// The cache directory is chosen at run-time and is content-addressed, so
+    /// extracted files are reused across runs.
+    ExtractToCache,
 }
 
 impl From<&ConcreteResourceLocation> for AbstractResourceLocation {
@@ -55,6 +64,7 @@ impl From<&ConcreteResourceLocation> for AbstractResourceLocation {
         match l {
             ConcreteResourceLocation::InMemory => AbstractResourceLocation::InMemory,
             ConcreteResourceLocation::RelativePath(_) => AbstractResourceLocation::RelativePath,
+            ConcreteResourceLocation::ExtractToCache => AbstractResourceLocation::ExtractToCache,
         }
     }
 }
@@ -66,6 +76,7 @@ impl ToString for ConcreteResourceLocation {
             ConcreteResourceLocation::RelativePath(prefix) => {
                 format!("filesystem-relative:{}", prefix)
             }
+            ConcreteResourceLocation::ExtractToCache => "extract-to-cache".to_string(),
         }
     }
 }
@@ -82,6 +93,8 @@ impl TryFrom<&str> for ConcreteResourceLocation {
     fn try_from(value: &str) -> Result<Self, Self::Error> {
         if value == "in-memory" {
             Ok(Self::InMemory)
+        } else if value == "extract-to-cache" {
+            Ok(Self::ExtractToCache)
         } else {
             let parts = value.splitn(2, ':').collect::<Vec<_>>();
 
@@ -115,6 +128,10 @@ mod tests {
             AbstractResourceLocation::try_from("filesystem-relative"),
             Ok(AbstractResourceLocation::RelativePath)
         );
+        assert_eq!(
+            AbstractResourceLocation::try_from("extract-to-cache"),
+            Ok(AbstractResourceLocation::ExtractToCache)
+        );
 
         Ok(())
     }
@@ -129,6 +146,10 @@ mod tests {
             ConcreteResourceLocation::try_from("filesystem-relative:lib"),
             Ok(ConcreteResourceLocation::RelativePath("lib".to_string()))
         );
+        assert_eq!(
+            ConcreteResourceLocation::try_from("extract-to-cache"),
+            Ok(ConcreteResourceLocation::ExtractToCache)
+        );
 
         Ok(())
     }
@@ -13,6 +13,29 @@ use {
     mailparse::parse_mail,
 };
 
+/// Normalize a distribution name, per PEP 503.
+///
+/// Names are compared case-insensitively and runs of `-`, `_` and `.` are
+/// equivalent.
+pub fn canonicalize_name(name: &str) -> String {
+    let mut res = String::with_capacity(name.len());
+    let mut in_separator = false;
+
+    for c in name.chars() {
+        if matches!(c, '-' | '_' | '.') {
+            if !in_separator {
+                res.push('-');
+            }
+            in_separator = true;
+        } else {
+            res.push(c.to_ascii_lowercase());
+            in_separator = false;
+        }
+    }
+
+    res
+}
+
 /// Represents a Python METADATA file.
 pub struct PythonPackageMetadata {
     headers: Vec<(String, String)>,
@@ -70,6 +93,13 @@ impl PythonPackageMetadata {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_canonicalize_name() {
+        assert_eq!(canonicalize_name("Foo_Bar"), "foo-bar");
+        assert_eq!(canonicalize_name("foo.-_bar"), "foo-bar");
+        assert_eq!(canonicalize_name("zope.interface"), "zope-interface");
+    }
+
     #[test]
     fn test_parse_metadata() -> Result<()> {
         let data = concat!(
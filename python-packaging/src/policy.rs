@@ -17,8 +17,9 @@ use {
         resource::{PythonExtensionModule, PythonExtensionModuleVariants, PythonResource},
         resource_collection::PythonResourceAddCollectionContext,
     },
-    anyhow::Result,
-    std::collections::{HashMap, HashSet},
+    anyhow::{anyhow, Result},
+    python_packed_resources::{BlobCompression, ResourceField, WriterOptions},
+    std::collections::{BTreeMap, HashMap, HashSet},
 };
 
 /// Denotes methods to filter extension modules.
@@ -178,6 +179,19 @@ pub struct PythonPackagingPolicy {
 
     /// Python modules for which bytecode should not be generated by default.
     no_bytecode_modules: HashSet<String>,
+
+    /// Version of the packed resources data format to write.
+    packed_resources_version: u8,
+
+    /// Whether to zstd compress data in packed resources data.
+    ///
+    /// Requires version 4 of the format.
+    packed_resources_compress: bool,
+
+    /// Whether to deduplicate identical data in packed resources data.
+    ///
+    /// Requires version 4 of the format.
+    packed_resources_deduplicate: bool,
 }
 
 impl Default for PythonPackagingPolicy {
@@ -202,6 +216,9 @@ impl Default for PythonPackagingPolicy {
             bytecode_optimize_level_one: false,
             bytecode_optimize_level_two: false,
             no_bytecode_modules: HashSet::new(),
+            packed_resources_version: 3,
+            packed_resources_compress: false,
+            packed_resources_deduplicate: false,
         }
     }
 }
@@ -383,6 +400,78 @@ impl PythonPackagingPolicy {
         self.bytecode_optimize_level_two = value;
     }
 
+    /// Version of the packed resources data format to write.
+    pub fn packed_resources_version(&self) -> u8 {
+        self.packed_resources_version
+    }
+
+    /// Set the version of the packed resources data format to write.
+    pub fn set_packed_resources_version(&mut self, version: u8) -> Result<()> {
+        if !matches!(version, 3 | 4) {
+            return Err(anyhow!(
+                "packed resources version must be 3 or 4; got {}",
+                version
+            ));
+        }
+
+        self.packed_resources_version = version;
+
+        Ok(())
+    }
+
+    /// Whether to zstd compress data in packed resources data.
+    pub fn packed_resources_compress(&self) -> bool {
+        self.packed_resources_compress
+    }
+
+    /// Set whether to zstd compress data in packed resources data.
+    pub fn set_packed_resources_compress(&mut self, value: bool) {
+        self.packed_resources_compress = value;
+    }
+
+    /// Whether to deduplicate identical data in packed resources data.
+    pub fn packed_resources_deduplicate(&self) -> bool {
+        self.packed_resources_deduplicate
+    }
+
+    /// Set whether to deduplicate identical data in packed resources data.
+    pub fn set_packed_resources_deduplicate(&mut self, value: bool) {
+        self.packed_resources_deduplicate = value;
+    }
+
+    /// Resolve options for writing packed resources data.
+    ///
+    /// Returns `None` if version 3 data should be written. Version 4 data
+    /// always has a name index.
+    pub fn packed_resources_writer_options(&self) -> Result<Option<WriterOptions>> {
+        if self.packed_resources_version == 3 {
+            if self.packed_resources_compress || self.packed_resources_deduplicate {
+                return Err(anyhow!(
+                    "compressing or deduplicating packed resources requires packed_resources_version = 4"
+                ));
+            }
+
+            return Ok(None);
+        }
+
+        let compression = if self.packed_resources_compress {
+            (0..=u8::MAX)
+                .filter_map(|value| ResourceField::try_from(value).ok())
+                .filter(|field| field.is_compressible())
+                .map(|field| (field, BlobCompression::Zstd))
+                .collect::<BTreeMap<_, _>>()
+        } else {
+            BTreeMap::new()
+        };
+
+        Ok(Some(WriterOptions {
+            compression,
+            name_index: true,
+            deduplicate: self.packed_resources_deduplicate,
+            ..WriterOptions::default()
+        }))
+    }
+
     /// Set the resource handling mode of the policy.
     ///
     /// This is a convenience function for mapping a `ResourceHandlingMode`
@@ -703,4 +792,29 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_packed_resources_writer_options() -> Result<()> {
+        let mut policy = PythonPackagingPolicy::default();
+        assert!(policy.packed_resources_writer_options()?.is_none());
+
+        policy.set_packed_resources_compress(true);
+        assert!(policy.packed_resources_writer_options().is_err());
+        assert!(policy.set_packed_resources_version(5).is_err());
+
+        policy.set_packed_resources_version(4)?;
+        let options = policy.packed_resources_writer_options()?.unwrap();
+        assert!(options.name_index);
+        assert!(!options.deduplicate);
+        assert_eq!(
+            options.compression.get(&ResourceField::InMemoryBytecode),
+            Some(&BlobCompression::Zstd)
+        );
+        assert!(options
+            .compression
+            .keys()
+            .all(|field| field.is_compressible()));
+
+        Ok(())
+    }
 }
@@ -0,0 +1,166 @@
+// Copyright 2022 Gregory Szorc.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/*! Interact with `pyproject.toml` files. */
+
+use {
+    anyhow::{anyhow, Context, Result},
+    serde::Deserialize,
+    std::path::Path,
+};
+
+/// Build requirements of projects not declaring a build system.
+pub const DEFAULT_BUILD_REQUIRES: &[&str] = &["setuptools>=40.8.0", "wheel"];
+
+/// Build backend of projects not declaring one.
+pub const DEFAULT_BUILD_BACKEND: &str = "setuptools.build_meta:__legacy__";
+
+#[derive(Deserialize)]
+struct PyProjectToml {
+    #[serde(rename = "build-system")]
+    build_system: Option<BuildSystemTable>,
+}
+
+#[derive(Deserialize)]
+struct BuildSystemTable {
+    requires: Option<Vec<String>>,
+    #[serde(rename = "build-backend")]
+    build_backend: Option<String>,
+    #[serde(rename = "backend-path", default)]
+    backend_path: Vec<String>,
+}
+
+/// How to build a source tree, as defined by PEP 517 and PEP 518.
+///
+/// The default value is the legacy setuptools build system pip uses for
+/// projects not declaring one.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BuildSystem {
+    /// Requirements to install before invoking the build backend.
+    pub requires: Vec<String>,
+
+    /// Object reference to the build backend (e.g. `flit_core.buildapi`).
+    pub build_backend: String,
+
+    /// Directories relative to the source tree to import the backend from.
+    pub backend_path: Vec<String>,
+}
+
+impl Default for BuildSystem {
+    fn default() -> Self {
+        Self {
+            requires: DEFAULT_BUILD_REQUIRES
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+            build_backend: DEFAULT_BUILD_BACKEND.to_string(),
+            backend_path: vec![],
+        }
+    }
+}
+
+impl BuildSystem {
+    /// Resolve the build system declared by the content of a `pyproject.toml` file.
+    ///
+    /// Like pip, content without a `[build-system]` table resolves to the
+    /// default build system.
+    pub fn from_pyproject_toml(data: &str) -> Result<Self> {
+        let doc: PyProjectToml = toml::from_str(data).context("parsing pyproject.toml")?;
+
+        let table = if let Some(table) = doc.build_system {
+            table
+        } else {
+            return Ok(Self::default());
+        };
+
+        let requires = table
+            .requires
+            .ok_or_else(|| anyhow!("[build-system] table has no requires key"))?;
+
+        for p in &table.backend_path {
+            if Path::new(p).is_absolute() || p.split(['/', '\\']).any(|c| c == "..") {
+                return Err(anyhow!(
+                    "backend-path entry {} is not within the source tree",
+                    p
+                ));
+            }
+        }
+
+        Ok(Self {
+            requires,
+            build_backend: table
+                .build_backend
+                .unwrap_or_else(|| DEFAULT_BUILD_BACKEND.to_string()),
+            backend_path: table.backend_path,
+        })
+    }
+
+    /// Resolve the build system of a source tree.
+    ///
+    /// Source trees without a `pyproject.toml` use the default build system.
+    pub fn from_directory(root: &Path) -> Result<Self> {
+        let path = root.join("pyproject.toml");
+
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let data = std::fs::read_to_string(&path)
+            .with_context(|| format!("reading {}", path.display()))?;
+
+        Self::from_pyproject_toml(&data)
+            .with_context(|| format!("resolving build system of {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_system() -> Result<()> {
+        let temp_dir = tempfile::Builder::new()
+            .prefix("python-packaging-test")
+            .tempdir()?;
+        let root = temp_dir.path();
+
+        let legacy = BuildSystem::from_directory(root)?;
+        assert_eq!(legacy.requires, vec!["setuptools>=40.8.0", "wheel"]);
+        assert_eq!(legacy.build_backend, "setuptools.build_meta:__legacy__");
+
+        std::fs::write(root.join("pyproject.toml"), "[project]\nname = \"app\"\n")?;
+        assert_eq!(BuildSystem::from_directory(root)?, legacy);
+
+        std::fs::write(
+            root.join("pyproject.toml"),
+            "[build-system]\nrequires = [\"hatchling\"]\nbuild-backend = \"hatchling.build\"\nbackend-path = [\"tools\"]\n",
+        )?;
+        assert_eq!(
+            BuildSystem::from_directory(root)?,
+            BuildSystem {
+                requires: vec!["hatchling".to_string()],
+                build_backend: "hatchling.build".to_string(),
+                backend_path: vec!["tools".to_string()],
+            }
+        );
+
+        std::fs::write(
+            root.join("pyproject.toml"),
+            "[build-system]\nbuild-backend = \"hatchling.build\"\n",
+        )?;
+        assert!(BuildSystem::from_directory(root).is_err());
+
+        std::fs::write(
+            root.join("pyproject.toml"),
+            "[build-system]\nrequires = []\nbackend-path = [\"../elsewhere\"]\n",
+        )?;
+        assert!(BuildSystem::from_directory(root).is_err());
+
+        Ok(())
+    }
+}
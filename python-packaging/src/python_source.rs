@@ -8,7 +8,7 @@
 
 /*! Utility functions related to Python source code. */
 
-use {anyhow::Result, once_cell::sync::Lazy};
+use {anyhow::Result, once_cell::sync::Lazy, std::borrow::Cow};
 
 static RE_CODING: Lazy<regex::bytes::Regex> = Lazy::new(|| {
     regex::bytes::Regex::new(r"^[ \t\f]*#.*?coding[:=][ \t]*([-_.a-zA-Z0-9]+)").unwrap()
@@ -34,11 +34,8 @@ pub fn python_source_encoding(source: &[u8]) -> Vec<u8> {
     b"utf-8".to_vec()
 }
 
-/// Whether __file__ occurs in Python source code.
-pub fn has_dunder_file(source: &[u8]) -> Result<bool> {
-    // We can't just look for b"__file__ because the source file may be in
-    // encodings like UTF-16. So we need to decode to Unicode first then look for
-    // the code points.
+/// Decode Python source code to Unicode using its declared encoding.
+fn decode_python_source(source: &[u8]) -> Cow<'_, str> {
     let encoding = python_source_encoding(source);
 
     let encoder = match encoding_rs::Encoding::for_label(&encoding) {
@@ -48,5 +45,373 @@ pub fn has_dunder_file(source: &[u8]) -> Result<bool> {
 
     let (source, ..) = encoder.decode(source);
 
-    Ok(source.contains("__file__"))
+    source
+}
+
+/// Whether __file__ occurs in Python source code.
+pub fn has_dunder_file(source: &[u8]) -> Result<bool> {
+    // We can't just look for b"__file__ because the source file may be in
+    // encodings like UTF-16. So we need to decode to Unicode first then look for
+    // the code points.
+    Ok(decode_python_source(source).contains("__file__"))
+}
+
+/// An import performed by Python source code.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PythonImport {
+    /// Number of leading dots of a relative import. 0 for absolute imports.
+    pub level: usize,
+
+    /// Dotted name of the imported module.
+    ///
+    /// Empty for relative imports like `from . import foo`.
+    pub module: String,
+
+    /// Names imported by a `from ... import` statement.
+    ///
+    /// Empty for `import` statements. Star imports have the name `*`.
+    pub names: Vec<String>,
+}
+
+/// A token of Python source code, as far as finding imports is concerned.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Name(String),
+    Str(String),
+    Op(char),
+    /// End of a simple statement.
+    End,
+}
+
+/// Whether a name preceding a quote is a string literal prefix.
+fn is_string_prefix(name: &str) -> bool {
+    matches!(
+        name.to_ascii_lowercase().as_str(),
+        "r" | "u" | "b" | "f" | "br" | "rb" | "fr" | "rf"
+    )
+}
+
+/// Read a string literal starting at the quote at `start`.
+///
+/// Returns the literal's content and the index following the literal.
+fn read_string_literal(chars: &[char], start: usize) -> (String, usize) {
+    let quote = chars[start];
+    let triple = chars.get(start + 1) == Some(&quote) && chars.get(start + 2) == Some(&quote);
+
+    let mut i = if triple { start + 3 } else { start + 1 };
+    let mut value = String::new();
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        if c == '\\' {
+            if let Some(next) = chars.get(i + 1) {
+                value.push(*next);
+            }
+            i += 2;
+        } else if triple {
+            if c == quote && chars.get(i + 1) == Some(&quote) && chars.get(i + 2) == Some(&quote) {
+                return (value, i + 3);
+            }
+
+            value.push(c);
+            i += 1;
+        } else if c == quote {
+            return (value, i + 1);
+        } else if c == '\n' {
+            // Unterminated string. Let the newline end the statement.
+            return (value, i);
+        } else {
+            value.push(c);
+            i += 1;
+        }
+    }
+
+    (value, i)
+}
+
+/// Split Python source code into tokens.
+///
+/// Comments are dropped. Statement boundaries are emitted for newlines
+/// outside of brackets and for `;` and `:` outside of brackets, so the
+/// body of a compound statement on the same line becomes its own statement.
+fn tokenize(source: &str) -> Vec<Token> {
+    let chars = source.chars().collect::<Vec<_>>();
+    let mut tokens = vec![];
+    let mut depth = 0usize;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        if c == '#' {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if c == '\\' {
+            // Line continuation.
+            i += 1;
+            if chars.get(i) == Some(&'\r') {
+                i += 1;
+            }
+            if chars.get(i) == Some(&'\n') {
+                i += 1;
+            }
+        } else if c == '\n' {
+            if depth == 0 {
+                tokens.push(Token::End);
+            }
+            i += 1;
+        } else if c == '\'' || c == '"' {
+            let (value, end) = read_string_literal(&chars, i);
+            tokens.push(Token::Str(value));
+            i = end;
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let name = chars[start..i].iter().collect::<String>();
+
+            if matches!(chars.get(i), Some('\'') | Some('"')) && is_string_prefix(&name) {
+                let (value, end) = read_string_literal(&chars, i);
+                tokens.push(Token::Str(value));
+                i = end;
+            } else {
+                tokens.push(Token::Name(name));
+            }
+        } else if c.is_ascii_digit() {
+            while i < chars.len()
+                && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '.')
+            {
+                i += 1;
+            }
+            tokens.push(Token::Op('0'));
+        } else {
+            match c {
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' | '}' => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+
+            if depth == 0 && (c == ';' || c == ':') {
+                tokens.push(Token::End);
+            } else if !c.is_whitespace() {
+                tokens.push(Token::Op(c));
+            }
+            i += 1;
+        }
+    }
+
+    tokens.push(Token::End);
+
+    tokens
+}
+
+/// Parse a dotted name at the start of `tokens`.
+///
+/// Returns the name and the number of tokens consumed.
+fn parse_dotted_name(tokens: &[Token]) -> (String, usize) {
+    let mut parts = vec![];
+    let mut i = 0;
+
+    while let Some(Token::Name(name)) = tokens.get(i) {
+        if name == "import" {
+            break;
+        }
+
+        parts.push(name.as_str());
+        i += 1;
+
+        if tokens.get(i) == Some(&Token::Op('.')) {
+            i += 1;
+        } else {
+            break;
+        }
+    }
+
+    (parts.join("."), i)
+}
+
+/// Parse the tokens of an `import` statement following the `import` keyword.
+fn parse_import_statement(tokens: &[Token], imports: &mut Vec<PythonImport>) {
+    let mut i = 0;
+
+    while i < tokens.len() {
+        let (module, consumed) = parse_dotted_name(&tokens[i..]);
+        if consumed == 0 {
+            return;
+        }
+        i += consumed;
+
+        imports.push(PythonImport {
+            module,
+            ..Default::default()
+        });
+
+        if tokens.get(i) == Some(&Token::Name("as".to_string())) {
+            i += 2;
+        }
+
+        if tokens.get(i) == Some(&Token::Op(',')) {
+            i += 1;
+        } else {
+            return;
+        }
+    }
+}
+
+/// Parse the tokens of a `from ... import` statement following the `from` keyword.
+fn parse_from_statement(tokens: &[Token], imports: &mut Vec<PythonImport>) {
+    let mut i = 0;
+    let mut level = 0;
+
+    while tokens.get(i) == Some(&Token::Op('.')) {
+        level += 1;
+        i += 1;
+    }
+
+    let (module, consumed) = parse_dotted_name(&tokens[i..]);
+    i += consumed;
+
+    if (level == 0 && module.is_empty())
+        || tokens.get(i) != Some(&Token::Name("import".to_string()))
+    {
+        return;
+    }
+    i += 1;
+
+    let mut names = vec![];
+
+    while let Some(token) = tokens.get(i) {
+        match token {
+            // Skip the alias following `as`.
+            Token::Name(name) if name == "as" => {
+                i += 1;
+            }
+            Token::Name(name) => {
+                names.push(name.clone());
+            }
+            Token::Op('*') => {
+                names.push("*".to_string());
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+
+    imports.push(PythonImport {
+        level,
+        module,
+        names,
+    });
+}
+
+/// Find imports performed by Python source code.
+///
+/// `import` and `from ... import` statements are found wherever they occur,
+/// including inside functions and conditional blocks. Calls of
+/// `importlib.import_module()` and `__import__()` with an absolute module name
+/// given as a string literal are also found. Other dynamic imports are not.
+pub fn find_imports(source: &[u8]) -> Vec<PythonImport> {
+    let tokens = tokenize(&decode_python_source(source));
+
+    let mut imports = vec![];
+
+    for statement in tokens.split(|token| token == &Token::End) {
+        match statement.first() {
+            Some(Token::Name(name)) if name == "import" => {
+                parse_import_statement(&statement[1..], &mut imports);
+            }
+            Some(Token::Name(name)) if name == "from" => {
+                parse_from_statement(&statement[1..], &mut imports);
+            }
+            _ => {}
+        }
+    }
+
+    for window in tokens.windows(3) {
+        if let [Token::Name(function), Token::Op('('), Token::Str(module)] = window {
+            if (function == "import_module" || function == "__import__")
+                && !module.is_empty()
+                && !module.starts_with('.')
+            {
+                imports.push(PythonImport {
+                    module: module.clone(),
+                    ..Default::default()
+                });
+            }
+        }
+    }
+
+    imports
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn import(level: usize, module: &str, names: &[&str]) -> PythonImport {
+        PythonImport {
+            level,
+            module: module.to_string(),
+            names: names.iter().map(|x| x.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_find_imports_statements() {
+        let source = b"import os, sys as system\n\
+            import foo.bar.baz\n\
+            from collections import OrderedDict as OD, deque\n\
+            from . import sibling\n\
+            from ..parent.mod import (\n    a,\n    b as c,\n)\n\
+            from .star import *\n\
+            def f():\n    import json; from re import compile\n\
+            if True: import typing\n";
+
+        assert_eq!(
+            find_imports(source),
+            vec![
+                import(0, "os", &[]),
+                import(0, "sys", &[]),
+                import(0, "foo.bar.baz", &[]),
+                import(0, "collections", &["OrderedDict", "deque"]),
+                import(1, "", &["sibling"]),
+                import(2, "parent.mod", &["a", "b"]),
+                import(1, "star", &["*"]),
+                import(0, "json", &[]),
+                import(0, "re", &["compile"]),
+                import(0, "typing", &[]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_find_imports_ignores_strings_and_comments() {
+        let source = b"\"\"\"\nimport docstring\n\"\"\"\n\
+            # import comment\n\
+            x = 'import quoted'\n\
+            y = r\"from raw import nothing\"\n\
+            importer = 1\n";
+
+        assert_eq!(find_imports(source), vec![]);
+    }
+
+    #[test]
+    fn test_find_imports_dynamic() {
+        let source = b"import importlib\n\
+            m = importlib.import_module('dynamic.mod')\n\
+            n = __import__(\"other\")\n\
+            o = importlib.import_module('.relative', 'pkg')\n";
+
+        assert_eq!(
+            find_imports(source),
+            vec![
+                import(0, "importlib", &[]),
+                import(0, "dynamic.mod", &[]),
+                import(0, "other", &[]),
+            ]
+        );
+    }
 }
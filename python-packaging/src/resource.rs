@@ -786,12 +786,63 @@ pub struct PythonPathExtension {
     pub data: FileData,
 }
 
+/// An entry in a Python path extension (.pth) file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PythonPathExtensionEntry {
+    /// A path to add to `sys.path`.
+    ///
+    /// Relative paths are relative to the directory holding the .pth file.
+    Path(String),
+
+    /// A line of Python code to execute.
+    ///
+    /// These are lines beginning with `import`.
+    Import(String),
+}
+
 impl PythonPathExtension {
     pub fn to_memory(&self) -> Result<Self> {
         Ok(Self {
             data: self.data.to_memory()?,
         })
     }
+
+    /// Parse the entries of the .pth file.
+    ///
+    /// This follows the rules of the `site` module: blank lines and lines
+    /// beginning with `#` are ignored, lines beginning with `import` followed
+    /// by a space or tab are code to execute, and all other lines are paths.
+    pub fn entries(&self) -> Result<Vec<PythonPathExtensionEntry>> {
+        let data = self.data.resolve_content()?;
+        let data = data.strip_prefix(b"\xef\xbb\xbf").unwrap_or(&data);
+
+        Ok(String::from_utf8_lossy(data)
+            .lines()
+            .filter(|line| !line.starts_with('#') && !line.trim().is_empty())
+            .map(|line| {
+                if line.starts_with("import ") || line.starts_with("import\t") {
+                    PythonPathExtensionEntry::Import(line.to_string())
+                } else {
+                    PythonPathExtensionEntry::Path(line.trim_end().to_string())
+                }
+            })
+            .collect())
+    }
+
+    /// Resolve the existing directories the .pth file adds to `sys.path`.
+    ///
+    /// `site_dir` is the directory holding the .pth file.
+    pub fn resolve_directories(&self, site_dir: &Path) -> Result<Vec<PathBuf>> {
+        Ok(self
+            .entries()?
+            .into_iter()
+            .filter_map(|entry| match entry {
+                PythonPathExtensionEntry::Path(path) => Some(site_dir.join(path)),
+                PythonPathExtensionEntry::Import(_) => None,
+            })
+            .filter(|path| path.is_dir())
+            .collect())
+    }
 }
 
 /// Represents a resource that can be read by Python somehow.
@@ -1022,6 +1073,28 @@ mod tests {
         assert!(!bytecode.is_in_packages(&["bar".to_string()]));
     }
 
+    #[test]
+    fn test_path_extension_entries() -> Result<()> {
+        let pth = PythonPathExtension {
+            data: FileData::Memory(
+                b"\xef\xbb\xbf# comment\n\nfoo\n../bar  \nimport sys; sys.flag = 1\nimportlib\n"
+                    .to_vec(),
+            ),
+        };
+
+        assert_eq!(
+            pth.entries()?,
+            vec![
+                PythonPathExtensionEntry::Path("foo".to_string()),
+                PythonPathExtensionEntry::Path("../bar".to_string()),
+                PythonPathExtensionEntry::Import("import sys; sys.flag = 1".to_string()),
+                PythonPathExtensionEntry::Path("importlib".to_string()),
+            ]
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn package_distribution_resources_path_normalization() {
         // Package names are normalized to lowercase and have hyphens replaced
@@ -13,6 +13,7 @@ use {
         bytecode::{
             compute_bytecode_header, BytecodeHeaderMode, CompileMode, PythonBytecodeCompiler,
         },
+        import_graph::{ImportGraph, TreeShakeReport},
         libpython::LibPythonBuildContext,
         licensing::{LicensedComponent, LicensedComponents},
         location::{AbstractResourceLocation, ConcreteResourceLocation},
@@ -21,11 +22,14 @@ use {
         resource::{
             BytecodeOptimizationLevel, PythonExtensionModule, PythonModuleBytecode,
             PythonModuleBytecodeFromSource, PythonModuleSource, PythonPackageDistributionResource,
-            PythonPackageResource, PythonResource, SharedLibrary,
+            PythonPackageResource, PythonPathExtension, PythonPathExtensionEntry, PythonResource,
+            SharedLibrary,
         },
     },
     anyhow::{anyhow, Context, Result},
-    python_packed_resources::Resource,
+    python_packed_resources::{
+        append_integrity_trailer, PackedResourcesStreamWriter, Resource, WriterOptions,
+    },
     simple_file_manifest::{File, FileData, FileEntry, FileManifest},
     std::{
         borrow::Cow,
@@ -89,6 +93,7 @@ pub struct PrePackagedResource {
     pub file_executable: bool,
     pub file_data_embedded: Option<FileData>,
     pub file_data_utf8_relative_path: Option<(PathBuf, FileData)>,
+    pub extract_to_cache: bool,
 }
 
 impl PrePackagedResource {
@@ -402,6 +407,7 @@ impl PrePackagedResource {
             } else {
                 None
             },
+            extract_to_cache: self.extract_to_cache,
         };
 
         if let Some((prefix, filename, location)) = &self.relative_path_shared_library {
@@ -425,6 +431,9 @@ impl PrePackagedResource {
 /// a particular field, we populate that field in all its parent
 /// packages. If a corresponding fields is already populated, we
 /// copy its data as well.
+///
+/// Parent packages that aren't Python modules themselves are marked as
+/// namespace packages and don't have any data populated.
 pub fn populate_parent_packages(
     resources: &mut BTreeMap<String, PrePackagedResource>,
 ) -> Result<()> {
@@ -439,12 +448,17 @@ pub fn populate_parent_packages(
         })
         .collect::<Vec<(String, PrePackagedResource)>>();
 
+    let module_names = original_resources
+        .iter()
+        .map(|(name, _)| name.clone())
+        .collect::<BTreeSet<_>>();
+
     for (name, original) in original_resources {
         for package in packages_from_module_name(&name) {
             let entry = resources
                 .entry(package.clone())
                 .or_insert_with(|| PrePackagedResource {
-                    name: package,
+                    name: package.clone(),
                     ..PrePackagedResource::default()
                 });
 
@@ -452,6 +466,14 @@ pub fn populate_parent_packages(
             entry.is_module = true;
             entry.is_package = true;
 
+            // Parents that weren't added as modules have no code of their own.
+            // They are PEP 420 namespace packages, which may be split across
+            // multiple distributions. Their portions are found by the importer.
+            if !module_names.contains(&package) {
+                entry.is_namespace_package = true;
+                continue;
+            }
+
             // We want to materialize bytecode on parent packages no matter
             // what. If the original resource has a variant of bytecode in a
             // location, we materialize that variant on parents. We take
@@ -642,20 +664,45 @@ pub struct CompiledResourcesCollection<'a> {
 
     /// Extra file installs that must be performed so referenced files are available.
     pub extra_files: Vec<FileInstall>,
+
+    /// Options for writing packed resources data, version 4.
+    ///
+    /// Version 3 data is written if not set.
+    pub writer_options: Option<WriterOptions>,
 }
 
 impl<'a> CompiledResourcesCollection<'a> {
-    /// Write resources to packed resources data, version 1.
+    /// Write resources to packed resources data.
+    ///
+    /// Version 4 data with an integrity trailer is written if `writer_options`
+    /// is set. Otherwise version 3 data is written.
+    ///
+    /// Serialized data is spooled to temporary files as resources are
+    /// processed instead of being buffered in memory. Resources themselves
+    /// are still held in memory by this collection, so peak memory usage is
+    /// reduced but not bounded.
     pub fn write_packed_resources<W: std::io::Write>(&self, writer: &mut W) -> Result<()> {
-        python_packed_resources::write_packed_resources_v3(
-            &self
-                .resources
-                .values()
-                .cloned()
-                .collect::<Vec<Resource<'a, u8>>>(),
-            writer,
-            None,
-        )
+        let mut stream = if let Some(options) = &self.writer_options {
+            PackedResourcesStreamWriter::new_v4(options)?
+        } else {
+            PackedResourcesStreamWriter::new_v3(None)?
+        };
+
+        for resource in self.resources.values() {
+            stream.add_resource(resource)?;
+        }
+
+        if self.writer_options.is_some() {
+            let mut data = vec![];
+            stream.finish(&mut data)?;
+            append_integrity_trailer(&mut data, None)?;
+
+            writer
+                .write_all(&data)
+                .context("writing packed resources data")
+        } else {
+            stream.finish(writer)
+        }
     }
 
     /// Convert the file installs to a [FileManifest].
@@ -712,6 +759,9 @@ pub struct PythonResourceCollector {
 
     /// Collection of software components which are licensed.
     licensed_components: LicensedComponents,
+
+    /// Python statements to execute when the interpreter starts.
+    startup_hooks: Vec<String>,
 }
 
 impl PythonResourceCollector {
@@ -735,6 +785,7 @@ impl PythonResourceCollector {
             allow_files,
             resources: BTreeMap::new(),
             licensed_components: LicensedComponents::default(),
+            startup_hooks: vec![],
         }
     }
 
@@ -800,6 +851,95 @@ impl PythonResourceCollector {
         Ok(())
     }
 
+    /// Obtain the graph of imports between Python modules in this collection.
+    pub fn import_graph(&self) -> Result<ImportGraph> {
+        ImportGraph::from_resources(self.resources.values())
+    }
+
+    /// Remove Python modules that aren't reachable from entry points in the import graph.
+    ///
+    /// `entry_points` are names of modules the application starts from. Modules
+    /// named in `keep`, along with their submodules, are always kept and are treated
+    /// as entry points as well. Use `keep` for modules that are imported dynamically.
+    ///
+    /// Built-in extension modules and frozen modules are part of the interpreter
+    /// and are never removed. Neither are packages holding distribution metadata,
+    /// as metadata is commonly looked up by name at run-time.
+    pub fn filter_resources_from_import_graph(
+        &mut self,
+        entry_points: &[&str],
+        keep: &[&str],
+    ) -> Result<TreeShakeReport> {
+        let graph = self.import_graph()?;
+
+        let is_kept = |name: &str| {
+            keep.iter()
+                .any(|k| name == *k || (name.starts_with(k) && name[k.len()..].starts_with('.')))
+        };
+
+        let roots = entry_points
+            .iter()
+            .copied()
+            .chain(
+                self.resources
+                    .keys()
+                    .map(|name| name.as_str())
+                    .filter(|name| is_kept(name)),
+            )
+            .collect::<Vec<_>>();
+
+        let kept = graph.reachable(roots.into_iter());
+
+        let removed = self
+            .resources
+            .values()
+            .filter(|r| {
+                r.is_python_resource()
+                    && !r.is_builtin_extension_module
+                    && !r.is_frozen_module
+                    && r.in_memory_distribution_resources.is_none()
+                    && r.relative_path_distribution_resources.is_none()
+                    && !kept.contains_key(&r.name)
+            })
+            .map(|r| {
+                let importers = graph.importers(&r.name);
+
+                let reason = if importers.is_empty() {
+                    "not imported by any module".to_string()
+                } else {
+                    format!(
+                        "only imported by unreachable modules: {}",
+                        importers.join(", ")
+                    )
+                };
+
+                (r.name.clone(), reason)
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        let top_level_names = self.all_top_level_module_names();
+
+        self.filter_resources_mut(|r| !removed.contains_key(&r.name))?;
+
+        let removed_top_level_names = top_level_names
+            .difference(&self.all_top_level_module_names())
+            .cloned()
+            .collect::<BTreeSet<_>>();
+
+        let unanalyzed = kept
+            .keys()
+            .filter(|name| graph.is_unanalyzed(name))
+            .cloned()
+            .collect::<BTreeSet<_>>();
+
+        Ok(TreeShakeReport {
+            kept,
+            removed,
+            removed_top_level_names,
+            unanalyzed,
+        })
+    }
+
     /// Obtain an iterator over the resources in this collector.
     pub fn iter_resources(&self) -> impl Iterator<Item = (&String, &PrePackagedResource)> {
         Box::new(self.resources.iter())
@@ -820,6 +960,29 @@ impl PythonResourceCollector {
         self.licensed_components.normalize_python_modules()
     }
 
+    /// Evaluate a Python path extension (.pth) file.
+    ///
+    /// `import` lines in the file are registered as startup hooks. These are
+    /// executed when the interpreter starts, just like the `site` module would
+    /// when processing the file. Path entries are ignored, as their content needs
+    /// to be collected as resources.
+    pub fn add_python_path_extension(&mut self, extension: &PythonPathExtension) -> Result<()> {
+        for entry in extension.entries()? {
+            if let PythonPathExtensionEntry::Import(code) = entry {
+                if !self.startup_hooks.contains(&code) {
+                    self.startup_hooks.push(code);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Obtain Python statements to execute when the interpreter starts.
+    pub fn startup_hooks(&self) -> &[String] {
+        &self.startup_hooks
+    }
+
     /// Add Python module source with a specific location.
     pub fn add_python_module_source(
         &mut self,
@@ -839,8 +1002,12 @@ impl PythonResourceCollector {
         entry.is_module = true;
         entry.is_package = module.is_package;
 
+        if location == &ConcreteResourceLocation::ExtractToCache {
+            entry.extract_to_cache = true;
+        }
+
         match location {
-            ConcreteResourceLocation::InMemory => {
+            ConcreteResourceLocation::InMemory | ConcreteResourceLocation::ExtractToCache => {
                 entry.in_memory_source = Some(module.source.clone());
             }
             ConcreteResourceLocation::RelativePath(prefix) => {
@@ -948,18 +1115,24 @@ impl PythonResourceCollector {
         let bytecode =
             PythonModuleBytecodeProvider::Provided(FileData::Memory(module.resolve_bytecode()?));
 
+        if location == &ConcreteResourceLocation::ExtractToCache {
+            entry.extract_to_cache = true;
+        }
+
         match location {
-            ConcreteResourceLocation::InMemory => match module.optimize_level {
-                BytecodeOptimizationLevel::Zero => {
-                    entry.in_memory_bytecode = Some(bytecode);
-                }
-                BytecodeOptimizationLevel::One => {
-                    entry.in_memory_bytecode_opt1 = Some(bytecode);
-                }
-                BytecodeOptimizationLevel::Two => {
-                    entry.in_memory_bytecode_opt2 = Some(bytecode);
+            ConcreteResourceLocation::InMemory | ConcreteResourceLocation::ExtractToCache => {
+                match module.optimize_level {
+                    BytecodeOptimizationLevel::Zero => {
+                        entry.in_memory_bytecode = Some(bytecode);
+                    }
+                    BytecodeOptimizationLevel::One => {
+                        entry.in_memory_bytecode_opt1 = Some(bytecode);
+                    }
+                    BytecodeOptimizationLevel::Two => {
+                        entry.in_memory_bytecode_opt2 = Some(bytecode);
+                    }
                 }
-            },
+            }
             ConcreteResourceLocation::RelativePath(prefix) => match module.optimize_level {
                 BytecodeOptimizationLevel::Zero => {
                     entry.relative_path_bytecode =
@@ -1061,18 +1234,24 @@ impl PythonResourceCollector {
 
         let bytecode = PythonModuleBytecodeProvider::FromSource(module.source.clone());
 
+        if location == &ConcreteResourceLocation::ExtractToCache {
+            entry.extract_to_cache = true;
+        }
+
         match location {
-            ConcreteResourceLocation::InMemory => match module.optimize_level {
-                BytecodeOptimizationLevel::Zero => {
-                    entry.in_memory_bytecode = Some(bytecode);
-                }
-                BytecodeOptimizationLevel::One => {
-                    entry.in_memory_bytecode_opt1 = Some(bytecode);
-                }
-                BytecodeOptimizationLevel::Two => {
-                    entry.in_memory_bytecode_opt2 = Some(bytecode);
+            ConcreteResourceLocation::InMemory | ConcreteResourceLocation::ExtractToCache => {
+                match module.optimize_level {
+                    BytecodeOptimizationLevel::Zero => {
+                        entry.in_memory_bytecode = Some(bytecode);
+                    }
+                    BytecodeOptimizationLevel::One => {
+                        entry.in_memory_bytecode_opt1 = Some(bytecode);
+                    }
+                    BytecodeOptimizationLevel::Two => {
+                        entry.in_memory_bytecode_opt2 = Some(bytecode);
+                    }
                 }
-            },
+            }
             ConcreteResourceLocation::RelativePath(prefix) => match module.optimize_level {
                 BytecodeOptimizationLevel::Zero => {
                     entry.relative_path_bytecode =
@@ -1177,8 +1356,12 @@ impl PythonResourceCollector {
         entry.is_module = true;
         entry.is_package = true;
 
+        if location == &ConcreteResourceLocation::ExtractToCache {
+            entry.extract_to_cache = true;
+        }
+
         match location {
-            ConcreteResourceLocation::InMemory => {
+            ConcreteResourceLocation::InMemory | ConcreteResourceLocation::ExtractToCache => {
                 if entry.in_memory_resources.is_none() {
                     entry.in_memory_resources = Some(BTreeMap::new());
                 }
@@ -1250,8 +1433,12 @@ impl PythonResourceCollector {
         entry.is_module = true;
         entry.is_package = true;
 
+        if location == &ConcreteResourceLocation::ExtractToCache {
+            entry.extract_to_cache = true;
+        }
+
         match location {
-            ConcreteResourceLocation::InMemory => {
+            ConcreteResourceLocation::InMemory | ConcreteResourceLocation::ExtractToCache => {
                 if entry.in_memory_distribution_resources.is_none() {
                     entry.in_memory_distribution_resources = Some(BTreeMap::new());
                 }
@@ -1348,24 +1535,33 @@ impl PythonResourceCollector {
         let mut relative_path = if let Some(location) = &add_context.location_fallback {
             match location {
                 ConcreteResourceLocation::RelativePath(ref prefix) => Some(prefix.clone()),
-                ConcreteResourceLocation::InMemory => None,
+                ConcreteResourceLocation::InMemory | ConcreteResourceLocation::ExtractToCache => {
+                    None
+                }
             }
         } else {
             None
         };
 
         let prefer_in_memory = add_context.location == ConcreteResourceLocation::InMemory;
+        // Extracting to a cache materializes a shared library file. So it counts
+        // as filesystem loading.
         let prefer_filesystem = match &add_context.location {
             ConcreteResourceLocation::RelativePath(_) => true,
+            ConcreteResourceLocation::ExtractToCache => true,
             ConcreteResourceLocation::InMemory => false,
         };
+        let prefer_extract = add_context.location == ConcreteResourceLocation::ExtractToCache;
 
         let fallback_in_memory =
             add_context.location_fallback == Some(ConcreteResourceLocation::InMemory);
         let fallback_filesystem = matches!(
             &add_context.location_fallback,
             Some(ConcreteResourceLocation::RelativePath(_))
+                | Some(ConcreteResourceLocation::ExtractToCache)
         );
+        let fallback_extract =
+            add_context.location_fallback == Some(ConcreteResourceLocation::ExtractToCache);
 
         // TODO support this.
         if prefer_filesystem && fallback_in_memory {
@@ -1381,7 +1577,7 @@ impl PythonResourceCollector {
             ConcreteResourceLocation::RelativePath(prefix) => {
                 relative_path = Some(prefix.clone());
             }
-            ConcreteResourceLocation::InMemory => {}
+            ConcreteResourceLocation::InMemory | ConcreteResourceLocation::ExtractToCache => {}
         }
 
         // We produce a builtin extension module (by linking object files) if any
@@ -1454,6 +1650,8 @@ impl PythonResourceCollector {
             // the resources collector.
             let location = if prefer_in_memory && can_load_dynamic_library_memory {
                 ConcreteResourceLocation::InMemory
+            } else if prefer_extract || (relative_path.is_none() && fallback_extract) {
+                ConcreteResourceLocation::ExtractToCache
             } else {
                 match relative_path {
                     Some(prefix) => ConcreteResourceLocation::RelativePath(prefix),
@@ -1505,7 +1703,8 @@ impl PythonResourceCollector {
         };
 
         match location {
-            ConcreteResourceLocation::RelativePath(_) => {
+            ConcreteResourceLocation::RelativePath(_)
+            | ConcreteResourceLocation::ExtractToCache => {
                 if !self
                     .allowed_extension_module_locations
                     .contains(&AbstractResourceLocation::RelativePath)
@@ -1530,6 +1729,10 @@ impl PythonResourceCollector {
             if link.dynamic_library.is_some() {
                 let library_location = match location {
                     ConcreteResourceLocation::InMemory => ConcreteResourceLocation::InMemory,
+                    // Libraries are extracted next to the extension module.
+                    ConcreteResourceLocation::ExtractToCache => {
+                        ConcreteResourceLocation::ExtractToCache
+                    }
                     ConcreteResourceLocation::RelativePath(prefix) => {
                         // We place the shared library next to the extension module.
                         let path = module
@@ -1565,8 +1768,12 @@ impl PythonResourceCollector {
             entry.is_package = true;
         }
 
+        if location == &ConcreteResourceLocation::ExtractToCache {
+            entry.extract_to_cache = true;
+        }
+
         match location {
-            ConcreteResourceLocation::InMemory => {
+            ConcreteResourceLocation::InMemory | ConcreteResourceLocation::ExtractToCache => {
                 entry.in_memory_extension_module_shared_library = Some(FileData::Memory(data));
             }
             ConcreteResourceLocation::RelativePath(prefix) => {
@@ -1602,8 +1809,12 @@ impl PythonResourceCollector {
 
         entry.is_shared_library = true;
 
+        if location == &ConcreteResourceLocation::ExtractToCache {
+            entry.extract_to_cache = true;
+        }
+
         match location {
-            ConcreteResourceLocation::InMemory => {
+            ConcreteResourceLocation::InMemory | ConcreteResourceLocation::ExtractToCache => {
                 entry.in_memory_shared_library = Some(library.data.clone());
             }
             ConcreteResourceLocation::RelativePath(prefix) => match &library.filename {
@@ -1646,7 +1857,9 @@ impl PythonResourceCollector {
         entry.file_executable = file.entry().is_executable();
 
         match location {
-            ConcreteResourceLocation::InMemory => {
+            // Files have no run-time consumer that could extract them. So they are
+            // always embedded.
+            ConcreteResourceLocation::InMemory | ConcreteResourceLocation::ExtractToCache => {
                 entry.file_data_embedded = Some(file.entry().file_data().clone());
             }
             ConcreteResourceLocation::RelativePath(prefix) => {
@@ -1788,6 +2001,12 @@ impl PythonResourceCollector {
                     }
                 }
             },
+            PythonResource::PathExtension(extension) => {
+                self.add_python_path_extension(extension)
+                    .context("adding PythonPathExtension")?;
+
+                Ok(vec![])
+            }
             _ => Err(anyhow!("PythonResource variant not yet supported")),
         }
     }
@@ -1865,6 +2084,7 @@ impl PythonResourceCollector {
         Ok(CompiledResourcesCollection {
             resources,
             extra_files,
+            writer_options: None,
         })
     }
 }
@@ -2754,7 +2974,7 @@ mod tests {
                 is_module: true,
                 name: "root.parent".to_string(),
                 is_package: true,
-                in_memory_source: Some(FileData::Memory(vec![])),
+                is_namespace_package: true,
                 ..PrePackagedResource::default()
             })
         );
@@ -2764,7 +2984,7 @@ mod tests {
                 is_module: true,
                 name: "root".to_string(),
                 is_package: true,
-                in_memory_source: Some(FileData::Memory(vec![])),
+                is_namespace_package: true,
                 ..PrePackagedResource::default()
             })
         );
@@ -2798,7 +3018,7 @@ mod tests {
                 is_module: true,
                 name: "root.parent".to_string(),
                 is_package: true,
-                relative_path_module_source: Some(("prefix".to_string(), FileData::Memory(vec![]))),
+                is_namespace_package: true,
                 ..PrePackagedResource::default()
             })
         );
@@ -2808,7 +3028,7 @@ mod tests {
                 is_module: true,
                 name: "root".to_string(),
                 is_package: true,
-                relative_path_module_source: Some(("prefix".to_string(), FileData::Memory(vec![]))),
+                is_namespace_package: true,
                 ..PrePackagedResource::default()
             })
         );
@@ -2841,9 +3061,7 @@ mod tests {
                 is_module: true,
                 name: "root.parent".to_string(),
                 is_package: true,
-                in_memory_bytecode: Some(PythonModuleBytecodeProvider::FromSource(
-                    FileData::Memory(vec![])
-                )),
+                is_namespace_package: true,
                 ..PrePackagedResource::default()
             })
         );
@@ -2853,9 +3071,7 @@ mod tests {
                 is_module: true,
                 name: "root".to_string(),
                 is_package: true,
-                in_memory_bytecode: Some(PythonModuleBytecodeProvider::FromSource(
-                    FileData::Memory(vec![])
-                )),
+                is_namespace_package: true,
                 ..PrePackagedResource::default()
             })
         );
@@ -2887,6 +3103,7 @@ mod tests {
                 is_module: true,
                 name: "foo".to_string(),
                 is_package: true,
+                is_namespace_package: true,
                 ..PrePackagedResource::default()
             })
         );
@@ -2920,6 +3137,7 @@ mod tests {
                 is_module: true,
                 name: "foo".to_string(),
                 is_package: true,
+                is_namespace_package: true,
                 ..PrePackagedResource::default()
             })
         );
@@ -2978,6 +3196,100 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_write_packed_resources() -> Result<()> {
+        let mut r = PythonResourceCollector::new(
+            vec![AbstractResourceLocation::InMemory],
+            vec![],
+            false,
+            false,
+        );
+        r.add_python_module_source(
+            &PythonModuleSource {
+                name: "foo".to_string(),
+                source: FileData::Memory(vec![42]),
+                is_package: false,
+                cache_tag: DEFAULT_CACHE_TAG.to_string(),
+                is_stdlib: false,
+                is_test: false,
+            },
+            &ConcreteResourceLocation::InMemory,
+        )?;
+
+        let mut compiler = FakeBytecodeCompiler { magic_number: 42 };
+        let mut resources = r.compile_resources(&mut compiler)?;
+
+        let mut data = vec![];
+        resources.write_packed_resources(&mut data)?;
+        assert!(data.starts_with(python_packed_resources::HEADER_V3));
+
+        resources.writer_options = Some(WriterOptions {
+            name_index: true,
+            ..WriterOptions::default()
+        });
+        let mut data = vec![];
+        resources.write_packed_resources(&mut data)?;
+        assert!(data.starts_with(python_packed_resources::HEADER_V4));
+
+        let loaded = python_packed_resources::load_resources_verified(&data, None)
+            .map_err(|e| anyhow!("{}", e))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| anyhow!("{}", e))?;
+        assert_eq!(loaded, vec![resources.resources["foo"].clone()]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_extract_to_cache_source_module() -> Result<()> {
+        let mut r = PythonResourceCollector::new(
+            vec![AbstractResourceLocation::ExtractToCache],
+            vec![],
+            false,
+            false,
+        );
+        r.add_python_module_source(
+            &PythonModuleSource {
+                name: "foo".to_string(),
+                source: FileData::Memory(vec![42]),
+                is_package: false,
+                cache_tag: DEFAULT_CACHE_TAG.to_string(),
+                is_stdlib: false,
+                is_test: false,
+            },
+            &ConcreteResourceLocation::ExtractToCache,
+        )?;
+
+        assert_eq!(
+            r.resources.get("foo"),
+            Some(&PrePackagedResource {
+                is_module: true,
+                name: "foo".to_string(),
+                in_memory_source: Some(FileData::Memory(vec![42])),
+                extract_to_cache: true,
+                ..PrePackagedResource::default()
+            })
+        );
+
+        let mut compiler = FakeBytecodeCompiler { magic_number: 42 };
+
+        let resources = r.compile_resources(&mut compiler)?;
+
+        assert_eq!(
+            resources.resources.get("foo"),
+            Some(&Resource {
+                is_python_module: true,
+                name: Cow::Owned("foo".to_string()),
+                in_memory_source: Some(Cow::Owned(vec![42])),
+                extract_to_cache: true,
+                ..Resource::default()
+            })
+        );
+        assert!(resources.extra_files.is_empty());
+
+        Ok(())
+    }
+
     #[test]
     fn test_add_in_memory_source_module_parents() -> Result<()> {
         let mut r = PythonResourceCollector::new(
@@ -3021,7 +3333,7 @@ mod tests {
                 is_python_module: true,
                 name: Cow::Owned("root".to_string()),
                 is_python_package: true,
-                in_memory_source: Some(Cow::Owned(vec![])),
+                is_python_namespace_package: true,
                 ..Resource::default()
             })
         );
@@ -3031,7 +3343,7 @@ mod tests {
                 is_python_module: true,
                 name: Cow::Owned("root.parent".to_string()),
                 is_python_package: true,
-                in_memory_source: Some(Cow::Owned(vec![])),
+                is_python_namespace_package: true,
                 ..Resource::default()
             })
         );
@@ -3097,9 +3409,7 @@ mod tests {
                 is_python_module: true,
                 name: Cow::Owned("foo".to_string()),
                 is_python_package: true,
-                relative_path_module_source: Some(Cow::Owned(PathBuf::from(
-                    "prefix/foo/__init__.py"
-                ))),
+                is_python_namespace_package: true,
                 ..Resource::default()
             })
         );
@@ -3114,18 +3424,11 @@ mod tests {
         );
         assert_eq!(
             resources.extra_files,
-            vec![
-                (
-                    PathBuf::from("prefix/foo/__init__.py"),
-                    FileData::Memory(vec![]),
-                    false
-                ),
-                (
-                    PathBuf::from("prefix/foo/bar.py"),
-                    FileData::Memory(vec![42]),
-                    false
-                )
-            ]
+            vec![(
+                PathBuf::from("prefix/foo/bar.py"),
+                FileData::Memory(vec![42]),
+                false
+            )]
         );
 
         Ok(())
@@ -3522,7 +3825,7 @@ mod tests {
                 is_python_module: true,
                 name: Cow::Owned("root".to_string()),
                 is_python_package: true,
-                in_memory_bytecode_opt1: Some(Cow::Owned(b"bc1".to_vec())),
+                is_python_namespace_package: true,
                 ..Resource::default()
             })
         );
@@ -3532,7 +3835,7 @@ mod tests {
                 is_python_module: true,
                 name: Cow::Owned("root.parent".to_string()),
                 is_python_package: true,
-                in_memory_bytecode_opt1: Some(Cow::Owned(b"bc1".to_vec())),
+                is_python_namespace_package: true,
                 ..Resource::default()
             })
         );
@@ -4354,6 +4657,7 @@ mod tests {
                 is_python_module: true,
                 name: Cow::Owned("foo".to_string()),
                 is_python_package: true,
+                is_python_namespace_package: true,
                 ..Resource::default()
             })
         );
@@ -4684,4 +4988,88 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_filter_resources_from_import_graph() -> Result<()> {
+        let mut r = PythonResourceCollector::new(
+            vec![AbstractResourceLocation::InMemory],
+            vec![],
+            false,
+            false,
+        );
+
+        for (name, is_package, source) in [
+            ("app", false, "import used\n"),
+            ("used", true, "from . import child\n"),
+            ("used.child", false, ""),
+            ("used.other", false, ""),
+            ("dead", false, "import used.other\n"),
+            ("dynamic", true, ""),
+            ("dynamic.plugin", false, ""),
+        ] {
+            r.add_python_module_source(
+                &PythonModuleSource {
+                    name: name.to_string(),
+                    source: FileData::Memory(source.as_bytes().to_vec()),
+                    is_package,
+                    cache_tag: DEFAULT_CACHE_TAG.to_string(),
+                    is_stdlib: false,
+                    is_test: false,
+                },
+                &ConcreteResourceLocation::InMemory,
+            )?;
+        }
+
+        let report = r.filter_resources_from_import_graph(&["app"], &["dynamic"])?;
+
+        assert_eq!(
+            r.resources.keys().collect::<Vec<_>>(),
+            vec!["app", "dynamic", "dynamic.plugin", "used", "used.child"]
+        );
+        assert_eq!(
+            report.removed,
+            [
+                ("dead".to_string(), "not imported by any module".to_string()),
+                (
+                    "used.other".to_string(),
+                    "only imported by unreachable modules: dead".to_string()
+                ),
+            ]
+            .into_iter()
+            .collect::<BTreeMap<_, _>>()
+        );
+        assert_eq!(
+            report.removed_top_level_names,
+            ["dead".to_string()].into_iter().collect::<BTreeSet<_>>()
+        );
+        assert!(report.unanalyzed.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_python_path_extension() -> Result<()> {
+        let mut r = PythonResourceCollector::new(
+            vec![AbstractResourceLocation::InMemory],
+            vec![],
+            false,
+            false,
+        );
+
+        let pth = PythonPathExtension {
+            data: FileData::Memory(b"foo\nimport bar; bar.setup()\n".to_vec()),
+        };
+
+        r.add_python_resource_with_locations(
+            &(&pth).into(),
+            &ConcreteResourceLocation::InMemory,
+            &None,
+        )?;
+        r.add_python_path_extension(&pth)?;
+
+        assert_eq!(r.startup_hooks(), &["import bar; bar.setup()".to_string()]);
+        assert!(r.resources.is_empty());
+
+        Ok(())
+    }
 }
@@ -0,0 +1,597 @@
+// Copyright 2022 Gregory Szorc.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/*! Interact with Python source distribution archives. */
+
+use {
+    crate::{
+        filesystem_scanning::PythonResourceIterator,
+        module_util::PythonModuleSuffixes,
+        package_metadata::{canonicalize_name, PythonPackageMetadata},
+        pyproject::BuildSystem,
+        resource::PythonResource,
+    },
+    anyhow::{anyhow, Context, Result},
+    flate2::read::GzDecoder,
+    simple_file_manifest::{File, FileEntry},
+    std::{
+        io::Read,
+        path::{Component, Path, PathBuf},
+    },
+    zip::ZipArchive,
+};
+
+/// Filename extensions of sources needing compilation.
+const COMPILED_SOURCE_EXTENSIONS: &[&str] = &[
+    "c", "cc", "cpp", "cu", "cxx", "f", "f90", "m", "mm", "pyx", "rs",
+];
+
+/// Build requirements implying that extension modules are compiled.
+const COMPILING_BUILD_REQUIREMENTS: &[&str] = &[
+    "cffi",
+    "cython",
+    "maturin",
+    "meson-python",
+    "pybind11",
+    "scikit-build",
+    "scikit-build-core",
+    "setuptools-rust",
+];
+
+/// Top-level directories not containing installable Python packages.
+const NON_PACKAGE_DIRECTORIES: &[&str] = &[
+    "benchmarks",
+    "build",
+    "dist",
+    "doc",
+    "docs",
+    "example",
+    "examples",
+    "test",
+    "testing",
+    "tests",
+];
+
+/// Top-level Python files that aren't installable modules.
+const NON_MODULE_FILES: &[&str] = &["conftest.py", "noxfile.py", "setup.py"];
+
+/// Prefixes of top-level files holding license information.
+const LICENSE_FILE_PREFIXES: &[&str] = &["COPYING", "LICENCE", "LICENSE", "NOTICE"];
+
+/// Obtain the distribution name of a requirement. e.g. `foo` for `foo>=1.0`.
+fn requirement_name(requirement: &str) -> &str {
+    let end = requirement
+        .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')))
+        .unwrap_or(requirement.len());
+
+    &requirement[..end]
+}
+
+/// Represents a Python source distribution archive.
+///
+/// Source distributions are `.tar.gz` or `.zip` archives holding a single
+/// `<name>-<version>` directory with a `PKG-INFO` metadata file.
+pub struct SdistArchive {
+    /// Files in the archive, relative to the top-level directory.
+    files: Vec<File>,
+
+    /// Name of the top-level directory.
+    root: String,
+}
+
+impl SdistArchive {
+    /// Construct an instance from a generic reader.
+    ///
+    /// `basename` is the filename of the source distribution. Its extension
+    /// determines the archive format.
+    pub fn from_reader<R>(reader: R, basename: &str) -> Result<Self>
+    where
+        R: std::io::Read + std::io::Seek,
+    {
+        let entries = if basename.ends_with(".tar.gz") || basename.ends_with(".tgz") {
+            Self::read_tar(GzDecoder::new(reader))?
+        } else if basename.ends_with(".zip") {
+            Self::read_zip(reader)?
+        } else {
+            return Err(anyhow!(
+                "unsupported source distribution archive format: {}",
+                basename
+            ));
+        };
+
+        let mut root = None;
+        let mut files = vec![];
+
+        for (path, entry) in entries {
+            let mut components = path.components().filter_map(|c| match c {
+                Component::Normal(c) => Some(Ok(c.to_string_lossy().to_string())),
+                Component::CurDir => None,
+                _ => Some(Err(anyhow!(
+                    "illegal path in source distribution: {}",
+                    path.display()
+                ))),
+            });
+
+            let top = components
+                .next()
+                .ok_or_else(|| anyhow!("empty path in source distribution"))??;
+            let rel_path = components.collect::<Result<PathBuf>>()?;
+
+            // Some archivers emit files for directory entries.
+            if rel_path.as_os_str().is_empty() {
+                continue;
+            }
+
+            match &root {
+                Some(root) if root != &top => {
+                    return Err(anyhow!(
+                        "source distribution has multiple top-level directories: {} and {}",
+                        root,
+                        top
+                    ));
+                }
+                Some(_) => {}
+                None => {
+                    root = Some(top);
+                }
+            }
+
+            files.push(File::new(rel_path, entry));
+        }
+
+        let root = root.ok_or_else(|| anyhow!("source distribution is empty"))?;
+        files.sort_by(|a, b| a.path().cmp(b.path()));
+
+        Ok(Self { files, root })
+    }
+
+    /// Construct an instance from a filesystem path.
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let fh = std::fs::File::open(path).with_context(|| {
+            format!("opening {} for source distribution reading", path.display())
+        })?;
+
+        let reader = std::io::BufReader::new(fh);
+        let basename = path
+            .file_name()
+            .ok_or_else(|| anyhow!("could not derive file name"))?
+            .to_string_lossy();
+
+        Self::from_reader(reader, &basename)
+    }
+
+    fn read_tar(reader: impl Read) -> Result<Vec<(PathBuf, FileEntry)>> {
+        let mut archive = tar::Archive::new(reader);
+        let mut res = vec![];
+
+        for entry in archive.entries().context("reading tar archive")? {
+            let mut entry = entry?;
+
+            // We only index regular files.
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+
+            let path = entry.path()?.to_path_buf();
+            let executable = entry.header().mode()? & 0o100 != 0;
+
+            let mut buffer = Vec::with_capacity(entry.size() as usize);
+            entry.read_to_end(&mut buffer)?;
+
+            res.push((path, FileEntry::new_from_data(buffer, executable)));
+        }
+
+        Ok(res)
+    }
+
+    fn read_zip<R>(reader: R) -> Result<Vec<(PathBuf, FileEntry)>>
+    where
+        R: std::io::Read + std::io::Seek,
+    {
+        let mut archive = ZipArchive::new(reader).context("reading zip archive")?;
+        let mut res = vec![];
+
+        for i in 0..archive.len() {
+            let mut file = archive.by_index(i)?;
+
+            // We only index files.
+            if file.is_dir() {
+                continue;
+            }
+
+            let mut buffer = Vec::with_capacity(file.size() as usize);
+            file.read_to_end(&mut buffer)?;
+
+            res.push((
+                PathBuf::from(file.name()),
+                FileEntry::new_from_data(buffer, file.unix_mode().unwrap_or(0) & 0o100 != 0),
+            ));
+        }
+
+        Ok(res)
+    }
+
+    /// Name of the top-level directory in the archive.
+    pub fn root(&self) -> &str {
+        &self.root
+    }
+
+    /// Obtain files in the archive.
+    ///
+    /// Paths are relative to the top-level directory.
+    pub fn files(&self) -> &[File] {
+        &self.files
+    }
+
+    /// Obtain the content of a file in the archive.
+    fn file_content(&self, path: &str) -> Result<Option<Vec<u8>>> {
+        self.files
+            .iter()
+            .find(|f| f.path() == Path::new(path))
+            .map(|f| f.entry().resolve_content())
+            .transpose()
+    }
+
+    /// Obtain the `PKG-INFO` content as a parsed object.
+    pub fn metadata(&self) -> Result<PythonPackageMetadata> {
+        let data = self
+            .file_content("PKG-INFO")?
+            .ok_or_else(|| anyhow!("PKG-INFO does not exist in {}", self.root))?;
+
+        PythonPackageMetadata::from_metadata(&data)
+    }
+
+    /// Resolve the build system of the source distribution.
+    ///
+    /// Like pip, source distributions without a `pyproject.toml` or a
+    /// `[build-system]` table in it use the default build system.
+    pub fn build_system(&self) -> Result<BuildSystem> {
+        if let Some(data) = self.file_content("pyproject.toml")? {
+            BuildSystem::from_pyproject_toml(
+                std::str::from_utf8(&data).context("decoding pyproject.toml as UTF-8")?,
+            )
+        } else {
+            Ok(BuildSystem::default())
+        }
+    }
+
+    /// Obtain the requirements needed to build the source distribution.
+    pub fn build_requires(&self) -> Result<Vec<String>> {
+        Ok(self.build_system()?.requires)
+    }
+
+    /// Whether building the source distribution likely compiles code.
+    ///
+    /// This looks for source files of compiled languages and for build
+    /// requirements used to compile extension modules.
+    pub fn requires_compilation(&self) -> Result<bool> {
+        let has_compiled_sources = self.files.iter().any(|f| {
+            f.path()
+                .extension()
+                .map(|ext| COMPILED_SOURCE_EXTENSIONS.contains(&ext.to_string_lossy().as_ref()))
+                .unwrap_or_default()
+        });
+
+        if has_compiled_sources {
+            return Ok(true);
+        }
+
+        Ok(self.build_requires()?.iter().any(|r| {
+            COMPILING_BUILD_REQUIREMENTS.contains(&canonicalize_name(requirement_name(r)).as_str())
+        }))
+    }
+
+    /// Obtain the files the source distribution would likely install.
+    ///
+    /// Projects using a `src/` directory are assumed to install what is in
+    /// it. Otherwise top-level Python modules and top-level directories
+    /// containing Python files are assumed to be installed, except for ones
+    /// commonly holding tests, documentation or build scripts.
+    ///
+    /// A `<name>-<version>.dist-info` directory is synthesized from the
+    /// `PKG-INFO` file and top-level license files.
+    fn installed_files(&self) -> Result<Vec<File>> {
+        let metadata = self.metadata()?;
+        let name = metadata
+            .name()
+            .ok_or_else(|| anyhow!("Name not found in PKG-INFO"))?;
+        let version = metadata
+            .version()
+            .ok_or_else(|| anyhow!("Version not found in PKG-INFO"))?;
+
+        let is_python = |p: &Path| p.extension().map(|ext| ext == "py").unwrap_or_default();
+
+        let src_layout = self
+            .files
+            .iter()
+            .any(|f| f.path().starts_with("src") && is_python(f.path()));
+
+        let package_dirs = self
+            .files
+            .iter()
+            .filter_map(|f| {
+                let path = if src_layout {
+                    f.path().strip_prefix("src").ok()?
+                } else {
+                    f.path()
+                };
+
+                let mut components = path.iter();
+                let top = components.next()?.to_string_lossy();
+                components.next()?;
+
+                if is_python(path)
+                    && !top.starts_with('.')
+                    && !top.ends_with(".egg-info")
+                    && !NON_PACKAGE_DIRECTORIES.contains(&top.as_ref())
+                {
+                    Some(top.to_string())
+                } else {
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let mut res = vec![];
+
+        for f in &self.files {
+            let path = if src_layout {
+                if let Ok(path) = f.path().strip_prefix("src") {
+                    path
+                } else {
+                    continue;
+                }
+            } else {
+                f.path()
+            };
+
+            let top = if let Some(top) = path.iter().next() {
+                top.to_string_lossy()
+            } else {
+                continue;
+            };
+
+            let installed = if path.iter().count() == 1 {
+                is_python(path) && !NON_MODULE_FILES.contains(&top.as_ref())
+            } else {
+                package_dirs.iter().any(|d| d == &top)
+            };
+
+            if installed {
+                res.push(File::new(path, f.entry().clone()));
+            }
+        }
+
+        let dist_info = PathBuf::from(format!(
+            "{}-{}.dist-info",
+            canonicalize_name(name).replace('-', "_"),
+            version
+        ));
+
+        res.push(File::new(
+            dist_info.join("METADATA"),
+            FileEntry::new_from_data(self.file_content("PKG-INFO")?.unwrap_or_default(), false),
+        ));
+
+        for f in &self.files {
+            let path = f.path();
+
+            if path.iter().count() == 1
+                && LICENSE_FILE_PREFIXES
+                    .iter()
+                    .any(|prefix| path.to_string_lossy().starts_with(prefix))
+            {
+                res.push(File::new(dist_info.join(path), f.entry().clone()));
+            }
+        }
+
+        Ok(res)
+    }
+
+    /// Obtain `PythonResource` for files the source distribution would install.
+    ///
+    /// This only works for source distributions not requiring compilation, as
+    /// determined by `requires_compilation()`. Which files are installed is
+    /// inferred from the layout of the source tree without running the build
+    /// backend. So results may be inaccurate for projects with custom build
+    /// logic.
+    pub fn python_resources<'a>(
+        &self,
+        cache_tag: &str,
+        suffixes: &PythonModuleSuffixes,
+        emit_files: bool,
+        classify_files: bool,
+    ) -> Result<Vec<PythonResource<'a>>> {
+        if self.requires_compilation()? {
+            return Err(anyhow!(
+                "{} requires compilation; its resources can only be obtained by building it",
+                self.root
+            ));
+        }
+
+        PythonResourceIterator::from_data_locations(
+            &self.installed_files()?,
+            cache_tag,
+            suffixes,
+            emit_files,
+            classify_files,
+        )?
+        .collect::<Result<Vec<_>>>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::resource::{PythonModuleSource, PythonPackageDistributionResourceFlavor},
+        flate2::{write::GzEncoder, Compression},
+        simple_file_manifest::FileData,
+        std::io::{Cursor, Write},
+        zip::{write::SimpleFileOptions, ZipWriter},
+    };
+
+    const DEFAULT_CACHE_TAG: &str = "cpython-39";
+
+    const PKG_INFO: &str = "Metadata-Version: 2.1\nName: Foo.Bar\nVersion: 1.0\nLicense: MIT\n";
+
+    fn suffixes() -> PythonModuleSuffixes {
+        PythonModuleSuffixes {
+            source: vec![".py".to_string()],
+            bytecode: vec![".pyc".to_string()],
+            debug_bytecode: vec![],
+            optimized_bytecode: vec![],
+            extension: vec![],
+        }
+    }
+
+    fn tar_gz(files: &[(&str, &str)]) -> Result<Vec<u8>> {
+        let mut builder = tar::Builder::new(GzEncoder::new(vec![], Compression::default()));
+
+        for (path, data) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, path, data.as_bytes())?;
+        }
+
+        Ok(builder.into_inner()?.finish()?)
+    }
+
+    #[test]
+    fn test_tar_gz_python_resources() -> Result<()> {
+        let data = tar_gz(&[
+            ("foo_bar-1.0/PKG-INFO", PKG_INFO),
+            ("foo_bar-1.0/LICENSE", "license text"),
+            (
+                "foo_bar-1.0/pyproject.toml",
+                "[build-system]\nrequires = [\"hatchling\"]\nbuild-backend = \"hatchling.build\"\n",
+            ),
+            ("foo_bar-1.0/setup.py", ""),
+            ("foo_bar-1.0/src/foo_bar/__init__.py", "VALUE = 42\n"),
+            ("foo_bar-1.0/src/foo_bar.egg-info/PKG-INFO", PKG_INFO),
+            ("foo_bar-1.0/tests/test_foo.py", ""),
+        ])?;
+
+        let sdist = SdistArchive::from_reader(Cursor::new(data), "foo_bar-1.0.tar.gz")?;
+        assert_eq!(sdist.root(), "foo_bar-1.0");
+        assert_eq!(sdist.files().len(), 7);
+        assert_eq!(sdist.metadata()?.name(), Some("Foo.Bar"));
+        assert_eq!(
+            sdist.build_system()?,
+            BuildSystem {
+                requires: vec!["hatchling".to_string()],
+                build_backend: "hatchling.build".to_string(),
+                backend_path: vec![],
+            }
+        );
+        assert!(!sdist.requires_compilation()?);
+
+        let resources = sdist.python_resources(DEFAULT_CACHE_TAG, &suffixes(), false, true)?;
+        assert_eq!(resources.len(), 3);
+
+        assert!(resources.contains(
+            &PythonModuleSource {
+                name: "foo_bar".to_string(),
+                source: FileData::Memory(b"VALUE = 42\n".to_vec()),
+                is_package: true,
+                cache_tag: DEFAULT_CACHE_TAG.to_string(),
+                is_stdlib: false,
+                is_test: false,
+            }
+            .into()
+        ));
+
+        let mut dist_resources = resources
+            .iter()
+            .filter_map(|r| match r {
+                PythonResource::PackageDistributionResource(r) => {
+                    assert_eq!(
+                        r.location,
+                        PythonPackageDistributionResourceFlavor::DistInfo
+                    );
+                    assert_eq!(r.package, "Foo.Bar");
+                    assert_eq!(r.version, "1.0");
+                    Some(r.name.clone())
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        dist_resources.sort();
+        assert_eq!(dist_resources, vec!["LICENSE", "METADATA"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_zip_flat_layout() -> Result<()> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::<u8>::new()));
+
+        for (path, data) in [
+            ("foo-1.0/PKG-INFO", PKG_INFO),
+            ("foo-1.0/foo.py", ""),
+            ("foo-1.0/setup.py", ""),
+            ("foo-1.0/docs/conf.py", ""),
+            ("foo-1.0/pkg/sub/mod.py", ""),
+        ] {
+            writer.start_file(path, SimpleFileOptions::default())?;
+            writer.write_all(data.as_bytes())?;
+        }
+
+        let sdist =
+            SdistArchive::from_reader(Cursor::new(writer.finish()?.into_inner()), "foo-1.0.zip")?;
+        assert_eq!(sdist.build_system()?, None);
+        assert_eq!(sdist.build_requires()?, vec!["setuptools>=40.8.0", "wheel"]);
+
+        let names = sdist
+            .python_resources(DEFAULT_CACHE_TAG, &suffixes(), false, true)?
+            .iter()
+            .map(|r| r.full_name())
+            .collect::<Vec<_>>();
+        assert!(names.contains(&"foo".to_string()));
+        assert!(names.contains(&"pkg.sub.mod".to_string()));
+        assert!(!names.iter().any(|n| n == "setup" || n.starts_with("docs")));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_requires_compilation() -> Result<()> {
+        let data = tar_gz(&[
+            ("foo-1.0/PKG-INFO", PKG_INFO),
+            ("foo-1.0/foo/__init__.py", ""),
+            ("foo-1.0/foo/_speedups.c", ""),
+        ])?;
+        let sdist = SdistArchive::from_reader(Cursor::new(data), "foo-1.0.tar.gz")?;
+        assert!(sdist.requires_compilation()?);
+        assert!(sdist
+            .python_resources(DEFAULT_CACHE_TAG, &suffixes(), false, true)
+            .is_err());
+
+        let data = tar_gz(&[
+            ("foo-1.0/PKG-INFO", PKG_INFO),
+            (
+                "foo-1.0/pyproject.toml",
+                "[build-system]\nrequires = [\"Maturin>=1.0\"]\n",
+            ),
+        ])?;
+        let sdist = SdistArchive::from_reader(Cursor::new(data), "foo-1.0.tar.gz")?;
+        assert!(sdist.requires_compilation()?);
+
+        let data = tar_gz(&[
+            ("foo-1.0/PKG-INFO", PKG_INFO),
+            ("bar-1.0/PKG-INFO", PKG_INFO),
+        ])?;
+        assert!(SdistArchive::from_reader(Cursor::new(data), "foo-1.0.tar.gz").is_err());
+
+        assert!(SdistArchive::from_reader(Cursor::new(vec![]), "foo-1.0.tar.bz2").is_err());
+
+        Ok(())
+    }
+}
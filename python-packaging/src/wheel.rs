@@ -45,16 +45,6 @@ impl WheelArchive {
     where
         R: std::io::Read + std::io::Seek,
     {
-        let captures = RE_WHEEL_INFO
-            .captures(basename)
-            .ok_or_else(|| anyhow!("failed to parse wheel basename: {}", basename))?;
-
-        let name_version = captures
-            .name("namever")
-            .ok_or_else(|| anyhow!("could not find name-version in wheel name"))?
-            .as_str()
-            .to_string();
-
         let mut archive = ZipArchive::new(reader)?;
 
         let mut files = FileManifest::default();
@@ -76,6 +66,25 @@ impl WheelArchive {
             )?;
         }
 
+        Self::from_manifest(files, basename)
+    }
+
+    /// Construct an instance from files already read from a wheel archive.
+    ///
+    /// `files` holds the members of the archive keyed by their path in the
+    /// archive. Only the content of `.dist-info` metadata files is read by
+    /// this type. So callers can defer reading the content of other members.
+    pub fn from_manifest(files: FileManifest, basename: &str) -> Result<Self> {
+        let captures = RE_WHEEL_INFO
+            .captures(basename)
+            .ok_or_else(|| anyhow!("failed to parse wheel basename: {}", basename))?;
+
+        let name_version = captures
+            .name("namever")
+            .ok_or_else(|| anyhow!("could not find name-version in wheel name"))?
+            .as_str()
+            .to_string();
+
         Ok(Self {
             files,
             name_version,
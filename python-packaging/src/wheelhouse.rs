@@ -0,0 +1,642 @@
+// Copyright 2022 Gregory Szorc.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/*! Select compatible wheels from a local directory of wheels.
+
+A *wheelhouse* is a directory holding `.whl` files. Given pinned
+requirements and the tags of a target Python distribution, this module
+selects the most preferred compatible wheel for each requirement without
+involving `pip`.
+*/
+
+pub use crate::package_metadata::canonicalize_name;
+
+use {
+    anyhow::{anyhow, Context, Result},
+    once_cell::sync::Lazy,
+    std::{
+        collections::HashMap,
+        path::{Path, PathBuf},
+    },
+};
+
+/// Regex for parsing wheel file names, per PEP 427.
+static RE_WHEEL_FILENAME: Lazy<regex::Regex> = Lazy::new(|| {
+    regex::Regex::new(r"^(?P<name>[^-]+)-(?P<ver>[^-]+)(-(?P<build>\d[^-]*))?-(?P<pyver>[^-]+)-(?P<abi>[^-]+)-(?P<plat>[^-]+)\.whl$").unwrap()
+});
+
+/// Regex for parsing a PEP 425 Python tag. e.g. `cp39`.
+static RE_PYTHON_TAG: Lazy<regex::Regex> =
+    Lazy::new(|| regex::Regex::new(r"^(?P<impl>[a-z]+)(?P<major>\d)(?P<minor>\d+)$").unwrap());
+
+/// Regex for parsing a manylinux platform tag.
+static RE_MANYLINUX: Lazy<regex::Regex> = Lazy::new(|| {
+    regex::Regex::new(
+        r"^manylinux(_(?P<major>\d+)_(?P<minor>\d+)|(?P<legacy>1|2010|2014))_(?P<arch>.+)$",
+    )
+    .unwrap()
+});
+
+/// Regex for parsing a macOS platform tag.
+static RE_MACOSX: Lazy<regex::Regex> = Lazy::new(|| {
+    regex::Regex::new(r"^macosx_(?P<major>\d+)_(?P<minor>\d+)_(?P<arch>.+)$").unwrap()
+});
+
+/// A requirement pinned to an exact version. e.g. `foo==1.0`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PinnedRequirement {
+    /// Name of the distribution, as specified.
+    pub name: String,
+
+    /// Version of the distribution.
+    pub version: String,
+}
+
+impl PinnedRequirement {
+    /// Parse a requirement string of the form `name==version`.
+    ///
+    /// Extras (`name[extra]==version`) are accepted and ignored. Any other
+    /// version specifier is an error, as requirements must be resolved ahead
+    /// of time.
+    pub fn parse(s: &str) -> Result<Self> {
+        let (name, version) = s
+            .split_once("==")
+            .ok_or_else(|| anyhow!("requirement is not pinned with ==: {}", s))?;
+
+        let name = match name.split_once('[') {
+            Some((name, _)) => name,
+            None => name,
+        }
+        .trim();
+        let version = version.trim();
+
+        if name.is_empty()
+            || version.is_empty()
+            || version.starts_with('=')
+            || version.contains(|c: char| c.is_whitespace() || matches!(c, ',' | ';' | '*'))
+        {
+            return Err(anyhow!("invalid pinned requirement: {}", s));
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            version: version.to_string(),
+        })
+    }
+
+    /// Whether a wheel provides this requirement.
+    pub fn matches(&self, wheel: &WheelFilename) -> bool {
+        canonicalize_name(&self.name) == canonicalize_name(&wheel.distribution)
+            && self.version.eq_ignore_ascii_case(&wheel.version)
+    }
+}
+
+impl std::fmt::Display for PinnedRequirement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}=={}", self.name, self.version)
+    }
+}
+
+/// The components of a wheel file name.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct WheelFilename {
+    /// Name of the distribution, with `-` escaped to `_`.
+    pub distribution: String,
+
+    /// Version of the distribution.
+    pub version: String,
+
+    /// Optional build tag.
+    pub build_tag: Option<String>,
+
+    /// Python tags. e.g. `py3` or `cp39`.
+    pub python_tags: Vec<String>,
+
+    /// ABI tags. e.g. `none`, `abi3` or `cp39`.
+    pub abi_tags: Vec<String>,
+
+    /// Platform tags. e.g. `any` or `manylinux2014_x86_64`.
+    pub platform_tags: Vec<String>,
+}
+
+impl WheelFilename {
+    /// Parse a wheel file name.
+    ///
+    /// Compressed tag sets (e.g. `py2.py3`) are expanded.
+    pub fn parse(basename: &str) -> Result<Self> {
+        let captures = RE_WHEEL_FILENAME
+            .captures(basename)
+            .ok_or_else(|| anyhow!("failed to parse wheel file name: {}", basename))?;
+
+        let split = |name: &str| {
+            captures[name]
+                .split('.')
+                .map(|s| s.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        Ok(Self {
+            distribution: captures["name"].to_string(),
+            version: captures["ver"].to_string(),
+            build_tag: captures.name("build").map(|m| m.as_str().to_string()),
+            python_tags: split("pyver"),
+            abi_tags: split("abi"),
+            platform_tags: split("plat"),
+        })
+    }
+
+    /// Obtain all `(python, abi, platform)` tag triples this wheel supports.
+    pub fn tags(&self) -> impl Iterator<Item = (&str, &str, &str)> + '_ {
+        self.python_tags.iter().flat_map(move |python| {
+            self.abi_tags.iter().flat_map(move |abi| {
+                self.platform_tags
+                    .iter()
+                    .map(move |platform| (python.as_str(), abi.as_str(), platform.as_str()))
+            })
+        })
+    }
+
+    /// Numeric prefix of the build tag, used to break ties between wheels.
+    fn build_number(&self) -> u64 {
+        self.build_tag
+            .as_ref()
+            .and_then(|tag| {
+                tag.chars()
+                    .take_while(|c| c.is_ascii_digit())
+                    .collect::<String>()
+                    .parse()
+                    .ok()
+            })
+            .unwrap_or(0)
+    }
+}
+
+/// Expand a platform tag into the platform tags it is compatible with.
+///
+/// Results are ordered from most to least preferred. A `manylinux` tag is
+/// compatible with `manylinux` tags requiring an older glibc. A `macosx` tag
+/// is compatible with older macOS versions and multi-architecture wheels.
+/// The special `none` platform is compatible with no platform specific
+/// wheels.
+fn compatible_platforms(platform_tag: &str) -> Vec<String> {
+    if platform_tag == "none" {
+        return vec![];
+    }
+
+    if let Some(captures) = RE_MANYLINUX.captures(platform_tag) {
+        let arch = &captures["arch"];
+        let glibc_minor = match captures.name("legacy").map(|m| m.as_str()) {
+            Some("1") => 5,
+            Some("2010") => 12,
+            Some("2014") => 17,
+            _ if &captures["major"] == "2" => captures["minor"].parse().unwrap_or(0),
+            _ => return vec![platform_tag.to_string()],
+        };
+
+        let mut res = vec![];
+        for minor in (5..=glibc_minor).rev() {
+            res.push(format!("manylinux_2_{}_{}", minor, arch));
+
+            match minor {
+                17 => res.push(format!("manylinux2014_{}", arch)),
+                12 => res.push(format!("manylinux2010_{}", arch)),
+                5 => res.push(format!("manylinux1_{}", arch)),
+                _ => {}
+            }
+        }
+
+        return res;
+    }
+
+    if let Some(captures) = RE_MACOSX.captures(platform_tag) {
+        let arch = &captures["arch"];
+        let major = captures["major"].parse::<u32>().unwrap_or(0);
+        let minor = captures["minor"].parse::<u32>().unwrap_or(0);
+
+        let mut arches = vec![arch.to_string()];
+        if arch == "x86_64" || arch == "arm64" {
+            arches.push("universal2".to_string());
+        }
+        if arch == "x86_64" {
+            arches.push("intel".to_string());
+            arches.push("universal".to_string());
+        }
+
+        // Since macOS 11, only the major version is significant.
+        let mut versions = vec![];
+        if major >= 11 {
+            versions.extend((11..=major).rev().map(|major| (major, 0)));
+        }
+        if major == 10 || (major >= 11 && arch == "x86_64") {
+            let minor = if major == 10 { minor } else { 16 };
+            versions.extend((4..=minor).rev().map(|minor| (10, minor)));
+        }
+
+        let mut res = vec![];
+        for (major, minor) in versions {
+            for arch in &arches {
+                res.push(format!("macosx_{}_{}_{}", major, minor, arch));
+            }
+        }
+
+        return res;
+    }
+
+    vec![platform_tag.to_string()]
+}
+
+/// Describes which wheels a Python distribution can use.
+#[derive(Clone, Debug)]
+pub struct WheelCompatibility {
+    /// Human readable description of the target.
+    description: String,
+
+    /// Supported `(python, abi, platform)` tag triples and their preference.
+    ///
+    /// Lower values are preferred.
+    tags: HashMap<(String, String, String), usize>,
+}
+
+impl WheelCompatibility {
+    /// Construct an instance from the PEP 425 tags of a Python distribution.
+    ///
+    /// `python_tag` is the tag of the interpreter. e.g. `cp39`. `abi_tag` is its
+    /// ABI tag, if any. e.g. `cp39`. `platform_tag` is the platform tag used to
+    /// indicate compatibility. e.g. `manylinux2014_x86_64`. A `platform_tag` of
+    /// `none` indicates extension modules can't be loaded, so only pure Python
+    /// wheels are compatible.
+    ///
+    /// The supported tags and their order mirror those of the `packaging.tags`
+    /// Python module.
+    pub fn new(python_tag: &str, abi_tag: Option<&str>, platform_tag: &str) -> Result<Self> {
+        let captures = RE_PYTHON_TAG
+            .captures(python_tag)
+            .ok_or_else(|| anyhow!("unable to parse Python tag: {}", python_tag))?;
+        let implementation = &captures["impl"];
+        let major = &captures["major"];
+        let minor = captures["minor"]
+            .parse::<u32>()
+            .context("parsing Python minor version")?;
+
+        let platforms = compatible_platforms(platform_tag);
+        let mut ordered = vec![];
+
+        // Interpreter specific tags.
+        for abi in abi_tag.into_iter().chain(["abi3", "none"]) {
+            if abi == "abi3" && implementation != "cp" {
+                continue;
+            }
+
+            for platform in &platforms {
+                ordered.push((python_tag.to_string(), abi.to_string(), platform.clone()));
+            }
+        }
+
+        // The stable ABI of older CPython versions.
+        if implementation == "cp" {
+            for minor in (2..minor).rev() {
+                for platform in &platforms {
+                    ordered.push((
+                        format!("cp{}{}", major, minor),
+                        "abi3".to_string(),
+                        platform.clone(),
+                    ));
+                }
+            }
+        }
+
+        // Generic Python versions. e.g. `py39`, `py3`, `py38`, ...
+        let mut generic = vec![format!("py{}{}", major, minor), format!("py{}", major)];
+        generic.extend(
+            (0..minor)
+                .rev()
+                .map(|minor| format!("py{}{}", major, minor)),
+        );
+
+        for python in &generic {
+            for platform in &platforms {
+                ordered.push((python.clone(), "none".to_string(), platform.clone()));
+            }
+        }
+
+        // Pure Python wheels.
+        ordered.push((
+            python_tag.to_string(),
+            "none".to_string(),
+            "any".to_string(),
+        ));
+        for python in &generic {
+            ordered.push((python.clone(), "none".to_string(), "any".to_string()));
+        }
+
+        let mut tags = HashMap::new();
+        for (i, tag) in ordered.into_iter().enumerate() {
+            tags.entry(tag).or_insert(i);
+        }
+
+        Ok(Self {
+            description: format!(
+                "{}-{}-{}",
+                python_tag,
+                abi_tag.unwrap_or("none"),
+                platform_tag
+            ),
+            tags,
+        })
+    }
+
+    /// Obtain the preference of a wheel, if it is compatible.
+    ///
+    /// Lower values are preferred.
+    pub fn wheel_priority(&self, wheel: &WheelFilename) -> Option<usize> {
+        wheel
+            .tags()
+            .filter_map(|(python, abi, platform)| {
+                self.tags
+                    .get(&(python.to_string(), abi.to_string(), platform.to_string()))
+                    .copied()
+            })
+            .min()
+    }
+
+    /// Whether a wheel is compatible.
+    pub fn is_compatible(&self, wheel: &WheelFilename) -> bool {
+        self.wheel_priority(wheel).is_some()
+    }
+}
+
+impl std::fmt::Display for WheelCompatibility {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.description)
+    }
+}
+
+/// A directory of wheel files.
+#[derive(Clone, Debug)]
+pub struct Wheelhouse {
+    path: PathBuf,
+    wheels: Vec<(WheelFilename, PathBuf)>,
+}
+
+impl Wheelhouse {
+    /// Index the `.whl` files in a directory.
+    ///
+    /// Subdirectories are not scanned.
+    pub fn from_directory(path: &Path) -> Result<Self> {
+        let mut wheels = vec![];
+
+        for entry in std::fs::read_dir(path)
+            .with_context(|| format!("reading wheelhouse {}", path.display()))?
+        {
+            let entry = entry?;
+            let file_name = entry.file_name();
+            let file_name = file_name.to_string_lossy();
+
+            if !file_name.ends_with(".whl") || !entry.file_type()?.is_file() {
+                continue;
+            }
+
+            wheels.push((WheelFilename::parse(&file_name)?, entry.path()));
+        }
+
+        wheels.sort_by(|a, b| a.1.cmp(&b.1));
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            wheels,
+        })
+    }
+
+    /// Obtain all indexed wheels.
+    pub fn wheels(&self) -> impl Iterator<Item = (&WheelFilename, &Path)> {
+        self.wheels
+            .iter()
+            .map(|(wheel, path)| (wheel, path.as_path()))
+    }
+
+    /// Find the most preferred compatible wheel for a requirement.
+    ///
+    /// Errors describe why no wheel was found: there are no wheels for the
+    /// distribution, none for the requested version, or none of the wheels of
+    /// that version are compatible.
+    pub fn find_wheel(
+        &self,
+        requirement: &PinnedRequirement,
+        compatibility: &WheelCompatibility,
+    ) -> Result<&Path> {
+        let name = canonicalize_name(&requirement.name);
+
+        let candidates = self
+            .wheels
+            .iter()
+            .filter(|(wheel, _)| canonicalize_name(&wheel.distribution) == name)
+            .collect::<Vec<_>>();
+
+        if candidates.is_empty() {
+            return Err(anyhow!(
+                "no wheels for {} in {}",
+                requirement.name,
+                self.path.display()
+            ));
+        }
+
+        let versioned = candidates
+            .iter()
+            .copied()
+            .filter(|(wheel, _)| requirement.matches(wheel))
+            .collect::<Vec<_>>();
+
+        if versioned.is_empty() {
+            let mut versions = candidates
+                .iter()
+                .map(|(wheel, _)| wheel.version.as_str())
+                .collect::<Vec<_>>();
+            versions.sort_unstable();
+            versions.dedup();
+
+            return Err(anyhow!(
+                "no wheels for {} in {}; available versions: {}",
+                requirement,
+                self.path.display(),
+                versions.join(", ")
+            ));
+        }
+
+        // Prefer the most preferred tags, then the highest build number.
+        versioned
+            .iter()
+            .copied()
+            .filter_map(|(wheel, path)| {
+                compatibility
+                    .wheel_priority(wheel)
+                    .map(|priority| (priority, std::cmp::Reverse(wheel.build_number()), path))
+            })
+            .min_by_key(|(priority, build, _)| (*priority, *build))
+            .map(|(_, _, path)| path.as_path())
+            .ok_or_else(|| {
+                anyhow!(
+                    "no wheel for {} compatible with {}; found: {}",
+                    requirement,
+                    compatibility,
+                    versioned
+                        .iter()
+                        .filter_map(|(_, path)| path.file_name())
+                        .map(|name| name.to_string_lossy())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::wheel_builder::WheelBuilder};
+
+    #[test]
+    fn test_pinned_requirement_parse() -> Result<()> {
+        assert_eq!(
+            PinnedRequirement::parse("foo==1.0")?,
+            PinnedRequirement {
+                name: "foo".to_string(),
+                version: "1.0".to_string()
+            }
+        );
+        assert_eq!(
+            PinnedRequirement::parse(" foo[bar] == 1.0 ")?,
+            PinnedRequirement {
+                name: "foo".to_string(),
+                version: "1.0".to_string()
+            }
+        );
+        assert!(PinnedRequirement::parse("foo").is_err());
+        assert!(PinnedRequirement::parse("foo>=1.0").is_err());
+        assert!(PinnedRequirement::parse("foo===1.0").is_err());
+        assert!(PinnedRequirement::parse("foo==1.*").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_wheel_filename_parse() -> Result<()> {
+        assert_eq!(
+            WheelFilename::parse("foo_bar-1.0-1-py2.py3-none-any.whl")?,
+            WheelFilename {
+                distribution: "foo_bar".to_string(),
+                version: "1.0".to_string(),
+                build_tag: Some("1".to_string()),
+                python_tags: vec!["py2".to_string(), "py3".to_string()],
+                abi_tags: vec!["none".to_string()],
+                platform_tags: vec!["any".to_string()],
+            }
+        );
+        assert!(WheelFilename::parse("foo-1.0.tar.gz").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_compatibility() -> Result<()> {
+        let compat = WheelCompatibility::new("cp39", Some("cp39"), "manylinux2014_x86_64")?;
+
+        let priority = |name: &str| compat.wheel_priority(&WheelFilename::parse(name).unwrap());
+
+        let native = priority("foo-1.0-cp39-cp39-manylinux2014_x86_64.whl");
+        let older_glibc = priority("foo-1.0-cp39-cp39-manylinux1_x86_64.whl");
+        let abi3 = priority("foo-1.0-cp36-abi3-manylinux_2_12_x86_64.whl");
+        let pure = priority("foo-1.0-py3-none-any.whl");
+
+        assert!(native.is_some());
+        assert!(native < older_glibc);
+        assert!(older_glibc < abi3);
+        assert!(abi3 < pure);
+
+        for name in [
+            "foo-1.0-cp39-cp39-manylinux_2_28_x86_64.whl",
+            "foo-1.0-cp39-cp39-manylinux2014_aarch64.whl",
+            "foo-1.0-cp310-cp310-manylinux2014_x86_64.whl",
+            "foo-1.0-cp39-cp39-win_amd64.whl",
+            "foo-1.0-py2-none-any.whl",
+        ] {
+            assert_eq!(priority(name), None, "{}", name);
+        }
+
+        // Without loadable extension modules, only pure Python wheels work.
+        let compat = WheelCompatibility::new("cp39", Some("cp39"), "none")?;
+        assert!(compat.is_compatible(&WheelFilename::parse("foo-1.0-py3-none-any.whl")?));
+        assert!(!compat.is_compatible(&WheelFilename::parse(
+            "foo-1.0-cp39-cp39-manylinux1_x86_64.whl"
+        )?));
+
+        let compat = WheelCompatibility::new("cp39", Some("cp39"), "macosx_11_0_arm64")?;
+        assert!(compat.is_compatible(&WheelFilename::parse(
+            "foo-1.0-cp39-cp39-macosx_11_0_universal2.whl"
+        )?));
+        assert!(!compat.is_compatible(&WheelFilename::parse(
+            "foo-1.0-cp39-cp39-macosx_10_9_x86_64.whl"
+        )?));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_wheelhouse_find_wheel() -> Result<()> {
+        let td = tempfile::Builder::new()
+            .prefix("python-packaging-test")
+            .tempdir()?;
+
+        for tag in [
+            "py3-none-any",
+            "cp39-cp39-manylinux2014_x86_64",
+            "cp39-cp39-win_amd64",
+        ] {
+            let mut builder = WheelBuilder::new("Foo_Bar", "1.0");
+            builder.set_tag(tag)?;
+            builder.write_wheel_into_directory(td.path())?;
+        }
+        WheelBuilder::new("foo-bar", "0.9").write_wheel_into_directory(td.path())?;
+
+        let wheelhouse = Wheelhouse::from_directory(td.path())?;
+        assert_eq!(wheelhouse.wheels().count(), 4);
+
+        let linux = WheelCompatibility::new("cp39", Some("cp39"), "manylinux2014_x86_64")?;
+        let macos = WheelCompatibility::new("cp39", Some("cp39"), "macosx_10_9_x86_64")?;
+
+        let path = wheelhouse.find_wheel(&PinnedRequirement::parse("foo_bar==1.0")?, &linux)?;
+        assert_eq!(
+            path.file_name().unwrap(),
+            "foo_bar-1.0-cp39-cp39-manylinux2014_x86_64.whl"
+        );
+
+        let path = wheelhouse.find_wheel(&PinnedRequirement::parse("foo-bar==1.0")?, &macos)?;
+        assert_eq!(path.file_name().unwrap(), "foo_bar-1.0-py3-none-any.whl");
+
+        let err = wheelhouse
+            .find_wheel(&PinnedRequirement::parse("baz==1.0")?, &linux)
+            .unwrap_err();
+        assert!(err.to_string().starts_with("no wheels for baz in "));
+
+        let err = wheelhouse
+            .find_wheel(&PinnedRequirement::parse("foo-bar==2.0")?, &linux)
+            .unwrap_err();
+        assert!(err.to_string().ends_with("available versions: 0.9, 1.0"));
+
+        let err = wheelhouse
+            .find_wheel(
+                &PinnedRequirement::parse("foo-bar==0.9")?,
+                &WheelCompatibility::new("cp27", Some("cp27mu"), "none")?,
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "no wheel for foo-bar==0.9 compatible with cp27-cp27mu-none; found: foo_bar-0.9-py3-none-any.whl"
+        );
+
+        Ok(())
+    }
+}
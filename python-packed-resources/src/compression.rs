@@ -0,0 +1,160 @@
+// Copyright 2022 Gregory Szorc.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/*! Compression of blob section entries. */
+
+use crate::serialization::BlobCompression;
+#[cfg(feature = "zstd")]
+use std::io::Read;
+
+/// Error when zstd compressed data is handled without zstd support.
+#[cfg(not(feature = "zstd"))]
+const ZSTD_NOT_ENABLED: &str = "zstd support not enabled; enable the zstd feature";
+
+/// The zstandard compression level used when writing compressed entries.
+///
+/// Packed resources are written once and read many times. So we favor
+/// a high compression ratio over compression speed.
+#[cfg(feature = "zstd")]
+const ZSTD_COMPRESSION_LEVEL: i32 = 19;
+
+/// Upper bound of memory reserved up front when decompressing an entry.
+#[cfg(feature = "zstd")]
+const MAX_INITIAL_CAPACITY: u64 = 1024 * 1024;
+
+/// Compress a single blob entry.
+pub(crate) fn compress_blob(compression: BlobCompression, data: &[u8]) -> std::io::Result<Vec<u8>> {
+    match compression {
+        BlobCompression::None => Ok(data.to_vec()),
+        BlobCompression::Zstd => compress_zstd(data),
+    }
+}
+
+#[cfg(feature = "zstd")]
+fn compress_zstd(data: &[u8]) -> std::io::Result<Vec<u8>> {
+    zstd::bulk::compress(data, ZSTD_COMPRESSION_LEVEL)
+}
+
+#[cfg(not(feature = "zstd"))]
+fn compress_zstd(_data: &[u8]) -> std::io::Result<Vec<u8>> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        ZSTD_NOT_ENABLED,
+    ))
+}
+
+/// Decompress a single blob entry.
+///
+/// `data` must be the full data for an entry in a blob section having
+/// compression `compression`.
+///
+/// Blob data is untrusted. So the content size declared by the zstd frame
+/// header isn't used to size allocations up front: data is decompressed
+/// incrementally and decompression fails if it yields more or less data than
+/// declared.
+///
+/// Decompressing zstd data requires the `zstd` feature.
+pub fn decompress_blob(compression: BlobCompression, data: &[u8]) -> Result<Vec<u8>, &'static str> {
+    match compression {
+        BlobCompression::None => Ok(data.to_vec()),
+        BlobCompression::Zstd => decompress_zstd(data),
+    }
+}
+
+#[cfg(feature = "zstd")]
+fn decompress_zstd(data: &[u8]) -> Result<Vec<u8>, &'static str> {
+    let size = zstd::zstd_safe::get_frame_content_size(data)
+        .map_err(|_| "unable to read zstd frame header")?
+        .ok_or("zstd frame does not define content size")?;
+
+    let decoder = zstd::stream::read::Decoder::with_buffer(data)
+        .map_err(|_| "failed decompressing blob data")?;
+
+    // Reading one byte past the declared size lets us detect frames
+    // yielding more data than they declare.
+    let mut res = Vec::with_capacity(size.min(MAX_INITIAL_CAPACITY) as usize);
+    decoder
+        .take(size.saturating_add(1))
+        .read_to_end(&mut res)
+        .map_err(|_| "failed decompressing blob data")?;
+
+    if res.len() as u64 != size {
+        return Err("decompressed blob data does not match declared size");
+    }
+
+    Ok(res)
+}
+
+#[cfg(not(feature = "zstd"))]
+fn decompress_zstd(_data: &[u8]) -> Result<Vec<u8>, &'static str> {
+    Err(ZSTD_NOT_ENABLED)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(not(feature = "zstd"))]
+    fn test_zstd_not_enabled() {
+        assert!(compress_blob(BlobCompression::Zstd, b"foo").is_err());
+        assert_eq!(
+            decompress_blob(BlobCompression::Zstd, b"foo"),
+            Err(ZSTD_NOT_ENABLED)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "zstd")]
+    fn test_zstd_roundtrip() {
+        let data = b"foo bar baz ".repeat(64);
+
+        let compressed = compress_blob(BlobCompression::Zstd, &data).unwrap();
+        assert!(compressed.len() < data.len());
+        assert_eq!(
+            decompress_blob(BlobCompression::Zstd, &compressed).unwrap(),
+            data
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "zstd")]
+    fn test_zstd_empty() {
+        let compressed = compress_blob(BlobCompression::Zstd, b"").unwrap();
+        assert_eq!(
+            decompress_blob(BlobCompression::Zstd, &compressed).unwrap(),
+            b""
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "zstd")]
+    fn test_zstd_oversized_declared_size() {
+        // A single segment frame declaring 1 TiB of content but holding a
+        // single raw block of 3 bytes.
+        let mut data = vec![0x28, 0xb5, 0x2f, 0xfd, 0xe0];
+        data.extend_from_slice(&(1u64 << 40).to_le_bytes());
+        data.extend_from_slice(&[0x19, 0x00, 0x00]);
+        data.extend_from_slice(b"foo");
+
+        assert_eq!(
+            zstd::zstd_safe::get_frame_content_size(&data).unwrap(),
+            Some(1 << 40)
+        );
+        assert!(decompress_blob(BlobCompression::Zstd, &data).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "zstd")]
+    fn test_zstd_invalid() {
+        assert_eq!(
+            decompress_blob(BlobCompression::Zstd, b"foo"),
+            Err("unable to read zstd frame header")
+        );
+    }
+}
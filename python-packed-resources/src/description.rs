@@ -0,0 +1,563 @@
+// Copyright 2022 Gregory Szorc.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/*! Serde compatible descriptions of packed resources.
+
+[ResourceDescription] mirrors [Resource] using types that serialize
+naturally to formats like JSON and YAML. Data fields are represented by
+[ResourceData], which holds either base64 encoded data or a reference to a
+file holding the data.
+
+[PackedResourcesDescription] describes a whole packed resources blob and can
+be converted to and from serialized packed resources data.
+*/
+
+use {
+    crate::{
+        parser::load_resources,
+        resource::Resource,
+        serialization::{HEADER_V3, HEADER_V4},
+        writer::{DataMap, PackedResourcesStreamWriter, WriterOptions},
+    },
+    anyhow::{anyhow, Context, Result},
+    base64::{engine::general_purpose::STANDARD, Engine},
+    serde::{Deserialize, Deserializer, Serialize, Serializer},
+    std::{
+        borrow::Cow,
+        collections::{BTreeMap, HashMap},
+        io::Write,
+        path::{Path, PathBuf},
+    },
+};
+
+/// Data for a resource field.
+///
+/// Serializes as a map with a single `base64` or `path` key.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(untagged, deny_unknown_fields)]
+pub enum ResourceData {
+    /// Base64 encoded data.
+    Base64 { base64: String },
+
+    /// Path to a file holding the data.
+    ///
+    /// Relative paths are resolved against the base directory passed to
+    /// [ResourceDescription::to_resource()].
+    Path { path: PathBuf },
+}
+
+impl ResourceData {
+    /// Construct an instance holding data inline.
+    pub fn from_data(data: &[u8]) -> Self {
+        Self::Base64 {
+            base64: STANDARD.encode(data),
+        }
+    }
+
+    /// Resolve the data.
+    ///
+    /// File references are only allowed if `base_dir` is defined.
+    pub fn resolve(&self, base_dir: Option<&Path>) -> Result<Vec<u8>> {
+        match self {
+            Self::Base64 { base64 } => STANDARD
+                .decode(base64)
+                .map_err(|e| anyhow!("invalid base64 data: {}", e)),
+            Self::Path { path } => {
+                let base_dir = base_dir.ok_or_else(|| {
+                    anyhow!(
+                        "cannot resolve file reference {} without a base directory",
+                        path.display()
+                    )
+                })?;
+                let path = base_dir.join(path);
+
+                std::fs::read(&path).with_context(|| format!("reading {}", path.display()))
+            }
+        }
+    }
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+/// A serde compatible description of a [Resource].
+///
+/// Fields have the same meaning as the identically named fields on
+/// [Resource]. Fields that are unset or false are omitted when serializing.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ResourceDescription {
+    pub name: String,
+    #[serde(skip_serializing_if = "is_false")]
+    pub is_python_module: bool,
+    #[serde(skip_serializing_if = "is_false")]
+    pub is_python_builtin_extension_module: bool,
+    #[serde(skip_serializing_if = "is_false")]
+    pub is_python_frozen_module: bool,
+    #[serde(skip_serializing_if = "is_false")]
+    pub is_python_extension_module: bool,
+    #[serde(skip_serializing_if = "is_false")]
+    pub is_shared_library: bool,
+    #[serde(skip_serializing_if = "is_false")]
+    pub is_utf8_filename_data: bool,
+    #[serde(skip_serializing_if = "is_false")]
+    pub is_python_package: bool,
+    #[serde(skip_serializing_if = "is_false")]
+    pub is_python_namespace_package: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub in_memory_source: Option<ResourceData>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub in_memory_bytecode: Option<ResourceData>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub in_memory_bytecode_opt1: Option<ResourceData>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub in_memory_bytecode_opt2: Option<ResourceData>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub in_memory_extension_module_shared_library: Option<ResourceData>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub in_memory_package_resources: Option<BTreeMap<String, ResourceData>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub in_memory_distribution_resources: Option<BTreeMap<String, ResourceData>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub in_memory_shared_library: Option<ResourceData>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shared_library_dependency_names: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub relative_path_module_source: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub relative_path_module_bytecode: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub relative_path_module_bytecode_opt1: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub relative_path_module_bytecode_opt2: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub relative_path_extension_module_shared_library: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub relative_path_package_resources: Option<BTreeMap<String, PathBuf>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub relative_path_distribution_resources: Option<BTreeMap<String, PathBuf>>,
+    #[serde(skip_serializing_if = "is_false")]
+    pub file_executable: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_data_embedded: Option<ResourceData>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_data_utf8_relative_path: Option<String>,
+    #[serde(skip_serializing_if = "is_false")]
+    pub extract_to_cache: bool,
+}
+
+impl<'a> From<&Resource<'a, u8>> for ResourceDescription {
+    fn from(resource: &Resource<'a, u8>) -> Self {
+        let data =
+            |value: &Option<Cow<'a, [u8]>>| value.as_ref().map(|x| ResourceData::from_data(x));
+        let data_map = |value: &Option<DataMap<'a>>| {
+            value.as_ref().map(|x| {
+                x.iter()
+                    .map(|(k, v)| (k.to_string(), ResourceData::from_data(v)))
+                    .collect::<BTreeMap<_, _>>()
+            })
+        };
+        let path = |value: &Option<Cow<'a, Path>>| value.as_ref().map(|x| x.to_path_buf());
+        let path_map = |value: &Option<HashMap<Cow<'a, str>, Cow<'a, Path>>>| {
+            value.as_ref().map(|x| {
+                x.iter()
+                    .map(|(k, v)| (k.to_string(), v.to_path_buf()))
+                    .collect::<BTreeMap<_, _>>()
+            })
+        };
+
+        Self {
+            name: resource.name.to_string(),
+            is_python_module: resource.is_python_module,
+            is_python_builtin_extension_module: resource.is_python_builtin_extension_module,
+            is_python_frozen_module: resource.is_python_frozen_module,
+            is_python_extension_module: resource.is_python_extension_module,
+            is_shared_library: resource.is_shared_library,
+            is_utf8_filename_data: resource.is_utf8_filename_data,
+            is_python_package: resource.is_python_package,
+            is_python_namespace_package: resource.is_python_namespace_package,
+            in_memory_source: data(&resource.in_memory_source),
+            in_memory_bytecode: data(&resource.in_memory_bytecode),
+            in_memory_bytecode_opt1: data(&resource.in_memory_bytecode_opt1),
+            in_memory_bytecode_opt2: data(&resource.in_memory_bytecode_opt2),
+            in_memory_extension_module_shared_library: data(
+                &resource.in_memory_extension_module_shared_library,
+            ),
+            in_memory_package_resources: data_map(&resource.in_memory_package_resources),
+            in_memory_distribution_resources: data_map(&resource.in_memory_distribution_resources),
+            in_memory_shared_library: data(&resource.in_memory_shared_library),
+            shared_library_dependency_names: resource
+                .shared_library_dependency_names
+                .as_ref()
+                .map(|x| x.iter().map(|x| x.to_string()).collect::<Vec<_>>()),
+            relative_path_module_source: path(&resource.relative_path_module_source),
+            relative_path_module_bytecode: path(&resource.relative_path_module_bytecode),
+            relative_path_module_bytecode_opt1: path(&resource.relative_path_module_bytecode_opt1),
+            relative_path_module_bytecode_opt2: path(&resource.relative_path_module_bytecode_opt2),
+            relative_path_extension_module_shared_library: path(
+                &resource.relative_path_extension_module_shared_library,
+            ),
+            relative_path_package_resources: path_map(&resource.relative_path_package_resources),
+            relative_path_distribution_resources: path_map(
+                &resource.relative_path_distribution_resources,
+            ),
+            file_executable: resource.file_executable,
+            file_data_embedded: data(&resource.file_data_embedded),
+            file_data_utf8_relative_path: resource
+                .file_data_utf8_relative_path
+                .as_ref()
+                .map(|x| x.to_string()),
+            extract_to_cache: resource.extract_to_cache,
+        }
+    }
+}
+
+impl ResourceDescription {
+    /// Convert to a [Resource].
+    ///
+    /// Data fields referencing files are read relative to `base_dir`. If
+    /// `base_dir` is not defined, file references are an error.
+    pub fn to_resource(&self, base_dir: Option<&Path>) -> Result<Resource<'static, u8>> {
+        let data =
+            |field: &str, value: &Option<ResourceData>| -> Result<Option<Cow<'static, [u8]>>> {
+                value
+                    .as_ref()
+                    .map(|x| {
+                        x.resolve(base_dir)
+                            .map(Cow::Owned)
+                            .with_context(|| format!("resolving {} of {}", field, self.name))
+                    })
+                    .transpose()
+            };
+        let data_map = |field: &str,
+                        value: &Option<BTreeMap<String, ResourceData>>|
+         -> Result<Option<DataMap<'static>>> {
+            value
+                .as_ref()
+                .map(|x| {
+                    x.iter()
+                        .map(|(k, v)| {
+                            Ok((
+                                Cow::Owned(k.clone()),
+                                Cow::Owned(v.resolve(base_dir).with_context(|| {
+                                    format!("resolving {} {} of {}", field, k, self.name)
+                                })?),
+                            ))
+                        })
+                        .collect::<Result<HashMap<_, _>>>()
+                })
+                .transpose()
+        };
+        let path = |value: &Option<PathBuf>| value.as_ref().map(|x| Cow::Owned(x.clone()));
+        let path_map = |value: &Option<BTreeMap<String, PathBuf>>| {
+            value.as_ref().map(|x| {
+                x.iter()
+                    .map(|(k, v)| (Cow::Owned(k.clone()), Cow::Owned(v.clone())))
+                    .collect::<HashMap<_, _>>()
+            })
+        };
+
+        Ok(Resource {
+            name: Cow::Owned(self.name.clone()),
+            is_python_module: self.is_python_module,
+            is_python_builtin_extension_module: self.is_python_builtin_extension_module,
+            is_python_frozen_module: self.is_python_frozen_module,
+            is_python_extension_module: self.is_python_extension_module,
+            is_shared_library: self.is_shared_library,
+            is_utf8_filename_data: self.is_utf8_filename_data,
+            is_python_package: self.is_python_package,
+            is_python_namespace_package: self.is_python_namespace_package,
+            in_memory_source: data("in_memory_source", &self.in_memory_source)?,
+            in_memory_bytecode: data("in_memory_bytecode", &self.in_memory_bytecode)?,
+            in_memory_bytecode_opt1: data(
+                "in_memory_bytecode_opt1",
+                &self.in_memory_bytecode_opt1,
+            )?,
+            in_memory_bytecode_opt2: data(
+                "in_memory_bytecode_opt2",
+                &self.in_memory_bytecode_opt2,
+            )?,
+            in_memory_extension_module_shared_library: data(
+                "in_memory_extension_module_shared_library",
+                &self.in_memory_extension_module_shared_library,
+            )?,
+            in_memory_package_resources: data_map(
+                "in_memory_package_resources",
+                &self.in_memory_package_resources,
+            )?,
+            in_memory_distribution_resources: data_map(
+                "in_memory_distribution_resources",
+                &self.in_memory_distribution_resources,
+            )?,
+            in_memory_shared_library: data(
+                "in_memory_shared_library",
+                &self.in_memory_shared_library,
+            )?,
+            shared_library_dependency_names: self
+                .shared_library_dependency_names
+                .as_ref()
+                .map(|x| x.iter().map(|x| Cow::Owned(x.clone())).collect::<Vec<_>>()),
+            relative_path_module_source: path(&self.relative_path_module_source),
+            relative_path_module_bytecode: path(&self.relative_path_module_bytecode),
+            relative_path_module_bytecode_opt1: path(&self.relative_path_module_bytecode_opt1),
+            relative_path_module_bytecode_opt2: path(&self.relative_path_module_bytecode_opt2),
+            relative_path_extension_module_shared_library: path(
+                &self.relative_path_extension_module_shared_library,
+            ),
+            relative_path_package_resources: path_map(&self.relative_path_package_resources),
+            relative_path_distribution_resources: path_map(
+                &self.relative_path_distribution_resources,
+            ),
+            file_executable: self.file_executable,
+            file_data_embedded: data("file_data_embedded", &self.file_data_embedded)?,
+            file_data_utf8_relative_path: self
+                .file_data_utf8_relative_path
+                .as_ref()
+                .map(|x| Cow::Owned(x.clone())),
+            extract_to_cache: self.extract_to_cache,
+        })
+    }
+}
+
+/// Resources serialize as a [ResourceDescription] with inline data.
+impl<'a> Serialize for Resource<'a, u8> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ResourceDescription::from(self).serialize(serializer)
+    }
+}
+
+/// Resources deserialize from a [ResourceDescription].
+///
+/// Since there is no base directory to resolve them against, data fields
+/// referencing files are rejected. Use [ResourceDescription::to_resource()]
+/// to resolve file references.
+impl<'de> Deserialize<'de> for Resource<'static, u8> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        ResourceDescription::deserialize(deserializer)?
+            .to_resource(None)
+            .map_err(|e| serde::de::Error::custom(format!("{:#}", e)))
+    }
+}
+
+fn default_format_version() -> u8 {
+    3
+}
+
+/// A serde compatible description of packed resources data.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct PackedResourcesDescription {
+    /// The packed resources format version. Either 3 or 4.
+    #[serde(default = "default_format_version")]
+    pub format_version: u8,
+
+    /// Resources, in the order they are written.
+    #[serde(default)]
+    pub resources: Vec<ResourceDescription>,
+}
+
+impl PackedResourcesDescription {
+    /// Describe serialized packed resources data.
+    ///
+    /// Data is always held inline.
+    pub fn from_packed_resources(data: &[u8]) -> Result<Self> {
+        let format_version = if data.starts_with(HEADER_V3) {
+            3
+        } else if data.starts_with(HEADER_V4) {
+            4
+        } else {
+            return Err(anyhow!("data does not have a packed resources header"));
+        };
+
+        let resources = load_resources(data)
+            .map_err(|e| anyhow!("error parsing packed resources: {}", e))?
+            .map(|resource| {
+                resource
+                    .map(|x| ResourceDescription::from(&x))
+                    .map_err(|e| anyhow!("error parsing packed resources: {}", e))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            format_version,
+            resources,
+        })
+    }
+
+    /// Write serialized packed resources data.
+    ///
+    /// Data fields referencing files are read relative to `base_dir`. See
+    /// [ResourceDescription::to_resource()].
+    pub fn write_packed_resources<W: Write>(
+        &self,
+        dest: &mut W,
+        base_dir: Option<&Path>,
+    ) -> Result<()> {
+        let mut writer = match self.format_version {
+            3 => PackedResourcesStreamWriter::new_v3(None)?,
+            4 => PackedResourcesStreamWriter::new_v4(&WriterOptions::default())?,
+            version => {
+                return Err(anyhow!(
+                    "unsupported packed resources format version: {}",
+                    version
+                ))
+            }
+        };
+
+        // Resources are resolved one at a time so only a single resource's
+        // data is held in memory.
+        for resource in &self.resources {
+            writer.add_resource(&resource.to_resource(base_dir)?)?;
+        }
+
+        writer.finish(dest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::writer::write_packed_resources_v4};
+
+    fn resources() -> Vec<Resource<'static, u8>> {
+        let mut package_resources = HashMap::new();
+        package_resources.insert(Cow::from("data.txt"), Cow::from(b"data".to_vec()));
+
+        vec![
+            Resource {
+                name: Cow::from("foo"),
+                is_python_module: true,
+                is_python_package: true,
+                in_memory_source: Some(Cow::from(b"import io".to_vec())),
+                in_memory_package_resources: Some(package_resources),
+                ..Resource::default()
+            },
+            Resource {
+                name: Cow::from("foo.bar"),
+                is_python_module: true,
+                relative_path_module_source: Some(Cow::from(Path::new("foo/bar.py"))),
+                shared_library_dependency_names: Some(vec![Cow::from("libfoo")]),
+                ..Resource::default()
+            },
+        ]
+    }
+
+    #[test]
+    fn test_description_roundtrip() -> Result<()> {
+        for resource in resources() {
+            let description = ResourceDescription::from(&resource);
+            assert_eq!(description.to_resource(None)?, resource);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_resource_json() -> Result<()> {
+        let resource = resources().remove(0);
+
+        let value = serde_json::to_value(&resource)?;
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "name": "foo",
+                "is_python_module": true,
+                "is_python_package": true,
+                "in_memory_source": {"base64": "aW1wb3J0IGlv"},
+                "in_memory_package_resources": {
+                    "data.txt": {"base64": "ZGF0YQ=="},
+                },
+            })
+        );
+
+        let parsed: Resource<u8> = serde_json::from_value(value)?;
+        assert_eq!(parsed, resource);
+
+        let err = serde_json::from_value::<Resource<u8>>(serde_json::json!({
+            "name": "foo",
+            "in_memory_source": {"path": "foo.py"},
+        }))
+        .unwrap_err();
+        assert!(err.to_string().contains("without a base directory"));
+
+        assert!(serde_json::from_value::<Resource<u8>>(serde_json::json!({
+            "name": "foo",
+            "unknown": true,
+        }))
+        .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_resource_data() -> Result<()> {
+        let data = ResourceData::from_data(b"foo");
+        assert_eq!(
+            data,
+            ResourceData::Base64 {
+                base64: "Zm9v".to_string()
+            }
+        );
+        assert_eq!(data.resolve(None)?, b"foo");
+
+        let temp_dir = tempfile::Builder::new()
+            .prefix("python-packed-resources-test")
+            .tempdir()?;
+        std::fs::write(temp_dir.path().join("foo.py"), b"import os")?;
+
+        let data = ResourceData::Path {
+            path: PathBuf::from("foo.py"),
+        };
+        assert!(data.resolve(None).is_err());
+        assert_eq!(data.resolve(Some(temp_dir.path()))?, b"import os");
+
+        assert!(ResourceData::Base64 {
+            base64: "!".to_string()
+        }
+        .resolve(None)
+        .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_packed_resources_roundtrip() -> Result<()> {
+        let resources = resources();
+
+        let mut data = Vec::new();
+        write_packed_resources_v4(&resources, &mut data, &WriterOptions::default())?;
+
+        let description = PackedResourcesDescription::from_packed_resources(&data)?;
+        assert_eq!(description.format_version, 4);
+        assert_eq!(description.resources.len(), 2);
+
+        let mut written = Vec::new();
+        description.write_packed_resources(&mut written, None)?;
+
+        let parsed = load_resources(&written)
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(parsed, resources);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unsupported_version() {
+        let description = PackedResourcesDescription {
+            format_version: 2,
+            resources: vec![],
+        };
+
+        assert!(description
+            .write_packed_resources(&mut Vec::new(), None)
+            .is_err());
+        assert!(PackedResourcesDescription::from_packed_resources(b"foo").is_err());
+    }
+}
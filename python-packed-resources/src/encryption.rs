@@ -0,0 +1,204 @@
+// Copyright 2022 Gregory Szorc.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/*! Encryption of blob section entries.
+
+AES-256-GCM support requires the `encryption` feature.
+*/
+
+use crate::serialization::BlobEncryption;
+
+#[cfg(feature = "encryption")]
+use {
+    aes_gcm::{
+        aead::{Aead, KeyInit},
+        Aes256Gcm, Key, Nonce,
+    },
+    sha2::{Digest, Sha256},
+};
+
+/// Length in bytes of the AES-GCM nonce prefixing each encrypted entry.
+#[cfg(feature = "encryption")]
+const NONCE_LENGTH: usize = 12;
+
+/// Length in bytes of the AES-GCM authentication tag ending each encrypted entry.
+#[cfg(feature = "encryption")]
+const TAG_LENGTH: usize = 16;
+
+#[cfg(not(feature = "encryption"))]
+const ENCRYPTION_NOT_ENABLED: &str =
+    "AES-256-GCM support not enabled; enable the encryption feature";
+
+#[cfg(feature = "encryption")]
+fn encrypt_aes256gcm(key: &[u8; 32], data: &[u8]) -> Result<Vec<u8>, &'static str> {
+    let mut hasher = Sha256::new();
+    hasher.update(key);
+    hasher.update(data);
+    let digest = hasher.finalize();
+    let nonce = Nonce::from_slice(&digest[0..NONCE_LENGTH]);
+
+    let ciphertext = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key))
+        .encrypt(nonce, data)
+        .map_err(|_| "failed encrypting blob data")?;
+
+    let mut res = Vec::with_capacity(NONCE_LENGTH + ciphertext.len());
+    res.extend_from_slice(nonce);
+    res.extend_from_slice(&ciphertext);
+
+    Ok(res)
+}
+
+#[cfg(not(feature = "encryption"))]
+fn encrypt_aes256gcm(_key: &[u8; 32], _data: &[u8]) -> Result<Vec<u8>, &'static str> {
+    Err(ENCRYPTION_NOT_ENABLED)
+}
+
+#[cfg(feature = "encryption")]
+fn decrypt_aes256gcm(key: &[u8; 32], data: &[u8]) -> Result<Vec<u8>, &'static str> {
+    if data.len() < NONCE_LENGTH + TAG_LENGTH {
+        return Err("encrypted blob data too short");
+    }
+
+    let (nonce, ciphertext) = data.split_at(NONCE_LENGTH);
+
+    Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key))
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| "failed decrypting blob data")
+}
+
+#[cfg(not(feature = "encryption"))]
+fn decrypt_aes256gcm(_key: &[u8; 32], _data: &[u8]) -> Result<Vec<u8>, &'static str> {
+    Err(ENCRYPTION_NOT_ENABLED)
+}
+
+/// Encrypt a single blob entry.
+///
+/// The nonce is derived from a SHA-256 digest of the key and the entry data.
+/// So output is reproducible and identical entries encrypt to identical
+/// ciphertext, allowing encrypted blob sections to be deduplicated.
+pub(crate) fn encrypt_blob(
+    encryption: BlobEncryption,
+    key: &[u8; 32],
+    data: &[u8],
+) -> Result<Vec<u8>, &'static str> {
+    match encryption {
+        BlobEncryption::None => Ok(data.to_vec()),
+        BlobEncryption::Aes256Gcm => encrypt_aes256gcm(key, data),
+    }
+}
+
+/// Decrypt a single blob entry.
+///
+/// `data` must be the full data for an entry in a blob section having
+/// encryption `encryption`. An error is returned if the data was not
+/// encrypted with `key` or was modified. AES-256-GCM requires the
+/// `encryption` feature.
+pub fn decrypt_blob(
+    encryption: BlobEncryption,
+    key: &[u8; 32],
+    data: &[u8],
+) -> Result<Vec<u8>, &'static str> {
+    match encryption {
+        BlobEncryption::None => Ok(data.to_vec()),
+        BlobEncryption::Aes256Gcm => decrypt_aes256gcm(key, data),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: [u8; 32] = [0x42; 32];
+
+    #[test]
+    fn test_none() {
+        assert_eq!(
+            encrypt_blob(BlobEncryption::None, &KEY, b"foo").unwrap(),
+            b"foo"
+        );
+        assert_eq!(
+            decrypt_blob(BlobEncryption::None, &KEY, b"foo").unwrap(),
+            b"foo"
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "encryption"))]
+    fn test_encryption_not_enabled() {
+        assert_eq!(
+            encrypt_blob(BlobEncryption::Aes256Gcm, &KEY, b"foo"),
+            Err(ENCRYPTION_NOT_ENABLED)
+        );
+        assert_eq!(
+            decrypt_blob(BlobEncryption::Aes256Gcm, &KEY, b"foo"),
+            Err(ENCRYPTION_NOT_ENABLED)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "encryption")]
+    fn test_aes256gcm_roundtrip() {
+        let data = b"foo bar baz ".repeat(64);
+
+        let encrypted = encrypt_blob(BlobEncryption::Aes256Gcm, &KEY, &data).unwrap();
+        assert_eq!(encrypted.len(), data.len() + NONCE_LENGTH + TAG_LENGTH);
+        assert_ne!(&encrypted[NONCE_LENGTH..NONCE_LENGTH + data.len()], &data);
+        assert_eq!(
+            decrypt_blob(BlobEncryption::Aes256Gcm, &KEY, &encrypted).unwrap(),
+            data
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "encryption")]
+    fn test_aes256gcm_deterministic() {
+        assert_eq!(
+            encrypt_blob(BlobEncryption::Aes256Gcm, &KEY, b"foo").unwrap(),
+            encrypt_blob(BlobEncryption::Aes256Gcm, &KEY, b"foo").unwrap()
+        );
+        assert_ne!(
+            encrypt_blob(BlobEncryption::Aes256Gcm, &KEY, b"foo").unwrap(),
+            encrypt_blob(BlobEncryption::Aes256Gcm, &KEY, b"bar").unwrap()
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "encryption")]
+    fn test_aes256gcm_empty() {
+        let encrypted = encrypt_blob(BlobEncryption::Aes256Gcm, &KEY, b"").unwrap();
+        assert_eq!(
+            decrypt_blob(BlobEncryption::Aes256Gcm, &KEY, &encrypted).unwrap(),
+            b""
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "encryption")]
+    fn test_aes256gcm_wrong_key() {
+        let encrypted = encrypt_blob(BlobEncryption::Aes256Gcm, &KEY, b"foo").unwrap();
+        assert_eq!(
+            decrypt_blob(BlobEncryption::Aes256Gcm, &[0x43; 32], &encrypted),
+            Err("failed decrypting blob data")
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "encryption")]
+    fn test_aes256gcm_tampered() {
+        let mut encrypted = encrypt_blob(BlobEncryption::Aes256Gcm, &KEY, b"foo").unwrap();
+        encrypted[NONCE_LENGTH] ^= 0x01;
+        assert_eq!(
+            decrypt_blob(BlobEncryption::Aes256Gcm, &KEY, &encrypted),
+            Err("failed decrypting blob data")
+        );
+        assert_eq!(
+            decrypt_blob(BlobEncryption::Aes256Gcm, &KEY, b"foo"),
+            Err("encrypted blob data too short")
+        );
+    }
+}
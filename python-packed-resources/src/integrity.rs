@@ -0,0 +1,309 @@
+// Copyright 2022 Gregory Szorc.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/*! Integrity digests and signatures over packed resources data.
+
+Version 4 of the packed resources format allows an *integrity trailer* to
+follow the last blob section. The trailer holds a SHA-256 digest of all
+preceding bytes (header, indices, and blob sections) and optionally an
+ed25519 signature over that digest. Producing and checking signatures
+requires the `signing` feature.
+*/
+
+use {
+    crate::{parser::load_resources, serialization::HEADER_V4},
+    anyhow::{anyhow, Result},
+    sha2::{Digest, Sha256},
+};
+
+#[cfg(feature = "signing")]
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+
+#[cfg(not(feature = "signing"))]
+const SIGNING_NOT_ENABLED: &str = "ed25519 signing support not enabled; enable the signing feature";
+
+/// Length in bytes of the SHA-256 digest in the integrity trailer.
+const DIGEST_LENGTH: usize = 32;
+
+/// Length in bytes of an ed25519 signature.
+const SIGNATURE_LENGTH: usize = 64;
+
+/// Describes the digest algorithm in an integrity trailer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum IntegrityDigest {
+    Sha256 = 0x01,
+}
+
+impl TryFrom<u8> for IntegrityDigest {
+    type Error = &'static str;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x01 => Ok(IntegrityDigest::Sha256),
+            _ => Err("invalid integrity digest type"),
+        }
+    }
+}
+
+/// Describes the signature algorithm in an integrity trailer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum IntegritySignature {
+    None = 0x00,
+    Ed25519 = 0x01,
+}
+
+impl TryFrom<u8> for IntegritySignature {
+    type Error = &'static str;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x00 => Ok(IntegritySignature::None),
+            0x01 => Ok(IntegritySignature::Ed25519),
+            _ => Err("invalid integrity signature type"),
+        }
+    }
+}
+
+/// Obtain the ed25519 public key corresponding to a secret signing key.
+#[cfg(feature = "signing")]
+pub fn ed25519_public_key(signing_key: &[u8; 32]) -> [u8; 32] {
+    SigningKey::from_bytes(signing_key)
+        .verifying_key()
+        .to_bytes()
+}
+
+#[cfg(feature = "signing")]
+fn sign_ed25519(signing_key: &[u8; 32], digest: &[u8]) -> Result<[u8; SIGNATURE_LENGTH]> {
+    Ok(SigningKey::from_bytes(signing_key).sign(digest).to_bytes())
+}
+
+#[cfg(not(feature = "signing"))]
+fn sign_ed25519(_signing_key: &[u8; 32], _digest: &[u8]) -> Result<[u8; SIGNATURE_LENGTH]> {
+    Err(anyhow!(SIGNING_NOT_ENABLED))
+}
+
+#[cfg(feature = "signing")]
+fn verify_ed25519(
+    public_key: &[u8; 32],
+    digest: &[u8],
+    signature: &[u8; SIGNATURE_LENGTH],
+) -> Result<(), &'static str> {
+    let key = VerifyingKey::from_bytes(public_key).map_err(|_| "invalid ed25519 public key")?;
+
+    key.verify_strict(digest, &Signature::from_bytes(signature))
+        .map_err(|_| "packed resources signature verification failed")
+}
+
+#[cfg(not(feature = "signing"))]
+fn verify_ed25519(
+    _public_key: &[u8; 32],
+    _digest: &[u8],
+    _signature: &[u8; SIGNATURE_LENGTH],
+) -> Result<(), &'static str> {
+    Err(SIGNING_NOT_ENABLED)
+}
+
+/// Append an integrity trailer to serialized packed resources data.
+///
+/// `data` must hold version 4 packed resources data without an integrity
+/// trailer. A SHA-256 digest of `data` is appended. If `signing_key` is
+/// defined, it is treated as an ed25519 secret key and a signature over the
+/// digest is appended as well. Signing requires the `signing` feature.
+pub fn append_integrity_trailer(data: &mut Vec<u8>, signing_key: Option<&[u8; 32]>) -> Result<()> {
+    if !data.starts_with(HEADER_V4) {
+        return Err(anyhow!(
+            "integrity trailers require version 4 packed resources data"
+        ));
+    }
+
+    let resources = load_resources(data).map_err(|e| anyhow!("{}", e))?;
+    if resources.payload_length() != data.len() {
+        return Err(anyhow!(
+            "packed resources data has trailing data; does it already have an integrity trailer?"
+        ));
+    }
+
+    let digest: [u8; DIGEST_LENGTH] = Sha256::digest(&data).into();
+
+    data.push(IntegrityDigest::Sha256 as u8);
+    data.extend_from_slice(&digest);
+
+    if let Some(key) = signing_key {
+        let signature = sign_ed25519(key, &digest)?;
+
+        data.push(IntegritySignature::Ed25519 as u8);
+        data.extend_from_slice(&signature);
+    } else {
+        data.push(IntegritySignature::None as u8);
+    }
+
+    Ok(())
+}
+
+/// Verify an integrity trailer.
+///
+/// `payload` is the packed resources data covered by the trailer and `trailer`
+/// is all data following it.
+///
+/// If `public_key` is defined, the trailer must contain an ed25519 signature
+/// made by the corresponding secret key. Checking the signature requires the
+/// `signing` feature.
+pub(crate) fn verify_integrity_trailer(
+    payload: &[u8],
+    trailer: &[u8],
+    public_key: Option<&[u8; 32]>,
+) -> Result<(), &'static str> {
+    let (digest_type, trailer) = trailer
+        .split_first()
+        .ok_or("packed resources data does not have an integrity trailer")?;
+
+    match IntegrityDigest::try_from(*digest_type)? {
+        IntegrityDigest::Sha256 => {}
+    }
+
+    if trailer.len() < DIGEST_LENGTH {
+        return Err("failed reading integrity digest");
+    }
+    let (expected_digest, trailer) = trailer.split_at(DIGEST_LENGTH);
+
+    let digest: [u8; DIGEST_LENGTH] = Sha256::digest(payload).into();
+    if digest != expected_digest {
+        return Err("packed resources digest mismatch");
+    }
+
+    let (signature_type, trailer) = trailer
+        .split_first()
+        .ok_or("failed reading integrity signature type")?;
+
+    let signature = match IntegritySignature::try_from(*signature_type)? {
+        IntegritySignature::None => None,
+        IntegritySignature::Ed25519 => {
+            if trailer.len() < SIGNATURE_LENGTH {
+                return Err("failed reading integrity signature");
+            }
+
+            let (signature, remaining) = trailer.split_at(SIGNATURE_LENGTH);
+            if !remaining.is_empty() {
+                return Err("unexpected data after integrity trailer");
+            }
+
+            Some(
+                <&[u8; SIGNATURE_LENGTH]>::try_from(signature).expect("signature length validated"),
+            )
+        }
+    };
+
+    if signature.is_none() && !trailer.is_empty() {
+        return Err("unexpected data after integrity trailer");
+    }
+
+    if let Some(public_key) = public_key {
+        let signature = signature.ok_or("packed resources data is not signed")?;
+        verify_ed25519(public_key, &digest, signature)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            resource::Resource,
+            writer::{write_packed_resources_v4, WriterOptions},
+        },
+    };
+
+    #[cfg(feature = "signing")]
+    const SIGNING_KEY: [u8; 32] = [42; 32];
+
+    fn empty_v4() -> Vec<u8> {
+        let mut data = vec![];
+        write_packed_resources_v4::<Resource<u8>, _>(&[], &mut data, &WriterOptions::default())
+            .unwrap();
+        data
+    }
+
+    #[test]
+    fn test_append_requires_v4() {
+        let mut data = b"pyembed\x03".to_vec();
+        assert!(append_integrity_trailer(&mut data, None).is_err());
+    }
+
+    #[test]
+    fn test_append_twice() {
+        let mut data = empty_v4();
+        append_integrity_trailer(&mut data, None).unwrap();
+        assert!(append_integrity_trailer(&mut data, None).is_err());
+    }
+
+    #[test]
+    fn test_digest_only() {
+        let mut data = empty_v4();
+        let payload_length = data.len();
+        append_integrity_trailer(&mut data, None).unwrap();
+        assert_eq!(data.len(), payload_length + 1 + DIGEST_LENGTH + 1);
+
+        let (payload, trailer) = data.split_at(payload_length);
+        verify_integrity_trailer(payload, trailer, None).unwrap();
+        assert_eq!(
+            verify_integrity_trailer(payload, trailer, Some(&[1; 32])),
+            Err("packed resources data is not signed")
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "signing")]
+    fn test_signed() {
+        let mut data = empty_v4();
+        let payload_length = data.len();
+        append_integrity_trailer(&mut data, Some(&SIGNING_KEY)).unwrap();
+
+        let (payload, trailer) = data.split_at(payload_length);
+        verify_integrity_trailer(payload, trailer, None).unwrap();
+        verify_integrity_trailer(payload, trailer, Some(&ed25519_public_key(&SIGNING_KEY)))
+            .unwrap();
+
+        assert_eq!(
+            verify_integrity_trailer(payload, trailer, Some(&ed25519_public_key(&[1; 32]))),
+            Err("packed resources signature verification failed")
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "signing"))]
+    fn test_signing_not_enabled() {
+        let mut data = empty_v4();
+        assert!(append_integrity_trailer(&mut data, Some(&[42; 32])).is_err());
+    }
+
+    #[test]
+    fn test_missing_trailer() {
+        let data = empty_v4();
+        assert_eq!(
+            verify_integrity_trailer(&data, &[], None),
+            Err("packed resources data does not have an integrity trailer")
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "signing")]
+    fn test_truncated_trailer() {
+        let mut data = empty_v4();
+        let payload_length = data.len();
+        append_integrity_trailer(&mut data, Some(&SIGNING_KEY)).unwrap();
+        data.pop();
+
+        let (payload, trailer) = data.split_at(payload_length);
+        assert_eq!(
+            verify_integrity_trailer(payload, trailer, None),
+            Err("failed reading integrity signature")
+        );
+    }
+}
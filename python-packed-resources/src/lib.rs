@@ -29,14 +29,33 @@ it can be used outside the PyOxidizer project. See the aforementioned docs
 for the canonical specification of this format.
 */
 
+mod compression;
+#[cfg(feature = "serde")]
+mod description;
+mod encryption;
+mod integrity;
 mod parser;
 mod resource;
 mod serialization;
 mod writer;
 
+#[cfg(feature = "signing")]
+pub use crate::integrity::ed25519_public_key;
+
+#[cfg(feature = "stream-writer")]
+pub use crate::writer::PackedResourcesStreamWriter;
+
+#[cfg(feature = "serde")]
+pub use crate::description::{PackedResourcesDescription, ResourceData, ResourceDescription};
+
 pub use crate::{
-    parser::{load_resources, ResourceParserIterator},
+    compression::decompress_blob,
+    encryption::decrypt_blob,
+    integrity::append_integrity_trailer,
+    parser::{load_resources, load_resources_verified, ResourceParserIterator},
     resource::Resource,
-    serialization::HEADER_V3,
-    writer::write_packed_resources_v3,
+    serialization::{
+        BlobCompression, BlobEncryption, BlobInteriorPadding, ResourceField, HEADER_V3, HEADER_V4,
+    },
+    writer::{write_packed_resources_v3, write_packed_resources_v4, WriterOptions},
 };
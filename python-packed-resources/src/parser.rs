@@ -10,10 +10,16 @@
 
 use {
     crate::{
+        compression::decompress_blob,
+        encryption::decrypt_blob,
+        integrity::verify_integrity_trailer,
         resource::Resource,
-        serialization::{BlobInteriorPadding, BlobSectionField, ResourceField, HEADER_V3},
+        serialization::{
+            BlobCompression, BlobEncryption, BlobInteriorPadding, BlobSectionField, ResourceField,
+            HEADER_V3, HEADER_V4, NAME_INDEX_BLOB_SECTION,
+        },
     },
-    byteorder::{LittleEndian, ReadBytesExt},
+    byteorder::{ByteOrder, LittleEndian, ReadBytesExt},
     std::{borrow::Cow, collections::HashMap, io::Cursor, path::Path},
 };
 
@@ -28,26 +34,92 @@ struct BlobSection {
     resource_field: u8,
     raw_payload_length: usize,
     interior_padding: Option<BlobInteriorPadding>,
+    compression: Option<BlobCompression>,
+    encryption: Option<BlobEncryption>,
+    deduplicated: bool,
 }
 
 /// Holds state used to read an individual blob section.
 #[derive(Clone, Copy, Debug)]
 struct BlobSectionReadState {
-    offset: usize,
+    start: usize,
+    /// Index of this section's current offset in [ParseCursor::blob_offsets].
+    slot: usize,
     interior_padding: BlobInteriorPadding,
+    compression: BlobCompression,
+    encryption: BlobEncryption,
+    /// Start of the data area of a deduplicated blob section.
+    ///
+    /// For deduplicated sections, the section's offset walks the table of
+    /// entry references instead of the entries themselves.
+    deduplicated_data_start: Option<usize>,
+}
+
+/// Read position within packed resources data.
+///
+/// Holds the position within the resources index and the offset of the next
+/// entry in each blob section. This is kept apart from the otherwise immutable
+/// state in [ResourceParserIterator] so an individual resource can be parsed
+/// from a freshly positioned cursor.
+#[derive(Clone, Debug, Default)]
+struct ParseCursor<'a> {
+    reader: Cursor<&'a [u8]>,
+    /// Current offsets in blob sections, indexed by [BlobSectionReadState::slot].
+    blob_offsets: Vec<usize>,
+    /// Number of resource entries started.
+    read_resources_count: usize,
+}
+
+/// Describes the layout of the name index blob section.
+///
+/// The section consists of a `u8` count of resource fields, the `u8` values
+/// of those fields, then a record for each resource sorted by resource name.
+/// Each record holds the `u32` offset of the resource's entry in the resources
+/// index, the `u16` length of its name, and a `u64` offset for each listed
+/// resource field denoting where the resource's data begins in that field's
+/// blob section.
+#[derive(Clone, Copy, Debug)]
+struct NameIndex {
+    fields_offset: usize,
+    field_count: usize,
+    name_position: usize,
+    records_offset: usize,
+    record_count: usize,
+}
+
+impl NameIndex {
+    fn record_length(&self) -> usize {
+        4 + 2 + 8 * self.field_count
+    }
 }
 
 /// An iterator over an actively parsed packed resources data structure.
 ///
 /// The iterator emits [Resource] instances. The index data for a given resource is
 /// not read or validated until the iterator attempts to deserialize it.
+///
+/// By default, data in compressed blob sections is decompressed as resources are
+/// emitted and the corresponding fields hold owned data. Decompression can be
+/// deferred to the consumer via [ResourceParserIterator::set_decompress()].
+///
+/// Data in encrypted blob sections is always decrypted as resources are emitted.
+/// This requires a key to be registered via
+/// [ResourceParserIterator::set_decryption_key()].
+///
+/// If the data contains a name index, individual resources can be found without
+/// iterating via [ResourceParserIterator::find_resource()].
+#[derive(Clone, Debug)]
 pub struct ResourceParserIterator<'a> {
     done: bool,
+    decompress: bool,
+    decryption_key: Option<[u8; 32]>,
     data: &'a [u8],
-    reader: Cursor<&'a [u8]>,
+    cursor: ParseCursor<'a>,
     blob_sections: [Option<BlobSectionReadState>; 256],
     claimed_resources_count: usize,
-    read_resources_count: usize,
+    payload_length: usize,
+    resources_index_offset: usize,
+    name_index: Option<NameIndex>,
 }
 
 impl<'a> ResourceParserIterator<'a> {
@@ -56,37 +128,265 @@ impl<'a> ResourceParserIterator<'a> {
         self.claimed_resources_count
     }
 
+    /// The length in bytes of the packed resources data, including its header.
+    ///
+    /// Any data beyond this length is not part of the indices or blob sections.
+    /// e.g. it may be an integrity trailer.
+    pub fn payload_length(&self) -> usize {
+        self.payload_length
+    }
+
+    /// Set whether data in compressed blob sections should be decompressed.
+    ///
+    /// If false, fields backed by compressed blob sections will reference the
+    /// raw, compressed data and it is up to the caller to decompress it. See
+    /// [ResourceParserIterator::field_compression()] for resolving which fields
+    /// are compressed.
+    pub fn set_decompress(&mut self, value: bool) {
+        self.decompress = value;
+    }
+
+    /// Obtain the compression applied to data for a given resource field.
+    pub fn field_compression(&self, field: ResourceField) -> BlobCompression {
+        match &self.blob_sections[field as usize] {
+            Some(state) => state.compression,
+            None => BlobCompression::None,
+        }
+    }
+
+    /// Set the key used to decrypt data in encrypted blob sections.
+    ///
+    /// Emitting a resource having data in an encrypted blob section is an
+    /// error if no key is set.
+    pub fn set_decryption_key(&mut self, key: Option<[u8; 32]>) {
+        self.decryption_key = key;
+    }
+
+    /// Obtain the encryption applied to data for a given resource field.
+    pub fn field_encryption(&self, field: ResourceField) -> BlobEncryption {
+        match &self.blob_sections[field as usize] {
+            Some(state) => state.encryption,
+            None => BlobEncryption::None,
+        }
+    }
+
+    /// Whether any blob section in the data is encrypted.
+    pub fn is_encrypted(&self) -> bool {
+        self.blob_sections
+            .iter()
+            .flatten()
+            .any(|state| state.encryption != BlobEncryption::None)
+    }
+
+    /// Whether the data contains a name index.
+    pub fn has_name_index(&self) -> bool {
+        self.name_index.is_some()
+    }
+
+    /// Find a single resource by name using the name index.
+    ///
+    /// The name index is binary searched and only the index entry of the
+    /// matching resource is parsed. So this is `O(log n)` and doesn't require
+    /// parsing the entire resources index.
+    ///
+    /// This does not change the state of the iterator. An error is returned if
+    /// the data doesn't have a name index.
+    pub fn find_resource(&self, name: &str) -> Result<Option<Resource<'a, u8>>, &'static str> {
+        let index = self
+            .name_index
+            .as_ref()
+            .ok_or("packed resources data does not have a name index")?;
+
+        let names_start = match &self.blob_sections[ResourceField::Name as usize] {
+            Some(state) => state.start,
+            None => return Ok(None),
+        };
+
+        let mut low = 0;
+        let mut high = index.record_count;
+
+        while low < high {
+            let middle = low + (high - low) / 2;
+            let record = index.records_offset + middle * index.record_length();
+            let candidate = self.name_index_record_name(index, names_start, record)?;
+
+            match candidate.cmp(name.as_bytes()) {
+                std::cmp::Ordering::Less => low = middle + 1,
+                std::cmp::Ordering::Greater => high = middle,
+                std::cmp::Ordering::Equal => {
+                    return self.parse_indexed_resource(index, record, name)
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Obtain the names of all resources using the name index.
+    ///
+    /// Names are returned in sorted order without parsing the resources index.
+    /// An error is returned if the data doesn't have a name index.
+    pub fn names(&self) -> Result<Vec<&'a str>, &'static str> {
+        let index = self
+            .name_index
+            .as_ref()
+            .ok_or("packed resources data does not have a name index")?;
+
+        let names_start = match &self.blob_sections[ResourceField::Name as usize] {
+            Some(state) => state.start,
+            None => return Ok(vec![]),
+        };
+
+        (0..index.record_count)
+            .map(|i| {
+                let record = index.records_offset + i * index.record_length();
+                let name = self.name_index_record_name(index, names_start, record)?;
+
+                std::str::from_utf8(name).map_err(|_| "name index entry name is not valid UTF-8")
+            })
+            .collect()
+    }
+
+    /// Resolve the name of the resource referenced by a name index record.
+    fn name_index_record_name(
+        &self,
+        index: &NameIndex,
+        names_start: usize,
+        record: usize,
+    ) -> Result<&'a [u8], &'static str> {
+        let data: &'a [u8] = self.data;
+
+        let name_length = LittleEndian::read_u16(&data[record + 4..record + 6]) as usize;
+        let name_offset = names_start
+            + LittleEndian::read_u64(&data[record + 6 + 8 * index.name_position..]) as usize;
+
+        data.get(name_offset..name_offset + name_length)
+            .ok_or("name index entry name out of bounds")
+    }
+
+    /// Parse the resource referenced by a name index record.
+    fn parse_indexed_resource(
+        &self,
+        index: &NameIndex,
+        record: usize,
+        name: &str,
+    ) -> Result<Option<Resource<'a, u8>>, &'static str> {
+        let entry_offset = LittleEndian::read_u32(&self.data[record..record + 4]) as usize;
+
+        let mut reader = Cursor::new(self.data);
+        reader.set_position((self.resources_index_offset + entry_offset) as u64);
+
+        // The name index records where the resource's data begins in every
+        // blob section. So a fresh cursor is all that is needed to parse it.
+        let mut cursor = ParseCursor {
+            reader,
+            blob_offsets: vec![0; self.cursor.blob_offsets.len()],
+            read_resources_count: 0,
+        };
+
+        for i in 0..index.field_count {
+            let field = self.data[index.fields_offset + i];
+            let offset = LittleEndian::read_u64(&self.data[record + 6 + 8 * i..]) as usize;
+
+            if let Some(state) = &self.blob_sections[field as usize] {
+                cursor.blob_offsets[state.slot] = state.start + offset;
+            }
+        }
+
+        match self.parse_entry(&mut cursor)? {
+            Some(resource) if resource.name == name => Ok(Some(resource)),
+            _ => Err("name index entry does not match resource"),
+        }
+    }
+
     /// Resolve a slice to an individual blob's data.
     ///
     /// This accepts a reference to the original blobs payload, an array of
     /// current blob section offsets, the resource field being accessed, and the
     /// length of the blob and returns a slice to that blob.
-    fn resolve_blob_data(&mut self, resource_field: ResourceField, length: usize) -> &'a [u8] {
-        let mut state = self.blob_sections[resource_field as usize]
-            .as_mut()
+    fn resolve_blob_data(
+        &self,
+        cursor: &mut ParseCursor<'a>,
+        resource_field: ResourceField,
+        length: usize,
+    ) -> &'a [u8] {
+        let state = self.blob_sections[resource_field as usize]
+            .as_ref()
             .expect("blob state not found");
+        let offset = &mut cursor.blob_offsets[state.slot];
 
-        let blob = &self.data[state.offset..state.offset + length];
+        if let Some(data_start) = state.deduplicated_data_start {
+            let reference = LittleEndian::read_u64(&self.data[*offset..*offset + 8]);
+            let entry_offset = data_start + reference as usize;
+            *offset += 8;
+
+            return &self.data[entry_offset..entry_offset + length];
+        }
+
+        let blob = &self.data[*offset..*offset + length];
 
         let increment = match &state.interior_padding {
             BlobInteriorPadding::None => length,
             BlobInteriorPadding::Null => length + 1,
         };
 
-        state.offset += increment;
+        *offset += increment;
 
         blob
     }
 
+    /// Resolve the data for an entry in a blob section holding opaque data.
+    ///
+    /// Unlike [Self::resolve_blob_data()], this will decrypt data from
+    /// encrypted blob sections and decompress data from compressed blob
+    /// sections if decompression is enabled.
+    fn resolve_blob_payload(
+        &self,
+        cursor: &mut ParseCursor<'a>,
+        resource_field: ResourceField,
+        length: usize,
+    ) -> Result<Cow<'a, [u8]>, &'static str> {
+        let compression = self.field_compression(resource_field);
+        let encryption = self.field_encryption(resource_field);
+        let data = self.resolve_blob_data(cursor, resource_field, length);
+
+        let data = if encryption == BlobEncryption::None {
+            Cow::Borrowed(data)
+        } else {
+            let key = self
+                .decryption_key
+                .as_ref()
+                .ok_or("packed resources data is encrypted but no decryption key is set")?;
+
+            Cow::Owned(decrypt_blob(encryption, key, data)?)
+        };
+
+        if compression == BlobCompression::None || !self.decompress {
+            Ok(data)
+        } else {
+            Ok(Cow::Owned(decompress_blob(compression, &data)?))
+        }
+    }
+
     #[cfg(unix)]
-    fn resolve_path(&mut self, resource_field: ResourceField, length: usize) -> Cow<'a, Path> {
-        let path_str = OsStr::from_bytes(self.resolve_blob_data(resource_field, length));
+    fn resolve_path(
+        &self,
+        cursor: &mut ParseCursor<'a>,
+        resource_field: ResourceField,
+        length: usize,
+    ) -> Cow<'a, Path> {
+        let path_str = OsStr::from_bytes(self.resolve_blob_data(cursor, resource_field, length));
         Cow::Borrowed(Path::new(path_str))
     }
 
     #[cfg(windows)]
-    fn resolve_path(&mut self, resource_field: ResourceField, length: usize) -> Cow<'a, Path> {
-        let raw = self.resolve_blob_data(resource_field, length);
+    fn resolve_path(
+        &self,
+        cursor: &mut ParseCursor<'a>,
+        resource_field: ResourceField,
+        length: usize,
+    ) -> Cow<'a, Path> {
+        let raw = self.resolve_blob_data(cursor, resource_field, length);
         let raw = unsafe { std::slice::from_raw_parts(raw.as_ptr() as *const u16, raw.len() / 2) };
 
         // There isn't an API that lets us get a OsStr from &[u16]. So we need to use
@@ -96,12 +396,18 @@ impl<'a> ResourceParserIterator<'a> {
         Cow::Owned(PathBuf::from(path_string))
     }
 
-    fn parse_next(&mut self) -> Result<Option<Resource<'a, u8>>, &'static str> {
+    /// Parse the next resource entry at a cursor.
+    ///
+    /// Returns `None` when the end of the resources index is reached.
+    fn parse_entry(
+        &self,
+        cursor: &mut ParseCursor<'a>,
+    ) -> Result<Option<Resource<'a, u8>>, &'static str> {
         let mut current_resource = Resource::default();
         let mut current_resource_name = None;
 
         loop {
-            let field_type = self
+            let field_type = cursor
                 .reader
                 .read_u8()
                 .map_err(|_| "failed reading field type")?;
@@ -110,16 +416,10 @@ impl<'a> ResourceParserIterator<'a> {
 
             match field_type {
                 ResourceField::EndOfIndex => {
-                    self.done = true;
-
-                    if self.read_resources_count != self.claimed_resources_count {
-                        return Err("mismatch between advertised index count and actual");
-                    }
-
                     return Ok(None);
                 }
                 ResourceField::StartOfEntry => {
-                    self.read_resources_count += 1;
+                    cursor.read_resources_count += 1;
                     current_resource = Resource::default();
                     current_resource_name = None;
                 }
@@ -133,14 +433,14 @@ impl<'a> ResourceParserIterator<'a> {
                     return res;
                 }
                 ResourceField::Name => {
-                    let l = self
+                    let l = cursor
                         .reader
                         .read_u16::<LittleEndian>()
                         .map_err(|_| "failed reading resource name length")?
                         as usize;
 
                     let name = unsafe {
-                        std::str::from_utf8_unchecked(self.resolve_blob_data(field_type, l))
+                        std::str::from_utf8_unchecked(self.resolve_blob_data(cursor, field_type, l))
                     };
 
                     current_resource_name = Some(name);
@@ -153,58 +453,58 @@ impl<'a> ResourceParserIterator<'a> {
                     current_resource.is_python_namespace_package = true;
                 }
                 ResourceField::InMemorySource => {
-                    let l = self
+                    let l = cursor
                         .reader
                         .read_u32::<LittleEndian>()
                         .map_err(|_| "failed reading source length")?
                         as usize;
 
                     current_resource.in_memory_source =
-                        Some(Cow::Borrowed(self.resolve_blob_data(field_type, l)));
+                        Some(self.resolve_blob_payload(cursor, field_type, l)?);
                 }
                 ResourceField::InMemoryBytecode => {
-                    let l = self
+                    let l = cursor
                         .reader
                         .read_u32::<LittleEndian>()
                         .map_err(|_| "failed reading bytecode length")?
                         as usize;
 
                     current_resource.in_memory_bytecode =
-                        Some(Cow::Borrowed(self.resolve_blob_data(field_type, l)));
+                        Some(self.resolve_blob_payload(cursor, field_type, l)?);
                 }
                 ResourceField::InMemoryBytecodeOpt1 => {
-                    let l = self
+                    let l = cursor
                         .reader
                         .read_u32::<LittleEndian>()
                         .map_err(|_| "failed reading bytecode length")?
                         as usize;
 
                     current_resource.in_memory_bytecode_opt1 =
-                        Some(Cow::Borrowed(self.resolve_blob_data(field_type, l)));
+                        Some(self.resolve_blob_payload(cursor, field_type, l)?);
                 }
                 ResourceField::InMemoryBytecodeOpt2 => {
-                    let l = self
+                    let l = cursor
                         .reader
                         .read_u32::<LittleEndian>()
                         .map_err(|_| "failed reading bytecode length")?
                         as usize;
 
                     current_resource.in_memory_bytecode_opt2 =
-                        Some(Cow::Borrowed(self.resolve_blob_data(field_type, l)));
+                        Some(self.resolve_blob_payload(cursor, field_type, l)?);
                 }
                 ResourceField::InMemoryExtensionModuleSharedLibrary => {
-                    let l = self
+                    let l = cursor
                         .reader
                         .read_u32::<LittleEndian>()
                         .map_err(|_| "failed reading extension module length")?
                         as usize;
 
                     current_resource.in_memory_extension_module_shared_library =
-                        Some(Cow::Borrowed(self.resolve_blob_data(field_type, l)));
+                        Some(self.resolve_blob_payload(cursor, field_type, l)?);
                 }
 
                 ResourceField::InMemoryResourcesData => {
-                    let resource_count = self
+                    let resource_count = cursor
                         .reader
                         .read_u32::<LittleEndian>()
                         .map_err(|_| "failed reading resources length")?
@@ -213,35 +513,37 @@ impl<'a> ResourceParserIterator<'a> {
                     let mut resources = HashMap::with_capacity(resource_count);
 
                     for _ in 0..resource_count {
-                        let resource_name_length = self
+                        let resource_name_length = cursor
                             .reader
                             .read_u16::<LittleEndian>()
                             .map_err(|_| "failed reading resource name")?
                             as usize;
 
                         let resource_name = unsafe {
-                            std::str::from_utf8_unchecked(
-                                self.resolve_blob_data(field_type, resource_name_length),
-                            )
+                            std::str::from_utf8_unchecked(self.resolve_blob_data(
+                                cursor,
+                                field_type,
+                                resource_name_length,
+                            ))
                         };
 
-                        let resource_length = self
+                        let resource_length = cursor
                             .reader
                             .read_u64::<LittleEndian>()
                             .map_err(|_| "failed reading resource length")?
                             as usize;
 
-                        let resource_data = self.resolve_blob_data(field_type, resource_length);
+                        let resource_data =
+                            self.resolve_blob_payload(cursor, field_type, resource_length)?;
 
-                        resources
-                            .insert(Cow::Borrowed(resource_name), Cow::Borrowed(resource_data));
+                        resources.insert(Cow::Borrowed(resource_name), resource_data);
                     }
 
                     current_resource.in_memory_package_resources = Some(resources);
                 }
 
                 ResourceField::InMemoryDistributionResource => {
-                    let resource_count = self
+                    let resource_count = cursor
                         .reader
                         .read_u32::<LittleEndian>()
                         .map_err(|_| "failed reading package distribution length")?
@@ -250,44 +552,47 @@ impl<'a> ResourceParserIterator<'a> {
                     let mut resources = HashMap::with_capacity(resource_count);
 
                     for _ in 0..resource_count {
-                        let name_length = self
+                        let name_length = cursor
                             .reader
                             .read_u16::<LittleEndian>()
                             .map_err(|_| "failed reading distribution metadata name")?
                             as usize;
 
                         let name = unsafe {
-                            std::str::from_utf8_unchecked(
-                                self.resolve_blob_data(field_type, name_length),
-                            )
+                            std::str::from_utf8_unchecked(self.resolve_blob_data(
+                                cursor,
+                                field_type,
+                                name_length,
+                            ))
                         };
 
                         let resource_length =
-                            self.reader.read_u64::<LittleEndian>().map_err(|_| {
+                            cursor.reader.read_u64::<LittleEndian>().map_err(|_| {
                                 "failed reading package distribution resource length"
                             })? as usize;
 
-                        let resource_data = self.resolve_blob_data(field_type, resource_length);
+                        let resource_data =
+                            self.resolve_blob_payload(cursor, field_type, resource_length)?;
 
-                        resources.insert(Cow::Borrowed(name), Cow::Borrowed(resource_data));
+                        resources.insert(Cow::Borrowed(name), resource_data);
                     }
 
                     current_resource.in_memory_distribution_resources = Some(resources);
                 }
 
                 ResourceField::InMemorySharedLibrary => {
-                    let l = self
+                    let l = cursor
                         .reader
                         .read_u64::<LittleEndian>()
                         .map_err(|_| "failed reading in-memory shared library length")?
                         as usize;
 
                     current_resource.in_memory_shared_library =
-                        Some(Cow::Borrowed(self.resolve_blob_data(field_type, l)));
+                        Some(self.resolve_blob_payload(cursor, field_type, l)?);
                 }
 
                 ResourceField::SharedLibraryDependencyNames => {
-                    let names_count = self
+                    let names_count = cursor
                         .reader
                         .read_u16::<LittleEndian>()
                         .map_err(|_| "failed reading shared library dependency names length")?
@@ -297,14 +602,16 @@ impl<'a> ResourceParserIterator<'a> {
 
                     for _ in 0..names_count {
                         let name_length =
-                            self.reader.read_u16::<LittleEndian>().map_err(|_| {
+                            cursor.reader.read_u16::<LittleEndian>().map_err(|_| {
                                 "failed reading shared library dependency name length"
                             })? as usize;
 
                         let name = unsafe {
-                            std::str::from_utf8_unchecked(
-                                self.resolve_blob_data(field_type, name_length),
-                            )
+                            std::str::from_utf8_unchecked(self.resolve_blob_data(
+                                cursor,
+                                field_type,
+                                name_length,
+                            ))
                         };
 
                         names.push(Cow::Borrowed(name));
@@ -314,86 +621,88 @@ impl<'a> ResourceParserIterator<'a> {
                 }
 
                 ResourceField::RelativeFilesystemModuleSource => {
-                    let path_length = self
+                    let path_length = cursor
                         .reader
                         .read_u32::<LittleEndian>()
                         .map_err(|_| "failed reading Python module relative path length")?
                         as usize;
 
-                    let path = self.resolve_path(field_type, path_length);
+                    let path = self.resolve_path(cursor, field_type, path_length);
 
                     current_resource.relative_path_module_source = Some(path);
                 }
 
                 ResourceField::RelativeFilesystemModuleBytecode => {
                     let path_length =
-                        self.reader.read_u32::<LittleEndian>().map_err(|_| {
+                        cursor.reader.read_u32::<LittleEndian>().map_err(|_| {
                             "failed reading Python module bytecode relative path length"
                         })? as usize;
 
-                    let path = self.resolve_path(field_type, path_length);
+                    let path = self.resolve_path(cursor, field_type, path_length);
 
                     current_resource.relative_path_module_bytecode = Some(path);
                 }
 
                 ResourceField::RelativeFilesystemModuleBytecodeOpt1 => {
-                    let path_length = self.reader.read_u32::<LittleEndian>().map_err(|_| {
+                    let path_length = cursor.reader.read_u32::<LittleEndian>().map_err(|_| {
                         "failed reading Python module bytecode opt 1 relative path length"
                     })? as usize;
 
-                    let path = self.resolve_path(field_type, path_length);
+                    let path = self.resolve_path(cursor, field_type, path_length);
 
                     current_resource.relative_path_module_bytecode_opt1 = Some(path);
                 }
 
                 ResourceField::RelativeFilesystemModuleBytecodeOpt2 => {
-                    let path_length = self.reader.read_u32::<LittleEndian>().map_err(|_| {
+                    let path_length = cursor.reader.read_u32::<LittleEndian>().map_err(|_| {
                         "failed reading Python module bytecode opt 2 relative path length"
                     })? as usize;
 
-                    let path = self.resolve_path(field_type, path_length);
+                    let path = self.resolve_path(cursor, field_type, path_length);
 
                     current_resource.relative_path_module_bytecode_opt2 = Some(path);
                 }
 
                 ResourceField::RelativeFilesystemExtensionModuleSharedLibrary => {
-                    let path_length = self.reader.read_u32::<LittleEndian>().map_err(|_| {
+                    let path_length = cursor.reader.read_u32::<LittleEndian>().map_err(|_| {
                         "failed reading Python extension module shared library relative path length"
                     })? as usize;
 
-                    let path = self.resolve_path(field_type, path_length);
+                    let path = self.resolve_path(cursor, field_type, path_length);
 
                     current_resource.relative_path_extension_module_shared_library = Some(path);
                 }
 
                 ResourceField::RelativeFilesystemPackageResources => {
                     let resource_count =
-                        self.reader.read_u32::<LittleEndian>().map_err(|_| {
+                        cursor.reader.read_u32::<LittleEndian>().map_err(|_| {
                             "failed reading package resources relative path item count"
                         })? as usize;
 
                     let mut resources = HashMap::with_capacity(resource_count);
 
                     for _ in 0..resource_count {
-                        let resource_name_length = self
+                        let resource_name_length = cursor
                             .reader
                             .read_u16::<LittleEndian>()
                             .map_err(|_| "failed reading resource name")?
                             as usize;
 
                         let resource_name = unsafe {
-                            std::str::from_utf8_unchecked(
-                                self.resolve_blob_data(field_type, resource_name_length),
-                            )
+                            std::str::from_utf8_unchecked(self.resolve_blob_data(
+                                cursor,
+                                field_type,
+                                resource_name_length,
+                            ))
                         };
 
-                        let path_length = self
+                        let path_length = cursor
                             .reader
                             .read_u32::<LittleEndian>()
                             .map_err(|_| "failed reading resource path length")?
                             as usize;
 
-                        let path = self.resolve_path(field_type, path_length);
+                        let path = self.resolve_path(cursor, field_type, path_length);
 
                         resources.insert(Cow::Borrowed(resource_name), path);
                     }
@@ -402,32 +711,34 @@ impl<'a> ResourceParserIterator<'a> {
                 }
 
                 ResourceField::RelativeFilesystemDistributionResource => {
-                    let resource_count = self.reader.read_u32::<LittleEndian>().map_err(|_| {
+                    let resource_count = cursor.reader.read_u32::<LittleEndian>().map_err(|_| {
                         "failed reading package distribution relative path item count"
                     })? as usize;
 
                     let mut resources = HashMap::with_capacity(resource_count);
 
                     for _ in 0..resource_count {
-                        let name_length = self
+                        let name_length = cursor
                             .reader
                             .read_u16::<LittleEndian>()
                             .map_err(|_| "failed reading package distribution metadata name")?
                             as usize;
 
                         let name = unsafe {
-                            std::str::from_utf8_unchecked(
-                                self.resolve_blob_data(field_type, name_length),
-                            )
+                            std::str::from_utf8_unchecked(self.resolve_blob_data(
+                                cursor,
+                                field_type,
+                                name_length,
+                            ))
                         };
 
-                        let path_length = self
+                        let path_length = cursor
                             .reader
                             .read_u32::<LittleEndian>()
                             .map_err(|_| "failed reading package distribution path length")?
                             as usize;
 
-                        let path = self.resolve_path(field_type, path_length);
+                        let path = self.resolve_path(cursor, field_type, path_length);
 
                         resources.insert(Cow::Borrowed(name), path);
                     }
@@ -463,26 +774,30 @@ impl<'a> ResourceParserIterator<'a> {
                     current_resource.file_executable = true;
                 }
 
+                ResourceField::ExtractToCache => {
+                    current_resource.extract_to_cache = true;
+                }
+
                 ResourceField::FileDataEmbedded => {
-                    let l = self
+                    let l = cursor
                         .reader
                         .read_u64::<LittleEndian>()
                         .map_err(|_| "failed reading embedded file data length")?
                         as usize;
 
                     current_resource.file_data_embedded =
-                        Some(Cow::Borrowed(self.resolve_blob_data(field_type, l)));
+                        Some(self.resolve_blob_payload(cursor, field_type, l)?);
                 }
 
                 ResourceField::FileDataUtf8RelativePath => {
-                    let l = self
+                    let l = cursor
                         .reader
                         .read_u32::<LittleEndian>()
                         .map_err(|_| "failed reading file data relative path length")?
                         as usize;
 
                     current_resource.file_data_utf8_relative_path = Some(Cow::Borrowed(unsafe {
-                        std::str::from_utf8_unchecked(self.resolve_blob_data(field_type, l))
+                        std::str::from_utf8_unchecked(self.resolve_blob_data(cursor, field_type, l))
                     }));
                 }
             }
@@ -498,8 +813,21 @@ impl<'a> Iterator for ResourceParserIterator<'a> {
             return None;
         }
 
-        match self.parse_next() {
-            Ok(res) => res.map(Ok),
+        let mut cursor = std::mem::take(&mut self.cursor);
+        let res = self.parse_entry(&mut cursor);
+        self.cursor = cursor;
+
+        match res {
+            Ok(Some(resource)) => Some(Ok(resource)),
+            Ok(None) => {
+                self.done = true;
+
+                if self.cursor.read_resources_count != self.claimed_resources_count {
+                    Some(Err("mismatch between advertised index count and actual"))
+                } else {
+                    None
+                }
+            }
             Err(e) => Some(Err(e)),
         }
     }
@@ -521,13 +849,52 @@ pub fn load_resources<'a>(data: &'a [u8]) -> Result<ResourceParserIterator<'a>,
     let header = &data[0..8];
 
     if header == HEADER_V3 {
-        load_resources_v3(&data[8..])
+        load_resources_v3(&data[8..], false)
+    } else if header == HEADER_V4 {
+        load_resources_v3(&data[8..], true)
     } else {
         Err("unrecognized file format")
     }
 }
 
-fn load_resources_v3<'a>(data: &'a [u8]) -> Result<ResourceParserIterator<'a>, &'static str> {
+/// Parse a packed resources data structure after verifying its integrity.
+///
+/// The data must be version 4 packed resources data having an integrity
+/// trailer. The content digest in the trailer is always verified. If
+/// `public_key` is defined, the trailer must also hold an ed25519 signature
+/// made by the secret key corresponding to this public key.
+///
+/// Verification reads all data up front. So this is more expensive than
+/// [load_resources()].
+pub fn load_resources_verified<'a>(
+    data: &'a [u8],
+    public_key: Option<&[u8; 32]>,
+) -> Result<ResourceParserIterator<'a>, &'static str> {
+    let resources = load_resources(data)?;
+
+    if &data[0..8] != HEADER_V4 {
+        return Err("integrity verification requires format version 4");
+    }
+
+    let payload_length = resources.payload_length();
+    if payload_length > data.len() {
+        return Err("blob data extends beyond end of data");
+    }
+
+    let (payload, trailer) = data.split_at(payload_length);
+    verify_integrity_trailer(payload, trailer, public_key)?;
+
+    Ok(resources)
+}
+
+/// Parse version 3 or 4 of the packed resources data structure.
+///
+/// Version 4 is a superset of version 3. `allow_v4` defines whether the
+/// version 4 features are allowed.
+fn load_resources_v3<'a>(
+    data: &'a [u8],
+    allow_v4: bool,
+) -> Result<ResourceParserIterator<'a>, &'static str> {
     let mut reader = Cursor::new(data);
 
     let blob_section_count = reader
@@ -547,6 +914,9 @@ fn load_resources_v3<'a>(data: &'a [u8]) -> Result<ResourceParserIterator<'a>, &
     let mut current_blob_field = None;
     let mut current_blob_raw_payload_length = None;
     let mut current_blob_interior_padding = None;
+    let mut current_blob_compression = None;
+    let mut current_blob_encryption = None;
+    let mut current_blob_deduplicated = false;
     let mut blob_entry_count = 0;
     let mut blob_sections = Vec::with_capacity(blob_section_count as usize);
 
@@ -565,6 +935,9 @@ fn load_resources_v3<'a>(data: &'a [u8]) -> Result<ResourceParserIterator<'a>, &
                     current_blob_field = None;
                     current_blob_raw_payload_length = None;
                     current_blob_interior_padding = None;
+                    current_blob_compression = None;
+                    current_blob_encryption = None;
+                    current_blob_deduplicated = false;
                 }
                 BlobSectionField::EndOfEntry => {
                     if current_blob_field.is_none() {
@@ -578,11 +951,17 @@ fn load_resources_v3<'a>(data: &'a [u8]) -> Result<ResourceParserIterator<'a>, &
                         resource_field: current_blob_field.unwrap(),
                         raw_payload_length: current_blob_raw_payload_length.unwrap(),
                         interior_padding: current_blob_interior_padding,
+                        compression: current_blob_compression,
+                        encryption: current_blob_encryption,
+                        deduplicated: current_blob_deduplicated,
                     });
 
                     current_blob_field = None;
                     current_blob_raw_payload_length = None;
                     current_blob_interior_padding = None;
+                    current_blob_compression = None;
+                    current_blob_encryption = None;
+                    current_blob_deduplicated = false;
                 }
                 BlobSectionField::ResourceFieldType => {
                     let field = reader
@@ -607,6 +986,35 @@ fn load_resources_v3<'a>(data: &'a [u8]) -> Result<ResourceParserIterator<'a>, &
                         _ => return Err("invalid value for interior padding field"),
                     });
                 }
+                BlobSectionField::Compression => {
+                    if !allow_v4 {
+                        return Err("blob compression not supported by format version");
+                    }
+
+                    let compression = reader
+                        .read_u8()
+                        .map_err(|_| "failed reading compression field value")?;
+
+                    current_blob_compression = Some(BlobCompression::try_from(compression)?);
+                }
+                BlobSectionField::Deduplicated => {
+                    if !allow_v4 {
+                        return Err("blob deduplication not supported by format version");
+                    }
+
+                    current_blob_deduplicated = true;
+                }
+                BlobSectionField::Encryption => {
+                    if !allow_v4 {
+                        return Err("blob encryption not supported by format version");
+                    }
+
+                    let encryption = reader
+                        .read_u8()
+                        .map_err(|_| "failed reading encryption field value")?;
+
+                    current_blob_encryption = Some(BlobEncryption::try_from(encryption)?);
+                }
             }
         }
     }
@@ -615,8 +1023,11 @@ fn load_resources_v3<'a>(data: &'a [u8]) -> Result<ResourceParserIterator<'a>, &
         return Err("mismatch between blob sections count");
     }
 
-    // Array indexing resource field to current payload offset within that section.
-    let mut blob_offsets: [Option<BlobSectionReadState>; 256] = [None; 256];
+    // Array indexing resource field to the read state of that section.
+    let mut blob_states: [Option<BlobSectionReadState>; 256] = [None; 256];
+
+    // Current payload offset within each section.
+    let mut blob_offsets = Vec::with_capacity(blob_sections.len());
 
     // Global payload offset where blobs data starts.
     let blob_start_offset: usize =
@@ -628,38 +1039,169 @@ fn load_resources_v3<'a>(data: &'a [u8]) -> Result<ResourceParserIterator<'a>, &
     // Current offset from start of blobs data.
     let mut current_blob_offset = 0;
 
+    let mut name_index = None;
+
     for section in &blob_sections {
         let section_start_offset = blob_start_offset + current_blob_offset;
-        blob_offsets[section.resource_field as usize] = Some(BlobSectionReadState {
-            offset: section_start_offset,
+
+        if section.encryption.is_some()
+            && !ResourceField::try_from(section.resource_field)
+                .map(|field| field.is_encryptable())
+                .unwrap_or(false)
+        {
+            return Err("blob section cannot be encrypted");
+        }
+
+        let deduplicated_data_start = if section.deduplicated {
+            Some(parse_deduplicated_references(
+                data,
+                section,
+                section_start_offset,
+            )?)
+        } else {
+            None
+        };
+
+        if section.resource_field == NAME_INDEX_BLOB_SECTION {
+            if !allow_v4 {
+                return Err("name index not supported by format version");
+            }
+
+            name_index = Some(parse_name_index(
+                data,
+                section,
+                section_start_offset,
+                resources_count,
+            )?);
+        }
+
+        blob_states[section.resource_field as usize] = Some(BlobSectionReadState {
+            start: section_start_offset,
+            slot: blob_offsets.len(),
             interior_padding: match section.interior_padding {
                 Some(padding) => padding,
                 None => BlobInteriorPadding::None,
             },
+            compression: match section.compression {
+                Some(compression) => compression,
+                None => BlobCompression::None,
+            },
+            encryption: match section.encryption {
+                Some(encryption) => encryption,
+                None => BlobEncryption::None,
+            },
+            deduplicated_data_start,
         });
+        // The reference table of deduplicated sections follows its u64 length.
+        blob_offsets.push(section_start_offset + if section.deduplicated { 8 } else { 0 });
         current_blob_offset += section.raw_payload_length;
     }
 
     Ok(ResourceParserIterator {
         done: resources_index_length == 0 || resources_count == 0,
+        decompress: true,
+        decryption_key: None,
         data,
-        reader,
-        blob_sections: blob_offsets,
+        cursor: ParseCursor {
+            reader,
+            blob_offsets,
+            read_resources_count: 0,
+        },
+        blob_sections: blob_states,
         claimed_resources_count: resources_count,
-        read_resources_count: 0,
+        payload_length: HEADER_V3.len() + blob_start_offset + current_blob_offset,
+        resources_index_offset: 1 + 4 + 4 + 4 + blob_index_length,
+        name_index,
     })
 }
 
+/// Parse and validate the reference table of a deduplicated blob section.
+///
+/// The section consists of a `u64` count of entry references, a `u64` offset
+/// for each entry relative to the end of the reference table, then the data
+/// of each unique entry. Returns the start offset of the entries data.
+fn parse_deduplicated_references(
+    data: &[u8],
+    section: &BlobSection,
+    start: usize,
+) -> Result<usize, &'static str> {
+    if !ResourceField::try_from(section.resource_field)
+        .map(|field| field.is_deduplicatable())
+        .unwrap_or(false)
+    {
+        return Err("blob section cannot be deduplicated");
+    }
+
+    let count = data
+        .get(start..start + 8)
+        .map(LittleEndian::read_u64)
+        .ok_or("deduplicated blob section extends beyond end of data")?;
+
+    let table_length = usize::try_from(count)
+        .ok()
+        .and_then(|count| count.checked_mul(8))
+        .and_then(|length| length.checked_add(8))
+        .filter(|length| *length <= section.raw_payload_length)
+        .ok_or("invalid deduplicated blob section reference count")?;
+
+    Ok(start + table_length)
+}
+
+/// Parse and validate the header of the name index blob section.
+fn parse_name_index(
+    data: &[u8],
+    section: &BlobSection,
+    start: usize,
+    resources_count: usize,
+) -> Result<NameIndex, &'static str> {
+    if section.compression.is_some() {
+        return Err("name index cannot be compressed");
+    }
+
+    let section_data = data
+        .get(start..start + section.raw_payload_length)
+        .ok_or("name index extends beyond end of data")?;
+
+    let field_count = *section_data
+        .first()
+        .ok_or("failed reading name index field count")? as usize;
+    let fields = section_data
+        .get(1..1 + field_count)
+        .ok_or("failed reading name index fields")?;
+
+    let index = NameIndex {
+        fields_offset: start + 1,
+        field_count,
+        name_position: fields
+            .iter()
+            .position(|field| *field == ResourceField::Name as u8)
+            .ok_or("name index does not record resource names")?,
+        records_offset: start + 1 + field_count,
+        record_count: resources_count,
+    };
+
+    if section.raw_payload_length != 1 + field_count + resources_count * index.record_length() {
+        return Err("invalid name index length");
+    }
+
+    Ok(index)
+}
+
 #[cfg(test)]
 mod tests {
     use {
         super::*,
         crate::{
-            resource::Resource, serialization::BlobInteriorPadding,
-            writer::write_packed_resources_v3,
+            integrity::append_integrity_trailer,
+            resource::Resource,
+            serialization::BlobInteriorPadding,
+            writer::{write_packed_resources_v3, write_packed_resources_v4, WriterOptions},
         },
     };
 
+    #[cfg(feature = "zstd")]
+    use std::collections::BTreeMap;
+
     #[test]
     fn test_too_short_header() {
         let data = b"foo";
@@ -674,7 +1216,7 @@ mod tests {
         let res = load_resources(data);
         assert_eq!(res.err(), Some("unrecognized file format"));
 
-        let data = b"pyembed\x04";
+        let data = b"pyembed\x05";
         let res = load_resources(data);
         assert_eq!(res.err(), Some("unrecognized file format"));
     }
@@ -1405,6 +1947,7 @@ mod tests {
             file_executable: true,
             file_data_embedded: Some(Cow::from(b"file_data_embedded".to_vec())),
             file_data_utf8_relative_path: Some(Cow::from("file_data_utf8_relative_path")),
+            extract_to_cache: true,
         };
 
         let mut data = Vec::new();
@@ -1519,6 +2062,7 @@ mod tests {
             entry.file_data_utf8_relative_path.as_ref().unwrap(),
             "file_data_utf8_relative_path"
         );
+        assert!(entry.extract_to_cache);
     }
 
     #[test]
@@ -1547,4 +2091,646 @@ mod tests {
 
         assert_eq!(resources, loaded);
     }
+
+    #[test]
+    fn test_v3_rejects_compression() {
+        // Blob index with a single entry declaring compression.
+        let mut data = b"pyembed\x03\x01\x06\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+        data.extend(b"\x01\x05\x02\xff\x00");
+
+        let res = load_resources(&data);
+        assert_eq!(
+            res.err(),
+            Some("blob compression not supported by format version")
+        );
+    }
+
+    #[test]
+    fn test_v3_rejects_encryption() {
+        // Blob index with a single entry declaring encryption.
+        let mut data = b"pyembed\x03\x01\x06\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+        data.extend(b"\x01\x07\x02\xff\x00");
+
+        let res = load_resources(&data);
+        assert_eq!(
+            res.err(),
+            Some("blob encryption not supported by format version")
+        );
+    }
+
+    #[test]
+    fn test_v3_rejects_deduplication() {
+        // Blob index with a single entry declaring deduplication.
+        let mut data = b"pyembed\x03\x01\x05\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+        data.extend(b"\x01\x06\xff\x00");
+
+        let res = load_resources(&data);
+        assert_eq!(
+            res.err(),
+            Some("blob deduplication not supported by format version")
+        );
+    }
+
+    #[test]
+    fn test_v4_deduplicated() {
+        let mut package_resources = HashMap::new();
+        package_resources.insert(Cow::from("LICENSE"), Cow::from(b"license".to_vec()));
+        package_resources.insert(Cow::from("py.typed"), Cow::from(b"".to_vec()));
+
+        let resources: Vec<Resource<u8>> = ["foo", "bar", "baz"]
+            .iter()
+            .map(|name| Resource {
+                name: Cow::from(*name),
+                is_python_module: true,
+                is_python_package: true,
+                in_memory_source: Some(Cow::from(b"".to_vec())),
+                in_memory_bytecode: Some(Cow::from(format!("{} bytecode", name).into_bytes())),
+                in_memory_package_resources: Some(package_resources.clone()),
+                ..Resource::default()
+            })
+            .collect::<Vec<_>>();
+
+        for padding in [None, Some(BlobInteriorPadding::Null)] {
+            let mut data = Vec::new();
+            write_packed_resources_v4(
+                &resources,
+                &mut data,
+                &WriterOptions {
+                    interior_padding: padding,
+                    name_index: true,
+                    deduplicate: true,
+                    ..WriterOptions::default()
+                },
+            )
+            .unwrap();
+
+            let parser = load_resources(&data).unwrap();
+            for resource in &resources {
+                assert_eq!(
+                    parser.find_resource(&resource.name).unwrap().as_ref(),
+                    Some(resource)
+                );
+            }
+
+            let loaded = parser
+                .collect::<Result<Vec<Resource<u8>>, &'static str>>()
+                .unwrap();
+            assert_eq!(resources, loaded);
+
+            // Identical payloads reference the same borrowed data.
+            let licenses = loaded
+                .iter()
+                .map(|resource| {
+                    match resource
+                        .in_memory_package_resources
+                        .as_ref()
+                        .unwrap()
+                        .get("LICENSE")
+                    {
+                        Some(Cow::Borrowed(data)) => data.as_ptr(),
+                        _ => panic!("expected borrowed data"),
+                    }
+                })
+                .collect::<Vec<_>>();
+            assert!(licenses.iter().all(|ptr| *ptr == licenses[0]));
+        }
+    }
+
+    #[test]
+    fn test_deduplicated_name_rejected() {
+        let mut data = b"pyembed\x04\x01\x0f\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+        // Blob index with a deduplicated name section.
+        data.extend(b"\x01\x02\x01\x03\x08\x00\x00\x00\x00\x00\x00\x00\x06\xff\x00");
+        data.extend(b"\x00\x00\x00\x00\x00\x00\x00\x00");
+
+        assert_eq!(
+            load_resources(&data).err(),
+            Some("blob section cannot be deduplicated")
+        );
+    }
+
+    #[test]
+    fn test_v4_uncompressed() {
+        let resources: Vec<Resource<u8>> = vec![Resource {
+            name: Cow::from("foo"),
+            is_python_module: true,
+            in_memory_source: Some(Cow::from(b"import io".to_vec())),
+            ..Resource::default()
+        }];
+
+        let mut data = Vec::new();
+        write_packed_resources_v4(&resources, &mut data, &WriterOptions::default()).unwrap();
+        assert_eq!(&data[0..8], HEADER_V4);
+
+        let loaded = load_resources(&data)
+            .unwrap()
+            .collect::<Result<Vec<Resource<u8>>, &'static str>>()
+            .unwrap();
+
+        assert_eq!(resources, loaded);
+        assert!(matches!(loaded[0].in_memory_source, Some(Cow::Borrowed(_))));
+    }
+
+    #[test]
+    #[cfg(feature = "zstd")]
+    fn test_v4_compressed() {
+        let mut package_resources = HashMap::new();
+        package_resources.insert(Cow::from("foo.txt"), Cow::from(b"foo ".repeat(32)));
+        package_resources.insert(Cow::from("bar.txt"), Cow::from(b"bar ".repeat(32)));
+
+        let resources: Vec<Resource<u8>> = vec![
+            Resource {
+                name: Cow::from("foo"),
+                is_python_module: true,
+                in_memory_source: Some(Cow::from(b"import io\n".repeat(32))),
+                in_memory_bytecode: Some(Cow::from(b"fake bytecode".to_vec())),
+                in_memory_package_resources: Some(package_resources),
+                ..Resource::default()
+            },
+            Resource {
+                name: Cow::from("bar"),
+                is_python_module: true,
+                in_memory_source: Some(Cow::from(b"import os\n".repeat(32))),
+                ..Resource::default()
+            },
+        ];
+
+        let mut compression = BTreeMap::new();
+        compression.insert(ResourceField::InMemorySource, BlobCompression::Zstd);
+        compression.insert(ResourceField::InMemoryResourcesData, BlobCompression::Zstd);
+
+        for padding in [None, Some(BlobInteriorPadding::Null)] {
+            let mut data = Vec::new();
+            write_packed_resources_v4(
+                &resources,
+                &mut data,
+                &WriterOptions {
+                    interior_padding: padding,
+                    compression: compression.clone(),
+                    ..WriterOptions::default()
+                },
+            )
+            .unwrap();
+
+            let mut uncompressed = Vec::new();
+            write_packed_resources_v3(&resources, &mut uncompressed, padding).unwrap();
+            assert!(data.len() < uncompressed.len());
+
+            let parser = load_resources(&data).unwrap();
+            assert_eq!(
+                parser.field_compression(ResourceField::InMemorySource),
+                BlobCompression::Zstd
+            );
+            assert_eq!(
+                parser.field_compression(ResourceField::InMemoryBytecode),
+                BlobCompression::None
+            );
+
+            let loaded = parser
+                .collect::<Result<Vec<Resource<u8>>, &'static str>>()
+                .unwrap();
+
+            assert_eq!(resources, loaded);
+            assert!(matches!(loaded[0].in_memory_source, Some(Cow::Owned(_))));
+            assert!(matches!(
+                loaded[0].in_memory_bytecode,
+                Some(Cow::Borrowed(_))
+            ));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "zstd")]
+    fn test_v4_compressed_no_decompress() {
+        let resources: Vec<Resource<u8>> = vec![Resource {
+            name: Cow::from("foo"),
+            is_python_module: true,
+            in_memory_source: Some(Cow::from(b"import io\n".repeat(32))),
+            ..Resource::default()
+        }];
+
+        let mut compression = BTreeMap::new();
+        compression.insert(ResourceField::InMemorySource, BlobCompression::Zstd);
+
+        let mut data = Vec::new();
+        write_packed_resources_v4(
+            &resources,
+            &mut data,
+            &WriterOptions {
+                compression,
+                ..WriterOptions::default()
+            },
+        )
+        .unwrap();
+
+        let mut parser = load_resources(&data).unwrap();
+        parser.set_decompress(false);
+
+        let loaded = parser
+            .collect::<Result<Vec<Resource<u8>>, &'static str>>()
+            .unwrap();
+
+        let source = loaded[0].in_memory_source.as_ref().unwrap();
+        assert!(matches!(source, Cow::Borrowed(_)));
+        assert_eq!(
+            decompress_blob(BlobCompression::Zstd, source).unwrap(),
+            b"import io\n".repeat(32)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "zstd")]
+    fn test_v4_compression_unsupported_field() {
+        let mut compression = BTreeMap::new();
+        compression.insert(ResourceField::Name, BlobCompression::Zstd);
+
+        let resources: Vec<Resource<u8>> = vec![];
+        let mut data = Vec::new();
+        assert!(write_packed_resources_v4(
+            &resources,
+            &mut data,
+            &WriterOptions {
+                compression,
+                ..WriterOptions::default()
+            }
+        )
+        .is_err());
+    }
+
+    #[test]
+    #[cfg(all(feature = "encryption", feature = "zstd"))]
+    fn test_v4_encrypted() {
+        let key = [0x42; 32];
+        let resources: Vec<Resource<u8>> = vec![
+            Resource {
+                name: Cow::from("foo"),
+                is_python_module: true,
+                in_memory_source: Some(Cow::from(b"import io\n".repeat(32))),
+                in_memory_bytecode: Some(Cow::from(b"bytecode".to_vec())),
+                ..Resource::default()
+            },
+            Resource {
+                name: Cow::from("bar"),
+                is_python_module: true,
+                in_memory_source: Some(Cow::from(b"import io\n".repeat(32))),
+                ..Resource::default()
+            },
+        ];
+
+        for (compress, deduplicate) in [(false, false), (true, false), (true, true)] {
+            let mut compression = BTreeMap::new();
+            if compress {
+                compression.insert(ResourceField::InMemorySource, BlobCompression::Zstd);
+            }
+
+            let mut data = Vec::new();
+            write_packed_resources_v4(
+                &resources,
+                &mut data,
+                &WriterOptions {
+                    compression,
+                    name_index: true,
+                    deduplicate,
+                    encryption_key: Some(key),
+                    ..WriterOptions::default()
+                },
+            )
+            .unwrap();
+
+            // Plaintext of opaque fields isn't present. Names are.
+            assert!(!data.windows(8).any(|w| w == b"bytecode"));
+            assert!(data.windows(3).any(|w| w == b"foo"));
+
+            let parser = load_resources(&data).unwrap();
+            assert!(parser.is_encrypted());
+            assert_eq!(
+                parser.field_encryption(ResourceField::InMemorySource),
+                BlobEncryption::Aes256Gcm
+            );
+            assert_eq!(
+                parser.field_encryption(ResourceField::Name),
+                BlobEncryption::None
+            );
+
+            // Emitting encrypted data requires a key.
+            assert_eq!(
+                parser.clone().next().unwrap(),
+                Err("packed resources data is encrypted but no decryption key is set")
+            );
+
+            let mut wrong_key = parser.clone();
+            wrong_key.set_decryption_key(Some([0x43; 32]));
+            assert_eq!(
+                wrong_key.next().unwrap(),
+                Err("failed decrypting blob data")
+            );
+
+            let mut parser = parser;
+            parser.set_decryption_key(Some(key));
+            assert_eq!(
+                parser.find_resource("bar").unwrap().as_ref(),
+                Some(&resources[1])
+            );
+
+            let loaded = parser
+                .collect::<Result<Vec<Resource<u8>>, &'static str>>()
+                .unwrap();
+            assert_eq!(resources, loaded);
+        }
+    }
+
+    #[test]
+    #[cfg(all(feature = "encryption", feature = "zstd"))]
+    fn test_v4_encrypted_no_decompress() {
+        let key = [0x42; 32];
+        let resources: Vec<Resource<u8>> = vec![Resource {
+            name: Cow::from("foo"),
+            is_python_module: true,
+            in_memory_source: Some(Cow::from(b"import io\n".repeat(32))),
+            ..Resource::default()
+        }];
+
+        let mut compression = BTreeMap::new();
+        compression.insert(ResourceField::InMemorySource, BlobCompression::Zstd);
+
+        let mut data = Vec::new();
+        write_packed_resources_v4(
+            &resources,
+            &mut data,
+            &WriterOptions {
+                compression,
+                encryption_key: Some(key),
+                ..WriterOptions::default()
+            },
+        )
+        .unwrap();
+
+        let mut parser = load_resources(&data).unwrap();
+        parser.set_decompress(false);
+        parser.set_decryption_key(Some(key));
+
+        let loaded = parser
+            .collect::<Result<Vec<Resource<u8>>, &'static str>>()
+            .unwrap();
+
+        // Data is decrypted but left compressed.
+        let source = loaded[0].in_memory_source.as_ref().unwrap();
+        assert_eq!(
+            decompress_blob(BlobCompression::Zstd, source).unwrap(),
+            b"import io\n".repeat(32)
+        );
+    }
+
+    fn signed_v4_resources(
+        signing_key: Option<&[u8; 32]>,
+    ) -> (Vec<Resource<'static, u8>>, Vec<u8>) {
+        let resources: Vec<Resource<u8>> = vec![Resource {
+            name: Cow::from("foo"),
+            is_python_module: true,
+            in_memory_source: Some(Cow::from(b"import io".to_vec())),
+            ..Resource::default()
+        }];
+
+        let mut data = Vec::new();
+        write_packed_resources_v4(&resources, &mut data, &WriterOptions::default()).unwrap();
+        append_integrity_trailer(&mut data, signing_key).unwrap();
+
+        (resources, data)
+    }
+
+    #[test]
+    fn test_payload_length() {
+        let (_, data) = signed_v4_resources(None);
+
+        let resources = load_resources(&data).unwrap();
+        assert_eq!(resources.payload_length(), data.len() - 34);
+    }
+
+    #[test]
+    fn test_verified_digest() {
+        let (resources, data) = signed_v4_resources(None);
+
+        // Trailers are ignored by regular loading.
+        let loaded = load_resources(&data)
+            .unwrap()
+            .collect::<Result<Vec<Resource<u8>>, &'static str>>()
+            .unwrap();
+        assert_eq!(resources, loaded);
+
+        let loaded = load_resources_verified(&data, None)
+            .unwrap()
+            .collect::<Result<Vec<Resource<u8>>, &'static str>>()
+            .unwrap();
+        assert_eq!(resources, loaded);
+    }
+
+    #[test]
+    fn test_verified_tampered() {
+        let (_, mut data) = signed_v4_resources(None);
+
+        let offset = data.windows(9).position(|w| w == b"import io").unwrap();
+        data[offset] = b'I';
+
+        assert_eq!(
+            load_resources_verified(&data, None).err(),
+            Some("packed resources digest mismatch")
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "signing")]
+    fn test_verified_signature() {
+        let signing_key = [7u8; 32];
+        let public_key = crate::integrity::ed25519_public_key(&signing_key);
+
+        let (resources, data) = signed_v4_resources(Some(&signing_key));
+        let loaded = load_resources_verified(&data, Some(&public_key))
+            .unwrap()
+            .collect::<Result<Vec<Resource<u8>>, &'static str>>()
+            .unwrap();
+        assert_eq!(resources, loaded);
+
+        let (_, data) = signed_v4_resources(None);
+        assert_eq!(
+            load_resources_verified(&data, Some(&public_key)).err(),
+            Some("packed resources data is not signed")
+        );
+    }
+
+    fn name_index_resources() -> Vec<Resource<'static, u8>> {
+        let mut package_resources = HashMap::new();
+        package_resources.insert(Cow::from("data.txt"), Cow::from(b"data".to_vec()));
+
+        vec![
+            Resource {
+                name: Cow::from("foo"),
+                is_python_module: true,
+                in_memory_source: Some(Cow::from(b"import io".to_vec())),
+                ..Resource::default()
+            },
+            Resource {
+                name: Cow::from("bar"),
+                is_python_module: true,
+                is_python_package: true,
+                in_memory_bytecode: Some(Cow::from(b"bytecode".to_vec())),
+                in_memory_package_resources: Some(package_resources),
+                ..Resource::default()
+            },
+            Resource {
+                name: Cow::from("baz.qux"),
+                is_python_module: true,
+                in_memory_source: Some(Cow::from(b"import os".to_vec())),
+                in_memory_bytecode: Some(Cow::from(b"more bytecode".to_vec())),
+                ..Resource::default()
+            },
+        ]
+    }
+
+    #[test]
+    fn test_name_index_absent() {
+        let mut data = Vec::new();
+        write_packed_resources_v4(
+            &name_index_resources(),
+            &mut data,
+            &WriterOptions::default(),
+        )
+        .unwrap();
+
+        let parser = load_resources(&data).unwrap();
+        assert!(!parser.has_name_index());
+        assert_eq!(
+            parser.find_resource("foo").err(),
+            Some("packed resources data does not have a name index")
+        );
+        assert_eq!(
+            parser.names().err(),
+            Some("packed resources data does not have a name index")
+        );
+    }
+
+    #[test]
+    fn test_name_index_names() {
+        let mut data = Vec::new();
+        write_packed_resources_v4(
+            &name_index_resources(),
+            &mut data,
+            &WriterOptions {
+                name_index: true,
+                ..WriterOptions::default()
+            },
+        )
+        .unwrap();
+
+        let parser = load_resources(&data).unwrap();
+        assert_eq!(parser.names().unwrap(), vec!["bar", "baz.qux", "foo"]);
+    }
+
+    #[test]
+    #[cfg(feature = "zstd")]
+    fn test_name_index() {
+        let resources = name_index_resources();
+
+        for padding in [None, Some(BlobInteriorPadding::Null)] {
+            let mut compression = BTreeMap::new();
+            compression.insert(ResourceField::InMemorySource, BlobCompression::Zstd);
+
+            let mut data = Vec::new();
+            write_packed_resources_v4(
+                &resources,
+                &mut data,
+                &WriterOptions {
+                    interior_padding: padding,
+                    compression,
+                    name_index: true,
+                    ..WriterOptions::default()
+                },
+            )
+            .unwrap();
+
+            let parser = load_resources(&data).unwrap();
+            assert!(parser.has_name_index());
+
+            for resource in &resources {
+                assert_eq!(
+                    parser.find_resource(&resource.name).unwrap().as_ref(),
+                    Some(resource)
+                );
+            }
+            assert_eq!(parser.find_resource("missing").unwrap(), None);
+            assert_eq!(parser.find_resource("").unwrap(), None);
+
+            // Regular iteration still works.
+            let loaded = parser
+                .collect::<Result<Vec<Resource<u8>>, &'static str>>()
+                .unwrap();
+            assert_eq!(resources, loaded);
+        }
+    }
+
+    #[test]
+    fn test_name_index_empty() {
+        let mut data = Vec::new();
+        write_packed_resources_v4::<Resource<u8>, _>(
+            &[],
+            &mut data,
+            &WriterOptions {
+                name_index: true,
+                ..WriterOptions::default()
+            },
+        )
+        .unwrap();
+
+        let parser = load_resources(&data).unwrap();
+        assert!(parser.has_name_index());
+        assert_eq!(parser.find_resource("foo").unwrap(), None);
+        assert!(parser.names().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_name_index_duplicate_names() {
+        let mut resources = name_index_resources();
+        resources.push(resources[0].clone());
+
+        let mut data = Vec::new();
+        assert!(write_packed_resources_v4(
+            &resources,
+            &mut data,
+            &WriterOptions {
+                name_index: true,
+                ..WriterOptions::default()
+            },
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_name_index_v3_rejected() {
+        let mut data = Vec::new();
+        write_packed_resources_v4(
+            &name_index_resources(),
+            &mut data,
+            &WriterOptions {
+                name_index: true,
+                ..WriterOptions::default()
+            },
+        )
+        .unwrap();
+        data[0..8].copy_from_slice(HEADER_V3);
+
+        assert_eq!(
+            load_resources(&data).err(),
+            Some("name index not supported by format version")
+        );
+    }
+
+    #[test]
+    fn test_verified_requires_v4() {
+        let mut data = Vec::new();
+        write_packed_resources_v3::<Resource<u8>, _>(&[], &mut data, None).unwrap();
+
+        assert_eq!(
+            load_resources_verified(&data, None).err(),
+            Some("integrity verification requires format version 4")
+        );
+    }
 }
@@ -115,6 +115,14 @@ where
 
     /// Holds arbitrary file data in a relative path encoded in UTF-8.
     pub file_data_utf8_relative_path: Option<Cow<'a, str>>,
+
+    /// Whether in-memory data should be extracted to a cache directory when accessed.
+    ///
+    /// This is for modules and package resources that must exist as files,
+    /// such as data read relative to `__file__` or extension modules that
+    /// can't be loaded from memory. Extracted files are referenced via the
+    /// module's `__file__`, `__path__` and `ModuleSpec.origin`.
+    pub extract_to_cache: bool,
 }
 
 impl<'a, X> Default for Resource<'a, X>
@@ -151,6 +159,7 @@ where
             file_executable: false,
             file_data_embedded: None,
             file_data_utf8_relative_path: None,
+            extract_to_cache: false,
         }
     }
 }
@@ -243,6 +252,7 @@ where
         if let Some(value) = other.file_data_utf8_relative_path {
             self.file_data_utf8_relative_path.replace(value);
         }
+        self.extract_to_cache |= other.extract_to_cache;
 
         Ok(())
     }
@@ -370,6 +380,7 @@ where
                 .file_data_utf8_relative_path
                 .as_ref()
                 .map(|value| Cow::Owned(value.clone().into_owned())),
+            extract_to_cache: self.extract_to_cache,
         }
     }
 }
@@ -11,6 +11,20 @@
 /// Header value for version 2 of resources payload.
 pub const HEADER_V3: &[u8] = b"pyembed\x03";
 
+/// Header value for version 4 of resources payload.
+///
+/// Version 4 is a superset of version 3 that allows blob sections to
+/// declare a compression format for their entries.
+pub const HEADER_V4: &[u8] = b"pyembed\x04";
+
+/// Resource field value identifying the blob section holding the name index.
+///
+/// Version 4 data can contain a blob section holding an index of resources
+/// sorted by name, allowing individual resources to be found without parsing
+/// the entire resources index. This value is not a [ResourceField]: it is only
+/// used to identify this blob section in the blob index.
+pub const NAME_INDEX_BLOB_SECTION: u8 = 0xfe;
+
 /// Defines interior padding mechanism between entries in blob sections.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum BlobInteriorPadding {
@@ -34,6 +48,79 @@ impl From<&BlobInteriorPadding> for u8 {
     }
 }
 
+/// Defines compression applied to entries within a blob section.
+///
+/// Compression is applied to each entry in a blob section independently.
+/// This allows individual entries to be decompressed without having to
+/// decompress the entire section.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlobCompression {
+    /// Entries are stored uncompressed.
+    None = 0x01,
+
+    /// Each entry is an individual zstandard frame.
+    Zstd = 0x02,
+}
+
+impl From<&BlobCompression> for u8 {
+    fn from(source: &BlobCompression) -> Self {
+        match source {
+            BlobCompression::None => 0x01,
+            BlobCompression::Zstd => 0x02,
+        }
+    }
+}
+
+impl TryFrom<u8> for BlobCompression {
+    type Error = &'static str;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x01 => Ok(BlobCompression::None),
+            0x02 => Ok(BlobCompression::Zstd),
+            _ => Err("invalid value for blob compression field"),
+        }
+    }
+}
+
+/// Defines encryption applied to entries within a blob section.
+///
+/// Like compression, encryption is applied to each entry in a blob section
+/// independently. When an entry is both compressed and encrypted, the
+/// compressed data is encrypted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlobEncryption {
+    /// Entries are stored unencrypted.
+    None = 0x01,
+
+    /// Each entry is AES-256-GCM encrypted.
+    ///
+    /// Entries consist of a 12 byte nonce, the ciphertext, and a 16 byte
+    /// authentication tag.
+    Aes256Gcm = 0x02,
+}
+
+impl From<&BlobEncryption> for u8 {
+    fn from(source: &BlobEncryption) -> Self {
+        match source {
+            BlobEncryption::None => 0x01,
+            BlobEncryption::Aes256Gcm => 0x02,
+        }
+    }
+}
+
+impl TryFrom<u8> for BlobEncryption {
+    type Error = &'static str;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x01 => Ok(BlobEncryption::None),
+            0x02 => Ok(BlobEncryption::Aes256Gcm),
+            _ => Err("invalid value for blob encryption field"),
+        }
+    }
+}
+
 /// Describes a blob section field type in the blob index.
 #[derive(Debug, PartialEq, Eq, PartialOrd)]
 pub enum BlobSectionField {
@@ -43,6 +130,9 @@ pub enum BlobSectionField {
     ResourceFieldType = 0x03,
     RawPayloadLength = 0x04,
     InteriorPadding = 0x05,
+    Compression = 0x06,
+    Deduplicated = 0x07,
+    Encryption = 0x08,
 }
 
 impl From<BlobSectionField> for u8 {
@@ -53,6 +143,9 @@ impl From<BlobSectionField> for u8 {
             BlobSectionField::ResourceFieldType => 0x02,
             BlobSectionField::RawPayloadLength => 0x03,
             BlobSectionField::InteriorPadding => 0x04,
+            BlobSectionField::Compression => 0x05,
+            BlobSectionField::Deduplicated => 0x06,
+            BlobSectionField::Encryption => 0x07,
             BlobSectionField::EndOfEntry => 0xff,
         }
     }
@@ -68,6 +161,9 @@ impl TryFrom<u8> for BlobSectionField {
             0x02 => Ok(BlobSectionField::ResourceFieldType),
             0x03 => Ok(BlobSectionField::RawPayloadLength),
             0x04 => Ok(BlobSectionField::InteriorPadding),
+            0x05 => Ok(BlobSectionField::Compression),
+            0x06 => Ok(BlobSectionField::Deduplicated),
+            0x07 => Ok(BlobSectionField::Encryption),
             0xff => Ok(BlobSectionField::EndOfEntry),
             _ => Err("invalid blob index field type"),
         }
@@ -109,6 +205,44 @@ pub enum ResourceField {
     FileExecutable = 0x1c,
     FileDataEmbedded = 0x1d,
     FileDataUtf8RelativePath = 0x1e,
+    ExtractToCache = 0x1f,
+}
+
+impl ResourceField {
+    /// Whether blob data for this field can be compressed.
+    ///
+    /// Only fields holding opaque data can be compressed. Names and paths
+    /// must remain uncompressed so they can be referenced without copying.
+    pub fn is_compressible(&self) -> bool {
+        matches!(
+            self,
+            ResourceField::InMemorySource
+                | ResourceField::InMemoryBytecode
+                | ResourceField::InMemoryBytecodeOpt1
+                | ResourceField::InMemoryBytecodeOpt2
+                | ResourceField::InMemoryExtensionModuleSharedLibrary
+                | ResourceField::InMemoryResourcesData
+                | ResourceField::InMemoryDistributionResource
+                | ResourceField::InMemorySharedLibrary
+                | ResourceField::FileDataEmbedded
+        )
+    }
+
+    /// Whether blob data for this field can be deduplicated.
+    ///
+    /// This is the same set of fields that can be compressed. Names are
+    /// excluded so the name index can reference them directly.
+    pub fn is_deduplicatable(&self) -> bool {
+        self.is_compressible()
+    }
+
+    /// Whether blob data for this field can be encrypted.
+    ///
+    /// This is the same set of fields that can be compressed. Names and
+    /// paths are never encrypted so resources can be indexed without a key.
+    pub fn is_encryptable(&self) -> bool {
+        self.is_compressible()
+    }
 }
 
 impl From<ResourceField> for u8 {
@@ -144,6 +278,7 @@ impl From<ResourceField> for u8 {
             ResourceField::FileExecutable => 0x1c,
             ResourceField::FileDataEmbedded => 0x1d,
             ResourceField::FileDataUtf8RelativePath => 0x1e,
+            ResourceField::ExtractToCache => 0x1f,
             ResourceField::EndOfEntry => 0xff,
         }
     }
@@ -184,6 +319,7 @@ impl TryFrom<u8> for ResourceField {
             0x1c => Ok(ResourceField::FileExecutable),
             0x1d => Ok(ResourceField::FileDataEmbedded),
             0x1e => Ok(ResourceField::FileDataUtf8RelativePath),
+            0x1f => Ok(ResourceField::ExtractToCache),
             0xff => Ok(ResourceField::EndOfEntry),
             _ => Err("invalid field type"),
         }
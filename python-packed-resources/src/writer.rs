@@ -10,14 +10,35 @@
 
 use {
     crate::{
+        compression::compress_blob,
+        encryption::encrypt_blob,
         resource::Resource,
-        serialization::{BlobInteriorPadding, BlobSectionField, ResourceField, HEADER_V3},
+        serialization::{
+            BlobCompression, BlobEncryption, BlobInteriorPadding, BlobSectionField, ResourceField,
+            HEADER_V3, HEADER_V4, NAME_INDEX_BLOB_SECTION,
+        },
     },
     anyhow::{anyhow, Context, Result},
     byteorder::{LittleEndian, WriteBytesExt},
-    std::{collections::BTreeMap, io::Write, path::Path},
+    sha2::{Digest, Sha256},
+    std::{
+        borrow::Cow,
+        collections::{hash_map, BTreeMap, HashMap},
+        io::Write,
+        path::Path,
+    },
+};
+
+#[cfg(feature = "stream-writer")]
+use std::{
+    collections::btree_map,
+    fs::File,
+    io::{BufWriter, Seek, SeekFrom},
 };
 
+/// A mapping of names to data, as used by resource fields holding multiple entries.
+pub(crate) type DataMap<'a> = HashMap<Cow<'a, str>, Cow<'a, [u8]>>;
+
 #[cfg(unix)]
 use std::os::unix::ffi::OsStrExt;
 #[cfg(windows)]
@@ -25,7 +46,7 @@ use std::os::windows::ffi::OsStrExt;
 
 #[cfg(unix)]
 fn path_bytes_length(p: &Path) -> usize {
-    p.as_os_str().as_bytes().len()
+    p.as_os_str().len()
 }
 
 #[cfg(unix)]
@@ -51,14 +72,120 @@ fn path_to_bytes(p: &Path) -> Vec<u8> {
     }
 }
 
+/// Tracks the entries of a deduplicated blob section.
+#[derive(Debug, Default)]
+struct DeduplicatedEntries {
+    /// Offset of each entry's data within the section's data area, in order.
+    references: Vec<u64>,
+    /// Whether each entry is the first occurrence of its content.
+    first_occurrences: Vec<bool>,
+    /// Offset of the data of each unique entry, keyed by content digest.
+    offsets: HashMap<[u8; 32], u64>,
+    /// Length of the section's data area, including interior padding.
+    data_length: u64,
+}
+
+impl DeduplicatedEntries {
+    /// Write the reference table preceding the data area.
+    fn write_references<W: Write>(&self, dest: &mut W) -> Result<()> {
+        dest.write_u64::<LittleEndian>(self.references.len() as u64)
+            .context("writing deduplicated entry count")?;
+        for reference in &self.references {
+            dest.write_u64::<LittleEndian>(*reference)
+                .context("writing deduplicated entry reference")?;
+        }
+
+        Ok(())
+    }
+}
+
 #[derive(Debug)]
 struct BlobSection {
-    resource_field: ResourceField,
+    resource_field: u8,
     raw_payload_length: usize,
     interior_padding: Option<BlobInteriorPadding>,
+    compression: Option<BlobCompression>,
+    encryption: Option<BlobEncryption>,
+    deduplicated: Option<DeduplicatedEntries>,
 }
 
 impl BlobSection {
+    fn new(field: ResourceField, options: &WriterOptions) -> Self {
+        let mut section = Self {
+            resource_field: field.into(),
+            raw_payload_length: 0,
+            interior_padding: options.interior_padding,
+            compression: options.compression.get(&field).copied(),
+            encryption: if options.encryption_key.is_some() && field.is_encryptable() {
+                Some(BlobEncryption::Aes256Gcm)
+            } else {
+                None
+            },
+            deduplicated: if options.deduplicate && field.is_deduplicatable() {
+                Some(DeduplicatedEntries::default())
+            } else {
+                None
+            },
+        };
+        section.raw_payload_length = section.first_entry_position();
+
+        section
+    }
+
+    /// The position of the first entry in the section.
+    fn first_entry_position(&self) -> usize {
+        // Deduplicated sections start with a u64 count of entry references.
+        if self.deduplicated.is_some() {
+            8
+        } else {
+            0
+        }
+    }
+
+    /// The position of the next entry in the section.
+    ///
+    /// For deduplicated sections, this is the position of the entry's
+    /// reference in the reference table.
+    fn entry_position(&self) -> usize {
+        match &self.deduplicated {
+            Some(entries) => 8 + 8 * entries.references.len(),
+            None => self.raw_payload_length,
+        }
+    }
+
+    /// Record an entry in a deduplicated section.
+    ///
+    /// Only the first occurrence of identical content is stored. Subsequent
+    /// occurrences reference it.
+    fn add_deduplicated_entry(&mut self, data: &[u8]) {
+        let padding = match self.interior_padding {
+            Some(BlobInteriorPadding::Null) => 1,
+            _ => 0,
+        };
+        let entries = self
+            .deduplicated
+            .as_mut()
+            .expect("blob section is deduplicated");
+
+        let digest: [u8; 32] = Sha256::digest(data).into();
+        let (offset, first) = match entries.offsets.entry(digest) {
+            hash_map::Entry::Occupied(entry) => (*entry.get(), false),
+            hash_map::Entry::Vacant(entry) => {
+                let offset = entries.data_length;
+                entries.data_length += (data.len() + padding) as u64;
+                (*entry.insert(offset), true)
+            }
+        };
+
+        entries.references.push(offset);
+        entries.first_occurrences.push(first);
+
+        self.raw_payload_length += 8;
+        if first {
+            self.raw_payload_length += data.len() + padding;
+        }
+    }
+
     /// Compute length of index entry for version 1 payload format.
     pub fn index_v1_length(&self) -> usize {
         // Start of index entry.
@@ -75,6 +202,21 @@ impl BlobSection {
             index += 2;
         }
 
+        if self.compression.is_some() {
+            // Field + value.
+            index += 2;
+        }
+
+        if self.encryption.is_some() {
+            // Field + value.
+            index += 2;
+        }
+
+        if self.deduplicated.is_some() {
+            // Field.
+            index += 1;
+        }
+
         // End of index entry.
         index += 1;
 
@@ -87,7 +229,7 @@ impl BlobSection {
 
         dest.write_u8(BlobSectionField::ResourceFieldType.into())
             .context("writing resource field type field")?;
-        dest.write_u8(self.resource_field.into())
+        dest.write_u8(self.resource_field)
             .context("writing resource field type value")?;
 
         dest.write_u8(BlobSectionField::RawPayloadLength.into())
@@ -102,6 +244,25 @@ impl BlobSection {
                 .context("writing interior padding value")?;
         }
 
+        if let Some(compression) = &self.compression {
+            dest.write_u8(BlobSectionField::Compression.into())
+                .context("writing compression field")?;
+            dest.write_u8(compression.into())
+                .context("writing compression value")?;
+        }
+
+        if let Some(encryption) = &self.encryption {
+            dest.write_u8(BlobSectionField::Encryption.into())
+                .context("writing encryption field")?;
+            dest.write_u8(encryption.into())
+                .context("writing encryption value")?;
+        }
+
+        if self.deduplicated.is_some() {
+            dest.write_u8(BlobSectionField::Deduplicated.into())
+                .context("writing deduplicated field")?;
+        }
+
         dest.write_u8(BlobSectionField::EndOfEntry.into())
             .context("writing end of index entry")?;
 
@@ -264,6 +425,10 @@ where
             index += 5;
         }
 
+        if self.extract_to_cache {
+            index += 1;
+        }
+
         // End of index entry.
         index += 1;
 
@@ -278,7 +443,7 @@ where
             ResourceField::EndOfIndex => 0,
             ResourceField::StartOfEntry => 0,
             ResourceField::EndOfEntry => 0,
-            ResourceField::Name => self.name.as_bytes().len(),
+            ResourceField::Name => self.name.len(),
             ResourceField::IsPythonPackage => 0,
             ResourceField::IsPythonNamespacePackage => 0,
             ResourceField::InMemorySource => {
@@ -320,7 +485,7 @@ where
                 if let Some(resources) = &self.in_memory_package_resources {
                     resources
                         .iter()
-                        .map(|(key, value)| key.as_bytes().len() + value.len())
+                        .map(|(key, value)| key.len() + value.len())
                         .sum()
                 } else {
                     0
@@ -330,7 +495,7 @@ where
                 if let Some(metadata) = &self.in_memory_distribution_resources {
                     metadata
                         .iter()
-                        .map(|(key, value)| key.as_bytes().len() + value.len())
+                        .map(|(key, value)| key.len() + value.len())
                         .sum()
                 } else {
                     0
@@ -345,7 +510,7 @@ where
             }
             ResourceField::SharedLibraryDependencyNames => {
                 if let Some(names) = &self.shared_library_dependency_names {
-                    names.iter().map(|s| s.as_bytes().len()).sum()
+                    names.iter().map(|s| s.len()).sum()
                 } else {
                     0
                 }
@@ -389,7 +554,7 @@ where
                 if let Some(resources) = &self.relative_path_package_resources {
                     resources
                         .iter()
-                        .map(|(key, value)| key.as_bytes().len() + path_bytes_length(value))
+                        .map(|(key, value)| key.len() + path_bytes_length(value))
                         .sum()
                 } else {
                     0
@@ -399,7 +564,7 @@ where
                 if let Some(metadata) = &self.relative_path_distribution_resources {
                     metadata
                         .iter()
-                        .map(|(key, value)| key.as_bytes().len() + path_bytes_length(value))
+                        .map(|(key, value)| key.len() + path_bytes_length(value))
                         .sum()
                 } else {
                     0
@@ -412,6 +577,7 @@ where
             ResourceField::IsSharedLibrary => 0,
             ResourceField::IsUtf8FilenameData => 0,
             ResourceField::FileExecutable => 0,
+            ResourceField::ExtractToCache => 0,
             ResourceField::FileDataEmbedded => {
                 if let Some(data) = &self.file_data_embedded {
                     data.len()
@@ -421,7 +587,7 @@ where
             }
             ResourceField::FileDataUtf8RelativePath => {
                 if let Some(path) = &self.file_data_utf8_relative_path {
-                    path.as_bytes().len()
+                    path.len()
                 } else {
                     0
                 }
@@ -561,6 +727,7 @@ where
             ResourceField::IsSharedLibrary => 0,
             ResourceField::IsUtf8FilenameData => 0,
             ResourceField::FileExecutable => 0,
+            ResourceField::ExtractToCache => 0,
             ResourceField::FileDataEmbedded => {
                 if self.file_data_embedded.is_some() {
                     1
@@ -587,8 +754,7 @@ where
 
     /// Write the version 1 index entry for a resource instance.
     pub fn write_index_v1<W: Write>(&self, dest: &mut W) -> Result<()> {
-        let name_len =
-            u16::try_from(self.name.as_bytes().len()).context("converting name to u16")?;
+        let name_len = u16::try_from(self.name.len()).context("converting name to u16")?;
 
         dest.write_u8(ResourceField::StartOfEntry.into())
             .context("writing start of index entry")?;
@@ -663,8 +829,8 @@ where
                 .context("writing in-memory resources data length")?;
 
             for (name, value) in resources.iter() {
-                let name_length = u16::try_from(name.as_bytes().len())
-                    .context("converting resource name length to u16")?;
+                let name_length =
+                    u16::try_from(name.len()).context("converting resource name length to u16")?;
                 dest.write_u16::<LittleEndian>(name_length)
                     .context("writing resource name length")?;
                 dest.write_u64::<LittleEndian>(value.len() as u64)
@@ -681,7 +847,7 @@ where
                 .context("writing in-memory package distribution length")?;
 
             for (name, value) in metadata {
-                let name_length = u16::try_from(name.as_bytes().len())
+                let name_length = u16::try_from(name.len())
                     .context("converting distribution name length to u16")?;
                 dest.write_u16::<LittleEndian>(name_length)
                     .context("writing distribution name length")?;
@@ -708,7 +874,7 @@ where
                 .context("writing shared library dependency names length")?;
 
             for name in names {
-                let name_length = u16::try_from(name.as_bytes().len())
+                let name_length = u16::try_from(name.len())
                     .context("converting shared library dependency name length to u16")?;
                 dest.write_u16::<LittleEndian>(name_length)
                     .context("writing shared library dependency name length")?;
@@ -769,8 +935,8 @@ where
                 .context("writing relative path resources resources data length")?;
 
             for (name, path) in resources.iter() {
-                let name_length = u16::try_from(name.as_bytes().len())
-                    .context("converting resource name length to u16")?;
+                let name_length =
+                    u16::try_from(name.len()).context("converting resource name length to u16")?;
                 let path_length = u32::try_from(path_bytes_length(path))
                     .context("converting resource path length to u32")?;
                 dest.write_u16::<LittleEndian>(name_length)
@@ -789,8 +955,8 @@ where
                 .context("writing relative path distribution data length")?;
 
             for (name, path) in metadata.iter() {
-                let name_length = u16::try_from(name.as_bytes().len())
-                    .context("converting resource name length to u16")?;
+                let name_length =
+                    u16::try_from(name.len()).context("converting resource name length to u16")?;
                 let path_length = u32::try_from(path_bytes_length(path))
                     .context("converting resource path length to u32")?;
                 dest.write_u16::<LittleEndian>(name_length)
@@ -845,7 +1011,7 @@ where
         }
 
         if let Some(path) = &self.file_data_utf8_relative_path {
-            let l = u32::try_from(path.as_bytes().len())
+            let l = u32::try_from(path.len())
                 .context("converting embedded file data relative path length to u32")?;
             dest.write_u8(ResourceField::FileDataUtf8RelativePath.into())
                 .context("writing file_data_utf8_relative_path field")?;
@@ -853,6 +1019,11 @@ where
                 .context("writing file_data_utf_relative_path field")?;
         }
 
+        if self.extract_to_cache {
+            dest.write_u8(ResourceField::ExtractToCache.into())
+                .context("writing extract_to_cache field")?;
+        }
+
         dest.write_u8(ResourceField::EndOfEntry.into())
             .map_err(|_| anyhow!("error writing end of index entry"))?;
 
@@ -860,321 +1031,805 @@ where
     }
 }
 
-/// Write packed resources data, version 3.
-#[allow(clippy::cognitive_complexity)]
-pub fn write_packed_resources_v3<'a, T: AsRef<Resource<'a, u8>>, W: Write>(
-    resources: &[T],
-    dest: &mut W,
-    interior_padding: Option<BlobInteriorPadding>,
-) -> Result<()> {
-    let mut blob_sections = BTreeMap::new();
-
-    let mut blob_section_count = 0;
-    // 1 for end of index field.
-    let mut blob_index_length = 1;
-
-    // 1 for end of index field.
-    let mut resource_index_length = 1;
-
-    let process_field = |blob_sections: &mut BTreeMap<ResourceField, BlobSection>,
-                         resource: &Resource<u8>,
-                         field: ResourceField| {
-        let padding = match &interior_padding {
-            Some(padding) => *padding,
-            None => BlobInteriorPadding::None,
-        };
-
-        let l = resource.field_blob_length(field)
-            + resource.field_blob_interior_padding_length(field, padding);
-        if l > 0 {
-            blob_sections
-                .entry(field)
-                .or_insert_with(|| BlobSection {
-                    resource_field: field,
-                    raw_payload_length: 0,
-                    interior_padding,
-                })
-                .raw_payload_length += l;
-        }
+/// Compress and encrypt a single blob entry according to writer options.
+///
+/// Data is compressed before it is encrypted, as encrypted data doesn't
+/// compress.
+fn encode_blob(field: ResourceField, data: &[u8], options: &WriterOptions) -> Result<Vec<u8>> {
+    let data = match options.compression.get(&field) {
+        Some(compression) => compress_blob(*compression, data).context("compressing")?,
+        None => data.to_vec(),
     };
 
-    let add_interior_padding = |dest: &mut W| -> Result<()> {
-        if interior_padding == Some(BlobInteriorPadding::Null) {
-            dest.write_all(b"\0")?;
+    match &options.encryption_key {
+        Some(key) if field.is_encryptable() => {
+            encrypt_blob(BlobEncryption::Aes256Gcm, key, &data).map_err(|e| anyhow!("{}", e))
         }
+        _ => Ok(data),
+    }
+}
 
-        Ok(())
+/// Obtain a copy of a resource with data in compressed and encrypted fields encoded.
+fn encode_resource<'a>(
+    resource: &Resource<'a, u8>,
+    options: &WriterOptions,
+) -> Result<Resource<'a, u8>> {
+    let encode = |field: ResourceField| {
+        options.compression.contains_key(&field)
+            || (options.encryption_key.is_some() && field.is_encryptable())
     };
 
-    for resource in resources {
-        let resource = resource.as_ref();
-        resource_index_length += resource.index_v1_length();
+    let encode_field =
+        |field: ResourceField, data: &Option<Cow<'a, [u8]>>| -> Result<Option<Cow<'a, [u8]>>> {
+            match data {
+                Some(data) if encode(field) => Ok(Some(Cow::Owned(
+                    encode_blob(field, data, options)
+                        .with_context(|| format!("encoding {:?} of {}", field, resource.name))?,
+                ))),
+                data => Ok(data.clone()),
+            }
+        };
 
-        process_field(&mut blob_sections, resource, ResourceField::Name);
-        process_field(&mut blob_sections, resource, ResourceField::InMemorySource);
-        process_field(
-            &mut blob_sections,
-            resource,
+    let encode_map =
+        |field: ResourceField, data: &Option<DataMap<'a>>| -> Result<Option<DataMap<'a>>> {
+            match data {
+                Some(data) if encode(field) => Ok(Some(
+                    data.iter()
+                        .map(|(key, value)| {
+                            Ok((
+                                key.clone(),
+                                Cow::Owned(encode_blob(field, value, options).with_context(
+                                    || format!("encoding {:?} {} of {}", field, key, resource.name),
+                                )?),
+                            ))
+                        })
+                        .collect::<Result<_>>()?,
+                )),
+                data => Ok(data.clone()),
+            }
+        };
+
+    Ok(Resource {
+        in_memory_source: encode_field(ResourceField::InMemorySource, &resource.in_memory_source)?,
+        in_memory_bytecode: encode_field(
             ResourceField::InMemoryBytecode,
-        );
-        process_field(
-            &mut blob_sections,
-            resource,
+            &resource.in_memory_bytecode,
+        )?,
+        in_memory_bytecode_opt1: encode_field(
             ResourceField::InMemoryBytecodeOpt1,
-        );
-        process_field(
-            &mut blob_sections,
-            resource,
+            &resource.in_memory_bytecode_opt1,
+        )?,
+        in_memory_bytecode_opt2: encode_field(
             ResourceField::InMemoryBytecodeOpt2,
-        );
-        process_field(
-            &mut blob_sections,
-            resource,
+            &resource.in_memory_bytecode_opt2,
+        )?,
+        in_memory_extension_module_shared_library: encode_field(
             ResourceField::InMemoryExtensionModuleSharedLibrary,
-        );
-        process_field(
-            &mut blob_sections,
-            resource,
+            &resource.in_memory_extension_module_shared_library,
+        )?,
+        in_memory_package_resources: encode_map(
             ResourceField::InMemoryResourcesData,
-        );
-        process_field(
-            &mut blob_sections,
-            resource,
+            &resource.in_memory_package_resources,
+        )?,
+        in_memory_distribution_resources: encode_map(
             ResourceField::InMemoryDistributionResource,
-        );
-        process_field(
-            &mut blob_sections,
-            resource,
+            &resource.in_memory_distribution_resources,
+        )?,
+        in_memory_shared_library: encode_field(
             ResourceField::InMemorySharedLibrary,
-        );
-        process_field(
-            &mut blob_sections,
-            resource,
-            ResourceField::SharedLibraryDependencyNames,
-        );
-        process_field(
-            &mut blob_sections,
-            resource,
-            ResourceField::RelativeFilesystemModuleSource,
-        );
-        process_field(
-            &mut blob_sections,
-            resource,
-            ResourceField::RelativeFilesystemModuleBytecode,
-        );
-        process_field(
-            &mut blob_sections,
-            resource,
-            ResourceField::RelativeFilesystemModuleBytecodeOpt1,
-        );
-        process_field(
-            &mut blob_sections,
-            resource,
-            ResourceField::RelativeFilesystemModuleBytecodeOpt2,
-        );
-        process_field(
-            &mut blob_sections,
-            resource,
-            ResourceField::RelativeFilesystemExtensionModuleSharedLibrary,
-        );
-        process_field(
-            &mut blob_sections,
-            resource,
-            ResourceField::RelativeFilesystemPackageResources,
-        );
-        process_field(
-            &mut blob_sections,
-            resource,
-            ResourceField::RelativeFilesystemDistributionResource,
-        );
-        process_field(
-            &mut blob_sections,
-            resource,
+            &resource.in_memory_shared_library,
+        )?,
+        file_data_embedded: encode_field(
             ResourceField::FileDataEmbedded,
-        );
-        process_field(
-            &mut blob_sections,
-            resource,
-            ResourceField::FileDataUtf8RelativePath,
-        );
+            &resource.file_data_embedded,
+        )?,
+        ..resource.clone()
+    })
+}
+
+/// Options controlling how version 4 packed resources data is written.
+#[derive(Clone, Debug, Default)]
+pub struct WriterOptions {
+    /// Padding to insert between entries in blob sections.
+    pub interior_padding: Option<BlobInteriorPadding>,
+
+    /// Compression to apply to the blob section of each [ResourceField].
+    ///
+    /// Fields not present in the map are stored uncompressed. Only fields
+    /// holding opaque data can be compressed. See
+    /// [ResourceField::is_compressible()].
+    pub compression: BTreeMap<ResourceField, BlobCompression>,
+
+    /// Whether to write a name index.
+    ///
+    /// The name index allows readers to find individual resources by name
+    /// without parsing the entire resources index. Resource names must be
+    /// unique when this is set.
+    pub name_index: bool,
+
+    /// Whether to deduplicate identical entries in blob sections.
+    ///
+    /// When set, blob sections of fields holding opaque data store each
+    /// distinct entry once and reference it by offset. See
+    /// [ResourceField::is_deduplicatable()]. This adds 8 bytes per entry, so
+    /// it pays off when resources share content.
+    pub deduplicate: bool,
+
+    /// AES-256-GCM key used to encrypt blob sections.
+    ///
+    /// When set, the blob sections of all fields holding opaque data are
+    /// encrypted. See [ResourceField::is_encryptable()]. Resource names and
+    /// paths remain readable, so resources can still be indexed without the
+    /// key. Each entry grows by 28 bytes.
+    pub encryption_key: Option<[u8; 32]>,
+}
+
+/// Write packed resources data, version 3.
+pub fn write_packed_resources_v3<'a, T: AsRef<Resource<'a, u8>>, W: Write>(
+    resources: &[T],
+    dest: &mut W,
+    interior_padding: Option<BlobInteriorPadding>,
+) -> Result<()> {
+    write_packed_resources(
+        resources,
+        dest,
+        HEADER_V3,
+        &WriterOptions {
+            interior_padding,
+            ..WriterOptions::default()
+        },
+    )
+}
+
+/// Validate version 4 writer options and drop no-op compression settings.
+fn normalize_v4_options(options: &WriterOptions) -> Result<WriterOptions> {
+    if let Some(field) = options
+        .compression
+        .keys()
+        .find(|field| !field.is_compressible())
+    {
+        return Err(anyhow!("{:?} does not support compression", field));
     }
 
-    for section in blob_sections.values() {
-        blob_section_count += 1;
-        blob_index_length += section.index_v1_length();
+    Ok(WriterOptions {
+        compression: options
+            .compression
+            .iter()
+            .filter(|(_, compression)| **compression != BlobCompression::None)
+            .map(|(field, compression)| (*field, *compression))
+            .collect::<BTreeMap<_, _>>(),
+        ..options.clone()
+    })
+}
+
+/// Write packed resources data, version 4.
+///
+/// Version 4 is like version 3 except entries in blob sections can be
+/// compressed, encrypted, or deduplicated and the data can contain a name index. See [WriterOptions]
+/// for how to control these features.
+pub fn write_packed_resources_v4<'a, T: AsRef<Resource<'a, u8>>, W: Write>(
+    resources: &[T],
+    dest: &mut W,
+    options: &WriterOptions,
+) -> Result<()> {
+    let options = normalize_v4_options(options)?;
+
+    if options.compression.is_empty() && options.encryption_key.is_none() {
+        write_packed_resources(resources, dest, HEADER_V4, &options)
+    } else {
+        let resources = resources
+            .iter()
+            .map(|resource| encode_resource(resource.as_ref(), &options))
+            .collect::<Result<Vec<_>>>()?;
+
+        write_packed_resources(&resources, dest, HEADER_V4, &options)
     }
+}
 
-    dest.write_all(HEADER_V3)?;
+/// Location of a resource's data, as recorded in the name index.
+struct NameIndexEntry<'a> {
+    name: Cow<'a, str>,
+    /// Offset of the resource's entry in the resources index.
+    index_offset: usize,
+    /// Offsets of the resource's data in each blob section.
+    blob_offsets: Vec<(ResourceField, usize)>,
+}
 
-    dest.write_u8(blob_section_count)?;
-    dest.write_u32::<LittleEndian>(blob_index_length as u32)?;
-    dest.write_u32::<LittleEndian>(resources.len() as u32)?;
-    dest.write_u32::<LittleEndian>(resource_index_length as u32)?;
+impl<'a> NameIndexEntry<'a> {
+    fn new(
+        name: Cow<'a, str>,
+        resource_index_length: usize,
+        blob_sections: &BTreeMap<ResourceField, BlobSection>,
+    ) -> Self {
+        Self {
+            name,
+            // The index length is seeded with the end of index marker.
+            index_offset: resource_index_length - 1,
+            blob_offsets: blob_sections
+                .iter()
+                .map(|(field, section)| (*field, section.entry_position()))
+                .collect::<Vec<_>>(),
+        }
+    }
+}
 
-    // Write the blob index.
-    for section in blob_sections.values() {
-        section.write_index_v1(dest)?;
+/// Build the content of the name index blob section.
+///
+/// `entries` holds an entry for each resource, in the order resources are
+/// written.
+fn build_name_index(
+    entries: &[NameIndexEntry],
+    blob_sections: &BTreeMap<ResourceField, BlobSection>,
+) -> Result<Vec<u8>> {
+    let mut fields = blob_sections.keys().copied().collect::<Vec<_>>();
+    if !fields.contains(&ResourceField::Name) {
+        fields.insert(0, ResourceField::Name);
     }
-    dest.write_u8(ResourceField::EndOfIndex.into())?;
 
-    // Write the resources index.
-    for resource in resources {
-        resource.as_ref().write_index_v1(dest)?;
+    let mut order = entries.iter().collect::<Vec<_>>();
+    order.sort_by(|a, b| a.name.as_bytes().cmp(b.name.as_bytes()));
+
+    if let Some(pair) = order.windows(2).find(|pair| pair[0].name == pair[1].name) {
+        return Err(anyhow!(
+            "name index requires unique resource names; {} is duplicated",
+            pair[0].name
+        ));
     }
-    dest.write_u8(ResourceField::EndOfIndex.into())?;
 
-    // Write blob data, one field at a time.
-    for resource in resources {
-        dest.write_all(resource.as_ref().name.as_bytes())?;
-        add_interior_padding(dest)?;
+    let mut data = Vec::with_capacity(1 + fields.len() + order.len() * (6 + 8 * fields.len()));
+
+    data.write_u8(fields.len() as u8)?;
+    for field in &fields {
+        data.write_u8((*field).into())?;
     }
 
-    for resource in resources {
-        if let Some(data) = &resource.as_ref().in_memory_source {
-            dest.write_all(data)?;
-            add_interior_padding(dest)?;
+    for entry in order {
+        data.write_u32::<LittleEndian>(entry.index_offset as u32)?;
+        data.write_u16::<LittleEndian>(entry.name.len() as u16)?;
+
+        for field in &fields {
+            // Sections created after the resource was added begin with its data.
+            let offset = entry
+                .blob_offsets
+                .iter()
+                .find(|(f, _)| f == field)
+                .map(|(_, offset)| *offset)
+                .unwrap_or_else(|| {
+                    blob_sections
+                        .get(field)
+                        .map_or(0, BlobSection::first_entry_position)
+                });
+
+            data.write_u64::<LittleEndian>(offset as u64)?;
         }
     }
 
-    for resource in resources {
-        if let Some(data) = &resource.as_ref().in_memory_bytecode {
-            dest.write_all(data)?;
-            add_interior_padding(dest)?;
-        }
+    Ok(data)
+}
+
+/// Fields whose data is stored in blob sections, in the order sections are written.
+const BLOB_FIELDS: [ResourceField; 19] = [
+    ResourceField::Name,
+    ResourceField::InMemorySource,
+    ResourceField::InMemoryBytecode,
+    ResourceField::InMemoryBytecodeOpt1,
+    ResourceField::InMemoryBytecodeOpt2,
+    ResourceField::InMemoryExtensionModuleSharedLibrary,
+    ResourceField::InMemoryResourcesData,
+    ResourceField::InMemoryDistributionResource,
+    ResourceField::InMemorySharedLibrary,
+    ResourceField::SharedLibraryDependencyNames,
+    ResourceField::RelativeFilesystemModuleSource,
+    ResourceField::RelativeFilesystemModuleBytecode,
+    ResourceField::RelativeFilesystemModuleBytecodeOpt1,
+    ResourceField::RelativeFilesystemModuleBytecodeOpt2,
+    ResourceField::RelativeFilesystemExtensionModuleSharedLibrary,
+    ResourceField::RelativeFilesystemPackageResources,
+    ResourceField::RelativeFilesystemDistributionResource,
+    ResourceField::FileDataEmbedded,
+    ResourceField::FileDataUtf8RelativePath,
+];
+
+/// Record the blob data of a resource field in its blob section.
+///
+/// Returns whether the resource has data for this field.
+fn add_field_to_blob_section(
+    blob_sections: &mut BTreeMap<ResourceField, BlobSection>,
+    resource: &Resource<u8>,
+    field: ResourceField,
+    options: &WriterOptions,
+) -> Result<bool> {
+    if options.deduplicate && field.is_deduplicatable() {
+        // Every entry needs a reference, even empty ones.
+        let mut present = false;
+
+        for_each_field_blob_entry(resource, field, &mut |data| {
+            present = true;
+            blob_sections
+                .entry(field)
+                .or_insert_with(|| BlobSection::new(field, options))
+                .add_deduplicated_entry(data);
+
+            Ok(())
+        })?;
+
+        return Ok(present);
     }
 
-    for resource in resources {
-        if let Some(data) = &resource.as_ref().in_memory_bytecode_opt1 {
-            dest.write_all(data)?;
-            add_interior_padding(dest)?;
-        }
+    let padding = options
+        .interior_padding
+        .unwrap_or(BlobInteriorPadding::None);
+
+    let l = resource.field_blob_length(field)
+        + resource.field_blob_interior_padding_length(field, padding);
+    if l > 0 {
+        blob_sections
+            .entry(field)
+            .or_insert_with(|| BlobSection::new(field, options))
+            .raw_payload_length += l;
     }
 
-    for resource in resources {
-        if let Some(data) = &resource.as_ref().in_memory_bytecode_opt2 {
-            dest.write_all(data)?;
-            add_interior_padding(dest)?;
-        }
+    Ok(l > 0)
+}
+
+/// Write a single blob section entry followed by its interior padding.
+fn write_blob_entry<W: Write>(
+    dest: &mut W,
+    data: &[u8],
+    interior_padding: Option<BlobInteriorPadding>,
+) -> Result<()> {
+    dest.write_all(data)?;
+
+    if interior_padding == Some(BlobInteriorPadding::Null) {
+        dest.write_all(b"\0")?;
     }
 
-    for resource in resources {
-        if let Some(data) = &resource.as_ref().in_memory_extension_module_shared_library {
-            dest.write_all(data)?;
-            add_interior_padding(dest)?;
-        }
+    Ok(())
+}
+
+/// Write the blob data of a single field of a resource.
+fn write_field_blob<W: Write>(
+    resource: &Resource<u8>,
+    field: ResourceField,
+    dest: &mut W,
+    interior_padding: Option<BlobInteriorPadding>,
+) -> Result<()> {
+    for_each_field_blob_entry(resource, field, &mut |data| {
+        write_blob_entry(dest, data, interior_padding)
+    })
+}
+
+/// Write the blob data of a single field of a resource to a deduplicated section.
+///
+/// `first_occurrences` yields whether each entry is the first occurrence of
+/// its content. Only first occurrences are written.
+fn write_deduplicated_field_blob<'b, W: Write>(
+    resource: &Resource<u8>,
+    field: ResourceField,
+    dest: &mut W,
+    interior_padding: Option<BlobInteriorPadding>,
+    first_occurrences: &mut impl Iterator<Item = &'b bool>,
+) -> Result<()> {
+    for_each_field_blob_entry(
+        resource,
+        field,
+        &mut |data| match first_occurrences.next() {
+            Some(true) => write_blob_entry(dest, data, interior_padding),
+            Some(false) => Ok(()),
+            None => Err(anyhow!("deduplicated {:?} entry count mismatch", field)),
+        },
+    )
+}
+
+/// Invoke a function with each blob section entry of a resource field.
+///
+/// Entries are emitted in the order they are written to the blob section.
+fn for_each_field_blob_entry(
+    resource: &Resource<u8>,
+    field: ResourceField,
+    write_entry: &mut dyn FnMut(&[u8]) -> Result<()>,
+) -> Result<()> {
+    let data = match field {
+        ResourceField::Name => Some(resource.name.as_bytes()),
+        ResourceField::InMemorySource => resource.in_memory_source.as_deref(),
+        ResourceField::InMemoryBytecode => resource.in_memory_bytecode.as_deref(),
+        ResourceField::InMemoryBytecodeOpt1 => resource.in_memory_bytecode_opt1.as_deref(),
+        ResourceField::InMemoryBytecodeOpt2 => resource.in_memory_bytecode_opt2.as_deref(),
+        ResourceField::InMemoryExtensionModuleSharedLibrary => resource
+            .in_memory_extension_module_shared_library
+            .as_deref(),
+        ResourceField::InMemorySharedLibrary => resource.in_memory_shared_library.as_deref(),
+        ResourceField::FileDataEmbedded => resource.file_data_embedded.as_deref(),
+        ResourceField::FileDataUtf8RelativePath => resource
+            .file_data_utf8_relative_path
+            .as_deref()
+            .map(|x| x.as_bytes()),
+        _ => None,
+    };
+
+    if let Some(data) = data {
+        return write_entry(data);
     }
 
-    for resource in resources {
-        if let Some(resources) = &resource.as_ref().in_memory_package_resources {
-            for (key, value) in resources.iter() {
-                dest.write_all(key.as_bytes())?;
-                add_interior_padding(dest)?;
-                dest.write_all(value)?;
-                add_interior_padding(dest)?;
-            }
+    let path = match field {
+        ResourceField::RelativeFilesystemModuleSource => {
+            resource.relative_path_module_source.as_deref()
         }
+        ResourceField::RelativeFilesystemModuleBytecode => {
+            resource.relative_path_module_bytecode.as_deref()
+        }
+        ResourceField::RelativeFilesystemModuleBytecodeOpt1 => {
+            resource.relative_path_module_bytecode_opt1.as_deref()
+        }
+        ResourceField::RelativeFilesystemModuleBytecodeOpt2 => {
+            resource.relative_path_module_bytecode_opt2.as_deref()
+        }
+        ResourceField::RelativeFilesystemExtensionModuleSharedLibrary => resource
+            .relative_path_extension_module_shared_library
+            .as_deref(),
+        _ => None,
+    };
+
+    if let Some(path) = path {
+        return write_entry(&path_to_bytes(path));
     }
 
-    for resource in resources {
-        if let Some(resources) = &resource.as_ref().in_memory_distribution_resources {
-            for (key, value) in resources {
-                dest.write_all(key.as_bytes())?;
-                add_interior_padding(dest)?;
-                dest.write_all(value)?;
-                add_interior_padding(dest)?;
+    match field {
+        ResourceField::InMemoryResourcesData | ResourceField::InMemoryDistributionResource => {
+            let map = if field == ResourceField::InMemoryResourcesData {
+                &resource.in_memory_package_resources
+            } else {
+                &resource.in_memory_distribution_resources
+            };
+
+            if let Some(map) = map {
+                for (key, value) in map {
+                    write_entry(key.as_bytes())?;
+                    write_entry(value)?;
+                }
+            }
+        }
+        ResourceField::RelativeFilesystemPackageResources
+        | ResourceField::RelativeFilesystemDistributionResource => {
+            let map = if field == ResourceField::RelativeFilesystemPackageResources {
+                &resource.relative_path_package_resources
+            } else {
+                &resource.relative_path_distribution_resources
+            };
+
+            if let Some(map) = map {
+                for (key, path) in map {
+                    write_entry(key.as_bytes())?;
+                    write_entry(&path_to_bytes(path))?;
+                }
             }
         }
+        ResourceField::SharedLibraryDependencyNames => {
+            if let Some(names) = &resource.shared_library_dependency_names {
+                for name in names {
+                    write_entry(name.as_bytes())?;
+                }
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// Write everything preceding the resources index.
+///
+/// The name index, if present, is always the last blob section.
+fn write_header_and_blob_index<W: Write>(
+    dest: &mut W,
+    header: &[u8],
+    blob_sections: &BTreeMap<ResourceField, BlobSection>,
+    name_index: Option<&BlobSection>,
+    resources_count: usize,
+    resource_index_length: usize,
+) -> Result<()> {
+    let mut blob_section_count = 0;
+    // 1 for end of index field.
+    let mut blob_index_length = 1;
+
+    for section in blob_sections.values().chain(name_index) {
+        blob_section_count += 1;
+        blob_index_length += section.index_v1_length();
+    }
+
+    dest.write_all(header)?;
+
+    dest.write_u8(blob_section_count)?;
+    dest.write_u32::<LittleEndian>(blob_index_length as u32)?;
+    dest.write_u32::<LittleEndian>(resources_count as u32)?;
+    dest.write_u32::<LittleEndian>(resource_index_length as u32)?;
+
+    for section in blob_sections.values().chain(name_index) {
+        section.write_index_v1(dest)?;
     }
+    dest.write_u8(ResourceField::EndOfIndex.into())?;
+
+    Ok(())
+}
+
+/// Build the blob section holding the name index.
+fn name_index_section(
+    entries: &[NameIndexEntry],
+    blob_sections: &BTreeMap<ResourceField, BlobSection>,
+) -> Result<(BlobSection, Vec<u8>)> {
+    let data = build_name_index(entries, blob_sections)?;
+
+    Ok((
+        BlobSection {
+            resource_field: NAME_INDEX_BLOB_SECTION,
+            raw_payload_length: data.len(),
+            interior_padding: None,
+            compression: None,
+            encryption: None,
+            deduplicated: None,
+        },
+        data,
+    ))
+}
+
+fn write_packed_resources<'a, T: AsRef<Resource<'a, u8>>, W: Write>(
+    resources: &[T],
+    dest: &mut W,
+    header: &[u8],
+    options: &WriterOptions,
+) -> Result<()> {
+    let mut blob_sections: BTreeMap<ResourceField, BlobSection> = BTreeMap::new();
+
+    // Only populated when writing a name index.
+    let mut name_index_entries = Vec::new();
+
+    // 1 for end of index field.
+    let mut resource_index_length = 1;
 
     for resource in resources {
-        if let Some(data) = &resource.as_ref().in_memory_shared_library {
-            dest.write_all(data)?;
-            add_interior_padding(dest)?;
+        let resource = resource.as_ref();
+
+        if options.name_index {
+            name_index_entries.push(NameIndexEntry::new(
+                Cow::Borrowed(resource.name.as_ref()),
+                resource_index_length,
+                &blob_sections,
+            ));
+        }
+
+        resource_index_length += resource.index_v1_length();
+
+        for field in BLOB_FIELDS {
+            add_field_to_blob_section(&mut blob_sections, resource, field, options)?;
         }
     }
 
+    let name_index = if options.name_index {
+        Some(name_index_section(&name_index_entries, &blob_sections)?)
+    } else {
+        None
+    };
+
+    write_header_and_blob_index(
+        dest,
+        header,
+        &blob_sections,
+        name_index.as_ref().map(|(section, _)| section),
+        resources.len(),
+        resource_index_length,
+    )?;
+
+    // Write the resources index.
     for resource in resources {
-        if let Some(names) = &resource.as_ref().shared_library_dependency_names {
-            for name in names {
-                dest.write_all(name.as_bytes())?;
-                add_interior_padding(dest)?;
+        resource.as_ref().write_index_v1(dest)?;
+    }
+    dest.write_u8(ResourceField::EndOfIndex.into())?;
+
+    // Write blob data, one field at a time.
+    for field in BLOB_FIELDS {
+        if let Some(entries) = blob_sections
+            .get(&field)
+            .and_then(|section| section.deduplicated.as_ref())
+        {
+            entries.write_references(dest)?;
+
+            let mut first_occurrences = entries.first_occurrences.iter();
+            for resource in resources {
+                write_deduplicated_field_blob(
+                    resource.as_ref(),
+                    field,
+                    dest,
+                    options.interior_padding,
+                    &mut first_occurrences,
+                )?;
+            }
+        } else {
+            for resource in resources {
+                write_field_blob(resource.as_ref(), field, dest, options.interior_padding)?;
             }
         }
     }
 
-    for resource in resources {
-        if let Some(path) = &resource.as_ref().relative_path_module_source {
-            dest.write_all(&path_to_bytes(path))?;
-            add_interior_padding(dest)?;
-        }
+    // The name index is always the last blob section.
+    if let Some((_, data)) = &name_index {
+        dest.write_all(data)?;
     }
 
-    for resource in resources {
-        if let Some(path) = &resource.as_ref().relative_path_module_bytecode {
-            dest.write_all(&path_to_bytes(path))?;
-            add_interior_padding(dest)?;
-        }
+    Ok(())
+}
+
+/// Incrementally writes packed resources data.
+///
+/// Unlike [write_packed_resources_v3()] and [write_packed_resources_v4()],
+/// which need every [Resource] up front, resources are added one at a time
+/// and their data is spooled to temporary files, one per blob section. Only
+/// the resources index and (if enabled) name index metadata are retained in
+/// memory. [PackedResourcesStreamWriter::finish()] then assembles the final
+/// data, which is identical to what the non-streaming writers produce for
+/// the same resources.
+///
+/// Requires the `stream-writer` feature.
+#[cfg(feature = "stream-writer")]
+pub struct PackedResourcesStreamWriter {
+    header: &'static [u8],
+    options: WriterOptions,
+    resources_count: usize,
+    /// Length of the resources index, including its end of index marker.
+    resource_index_length: usize,
+    resource_index: BufWriter<File>,
+    blob_sections: BTreeMap<ResourceField, BlobSection>,
+    blob_section_data: BTreeMap<ResourceField, BufWriter<File>>,
+    name_index_entries: Vec<NameIndexEntry<'static>>,
+}
+
+#[cfg(feature = "stream-writer")]
+impl PackedResourcesStreamWriter {
+    fn new(header: &'static [u8], options: WriterOptions) -> Result<Self> {
+        Ok(Self {
+            header,
+            options,
+            resources_count: 0,
+            resource_index_length: 1,
+            resource_index: BufWriter::new(
+                tempfile::tempfile().context("creating resources index spool file")?,
+            ),
+            blob_sections: BTreeMap::new(),
+            blob_section_data: BTreeMap::new(),
+            name_index_entries: vec![],
+        })
     }
 
-    for resource in resources {
-        if let Some(path) = &resource.as_ref().relative_path_module_bytecode_opt1 {
-            dest.write_all(&path_to_bytes(path))?;
-            add_interior_padding(dest)?;
-        }
+    /// Construct an instance writing packed resources data, version 3.
+    pub fn new_v3(interior_padding: Option<BlobInteriorPadding>) -> Result<Self> {
+        Self::new(
+            HEADER_V3,
+            WriterOptions {
+                interior_padding,
+                ..WriterOptions::default()
+            },
+        )
     }
 
-    for resource in resources {
-        if let Some(path) = &resource.as_ref().relative_path_module_bytecode_opt2 {
-            dest.write_all(&path_to_bytes(path))?;
-            add_interior_padding(dest)?;
-        }
+    /// Construct an instance writing packed resources data, version 4.
+    pub fn new_v4(options: &WriterOptions) -> Result<Self> {
+        Self::new(HEADER_V4, normalize_v4_options(options)?)
     }
 
-    for resource in resources {
-        if let Some(path) = &resource
-            .as_ref()
-            .relative_path_extension_module_shared_library
-        {
-            dest.write_all(&path_to_bytes(path))?;
-            add_interior_padding(dest)?;
+    /// Add a resource.
+    ///
+    /// Resources are written in the order they are added.
+    pub fn add_resource(&mut self, resource: &Resource<u8>) -> Result<()> {
+        if self.options.compression.is_empty() && self.options.encryption_key.is_none() {
+            self.add_resource_data(resource)
+        } else {
+            self.add_resource_data(&encode_resource(resource, &self.options)?)
         }
     }
 
-    for resource in resources {
-        if let Some(resources) = &resource.as_ref().relative_path_package_resources {
-            for (key, path) in resources.iter() {
-                dest.write_all(key.as_bytes())?;
-                add_interior_padding(dest)?;
-                dest.write_all(&path_to_bytes(path))?;
-                add_interior_padding(dest)?;
+    fn add_resource_data(&mut self, resource: &Resource<u8>) -> Result<()> {
+        if self.options.name_index {
+            self.name_index_entries.push(NameIndexEntry::new(
+                Cow::Owned(resource.name.to_string()),
+                self.resource_index_length,
+                &self.blob_sections,
+            ));
+        }
+
+        resource
+            .write_index_v1(&mut self.resource_index)
+            .with_context(|| format!("spooling index entry of {}", resource.name))?;
+        self.resource_index_length += resource.index_v1_length();
+        self.resources_count += 1;
+
+        for field in BLOB_FIELDS {
+            let previous_entries = self
+                .blob_sections
+                .get(&field)
+                .and_then(|section| section.deduplicated.as_ref())
+                .map_or(0, |entries| entries.first_occurrences.len());
+
+            if !add_field_to_blob_section(&mut self.blob_sections, resource, field, &self.options)?
+            {
+                continue;
             }
+
+            // Only the first occurrence of deduplicated content is spooled.
+            let first_occurrences = self.blob_sections[&field]
+                .deduplicated
+                .as_ref()
+                .map(|entries| entries.first_occurrences[previous_entries..].to_vec());
+
+            let dest = match self.blob_section_data.entry(field) {
+                btree_map::Entry::Occupied(entry) => entry.into_mut(),
+                btree_map::Entry::Vacant(entry) => entry
+                    .insert(BufWriter::new(tempfile::tempfile().with_context(|| {
+                        format!("creating {:?} blob section spool file", field)
+                    })?)),
+            };
+
+            if let Some(first_occurrences) = first_occurrences {
+                write_deduplicated_field_blob(
+                    resource,
+                    field,
+                    dest,
+                    self.options.interior_padding,
+                    &mut first_occurrences.iter(),
+                )
+            } else {
+                write_field_blob(resource, field, dest, self.options.interior_padding)
+            }
+            .with_context(|| format!("spooling {:?} of {}", field, resource.name))?;
         }
+
+        Ok(())
     }
 
-    for resource in resources {
-        if let Some(resources) = &resource.as_ref().relative_path_distribution_resources {
-            for (key, path) in resources {
-                dest.write_all(key.as_bytes())?;
-                add_interior_padding(dest)?;
-                dest.write_all(&path_to_bytes(path))?;
-                add_interior_padding(dest)?;
+    /// Write the packed resources data to a destination.
+    pub fn finish<W: Write>(self, dest: &mut W) -> Result<()> {
+        let name_index = if self.options.name_index {
+            Some(name_index_section(
+                &self.name_index_entries,
+                &self.blob_sections,
+            )?)
+        } else {
+            None
+        };
+
+        write_header_and_blob_index(
+            dest,
+            self.header,
+            &self.blob_sections,
+            name_index.as_ref().map(|(section, _)| section),
+            self.resources_count,
+            self.resource_index_length,
+        )?;
+
+        copy_spool_file(self.resource_index, dest).context("copying resources index")?;
+        dest.write_u8(ResourceField::EndOfIndex.into())?;
+
+        for (field, data) in self.blob_section_data {
+            if let Some(entries) = &self.blob_sections[&field].deduplicated {
+                entries.write_references(dest)?;
             }
+
+            copy_spool_file(data, dest)
+                .with_context(|| format!("copying {:?} blob section", field))?;
         }
-    }
 
-    for resource in resources {
-        if let Some(data) = &resource.as_ref().file_data_embedded {
+        if let Some((_, data)) = &name_index {
             dest.write_all(data)?;
-            add_interior_padding(dest)?;
         }
-    }
 
-    for resource in resources {
-        if let Some(path) = &resource.as_ref().file_data_utf8_relative_path {
-            dest.write_all(path.as_bytes())?;
-            add_interior_padding(dest)?;
-        }
+        Ok(())
     }
+}
+
+/// Copy the content of a spooled temporary file to a destination.
+#[cfg(feature = "stream-writer")]
+fn copy_spool_file<W: Write>(spool: BufWriter<File>, dest: &mut W) -> Result<()> {
+    let mut fh = spool.into_inner().map_err(|e| e.into_error())?;
+    fh.seek(SeekFrom::Start(0))?;
+    std::io::copy(&mut fh, dest)?;
 
     Ok(())
 }
@@ -1247,4 +1902,225 @@ mod tests {
 
         Ok(())
     }
+
+    #[cfg(feature = "stream-writer")]
+    fn stream_test_resources() -> Vec<Resource<'static, u8>> {
+        let mut package_resources = HashMap::new();
+        package_resources.insert(Cow::from("data.txt"), Cow::from(b"data".repeat(64)));
+        package_resources.insert(Cow::from("other.txt"), Cow::from(b"other".to_vec()));
+
+        let mut relative_resources = HashMap::new();
+        relative_resources.insert(Cow::from("METADATA"), Cow::from(Path::new("foo/METADATA")));
+
+        vec![
+            Resource {
+                name: Cow::from("foo"),
+                is_python_module: true,
+                is_python_package: true,
+                in_memory_source: Some(Cow::from(b"import io\n".repeat(32))),
+                in_memory_bytecode: Some(Cow::from(b"bytecode".to_vec())),
+                in_memory_package_resources: Some(package_resources),
+                relative_path_distribution_resources: Some(relative_resources),
+                ..Resource::default()
+            },
+            Resource {
+                name: Cow::from("foo.bar"),
+                is_python_module: true,
+                relative_path_module_source: Some(Cow::from(Path::new("foo/bar.py"))),
+                shared_library_dependency_names: Some(vec![Cow::from("libfoo")]),
+                ..Resource::default()
+            },
+            Resource {
+                name: Cow::from("file.txt"),
+                is_utf8_filename_data: true,
+                file_executable: true,
+                file_data_embedded: Some(Cow::from(b"file data".to_vec())),
+                ..Resource::default()
+            },
+        ]
+    }
+
+    #[test]
+    #[cfg(feature = "stream-writer")]
+    fn test_stream_writer_empty() -> Result<()> {
+        let mut expected = Vec::new();
+        write_packed_resources_v3::<Resource<u8>, _>(&[], &mut expected, None)?;
+
+        let mut data = Vec::new();
+        PackedResourcesStreamWriter::new_v3(None)?.finish(&mut data)?;
+
+        assert_eq!(data, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "stream-writer")]
+    fn test_stream_writer_v3() -> Result<()> {
+        let resources = stream_test_resources();
+
+        for padding in [None, Some(BlobInteriorPadding::Null)] {
+            let mut expected = Vec::new();
+            write_packed_resources_v3(&resources, &mut expected, padding)?;
+
+            let mut writer = PackedResourcesStreamWriter::new_v3(padding)?;
+            for resource in &resources {
+                writer.add_resource(resource)?;
+            }
+            let mut data = Vec::new();
+            writer.finish(&mut data)?;
+
+            assert_eq!(data, expected);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(all(feature = "stream-writer", feature = "zstd"))]
+    fn test_stream_writer_v4() -> Result<()> {
+        let resources = stream_test_resources();
+
+        let mut compression = BTreeMap::new();
+        compression.insert(ResourceField::InMemorySource, BlobCompression::Zstd);
+
+        let mut options = WriterOptions {
+            interior_padding: Some(BlobInteriorPadding::Null),
+            compression,
+            name_index: true,
+            ..WriterOptions::default()
+        };
+
+        let mut expected = Vec::new();
+        write_packed_resources_v4(&resources, &mut expected, &options)?;
+
+        let mut writer = PackedResourcesStreamWriter::new_v4(&options)?;
+        for resource in &resources {
+            writer.add_resource(resource)?;
+        }
+        let mut data = Vec::new();
+        writer.finish(&mut data)?;
+
+        assert_eq!(data, expected);
+
+        // Compressing mapped fields rebuilds their maps, so entry order (and
+        // therefore output) can legitimately differ between writers.
+        options
+            .compression
+            .insert(ResourceField::InMemoryResourcesData, BlobCompression::Zstd);
+
+        let mut writer = PackedResourcesStreamWriter::new_v4(&options)?;
+        for resource in &resources {
+            writer.add_resource(resource)?;
+        }
+        let mut data = Vec::new();
+        writer.finish(&mut data)?;
+
+        let parsed = crate::parser::load_resources(&data)
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(parsed, resources);
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(all(feature = "encryption", feature = "stream-writer", feature = "zstd"))]
+    fn test_write_deduplicated() -> Result<()> {
+        let mut resources = stream_test_resources();
+        resources.push(Resource {
+            name: Cow::from("foo.baz"),
+            is_python_module: true,
+            in_memory_source: resources[0].in_memory_source.clone(),
+            in_memory_bytecode: Some(Cow::from(b"bytecode".to_vec())),
+            in_memory_package_resources: resources[0].in_memory_package_resources.clone(),
+            ..Resource::default()
+        });
+
+        let mut compression = BTreeMap::new();
+        compression.insert(ResourceField::InMemorySource, BlobCompression::Zstd);
+
+        // Encrypted entries use deterministic nonces, so they deduplicate too.
+        for (padding, encryption_key) in [
+            (None, None),
+            (Some(BlobInteriorPadding::Null), None),
+            (None, Some([0x42; 32])),
+        ] {
+            let options = WriterOptions {
+                interior_padding: padding,
+                compression: compression.clone(),
+                name_index: true,
+                deduplicate: true,
+                encryption_key,
+            };
+
+            let mut expected = Vec::new();
+            write_packed_resources_v4(&resources, &mut expected, &options)?;
+
+            let mut duplicated = Vec::new();
+            write_packed_resources_v4(
+                &resources,
+                &mut duplicated,
+                &WriterOptions {
+                    deduplicate: false,
+                    ..options.clone()
+                },
+            )?;
+            assert!(expected.len() < duplicated.len());
+
+            let mut writer = PackedResourcesStreamWriter::new_v4(&options)?;
+            for resource in &resources {
+                writer.add_resource(resource)?;
+            }
+            let mut data = Vec::new();
+            writer.finish(&mut data)?;
+
+            if let Some(key) = encryption_key {
+                // Encoding package resources produces a new map, whose entries
+                // may be written in a different order. So compare content.
+                assert_eq!(data.len(), expected.len());
+                let mut parser = crate::parser::load_resources(&data).unwrap();
+                parser.set_decryption_key(Some(key));
+                assert_eq!(parser.collect::<Result<Vec<_>, _>>().unwrap(), resources);
+            } else {
+                assert_eq!(data, expected);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "stream-writer")]
+    fn test_stream_writer_v4_rejects_compression() {
+        let mut compression = BTreeMap::new();
+        compression.insert(ResourceField::Name, BlobCompression::Zstd);
+
+        assert!(PackedResourcesStreamWriter::new_v4(&WriterOptions {
+            compression,
+            ..WriterOptions::default()
+        })
+        .is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "stream-writer")]
+    fn test_stream_writer_duplicate_names() -> Result<()> {
+        let resource = Resource {
+            name: Cow::from("foo"),
+            ..Resource::default()
+        };
+
+        let mut writer = PackedResourcesStreamWriter::new_v4(&WriterOptions {
+            name_index: true,
+            ..WriterOptions::default()
+        })?;
+        writer.add_resource(&resource)?;
+        writer.add_resource(&resource)?;
+
+        assert!(writer.finish(&mut Vec::new()).is_err());
+
+        Ok(())
+    }
 }